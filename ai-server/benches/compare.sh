@@ -0,0 +1,26 @@
+#!/bin/sh
+# Compares the engine benchmarks of two revisions, e.g. `benches/compare.sh main HEAD`
+#
+# Both revisions are built in a temporary worktree sharing this crate's target directory, so that
+# Criterion reports the change of the second revision against the baseline of the first one.
+set -e
+
+crate=$(cd "$(dirname "$0")/.." && pwd)
+root=$(git -C "$crate" rev-parse --show-toplevel)
+prefix=$(git -C "$crate" rev-parse --show-prefix)
+
+# Resolved here, since HEAD inside the worktree would be the base revision
+base=${1:?usage: compare.sh <base revision> [<revision>]}
+base=$(git -C "$root" rev-parse --verify "$base^{commit}")
+revision=$(git -C "$root" rev-parse --verify "${2:-HEAD}^{commit}")
+baseline=compare-base
+worktree=$(mktemp -d)
+trap 'git -C "$root" worktree remove --force "$worktree"' EXIT
+
+export CARGO_TARGET_DIR="$crate/target"
+
+git -C "$root" worktree add --detach "$worktree" "$base"
+(cd "$worktree/$prefix" && cargo bench --bench engine -- --save-baseline "$baseline")
+
+git -C "$worktree" checkout --detach "$revision"
+(cd "$worktree/$prefix" && cargo bench --bench engine -- --baseline "$baseline")
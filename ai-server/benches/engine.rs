@@ -0,0 +1,89 @@
+//! Benchmarks of the primitives of the engine, run with `cargo bench --bench engine`
+//!
+//! Use `benches/compare.sh` to compare them between two revisions.
+
+use std::hint::black_box;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use desdemona::bot::Bot;
+use desdemona::game::{Game, Player};
+
+/// Number of plies played from the start to reach the midgame position
+const MIDGAME_PLIES: usize = 20;
+
+/// Depths of the fixed-depth searches
+const SEARCH_DEPTHS: [u32; 4] = [1, 2, 3, 4];
+
+/// Returns the positions measured by every benchmark, which are always the same
+fn positions() -> [(&'static str, Game); 2] {
+    let mut midgame = Game::new();
+    for _ in 0..MIDGAME_PLIES {
+        let player = midgame.current_player();
+        let action = midgame.actions(player).next();
+        midgame = match action {
+            Some(action) => midgame.result(&action),
+            None => midgame.pass(),
+        };
+    }
+    // Searches are measured from the bot's point of view
+    if midgame.current_player() != Player::Bot {
+        midgame = midgame.pass();
+    }
+
+    [("opening", Game::new()), ("midgame", midgame)]
+}
+
+fn actions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("actions");
+    for (name, game) in positions() {
+        let player = game.current_player();
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(&game).actions(player).count())
+        });
+    }
+    group.finish();
+}
+
+fn result(c: &mut Criterion) {
+    let mut group = c.benchmark_group("result");
+    for (name, game) in positions() {
+        let actions: Vec<_> = game.actions(game.current_player()).collect();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for action in &actions {
+                    black_box(black_box(&game).result(action));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn evaluate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluate");
+    for (name, game) in positions() {
+        group.bench_function(name, |b| b.iter(|| black_box(&game).evaluate()));
+    }
+    group.finish();
+}
+
+fn search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+    group.sample_size(10);
+    for (name, game) in positions() {
+        for depth in SEARCH_DEPTHS {
+            // Every search starts with an empty cache, so that it measures the search itself
+            group.bench_with_input(BenchmarkId::new(name, depth), &depth, |b, &depth| {
+                b.iter_batched(
+                    || Bot::new(depth),
+                    |mut bot| bot.decide(black_box(&game)).unwrap(),
+                    BatchSize::PerIteration,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, actions, result, evaluate, search);
+criterion_main!(benches);
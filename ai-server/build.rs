@@ -0,0 +1,26 @@
+//! Generates the C header of the engine when the `ffi` feature is enabled
+
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+
+        let dir = std::env::var("CARGO_MANIFEST_DIR").expect("Cargo sets the manifest directory");
+        let config = cbindgen::Config {
+            language: cbindgen::Language::C,
+            include_guard: Some("DESDEMONA_H".to_string()),
+            cpp_compat: true,
+            documentation: true,
+            ..Default::default()
+        };
+
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{}/src/ffi.rs", dir))
+            .generate()
+            .expect("Failed to generate the C header")
+            .write_to_file(format!("{}/include/desdemona.h", dir));
+    }
+
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,17 @@
+//! Parses arbitrary text into a board, which must either fail or give a board that parses back
+//! to itself
+#![no_main]
+
+use desdemona::board::Board;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    if let Ok(board) = Board::parse(data.to_string()) {
+        let text = board.to_string();
+        assert_eq!(Board::parse(text.clone()).unwrap().to_string(), text);
+        board.pretty();
+        board.stable_disks();
+        board.frontier_disks();
+        board.inconsistencies();
+    }
+});
@@ -0,0 +1,31 @@
+//! Parses arbitrary text, whose first character is the player to move and the rest the board,
+//! into a game and inspects its state, which must never panic however the board looks
+#![no_main]
+
+use desdemona::board::Board;
+use desdemona::game::{Game, Player};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    let mut chars = data.chars();
+    let player = match chars.next().map(Player::parse) {
+        Some(Ok(player)) => player,
+        _ => return,
+    };
+    let board = match Board::parse(chars.as_str().to_string()) {
+        Ok(board) => board,
+        Err(_) => return,
+    };
+
+    let game = Game::parse(board, player);
+    game.evaluate();
+    game.potential_mobility(player);
+    for action in game.actions(player) {
+        game.captures(&action);
+    }
+    assert_eq!(game.winner().is_ok(), game.is_over());
+    assert_eq!(game.utility().is_ok(), game.is_over());
+    if game.must_pass() {
+        assert!(game.pass().actions(player.opponent()).next().is_some());
+    }
+});
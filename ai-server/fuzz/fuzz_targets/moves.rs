@@ -0,0 +1,42 @@
+//! Plays a game from the start where each byte picks a square, which is played if it is legal
+//! and otherwise picks one of the legal actions, checking the rules hold after every move
+#![no_main]
+
+use desdemona::board::{Disk, Position, BOARD_SIZE};
+use desdemona::game::{Action, Game};
+use libfuzzer_sys::fuzz_target;
+
+fn num_disks(game: &Game) -> i32 {
+    game.board().num_disks(Disk::Dark) + game.board().num_disks(Disk::Light)
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut game = Game::new();
+
+    for &byte in data {
+        if game.is_over() {
+            assert!(game.winner().is_ok());
+            return;
+        }
+        if game.must_pass() {
+            game = game.pass();
+            continue;
+        }
+
+        let player = game.current_player();
+        let square = byte as usize;
+        let action = Action::parse(player, Position::new(square / BOARD_SIZE,
+                                                         square % BOARD_SIZE));
+        let result = match game.play(&action) {
+            Ok(result) => result,
+            Err(_) => {
+                let actions = game.actions(player).collect::<Vec<_>>();
+                game.play(&actions[square % actions.len()]).unwrap()
+            }
+        };
+
+        assert_eq!(num_disks(&result), num_disks(&game) + 1);
+        assert_eq!(result.board().num_empty() + 1, game.board().num_empty());
+        game = result;
+    }
+});
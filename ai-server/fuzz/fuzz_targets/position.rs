@@ -0,0 +1,17 @@
+//! Parses arbitrary text into a position, either as coordinates such as "4,5" or as notation
+//! such as "f5", which must either fail or give a position on the board
+#![no_main]
+
+use desdemona::board::Position;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    if let Ok(pos) = Position::parse(data.to_string()) {
+        assert!(pos.is_inbound());
+        assert_eq!(Position::parse(pos.to_string()).unwrap(), pos);
+    }
+    if let Ok(pos) = Position::parse_notation(data) {
+        assert!(pos.is_inbound());
+        assert_eq!(Position::parse_notation(&pos.notation()).unwrap(), pos);
+    }
+});
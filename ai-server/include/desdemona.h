@@ -0,0 +1,151 @@
+#ifndef DESDEMONA_H
+#define DESDEMONA_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Returned when an argument is null or invalid
+ */
+#define DESDEMONA_INVALID_ARGUMENT -1
+
+/**
+ * Returned when the move is not allowed in the game
+ */
+#define DESDEMONA_ILLEGAL_MOVE -2
+
+/**
+ * Returned when the player to move has no move
+ */
+#define DESDEMONA_NO_MOVES -3
+
+/**
+ * Opaque handle of a game state
+ */
+typedef struct DesdemonaGame DesdemonaGame;
+
+/**
+ * Square of the board, counted from the top left corner
+ */
+typedef struct DesdemonaMove {
+  uint8_t row;
+  uint8_t col;
+} DesdemonaMove;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a game at the initial board with the human to move
+ */
+struct DesdemonaGame *desdemona_game_new(void);
+
+/**
+ * Creates a game from the given board, written as rows of `E`, `D` and `L` joined by newlines,
+ * and the player to move, `H` for the human with the dark disks or `B` for the bot with the
+ * light disks
+ *
+ * Returns null when the board or the player is invalid.
+ *
+ * # Safety
+ * `board` must be null or point to a null-terminated string.
+ */
+struct DesdemonaGame *desdemona_game_parse(const char *board, char player);
+
+/**
+ * Creates a copy of the given game, or returns null when it is null
+ *
+ * # Safety
+ * `game` must be null or a live handle returned by this API.
+ */
+struct DesdemonaGame *desdemona_game_clone(const struct DesdemonaGame *game);
+
+/**
+ * Frees the given game, doing nothing when it is null
+ *
+ * # Safety
+ * `game` must be null or a live handle returned by this API, which must not be used afterwards.
+ */
+void desdemona_game_free(struct DesdemonaGame *game);
+
+/**
+ * Returns the player to move, `H` or `B`, or 0 when the game is null
+ *
+ * # Safety
+ * `game` must be null or a live handle returned by this API.
+ */
+char desdemona_game_current_player(const struct DesdemonaGame *game);
+
+/**
+ * Returns 1 when the game is over, 0 when it is not and a negative value when it is null
+ *
+ * # Safety
+ * `game` must be null or a live handle returned by this API.
+ */
+int desdemona_game_is_over(const struct DesdemonaGame *game);
+
+/**
+ * Returns the board of the game in the format of `desdemona_game_parse`, or null when the game
+ * is null
+ *
+ * # Safety
+ * `game` must be null or a live handle returned by this API.
+ */
+char *desdemona_game_board(const struct DesdemonaGame *game);
+
+/**
+ * Frees the given string returned by this API, doing nothing when it is null
+ *
+ * # Safety
+ * `s` must be null or a string returned by this API, which must not be used afterwards.
+ */
+void desdemona_string_free(char *s);
+
+/**
+ * Writes up to `capacity` moves of the player to move into `moves`, returning how many moves
+ * there are, which may exceed `capacity`, or a negative value when the game is null
+ *
+ * # Safety
+ * `game` must be null or a live handle returned by this API, and `moves` must point to
+ * `capacity` writable moves unless `capacity` is 0.
+ */
+int desdemona_game_moves(const struct DesdemonaGame *game,
+                         struct DesdemonaMove *moves,
+                         uintptr_t capacity);
+
+/**
+ * Plays the given move for the player to move
+ *
+ * # Safety
+ * `game` must be null or a live handle returned by this API.
+ */
+int desdemona_game_play(struct DesdemonaGame *game, struct DesdemonaMove placement);
+
+/**
+ * Passes the turn of the player to move, which is only allowed when they have no moves
+ *
+ * # Safety
+ * `game` must be null or a live handle returned by this API.
+ */
+int desdemona_game_pass(struct DesdemonaGame *game);
+
+/**
+ * Searches the best move of the player to move with the given intelligence and writes it into
+ * `placement`, without playing it
+ *
+ * # Safety
+ * `game` must be null or a live handle returned by this API, and `placement` must be null or
+ * point to a writable move.
+ */
+int desdemona_game_decide(const struct DesdemonaGame *game,
+                          uint32_t intelligence,
+                          struct DesdemonaMove *placement);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* DESDEMONA_H */
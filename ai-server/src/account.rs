@@ -0,0 +1,396 @@
+use std::cmp::Reverse;
+use std::sync::{Arc, Mutex};
+
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::status::Custom;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Request, Route, State};
+use serde_json::json;
+
+use crate::errors::Error;
+use crate::errors::Error::InvalidArgument;
+use crate::game::Player;
+use crate::rating::{self, INITIAL_RATING};
+use crate::storage::Storage;
+use crate::validation;
+
+const ACCOUNTS: &str = "accounts";
+const TOKENS: &str = "tokens";
+
+/// Score a human loses for each move taken back in a rated game
+const TAKEBACK_PENALTY: f64 = 0.1;
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+
+/// Generates a random string of the given length
+pub fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// Identity and statistics of a human player
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Account {
+    pub id: String,
+    token: String,
+    pub games_played: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    #[serde(default)]
+    pub rated_games: u32,
+    pub rating: f64,
+    /// Number of games won in a row up to the last one
+    #[serde(default)]
+    pub streak: u32,
+    #[serde(default)]
+    pub best_streak: u32,
+}
+
+impl Account {
+
+    /// Creates a new account with a random id and token
+    pub fn new() -> Self {
+        Self {
+            id: random_string(12),
+            token: random_string(32),
+            games_played: 0,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+            rated_games: 0,
+            rating: INITIAL_RATING,
+            streak: 0,
+            best_streak: 0,
+        }
+    }
+
+    /// Returns the ratio of games won to games played
+    pub fn win_rate(&self) -> f64 {
+        if self.games_played == 0 {
+            0.0
+        } else {
+            self.wins as f64 / self.games_played as f64
+        }
+    }
+
+    /// Records a finished game in which the human took back the given number of moves, against
+    /// the bot with the given rating if the game is rated, returning the score of the human
+    pub fn record(&mut self, winner: Option<Player>, takebacks: u32, bot_rating: Option<f64>)
+        -> f64 {
+        let score = match winner {
+            Some(Player::Human) => {
+                self.wins += 1;
+                1.0
+            }
+            Some(Player::Bot) => {
+                self.losses += 1;
+                0.0
+            }
+            None => {
+                self.draws += 1;
+                0.5
+            }
+        };
+
+        self.streak = match winner {
+            Some(Player::Human) => self.streak + 1,
+            _ => 0,
+        };
+        self.best_streak = self.best_streak.max(self.streak);
+
+        // A game won with the help of takebacks is worth less than one won without
+        let score = (score - TAKEBACK_PENALTY * takebacks as f64).max(0.0);
+
+        self.games_played += 1;
+        if let Some(bot_rating) = bot_rating {
+            self.rated_games += 1;
+            self.rating = rating::updated_rating(self.rating, bot_rating, score);
+        }
+        score
+    }
+}
+
+/// Order of the players on the leaderboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ranking {
+    Rating,
+    Streak,
+    GamesPlayed,
+}
+
+impl Ranking {
+
+    /// Parses the name of a ranking, such as "rating", "streak" or "games"
+    fn parse(s: &str) -> Result<Self, Error> {
+        match s {
+            "rating" => Ok(Self::Rating),
+            "streak" => Ok(Self::Streak),
+            "games" => Ok(Self::GamesPlayed),
+            _ => Err(InvalidArgument(format!("Invalid ranking: {}", s))),
+        }
+    }
+}
+
+/// Sorts the players who played at least one game by the given ranking, best first, returning
+/// how many there are and the given page of them, counted from 1
+fn leaderboard(mut accounts: Vec<Account>, ranking: Ranking, page: usize, per_page: usize)
+    -> (usize, Vec<Account>) {
+    accounts.retain(|account| account.games_played > 0);
+    match ranking {
+        Ranking::Rating => accounts.sort_by(|a, b| b.rating.total_cmp(&a.rating)),
+        Ranking::Streak => accounts.sort_by_key(|a| (Reverse(a.best_streak), Reverse(a.streak))),
+        Ranking::GamesPlayed => accounts.sort_by_key(|a| Reverse(a.games_played)),
+    }
+
+    let total = accounts.len();
+    let start = (page - 1).saturating_mul(per_page).min(total);
+    let end = start.saturating_add(per_page).min(total);
+    (total, accounts.drain(start..end).collect())
+}
+
+/// Accounts kept in the storage
+///
+/// Clones share the same lock on the storage.
+#[derive(Clone)]
+pub struct Accounts {
+    storage: Storage,
+    lock: Arc<Mutex<()>>,
+    /// Every account, loaded by the leaderboard and dropped whenever an account changes
+    all: Arc<Mutex<Option<Vec<Account>>>>,
+}
+
+impl Accounts {
+
+    /// Creates the accounts kept in the given storage
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            storage,
+            lock: Arc::new(Mutex::new(())),
+            all: Arc::default(),
+        }
+    }
+
+    /// Returns every account, loading them from the storage only after they change
+    fn all(&self) -> Vec<Account> {
+        let mut all = self.all.lock().unwrap();
+        all.get_or_insert_with(|| self.storage.keys(ACCOUNTS).iter()
+            .filter_map(|id| self.get(id))
+            .collect())
+            .clone()
+    }
+
+    /// Creates and saves a new account
+    pub fn create(&self) -> Result<Account, Error> {
+        let account = Account::new();
+
+        let _guard = self.lock.lock().unwrap();
+        self.storage.save(ACCOUNTS, &account.id, &account)?;
+        self.storage.save(TOKENS, &account.token, &account.id)?;
+        *self.all.lock().unwrap() = None;
+        Ok(account)
+    }
+
+    /// Returns the account with the given id
+    pub fn get(&self, id: &str) -> Option<Account> {
+        self.storage.load(ACCOUNTS, id)
+    }
+
+    /// Returns the account that owns the given token
+    pub fn authenticate(&self, token: &str) -> Option<Account> {
+        let id: String = self.storage.load(TOKENS, token)?;
+        self.get(&id)
+    }
+
+    /// Records a finished game of the account with the given id like `Account::record`,
+    /// returning the rating of the human before the game and their score if the game is rated
+    pub fn record(&self, id: &str, winner: Option<Player>, takebacks: u32,
+                  bot_rating: Option<f64>) -> Result<Option<(f64, f64)>, Error> {
+        let _guard = self.lock.lock().unwrap();
+
+        let Some(mut account) = self.get(id) else {
+            return Ok(None);
+        };
+        let rating = account.rating;
+        let score = account.record(winner, takebacks, bot_rating);
+        self.storage.save(ACCOUNTS, id, &account)?;
+        *self.all.lock().unwrap() = None;
+        Ok(bot_rating.map(|_| (rating, score)))
+    }
+}
+
+/// Request guard of the account identified by the `Authorization: Bearer <token>` header
+pub struct Authenticated(pub Account);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authenticated {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = request.headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "));
+
+        let accounts = request.rocket().state::<Accounts>();
+
+        match (token, accounts) {
+            (Some(token), Some(accounts)) => match accounts.authenticate(token) {
+                Some(account) => Outcome::Success(Authenticated(account)),
+                None => Outcome::Failure((Status::Unauthorized, ())),
+            },
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+#[post("/players")]
+fn create(accounts: &State<Accounts>) -> Result<String, Custom<String>> {
+    let account = accounts.create()?;
+
+    Ok(json!({
+        "id": account.id,
+        "token": account.token,
+    }).to_string())
+}
+
+#[get("/players/<id>/stats")]
+fn stats(id: &str, accounts: &State<Accounts>) -> Result<String, Custom<String>> {
+    let account = accounts.get(id)
+        .ok_or_else(|| Custom(Status::NotFound, format!("No player with the id: {}", id)))?;
+
+    Ok(json!({
+        "id": account.id,
+        "games_played": account.games_played,
+        "wins": account.wins,
+        "losses": account.losses,
+        "draws": account.draws,
+        "rated_games": account.rated_games,
+        "streak": account.streak,
+        "best_streak": account.best_streak,
+        "win_rate": account.win_rate(),
+        "rating": account.rating,
+    }).to_string())
+}
+
+/// Returns a page of the players who played at least one game, ranked by their rating, their
+/// best streak of wins or the number of games they played
+#[get("/leaderboard?<sort>&<page>&<per_page>")]
+fn ranking(sort: Option<&str>, page: Option<usize>, per_page: Option<usize>,
+           accounts: &State<Accounts>) -> Result<String, Custom<String>> {
+    let sort = sort.unwrap_or("rating");
+    let ranking = Ranking::parse(sort)?;
+    let page = page.unwrap_or(1);
+    let per_page = per_page.unwrap_or(DEFAULT_PAGE_SIZE);
+    if page == 0 || per_page == 0 || per_page > MAX_PAGE_SIZE {
+        return Err(validation::invalid(Status::BadRequest, "page", format!(
+            "The page must be positive with between 1 and {} players", MAX_PAGE_SIZE)));
+    }
+
+    let (total, players) = leaderboard(accounts.all(), ranking, page, per_page);
+    Ok(json!({
+        "sort": sort,
+        "page": page,
+        "per_page": per_page,
+        "total": total,
+        "players": players.iter().enumerate().map(|(i, account)| json!({
+            "rank": (page - 1) * per_page + i + 1,
+            "id": account.id,
+            "rating": account.rating,
+            "games_played": account.games_played,
+            "wins": account.wins,
+            "streak": account.streak,
+            "best_streak": account.best_streak,
+        })).collect::<Vec<_>>(),
+    }).to_string())
+}
+
+/// Returns the routes of the player accounts
+pub fn routes() -> Vec<Route> {
+    routes![create, stats, ranking]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{leaderboard, Account, Ranking};
+    use crate::game::Player::{Bot, Human};
+    use crate::rating::{initial_bot_rating, INITIAL_RATING};
+
+    #[test]
+    fn record() {
+        let mut account = Account::new();
+        let bot_rating = Some(initial_bot_rating(1));
+        assert!(initial_bot_rating(1) < INITIAL_RATING);
+
+        assert_eq!(account.record(Some(Human), 0, bot_rating), 1.0);
+        assert_eq!(account.record(Some(Bot), 0, bot_rating), 0.0);
+        assert_eq!(account.record(None, 0, bot_rating), 0.5);
+
+        assert_eq!(account.games_played, 3);
+        assert_eq!((account.wins, account.losses, account.draws), (1, 1, 1));
+        assert!((account.win_rate() - 1.0 / 3.0).abs() < f64::EPSILON);
+
+        // Unrated games count without moving the rating
+        let rating = account.rating;
+        account.record(Some(Human), 0, None);
+        assert_eq!((account.games_played, account.rated_games), (4, 3));
+        assert_eq!(account.rating, rating);
+    }
+
+    #[test]
+    fn streaks() {
+        let mut account = Account::new();
+        for winner in [Some(Human), Some(Human), None, Some(Human)] {
+            account.record(winner, 0, None);
+        }
+        assert_eq!((account.streak, account.best_streak), (1, 2));
+    }
+
+    #[test]
+    fn rank() {
+        let accounts = (0..5).map(|i| {
+            let mut account = Account::new();
+            account.games_played = i;
+            account.rating = INITIAL_RATING + 10.0 * (i % 3) as f64;
+            account.best_streak = 4 - i;
+            account
+        }).collect::<Vec<_>>();
+
+        // Players who never played are left out
+        let (total, page) = leaderboard(accounts.clone(), Ranking::Rating, 1, 2);
+        assert_eq!(total, 4);
+        assert_eq!(page.iter().map(|a| a.games_played).collect::<Vec<_>>(), vec![2, 1]);
+
+        let (_, page) = leaderboard(accounts.clone(), Ranking::GamesPlayed, 2, 3);
+        assert_eq!(page.iter().map(|a| a.games_played).collect::<Vec<_>>(), vec![1]);
+        let (_, page) = leaderboard(accounts.clone(), Ranking::Streak, 1, 1);
+        assert_eq!(page[0].best_streak, 3);
+        assert!(leaderboard(accounts, Ranking::Rating, 3, 2).1.is_empty());
+        assert!(Ranking::parse("wins").is_err());
+    }
+
+    #[test]
+    fn record_takebacks() {
+        let bot_rating = Some(initial_bot_rating(1));
+        let (mut clean, mut helped) = (Account::new(), Account::new());
+        clean.record(Some(Human), 0, bot_rating);
+        assert!((helped.record(Some(Human), 2, bot_rating) - 0.8).abs() < f64::EPSILON);
+
+        // The win still counts, but raises the rating less
+        assert_eq!(helped.wins, 1);
+        assert!(INITIAL_RATING < helped.rating && helped.rating < clean.rating);
+
+        let mut lost = Account::new();
+        lost.record(Some(Bot), 3, bot_rating);
+        let mut unhelped = Account::new();
+        unhelped.record(Some(Bot), 0, bot_rating);
+        assert_eq!(lost.rating, unhelped.rating);
+    }
+}
@@ -0,0 +1,29 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+
+use crate::config::EngineConfig;
+
+/// Request guard of the operator identified by the `Authorization: Bearer <admin_token>` header
+///
+/// Admin routes are disabled when no admin token is configured.
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = request.headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "));
+
+        let admin_token = request.rocket().state::<EngineConfig>()
+            .and_then(|config| config.admin_token.as_deref());
+
+        match (token, admin_token) {
+            (Some(token), Some(admin_token)) if token == admin_token => Outcome::Success(Admin),
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
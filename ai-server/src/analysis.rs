@@ -0,0 +1,390 @@
+use rocket::form;
+use rocket::futures::stream::{self, StreamExt};
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::{Route, State};
+use serde_json::{json, Value};
+
+use crate::board::{Board, Position};
+use crate::engine::Engine;
+use crate::errors::Error;
+use crate::errors::Error::IllegalMove;
+use crate::game::{self, Action, Game, Player};
+use crate::predict::SOLVE_EMPTIES;
+use crate::session::{SessionId, Sessions};
+use crate::validation;
+
+/// Disk difference of a game won with every disk, onto which the evaluations are scaled
+const MAX_DISK_DIFFERENCE: i64 = 64;
+
+/// Losses in centi-disks from which a move is an inaccuracy, a mistake and a blunder
+const INACCURACY_LOSS: i32 = 200;
+const MISTAKE_LOSS: i32 = 400;
+const BLUNDER_LOSS: i32 = 800;
+
+/// Converts the given difference of evaluations into hundredths of a disk, scaling the best
+/// evaluation to a game won with every disk
+pub(crate) fn centi_disks(value: i32) -> i32 {
+    (value as i64 * MAX_DISK_DIFFERENCE * 100 / game::max_best_evaluation().max(1) as i64) as i32
+}
+
+/// Quality of a move by how much it loses against the best one
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Classification {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Classification {
+
+    /// Classifies a move losing the given number of centi-disks
+    pub(crate) fn from_loss(centi_disks: i32) -> Self {
+        match centi_disks {
+            loss if loss <= 0 => Self::Best,
+            loss if loss < INACCURACY_LOSS => Self::Good,
+            loss if loss < MISTAKE_LOSS => Self::Inaccuracy,
+            loss if loss < BLUNDER_LOSS => Self::Mistake,
+            _ => Self::Blunder,
+        }
+    }
+
+    /// Returns the name of this classification
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Best => "best",
+            Self::Good => "good",
+            Self::Inaccuracy => "inaccuracy",
+            Self::Mistake => "mistake",
+            Self::Blunder => "blunder",
+        }
+    }
+
+    /// Returns the annotation symbol of this classification, if moves of its quality have one
+    pub(crate) fn symbol(&self) -> Option<&'static str> {
+        match self {
+            Self::Best | Self::Good => None,
+            Self::Inaccuracy => Some("?!"),
+            Self::Mistake => Some("?"),
+            Self::Blunder => Some("??"),
+        }
+    }
+}
+
+/// Review of a move of a game against the best action the bot finds from the same position
+struct MoveReport {
+    player: Player,
+    played: Position,
+    best: Option<Action>,
+    /// Scores from the point of view of the player of the move, None when the search ran out of
+    /// time before scoring the action
+    played_score: Option<i32>,
+    best_score: Option<i32>,
+    /// Final disk differences from the point of view of the player of the move, when the last
+    /// empty squares are searched to the end of the game
+    played_difference: Option<i32>,
+    best_difference: Option<i32>,
+}
+
+impl MoveReport {
+
+    /// Returns how much worse the played move is than the best one
+    fn loss(&self) -> Option<i32> {
+        Some((self.best_score? - self.played_score?).max(0))
+    }
+
+    /// Returns how much worse the played move is than the best one, in hundredths of a disk,
+    /// which is exact when the outcomes of the moves are proven
+    fn centi_disk_loss(&self) -> Option<i32> {
+        match (self.best_difference, self.played_difference) {
+            (Some(best), Some(played)) => Some((best - played).max(0) * 100),
+            _ => self.loss().map(centi_disks),
+        }
+    }
+
+    fn classification(&self) -> Option<Classification> {
+        self.centi_disk_loss().map(Classification::from_loss)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "player": self.player.to_string(),
+            "played": self.played.to_string(),
+            "best": self.best.as_ref().map(|a| a.placement().to_string()),
+            "score": self.played_score,
+            "best_score": self.best_score,
+            "loss": self.loss(),
+            "centi_disk_loss": self.centi_disk_loss(),
+            "classification": self.classification().map(|c| c.name()),
+            "symbol": self.classification().and_then(|c| c.symbol()),
+            "proven": self.played_difference.is_some(),
+            "disk_difference": self.played_difference,
+            "best_disk_difference": self.best_difference,
+        })
+    }
+}
+
+/// Replays the given moves from the given state, returning the state before each placement
+/// together with its ply and the placement
+fn positions(initial: &Game, moves: &[Option<Position>])
+    -> Result<Vec<(usize, Game, Position)>, Error> {
+    let mut game = initial.clone();
+    let mut positions = Vec::new();
+
+    for (ply, placement) in moves.iter().enumerate() {
+        game = match placement {
+            None => game.pass(),
+            Some(placement) => {
+                let action = Action::parse(game.current_player(), placement.clone());
+                if !game.actions(game.current_player()).any(|a| a == action) {
+                    return Err(IllegalMove(format!("Illegal move at ply {}", ply + 1)));
+                }
+                positions.push((ply + 1, game.clone(), placement.clone()));
+                game.result(&action)
+            }
+        };
+    }
+    Ok(positions)
+}
+
+/// Ranks the actions from the given position and reviews the placement played from it, proving
+/// the outcome of each action once few enough empty squares are left
+async fn review(engine: &Engine, intelligence: u32, game: Game, played: Position)
+    -> Result<MoveReport, Error> {
+    let player = game.current_player();
+    let (ranking, solved) = engine.search(intelligence, move |bot| {
+        // Ranked first, so that a solve running out of time leaves the ranking whole
+        let ranking = bot.rank(&game, player);
+        let solved = match game.board().num_empty() <= SOLVE_EMPTIES {
+            true => bot.solve_actions(&game, player),
+            false => None,
+        };
+        (ranking, solved)
+    }).await?;
+
+    // Scores are from the point of view of the bot
+    let score = |value: i32| match player {
+        Player::Bot => value,
+        Player::Human => -value,
+    };
+    let find = |ranking: &[(Action, i32)]| ranking.iter()
+        .find(|(action, _)| *action.placement() == played)
+        .map(|&(_, value)| score(value));
+    let solved = solved.unwrap_or_default();
+    let best = solved.first().or(ranking.first()).map(|(action, _)| action.clone());
+    // The score of the same action, which the ranking may not put first once it is solved
+    let best_score = best.as_ref()
+        .and_then(|best| ranking.iter().find(|(action, _)| action == best))
+        .map(|&(_, value)| score(value));
+    Ok(MoveReport {
+        player,
+        best,
+        best_score,
+        played_score: find(&ranking),
+        best_difference: solved.first().map(|&(_, value)| score(value)),
+        played_difference: find(&solved),
+        played,
+    })
+}
+
+/// Summarizes the reviews of the moves of the given player, averaging the losses over the moves
+/// whose loss is known
+fn summary(reports: &[&MoveReport], player: Player) -> Value {
+    let reports = reports.iter()
+        .copied()
+        .filter(|report| report.player == player)
+        .collect::<Vec<_>>();
+    let average = |loss: fn(&MoveReport) -> Option<i32>| {
+        let losses = reports.iter().filter_map(|&report| loss(report)).collect::<Vec<i32>>();
+        (!losses.is_empty()).then(|| losses.iter().sum::<i32>() as f64 / losses.len() as f64)
+    };
+    let count = |classification: Classification| reports.iter()
+        .filter(|report| report.classification() == Some(classification))
+        .count();
+
+    json!({
+        "average_loss": average(MoveReport::loss),
+        "average_centi_disk_loss": average(MoveReport::centi_disk_loss),
+        "inaccuracies": count(Classification::Inaccuracy),
+        "mistakes": count(Classification::Mistake),
+        "blunders": count(Classification::Blunder),
+    })
+}
+
+/// Reviews every move of the given game, as many at a time as the engine has threads so that
+/// the game leaves no search of the other requests waiting behind all of its positions
+///
+/// A move whose review fails, such as when the engine stays busy, is reported with its error
+/// instead of failing the whole analysis.
+async fn analyze(engine: &Engine, intelligence: u32, initial: &Game, moves: &[Option<Position>])
+    -> Result<Value, Error> {
+    let positions = positions(initial, moves)?;
+    let reviews = positions.iter()
+        .map(|(_, game, played)| review(engine, intelligence, game.clone(), played.clone()))
+        .collect::<Vec<_>>();
+    let reviews = stream::iter(reviews)
+        .buffered(engine.threads())
+        .collect::<Vec<_>>()
+        .await;
+    let reports = reviews.iter().flatten().collect::<Vec<_>>();
+
+    Ok(json!({
+        "intelligence": intelligence,
+        "complete": reports.len() == reviews.len(),
+        "moves": positions.iter().zip(&reviews)
+            .map(|((ply, game, played), review)| {
+                let mut json = match review {
+                    Ok(report) => report.to_json(),
+                    Err(e) => json!({
+                        "player": game.current_player().to_string(),
+                        "played": played.to_string(),
+                        "error": e.to_string(),
+                    }),
+                };
+                json["ply"] = json!(ply);
+                json
+            })
+            .collect::<Vec<Value>>(),
+        "players": {
+            Player::Human.to_string(): summary(&reports, Player::Human),
+            Player::Bot.to_string(): summary(&reports, Player::Bot),
+        },
+    }))
+}
+
+/// Reviews every move of a game session, searching the positions in parallel
+#[get("/games/<id>/analysis?<intelligence>")]
+async fn get(id: SessionId, intelligence: Option<u32>, sessions: &State<Sessions>,
+             engine: &State<Engine>) -> Result<String, Custom<String>> {
+    let (initial, moves, session_intelligence) = sessions
+        .with(id, |s| (s.initial().clone(), s.moves().to_vec(), s.intelligence()))
+        .ok_or_else(|| Custom(Status::NotFound, format!("No game with the id: {}", id)))?;
+    let intelligence = validation::intelligence(
+        intelligence.unwrap_or(session_intelligence), engine.config())?;
+
+    let analysis = analyze(engine, intelligence, &initial, &moves).await?;
+    // The history of the session reports the reviews while the moves stay the same
+    sessions.with(id, |s| s.set_analysis(moves, analysis.clone()));
+    Ok(analysis.to_string())
+}
+
+/// Compares the given move of the given player with the best action the bot finds from the same
+/// position, so that UIs can annotate the move as soon as it is played
+#[get("/annotate?<board>&<player>&<position>&<depth>")]
+async fn annotate(board: form::Result<'_, Board>, player: form::Result<'_, Player>,
+                  position: form::Result<'_, Position>, depth: form::Result<'_, u32>,
+                  engine: &State<Engine>)
+    -> Result<String, Custom<String>> {
+    let board = validation::param(board, "board")?;
+    let player = validation::param(player, "player")?;
+    let position = validation::param(position, "position")?;
+    let depth = validation::intelligence(validation::param(depth, "depth")?, engine.config())?;
+
+    let game = Game::parse(board, player);
+    validation::action(&game, player, position.clone())?;
+
+    let mut json = review(engine, depth, game, position).await?.to_json();
+    json["depth"] = json!(depth);
+    Ok(json.to_string())
+}
+
+/// Returns the routes of the analysis of moves and whole games
+pub fn routes() -> Vec<Route> {
+    routes![get, annotate]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use rocket::futures::future::join;
+
+    use crate::analysis::{analyze, review, Classification};
+    use crate::board::Position;
+    use crate::bot::CancellationToken;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+    use crate::game::{Game, Player};
+
+    #[rocket::async_test]
+    async fn analyze_game() {
+        let engine = Engine::new(EngineConfig::default(), CancellationToken::new());
+        let initial = Game::parse(Game::new().board().clone(), Player::Human);
+        let moves = [Some(Position::new(2, 4)), Some(Position::new(2, 3)),
+                     Some(Position::new(2, 2))];
+
+        let report = analyze(&engine, 2, &initial, &moves).await.unwrap();
+        let reviewed = report["moves"].as_array().unwrap();
+        assert_eq!(reviewed.len(), 3);
+        assert_eq!(reviewed[0]["player"], "H");
+        assert_eq!(reviewed[1]["player"], "B");
+        assert!(reviewed.iter().all(|review| review["loss"].as_i64().unwrap() >= 0));
+        assert!(reviewed.iter().all(|review| review["classification"].is_string()));
+        assert!(report["players"]["H"]["average_loss"].is_number());
+        assert!(report["players"]["B"]["average_centi_disk_loss"].is_number());
+
+        assert!(analyze(&engine, 2, &initial, &[Some(Position::new(0, 0))]).await.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn analyze_on_one_thread() {
+        let config = EngineConfig { threads: 1, max_queue_ms: 1, ..EngineConfig::default() };
+        let engine = Engine::new(config, CancellationToken::new());
+        let initial = Game::parse(Game::new().board().clone(), Player::Human);
+        let mut game = initial.clone();
+        let mut moves = Vec::new();
+        while moves.len() < 20 {
+            let action = game.actions(game.current_player()).next().unwrap();
+            moves.push(Some(action.placement().clone()));
+            game = game.result(&action);
+            if game.must_pass() {
+                moves.push(None);
+                game = game.pass();
+            }
+        }
+
+        // The positions wait for each other instead of for longer than the queue allows
+        let report = analyze(&engine, 4, &initial, &moves).await.unwrap();
+        assert_eq!(report["complete"], true);
+        let reviewed = report["moves"].as_array().unwrap();
+        assert_eq!(reviewed.len(), moves.iter().flatten().count());
+        assert!(reviewed.iter().all(|review| review["error"].is_null()));
+
+        // The moves reviewed while another search holds the engine are reported as failed
+        let busy = engine.run(|| thread::sleep(Duration::from_millis(200)));
+        let (_, report) = join(busy, analyze(&engine, 4, &initial, &moves)).await;
+        let report = report.unwrap();
+        assert_eq!(report["complete"], false);
+        assert!(report["moves"][0]["error"].is_string());
+        assert_eq!(report["moves"].as_array().unwrap().len(), reviewed.len());
+    }
+
+    #[test]
+    fn classify() {
+        assert_eq!(Classification::from_loss(0), Classification::Best);
+        assert_eq!(Classification::from_loss(199), Classification::Good);
+        assert_eq!(Classification::from_loss(200), Classification::Inaccuracy);
+        assert_eq!(Classification::from_loss(400), Classification::Mistake);
+        assert_eq!(Classification::from_loss(6400), Classification::Blunder);
+        assert_eq!(Classification::from_loss(400).symbol(), Some("?"));
+        assert_eq!(Classification::from_loss(0).symbol(), None);
+    }
+
+    #[rocket::async_test]
+    async fn annotate_move() {
+        let engine = Engine::new(EngineConfig::default(), CancellationToken::new());
+        let game = Game::parse(Game::new().board().clone(), Player::Human);
+
+        // The openings are symmetric, so every one of them is as good as the best
+        for action in game.actions(Player::Human) {
+            let played = action.placement().clone();
+            let report = review(&engine, 3, game.clone(), played.clone()).await.unwrap();
+            assert_eq!(report.played, played);
+            assert_eq!(report.loss(), Some(0));
+            assert_eq!(report.classification(), Some(Classification::Best));
+        }
+    }
+}
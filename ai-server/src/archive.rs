@@ -0,0 +1,257 @@
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Route, State};
+use serde_json::{json, Value};
+
+use crate::errors::Error;
+use crate::game::Player;
+use crate::ggf;
+use crate::session::{Finished, SessionId};
+use crate::storage::Storage;
+use crate::validation;
+
+const GAMES: &str = "games";
+
+/// Number of plies of a game that make its opening
+const OPENING_PLIES: usize = 6;
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+
+/// Summary of a finished game kept in the archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Summary {
+    pub key: String,
+    /// Account of the human, if any
+    pub player: Option<String>,
+    /// Result from the point of view of the human, which is "win", "loss" or "draw"
+    pub result: String,
+    /// Placements of the first plies in the notation of transcripts, such as "f5d6c3"
+    pub opening: String,
+    /// Number of placements, not counting the passes
+    pub length: usize,
+    /// Seconds since the Unix epoch at which the game finished
+    pub finished_at: u64,
+    pub intelligence: u32,
+    pub rated: bool,
+    /// Whether the bot played both sides, where the human is the dark side
+    #[serde(default)]
+    pub selfplay: bool,
+}
+
+/// Document of a finished game in the storage
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Entry {
+    #[serde(flatten)]
+    summary: Summary,
+    record: Value,
+}
+
+/// Returns the name of the result of the human when the given player wins
+fn result_name(winner: Option<Player>) -> &'static str {
+    match winner {
+        Some(Player::Human) => "win",
+        Some(Player::Bot) => "loss",
+        None => "draw",
+    }
+}
+
+/// Conditions the games of a listing meet, where None accepts every game
+#[derive(Default)]
+struct Filter<'a> {
+    player: Option<&'a str>,
+    result: Option<&'a str>,
+    from: Option<u64>,
+    to: Option<u64>,
+    selfplay: Option<bool>,
+}
+
+impl Filter<'_> {
+
+    fn accepts(&self, summary: &Summary) -> bool {
+        self.player.is_none_or(|player| summary.player.as_deref() == Some(player))
+            && self.result.is_none_or(|result| summary.result == result)
+            && self.from.is_none_or(|from| summary.finished_at >= from)
+            && self.to.is_none_or(|to| summary.finished_at <= to)
+            && self.selfplay.is_none_or(|selfplay| summary.selfplay == selfplay)
+    }
+}
+
+/// Returns how many of the given games meet the given filter and the given page of them, counted
+/// from 1, most recent first
+fn list(mut summaries: Vec<Summary>, filter: &Filter, page: usize, per_page: usize)
+    -> (usize, Vec<Summary>) {
+    summaries.retain(|summary| filter.accepts(summary));
+    summaries.sort_by(|a, b| b.finished_at.cmp(&a.finished_at).then_with(|| b.key.cmp(&a.key)));
+
+    let total = summaries.len();
+    let start = (page - 1).saturating_mul(per_page).min(total);
+    let end = start.saturating_add(per_page).min(total);
+    (total, summaries.drain(start..end).collect())
+}
+
+/// Finished games kept in the storage
+///
+/// Clones share the same summaries.
+#[derive(Clone)]
+pub struct Archive {
+    storage: Storage,
+    /// Summaries of every game, loaded by the listing and dropped whenever a game is archived
+    summaries: Arc<Mutex<Option<Vec<Summary>>>>,
+}
+
+impl Archive {
+
+    /// Creates the archive kept in the given storage
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            storage,
+            summaries: Arc::default(),
+        }
+    }
+
+    /// Archives the given finished game of the session with the given id
+    pub fn save(&self, id: SessionId, finished: &Finished) -> Result<(), Error> {
+        self.insert(&id.to_string(), finished, false).map(|_| ())
+    }
+
+    /// Archives the given game of the self-play run with the given id at the given index and
+    /// returns its key
+    pub fn save_selfplay(&self, run: u64, index: u32, finished: &Finished)
+        -> Result<String, Error> {
+        self.insert(&format!("selfplay-{}-{}", run, index), finished, true)
+    }
+
+    /// Archives the given finished game under a key made of the time and the given id, and
+    /// returns the key
+    fn insert(&self, id: &str, finished: &Finished, selfplay: bool) -> Result<String, Error> {
+        let finished_at = SystemTime::now().duration_since(UNIX_EPOCH)
+            .unwrap_or_default().as_secs();
+        let plies = finished.moves.len().min(OPENING_PLIES);
+        let entry = Entry {
+            summary: Summary {
+                key: format!("{}-{}", finished_at, id),
+                player: finished.account.clone(),
+                result: result_name(finished.winner).to_string(),
+                opening: ggf::transcript(&finished.moves[..plies]),
+                length: finished.moves.iter().flatten().count(),
+                finished_at,
+                intelligence: finished.intelligence,
+                rated: finished.rated,
+                selfplay,
+            },
+            record: finished.record.clone(),
+        };
+
+        self.storage.save(GAMES, &entry.summary.key, &entry)?;
+        *self.summaries.lock().unwrap() = None;
+        Ok(entry.summary.key)
+    }
+
+    /// Returns the summaries of every game, loading them from the storage only after a game is
+    /// archived
+    fn summaries(&self) -> Vec<Summary> {
+        let mut summaries = self.summaries.lock().unwrap();
+        summaries.get_or_insert_with(|| self.storage.keys(GAMES).iter()
+            .filter_map(|key| self.storage.load::<Entry>(GAMES, key))
+            .map(|entry| entry.summary)
+            .collect())
+            .clone()
+    }
+}
+
+/// Returns a page of the summaries of the finished games, most recent first, filtered by the
+/// account of the human, the result of the human, the range of times the games finished at and
+/// whether the bot played both sides
+#[get("/games?<player>&<result>&<from>&<to>&<selfplay>&<page>&<per_page>")]
+#[allow(clippy::too_many_arguments)]
+fn games(player: Option<&str>, result: Option<&str>, from: Option<u64>, to: Option<u64>,
+         selfplay: Option<bool>, page: Option<usize>, per_page: Option<usize>,
+         archive: &State<Archive>)
+    -> Result<String, Custom<String>> {
+    if result.is_some_and(|result| !["win", "loss", "draw"].contains(&result)) {
+        return Err(validation::invalid(Status::BadRequest, "result",
+                          "The result must be one of win, loss and draw"));
+    }
+    let page = page.unwrap_or(1);
+    let per_page = per_page.unwrap_or(DEFAULT_PAGE_SIZE);
+    if page == 0 || per_page == 0 || per_page > MAX_PAGE_SIZE {
+        return Err(validation::invalid(Status::BadRequest, "page", format!(
+            "The page must be positive with between 1 and {} games", MAX_PAGE_SIZE)));
+    }
+
+    let filter = Filter { player, result, from, to, selfplay };
+    let (total, games) = list(archive.summaries(), &filter, page, per_page);
+    Ok(json!({
+        "page": page,
+        "per_page": per_page,
+        "total": total,
+        "games": games,
+    }).to_string())
+}
+
+/// Returns the summary and the record of an archived game
+#[get("/archive/<key>")]
+fn get(key: &str, archive: &State<Archive>) -> Result<String, Custom<String>> {
+    archive.storage.load::<Entry>(GAMES, key)
+        .map(|entry| json!(entry).to_string())
+        .ok_or_else(|| Custom(Status::NotFound,
+                              format!("No archived game with the key: {}", key)))
+}
+
+/// Returns the routes of the archive of finished games
+pub fn routes() -> Vec<Route> {
+    routes![games, get]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archive::{list, Filter, Summary};
+
+    fn summary(key: &str, player: Option<&str>, result: &str, finished_at: u64) -> Summary {
+        Summary {
+            key: key.to_string(),
+            player: player.map(|player| player.to_string()),
+            result: result.to_string(),
+            opening: String::new(),
+            length: 60,
+            finished_at,
+            intelligence: 1,
+            rated: player.is_some(),
+            selfplay: key == "b",
+        }
+    }
+
+    #[test]
+    fn filter_games() {
+        let summaries = vec![
+            summary("a", Some("alice"), "win", 10),
+            summary("b", None, "loss", 20),
+            summary("c", Some("alice"), "loss", 30),
+            summary("d", Some("bob"), "draw", 40),
+        ];
+        let keys = |games: Vec<Summary>| games.into_iter().map(|g| g.key).collect::<Vec<_>>();
+
+        let (total, games) = list(summaries.clone(), &Filter::default(), 1, 3);
+        assert_eq!((total, keys(games)), (4, vec!["d".into(), "c".into(), "b".into()]));
+        assert_eq!(keys(list(summaries.clone(), &Filter::default(), 2, 3).1), vec!["a"]);
+
+        let alice = Filter { player: Some("alice"), ..Filter::default() };
+        assert_eq!(keys(list(summaries.clone(), &alice, 1, 10).1), vec!["c", "a"]);
+
+        let losses = Filter { result: Some("loss"), from: Some(25), ..Filter::default() };
+        assert_eq!(keys(list(summaries.clone(), &losses, 1, 10).1), vec!["c"]);
+
+        let early = Filter { to: Some(20), ..Filter::default() };
+        assert_eq!(list(summaries.clone(), &early, 1, 10).0, 2);
+
+        let selfplay = Filter { selfplay: Some(true), ..Filter::default() };
+        assert_eq!(keys(list(summaries, &selfplay, 1, 10).1), vec!["b"]);
+    }
+}
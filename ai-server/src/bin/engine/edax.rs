@@ -0,0 +1,183 @@
+//! Text interface of the Edax engine, so that tools built around Edax can drive this engine
+//!
+//! Moves are written in Edax's notation such as `F5`, and `PS` for a pass. The initial board of
+//! Edax is the mirror image of the one of this engine, so columns are mirrored in both
+//! directions. Commands:
+//! * `init` or `i`: starts from the initial board
+//! * `setboard <board>`: sets the 64 squares written with `X` or `*` for black, `O` for white and
+//!   `-` or `.` for empty squares, followed by `X` or `O` for the side to move
+//! * `play <moves>` or `p <moves>`: plays the given sequence of moves such as `f5d6c3`, which can
+//!   also be typed without the command
+//! * `go`: searches and plays the move of the side to move, answering `Edax plays <move>`
+//! * `hint [n]`: answers the best n moves of the side to move with their scores
+//! * `level <n>`, `set level <n>` or `set depth <n>`: sets the search depth
+//! * `set move-time <seconds>`: sets the time allowed for a search
+
+use std::time::Duration;
+
+use desdemona::board::{Position, BOARD_SIZE, DARK_CHAR, EMPTY_CHAR, LIGHT_CHAR};
+use desdemona::errors::Error;
+use desdemona::errors::Error::{InvalidArgument, ParseError};
+use desdemona::ggf;
+
+use crate::Engine;
+
+/// Notation of a pass
+const PASS: &str = "PS";
+
+/// Returns the Edax notation of the given placement, or of a pass when it is none
+fn notation(placement: Option<&Position>) -> String {
+    placement.map_or(PASS.to_string(), |pos| ggf::notation(pos).to_uppercase())
+}
+
+/// Parses the given sequence of moves such as "f5d6c3", where none is a pass
+fn parse_moves(moves: &str) -> Result<Vec<Option<Position>>, Error> {
+    let chars: Vec<char> = moves.chars().filter(|ch| !ch.is_whitespace()).collect();
+    if !chars.len().is_multiple_of(2) {
+        return Err(ParseError(format!("Invalid moves: {}", moves)));
+    }
+
+    chars.chunks(2)
+        .map(|chunk| {
+            let placement = chunk.iter().collect::<String>().to_lowercase();
+            match placement.as_str() {
+                "ps" | "pa" => Ok(None),
+                placement => ggf::parse_notation(placement).map(Some),
+            }
+        })
+        .collect()
+}
+
+/// Converts the given board of Edax into the board and the disk to move of the engine
+fn parse_board(board: &str) -> Result<(String, String), Error> {
+    let mut chars: Vec<char> = board.chars().filter(|ch| !ch.is_whitespace()).collect();
+    let disk = chars.pop()
+        .ok_or_else(|| ParseError(format!("Invalid board: {}", board)))?;
+
+    let convert = |ch: char| match ch.to_ascii_uppercase() {
+        'X' | '*' => Ok(DARK_CHAR),
+        'O' => Ok(LIGHT_CHAR),
+        '-' | '.' => Ok(EMPTY_CHAR),
+        _ => Err(ParseError(format!("Invalid square of the board: {}", ch))),
+    };
+
+    if chars.len() != BOARD_SIZE * BOARD_SIZE {
+        return Err(ParseError(format!("Invalid board: {}", board)));
+    }
+    let squares = chars.chunks(BOARD_SIZE)
+        .flat_map(|row| row.iter().rev())
+        .map(|&ch| convert(ch))
+        .collect::<Result<String, Error>>()?;
+    let disk = match convert(disk)? {
+        EMPTY_CHAR => return Err(ParseError(format!("Invalid side to move: {}", disk))),
+        disk => disk.to_string(),
+    };
+    Ok((squares, disk))
+}
+
+/// Parses the given search depth
+fn parse_level(level: &str) -> Result<u32, Error> {
+    level.parse().map_err(|_| ParseError(format!("Invalid level: {}", level)))
+}
+
+/// Executes the given command of Edax, returning the lines to answer with
+pub fn handle(engine: &mut Engine, line: &str) -> Result<Vec<String>, Error> {
+    let args: Vec<&str> = line.split_whitespace().collect();
+
+    match args[..] {
+        [] => Ok(Vec::new()),
+        ["init" | "i" | "new" | "n"] => {
+            engine.game = Engine::new().game;
+            Ok(Vec::new())
+        }
+        ["setboard", ..] => {
+            let (squares, disk) = parse_board(&args[1..].concat())?;
+            engine.set_position(&squares, &disk)?;
+            Ok(Vec::new())
+        }
+        ["play" | "p", ..] => play(engine, &args[1..].concat()),
+        ["go"] => match engine.genmove()? {
+            Some(pos) => Ok(vec![format!("Edax plays {}", notation(Some(&pos)))]),
+            None => Ok(vec!["Edax passes".to_string()]),
+        },
+        ["hint"] => hint(engine, 1),
+        ["hint", n] => hint(engine, n.parse()
+            .map_err(|_| ParseError(format!("Invalid number of moves: {}", n)))?),
+        ["level" | "l", level] | ["set", "level" | "depth", level] => {
+            engine.intelligence = parse_level(level)?;
+            Ok(Vec::new())
+        }
+        ["set", "move-time", seconds] => {
+            let seconds: f64 = seconds.parse()
+                .map_err(|_| ParseError(format!("Invalid move time: {}", seconds)))?;
+            engine.movetime = Duration::try_from_secs_f64(seconds)
+                .map_err(|_| ParseError(format!("Invalid move time: {}", seconds)))?;
+            Ok(Vec::new())
+        }
+        [moves] if parse_moves(moves).is_ok() => play(engine, moves),
+        _ => Err(InvalidArgument(format!("Unknown command: {}", line.trim()))),
+    }
+}
+
+/// Plays the given sequence of moves, stopping at the first illegal one
+fn play(engine: &mut Engine, moves: &str) -> Result<Vec<String>, Error> {
+    for placement in parse_moves(moves)? {
+        engine.play(placement)?;
+    }
+    Ok(Vec::new())
+}
+
+/// Answers the best given number of moves of the side to move with their scores
+fn hint(engine: &Engine, n: usize) -> Result<Vec<String>, Error> {
+    if engine.game.must_pass() && !engine.game.is_over() {
+        return Ok(vec![notation(None)]);
+    }
+
+    Ok(engine.rank().iter()
+        .take(n)
+        .map(|(pos, score)| format!("{} {:+}", notation(Some(pos)), score))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use desdemona::board::Disk;
+
+    use crate::edax::handle;
+    use crate::Engine;
+
+    /// Initial board of Edax without the side to move
+    const INITIAL: &str = "---------------------------OX------XO---------------------------";
+
+    /// Returns the side to move in the notation of Edax
+    fn side_to_move(engine: &Engine) -> char {
+        if engine.game.current_player().disk() == Disk::Dark { 'X' } else { 'O' }
+    }
+
+    #[test]
+    fn edax() {
+        let mut engine = Engine::new();
+        handle(&mut engine, "level 1").unwrap();
+        handle(&mut engine, "f5d6").unwrap();
+        assert_eq!(side_to_move(&engine), 'X');
+
+        let answer = handle(&mut engine, "go").unwrap();
+        assert!(answer[0].starts_with("Edax plays "));
+        assert_eq!(side_to_move(&engine), 'O');
+
+        assert_eq!(handle(&mut engine, "hint 2").unwrap().len(), 2);
+        assert!(handle(&mut engine, "play a1").is_err());
+        assert!(handle(&mut engine, "unknown").is_err());
+    }
+
+    #[test]
+    fn setboard() {
+        let mut engine = Engine::new();
+        handle(&mut engine, &format!("setboard {} O", INITIAL)).unwrap();
+        assert_eq!(side_to_move(&engine), 'O');
+        assert_eq!(handle(&mut engine, "hint 4").unwrap().len(), 4);
+
+        assert!(handle(&mut engine, "setboard ---- O").is_err());
+        assert!(handle(&mut engine, &format!("setboard {} -", INITIAL)).is_err());
+    }
+}
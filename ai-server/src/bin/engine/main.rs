@@ -0,0 +1,268 @@
+//! Engine speaking a line-based protocol over stdin and stdout, so that scripts and GUIs can
+//! drive it without the web server
+//!
+//! Commands:
+//! * `isready`: answers `readyok`
+//! * `newgame`: starts from the initial board with dark to move
+//! * `setposition <board> <D|L>`: sets the 64 squares of the board row by row, written with
+//!   `E`, `D` and `L` and optionally separated by `/`, and the disk to move
+//! * `setoption <intelligence|movetime> <value>`: sets the search depth or the milliseconds
+//!   allowed for a search
+//! * `play <row,col|pass>`: plays the given move for the side to move
+//! * `genmove`: searches and plays the move of the side to move, answering `bestmove <move>`
+//! * `analyze`: answers `info move <row,col> score <score>` for every move, best first, then
+//!   `done`, where the score is from the perspective of the side to move
+//! * `show`: prints the board and the side to move
+//! * `quit`: exits
+//!
+//! Failed commands are answered with `error <reason>`. Run with `--edax` to speak the text
+//! interface of Edax instead, as described in the `edax` module.
+
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant};
+
+use desdemona::board::{Board, Disk, Position, BOARD_SIZE, EMPTY_CHAR};
+use desdemona::bot::Bot;
+use desdemona::errors::Error;
+use desdemona::errors::Error::{IllegalMove, InvalidArgument, ParseError};
+use desdemona::game::{Action, Game, Player};
+
+mod edax;
+
+/// Depth searched unless the intelligence option is set
+const DEFAULT_INTELLIGENCE: u32 = 4;
+
+/// Milliseconds allowed for a search unless the movetime option is set
+const DEFAULT_MOVETIME_MS: u64 = 5000;
+
+/// Returns the player owning the given disk
+fn player_of(disk: Disk) -> Player {
+    if disk == Player::Human.disk() { Player::Human } else { Player::Bot }
+}
+
+/// State of the engine between commands
+struct Engine {
+    game: Game,
+    intelligence: u32,
+    movetime: Duration,
+}
+
+impl Engine {
+
+    /// Creates an engine at the initial board with dark to move
+    fn new() -> Self {
+        Self {
+            game: Game::parse(Board::new(), player_of(Disk::Dark)),
+            intelligence: DEFAULT_INTELLIGENCE,
+            movetime: Duration::from_millis(DEFAULT_MOVETIME_MS),
+        }
+    }
+
+    /// Executes the given command, returning the lines to answer with
+    fn handle(&mut self, line: &str) -> Result<Vec<String>, Error> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+
+        match args[..] {
+            [] => Ok(Vec::new()),
+            ["isready"] => Ok(vec!["readyok".to_string()]),
+            ["newgame"] => {
+                self.game = Self::new().game;
+                Ok(Vec::new())
+            }
+            ["setposition", board, disk] => {
+                self.set_position(board, disk)?;
+                Ok(Vec::new())
+            }
+            ["setoption", name, value] => {
+                self.set_option(name, value)?;
+                Ok(Vec::new())
+            }
+            ["play", "pass"] => {
+                self.play(None)?;
+                Ok(Vec::new())
+            }
+            ["play", placement] => {
+                self.play(Some(Position::parse(placement.to_string())?))?;
+                Ok(Vec::new())
+            }
+            ["genmove"] => Ok(vec![format!("bestmove {}", self.genmove()?
+                .map_or("pass".to_string(), |pos| pos.to_string()))]),
+            ["analyze"] => self.analyze(),
+            ["show"] => Ok(vec![self.game.board().pretty(),
+                                format!("{} to move", self.game.current_player().disk())]),
+            _ => Err(InvalidArgument(format!("Unknown command: {}", line.trim()))),
+        }
+    }
+
+    /// Sets the board and the disk to move
+    fn set_position(&mut self, board: &str, disk: &str) -> Result<(), Error> {
+        let squares: String = board.chars().filter(|&ch| ch != '/').collect();
+        if squares.chars().count() != BOARD_SIZE * BOARD_SIZE {
+            return Err(ParseError(format!("Invalid board: {}", board)));
+        }
+
+        let rows = squares.chars()
+            .collect::<Vec<char>>()
+            .chunks(BOARD_SIZE)
+            .map(|row| row.iter().collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n");
+        let board = Board::parse(rows)?;
+
+        let disk = match disk.chars().collect::<Vec<char>>()[..] {
+            [ch] if ch != EMPTY_CHAR => Disk::parse(ch)?,
+            _ => return Err(ParseError(format!("Invalid disk to move: {}", disk))),
+        };
+
+        self.game = Game::parse(board, player_of(disk));
+        Ok(())
+    }
+
+    /// Sets the option with the given name
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let invalid = || ParseError(format!("Invalid value of {}: {}", name, value));
+
+        match name {
+            "intelligence" => self.intelligence = value.parse().map_err(|_| invalid())?,
+            "movetime" => self.movetime = Duration::from_millis(
+                value.parse().map_err(|_| invalid())?),
+            _ => return Err(InvalidArgument(format!("Unknown option: {}", name))),
+        }
+        Ok(())
+    }
+
+    /// Plays the given placement for the side to move, or passes when it is none
+    fn play(&mut self, placement: Option<Position>) -> Result<(), Error> {
+        if self.game.is_over() {
+            return Err(InvalidArgument("The game is over".to_string()));
+        }
+
+        let placement = match placement {
+            Some(placement) => placement,
+            None if self.game.must_pass() => {
+                self.game = self.game.pass();
+                return Ok(());
+            }
+            None => return Err(InvalidArgument(
+                "Passing is only allowed without moves".to_string())),
+        };
+
+        let player = self.game.current_player();
+        let action = Action::parse(player, placement.clone());
+        if !self.game.actions(player).any(|a| a == action) {
+            return Err(IllegalMove(format!("Illegal move: {}", placement)));
+        }
+
+        self.game = self.game.result(&action);
+        Ok(())
+    }
+
+    /// Creates a bot searching within the options of this engine
+    fn bot(&self) -> Bot {
+        let mut bot = Bot::new(self.intelligence);
+        bot.set_deadline(Instant::now() + self.movetime);
+        bot
+    }
+
+    /// Searches and plays the best move of the side to move, returning its placement or none
+    /// when it passes
+    fn genmove(&mut self) -> Result<Option<Position>, Error> {
+        if self.game.is_over() {
+            return Err(InvalidArgument("The game is over".to_string()));
+        }
+        if self.game.must_pass() {
+            self.game = self.game.pass();
+            return Ok(None);
+        }
+
+        let mut bot = self.bot();
+        let (action, result) = match self.game.current_player() {
+            Player::Bot => bot.decide(&self.game)?,
+            Player::Human => bot.hint(&self.game)?,
+        };
+
+        self.game = result;
+        Ok(Some(action.placement().clone()))
+    }
+
+    /// Scores every move of the side to move from its perspective, best first
+    fn rank(&self) -> Vec<(Position, i32)> {
+        let player = self.game.current_player();
+        // The scores of the bot are positive when the bot is winning
+        let sign = if player == Player::Bot { 1 } else { -1 };
+
+        self.bot().rank(&self.game, player).into_iter()
+            .map(|(action, score)| (action.placement().clone(), sign * score))
+            .collect()
+    }
+
+    /// Answers every move of the side to move with its score, best first
+    fn analyze(&self) -> Result<Vec<String>, Error> {
+        let mut lines: Vec<String> = self.rank().iter()
+            .map(|(pos, score)| format!("info move {} score {}", pos, score))
+            .collect();
+        lines.push("done".to_string());
+        Ok(lines)
+    }
+}
+
+fn main() {
+    let mut engine = Engine::new();
+    let edax = std::env::args().any(|arg| arg == "--edax");
+    let stdout = io::stdout();
+
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if matches!(line.trim(), "quit" | "q" | "exit") {
+            break;
+        }
+
+        let answer = if edax { edax::handle(&mut engine, &line) } else { engine.handle(&line) };
+        let mut out = stdout.lock();
+        let written = match answer {
+            Ok(lines) => lines.iter().try_for_each(|l| writeln!(out, "{}", l)),
+            Err(e) => writeln!(out, "error {}", e),
+        };
+        if written.and_then(|_| out.flush()).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Engine;
+
+    #[test]
+    fn protocol() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.handle("isready").unwrap(), vec!["readyok"]);
+        assert!(engine.handle("play 0,0").is_err());
+        assert!(engine.handle("setoption depth 3").is_err());
+        assert!(engine.handle("unknown").is_err());
+
+        engine.handle("setoption intelligence 1").unwrap();
+        engine.handle("play 2,4").unwrap();
+        let answer = engine.handle("genmove").unwrap();
+        assert!(answer[0].starts_with("bestmove "));
+
+        let analysis = engine.handle("analyze").unwrap();
+        assert_eq!(analysis.last().unwrap(), "done");
+        assert!(analysis[0].starts_with("info move "));
+    }
+
+    #[test]
+    fn set_position() {
+        let mut engine = Engine::new();
+        engine.handle("setposition EEEEEEEE/EEEEEEEE/EEEEEEEE/EEEDLEEE/EEELDEEE/EEEEEEEE/\
+                       EEEEEEEE/EEEEEEEE L").unwrap();
+        assert_eq!(engine.handle("show").unwrap()[1], "L to move");
+
+        assert!(engine.handle("setposition EEE L").is_err());
+        assert!(engine.handle("setposition EEEEEEEE/EEEEEEEE/EEEEEEEE/EEEDLEEE/EEELDEEE/EEEEEEEE/\
+                               EEEEEEEE/EEEEEEEE E").is_err());
+    }
+}
@@ -0,0 +1,283 @@
+//! Client that logs the bot into a GGS (Generic Game Server) and plays the Othello games of its
+//! `/os` service automatically, accepting every match request it receives
+//!
+//! Usage: `GGS_PASSWORD=<password> ggs <host:port> <handle> [intelligence] [movetime_ms]`
+//!
+//! Games are tracked from the GGF records sent when they start, so positions set up by the
+//! server and games resumed after a reconnection are played as well.
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use desdemona::bot::Bot;
+use desdemona::errors::Error;
+use desdemona::errors::Error::InvalidArgument;
+use desdemona::game::{Game, Player};
+use desdemona::ggf;
+
+/// Depth searched unless given on the command line
+const DEFAULT_INTELLIGENCE: u32 = 6;
+
+/// Milliseconds allowed for a search unless given on the command line
+const DEFAULT_MOVETIME_MS: u64 = 5000;
+
+/// Prefix of the messages of the Othello service
+const SERVICE: &str = "/os:";
+
+/// Message of the Othello service that the client acts on
+#[derive(Debug, PartialEq)]
+enum Message {
+    /// Another player asks for a match with the given request id
+    Request { id: String },
+    /// A game with the given id starts or resumes from the given GGF record
+    Join { id: String, record: String },
+    /// A move, written as in GGF such as "f5" or "PA", was played in the given game
+    Update { id: String, placement: String },
+    /// The given game ended
+    End { id: String },
+}
+
+impl Message {
+
+    /// Parses the given line of the server, if it is a message the client acts on
+    fn parse(line: &str) -> Option<Self> {
+        let mut words = line.strip_prefix(SERVICE)?.split_whitespace();
+
+        match words.next()? {
+            "+" => match words.next()? {
+                // Matches in progress are announced as "+ match .<id> ..."
+                "match" => None,
+                id if id.starts_with('.') => Some(Message::Request { id: id.to_string() }),
+                _ => None,
+            },
+            "join" => {
+                let id = words.next()?.to_string();
+                let record = line[line.find("(;")?..].to_string();
+                Some(Message::Join { id, record })
+            }
+            "update" => {
+                let id = words.next()?.to_string();
+                let placement = ggf::tags(line).into_iter()
+                    .find(|(tag, _)| tag == "B" || tag == "W")
+                    .map(|(_, value)| value.split('/').next().unwrap_or_default().to_string())?;
+                Some(Message::Update { id, placement })
+            }
+            "end" => Some(Message::End { id: words.next()?.to_string() }),
+            _ => None,
+        }
+    }
+}
+
+/// Replays the given GGF record, returning the state reached and the player of the given handle
+fn replay(record: &str, handle: &str) -> Result<(Game, Player), Error> {
+    let record = ggf::parse(record)?;
+
+    let player = if record.black.as_deref() == Some(handle) {
+        ggf::player_of('B')?
+    } else if record.white.as_deref() == Some(handle) {
+        ggf::player_of('W')?
+    } else {
+        return Err(InvalidArgument(format!("{} does not play in the game", handle)));
+    };
+    Ok((record.last, player))
+}
+
+/// Searches the move of the player to move, returning it in GGF
+fn decide(game: &Game, intelligence: u32, movetime: Duration) -> Result<String, Error> {
+    if game.must_pass() {
+        return Ok("PA".to_string());
+    }
+
+    let mut bot = Bot::new(intelligence);
+    bot.set_deadline(Instant::now() + movetime);
+    let (action, _) = match game.current_player() {
+        Player::Bot => bot.decide(game)?,
+        Player::Human => bot.hint(game)?,
+    };
+    Ok(ggf::notation(action.placement()))
+}
+
+/// Client playing the games of one handle
+struct Client {
+    handle: String,
+    intelligence: u32,
+    movetime: Duration,
+    games: HashMap<String, (Game, Player)>,
+}
+
+impl Client {
+
+    /// Handles the given line of the server, returning the commands to answer with
+    fn handle(&mut self, line: &str) -> Vec<String> {
+        let message = match Message::parse(line) {
+            Some(message) => message,
+            None => return Vec::new(),
+        };
+
+        let id = match message {
+            Message::Request { id } => return vec![format!("t /os accept {}", id)],
+            Message::Join { id, record } => match replay(&record, &self.handle) {
+                Ok(state) => {
+                    self.games.insert(id.clone(), state);
+                    id
+                }
+                Err(e) => {
+                    eprintln!("Ignoring game {}: {}", id, e);
+                    return Vec::new();
+                }
+            },
+            Message::Update { id, placement } => {
+                let Some((game, _)) = self.games.get_mut(&id) else {
+                    return Vec::new();
+                };
+                match ggf::apply(game, &placement) {
+                    Ok(result) => *game = result,
+                    Err(e) => {
+                        eprintln!("Leaving game {} out of sync: {}", id, e);
+                        self.games.remove(&id);
+                        return Vec::new();
+                    }
+                }
+                id
+            }
+            Message::End { id } => {
+                self.games.remove(&id);
+                return Vec::new();
+            }
+        };
+
+        // Only the game that changed is answered, since the moves already sent to the others
+        // are not applied until the server echoes them
+        self.reply(&id).into_iter().collect()
+    }
+
+    /// Returns the command playing the move of the given game if it is the turn of the client
+    fn reply(&self, id: &str) -> Option<String> {
+        let (game, player) = self.games.get(id)?;
+        if game.is_over() || game.current_player() != *player {
+            return None;
+        }
+
+        match decide(game, self.intelligence, self.movetime) {
+            Ok(placement) => Some(format!("t /os play {} {}", id, placement)),
+            Err(e) => {
+                eprintln!("Failed to decide in game {}: {}", id, e);
+                None
+            }
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    let (address, handle) = match &args[..] {
+        [_, address, handle, ..] => (address.clone(), handle.clone()),
+        _ => {
+            eprintln!("Usage: GGS_PASSWORD=<password> ggs <host:port> <handle> [intelligence] \
+                       [movetime_ms]");
+            return ExitCode::FAILURE;
+        }
+    };
+    let Ok(password) = env::var("GGS_PASSWORD") else {
+        eprintln!("GGS_PASSWORD is not set");
+        return ExitCode::FAILURE;
+    };
+
+    let mut client = Client {
+        handle: handle.clone(),
+        intelligence: args.get(3).and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_INTELLIGENCE),
+        movetime: Duration::from_millis(
+            args.get(4).and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_MOVETIME_MS)),
+        games: HashMap::new(),
+    };
+
+    let stream = match TcpStream::connect(&address) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("Failed to connect to {}: {}", address, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            eprintln!("Failed to open the connection for writing: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // The server asks for the handle and the password in turn, then takes service commands
+    let login = [handle, password, "t /os open 1".to_string()];
+    if login.iter().try_for_each(|line| writeln!(writer, "{}", line)).is_err() {
+        eprintln!("Failed to log in to {}", address);
+        return ExitCode::FAILURE;
+    }
+
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        println!("{}", line);
+
+        for command in client.handle(&line) {
+            println!("> {}", command);
+            if writeln!(writer, "{}", command).is_err() {
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    eprintln!("The server closed the connection");
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use desdemona::game::Player;
+
+    use crate::{Client, Message, replay};
+
+    const RECORD: &str = "(;GM[Othello]PC[GGS/os]PB[alice]PW[desdemona]TY[8]\
+                          BO[8 ---------------------------O*------*O--------------------------- *]\
+                          B[f5//1.2]W[f6//0.5];)";
+
+    #[test]
+    fn message() {
+        assert_eq!(Message::parse("/os: +  .12 1800 alice 05:00//02:00 8 R"),
+                   Some(Message::Request { id: ".12".to_string() }));
+        assert_eq!(Message::parse("/os: update .5 8 B[f5//1.2]"),
+                   Some(Message::Update { id: ".5".to_string(), placement: "f5".to_string() }));
+        assert_eq!(Message::parse("/os: end .5 alice 34 desdemona 30"),
+                   Some(Message::End { id: ".5".to_string() }));
+        assert_eq!(Message::parse("/os: + match .5 1800 alice 1700 desdemona 8 R"), None);
+        assert_eq!(Message::parse("alice: hello"), None);
+    }
+
+    #[test]
+    fn join() {
+        let (game, player) = replay(RECORD, "desdemona").unwrap();
+        assert_eq!(player, Player::Bot);
+        assert_eq!(game.current_player(), Player::Human);
+        assert!(replay(RECORD, "bob").is_err());
+
+        let mut client = Client {
+            handle: "alice".to_string(),
+            intelligence: 1,
+            movetime: Duration::from_secs(1),
+            games: HashMap::new(),
+        };
+        let commands = client.handle(&format!("/os: join .7 {}", RECORD));
+        assert_eq!(commands.len(), 1);
+        assert!(commands[0].starts_with("t /os play .7 "));
+
+        assert!(client.handle("/os: end .7 alice 40 desdemona 24").is_empty());
+        assert!(client.games.is_empty());
+    }
+}
@@ -1,19 +1,24 @@
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
 
 use itertools::Itertools;
+#[cfg(feature = "server")]
+use rocket::form::{self, FromFormField, ValueField};
+use serde_json::{json, Value};
 
 use Direction::{East, North, NorthEast, NorthWest, South, SouthEast, SouthWest, West};
 
 use crate::board::Disk::{Dark, Light};
 use crate::errors::Error;
-use crate::errors::Error::{InvalidArgument, ParseError};
+use crate::errors::Error::{InvalidArgument, OutOfRange, ParseError};
 
 pub const BOARD_SIZE: usize = 8;
 
 pub const DARK_CHAR: char = 'D';
 pub const LIGHT_CHAR: char = 'L';
 pub const EMPTY_CHAR: char = 'E';
+pub const BLOCKED_CHAR: char = 'X';
 
 const POSITION_WEIGHTS: [[i32; BOARD_SIZE]; BOARD_SIZE] = [
     [100, -10,  30,  20,  20,  30, -10, 100],
@@ -38,11 +43,26 @@ pub enum Direction {
     NorthWest,
 }
 
+impl Display for Direction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match *self {
+            North => "north",
+            NorthEast => "north-east",
+            East => "east",
+            SouthEast => "south-east",
+            South => "south",
+            SouthWest => "south-west",
+            West => "west",
+            NorthWest => "north-west",
+        })
+    }
+}
+
 impl Direction {
     
     /// Returns the iterator for all possible directions
     pub fn all() -> impl Iterator<Item=Direction> {
-       vec![North, NorthEast, East, SouthEast, South, SouthWest, West, NorthWest].into_iter()
+       [North, NorthEast, East, SouthEast, South, SouthWest, West, NorthWest].into_iter()
     }
 }
 
@@ -89,22 +109,54 @@ impl Default for Position {
 
 impl Position {
     
-    /// Parses the given string into a position
+    /// Parses the given string such as "4,5" into a position, where the first number is the row
+    /// and the second is the column
     pub fn parse(s: String) -> Result<Self, Error> {
-        if let [row, col] = s.split(",")
-            .map(|s| s.parse::<usize>())
-            .filter(|result| result.is_ok())
-            .map(|result| result.unwrap())
-            .collect_vec()[..] {
-            Ok(Self {
-                row,
-                col,
-            })
-        } else {
-            Err(ParseError(format!("Invalid string to parse into a position: {}", s)))
+        let components = s.split(',')
+            .map(|component| component.trim().parse::<usize>())
+            .collect_vec();
+
+        match components[..] {
+            [Ok(row), Ok(col)] => {
+                let pos = Self { row, col };
+                if pos.is_inbound() {
+                    Ok(pos)
+                } else {
+                    Err(OutOfRange(format!("Position out of the board of size {}: {}",
+                                           BOARD_SIZE, s)))
+                }
+            }
+            _ => Err(ParseError(format!("Invalid string to parse into a position: {}", s))),
         }
     }
     
+    /// Parses the given algebraic notation such as "f5" into a position, where the letter is the
+    /// column and the number is the row
+    pub fn parse_notation(s: &str) -> Result<Self, Error> {
+        let mut chars = s.chars();
+
+        match (chars.next(), chars.as_str().parse::<usize>()) {
+            (Some(col @ 'a'..='z'), Ok(row @ 1..)) => {
+                let pos = Self {
+                    row: row - 1,
+                    col: col as usize - 'a' as usize,
+                };
+
+                if pos.is_inbound() {
+                    Ok(pos)
+                } else {
+                    Err(ParseError(format!("Invalid notation to parse into a position: {}", s)))
+                }
+            }
+            _ => Err(ParseError(format!("Invalid notation to parse into a position: {}", s))),
+        }
+    }
+
+    /// Returns the algebraic notation of this position such as "f5"
+    pub fn notation(&self) -> String {
+        format!("{}{}", (b'a' + self.col as u8) as char, self.row + 1)
+    }
+    
     /// Creates a new Position
     pub fn new(row: usize, col: usize) -> Self {
         Self {
@@ -114,9 +166,19 @@ impl Position {
     }
     
     /// Checks if this position is in bound
-    fn is_inbound(&self) -> bool {
+    pub fn is_inbound(&self) -> bool {
         self.row < BOARD_SIZE && self.col < BOARD_SIZE
     }
+
+    /// Returns the row of this position
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    /// Returns the column of this position
+    pub fn col(&self) -> usize {
+        self.col
+    }
     
     /// Returns the direction towards the target
     /// Pre-conditions:
@@ -158,16 +220,50 @@ impl Position {
         POSITION_WEIGHTS[self.row][self.col]
     }
 
+    /// Returns the four positions at the center of the board
+    pub fn center() -> impl Iterator<Item=Position> {
+        let mid = BOARD_SIZE / 2;
+        [(mid - 1, mid - 1), (mid - 1, mid), (mid, mid - 1), (mid, mid)].into_iter()
+            .map(|(row, col)| Position::new(row, col))
+    }
+
     /// Returns all possible positions of the board
     pub fn all() -> impl Iterator<Item=Position> {
-        let mut positions = vec![];
-        for i in 0..BOARD_SIZE {
-            for j in 0..BOARD_SIZE {
-                positions.push(Position::new(i, j));
-            }
-        }
+        (0..BOARD_SIZE).flat_map(|i| (0..BOARD_SIZE).map(move |j| Position::new(i, j)))
+    }
+
+    /// Returns the four corners of the board
+    pub fn corners() -> impl Iterator<Item=Position> {
+        let last = BOARD_SIZE - 1;
+        [(0, 0), (0, last), (last, 0), (last, last)].into_iter()
+            .map(|(row, col)| Position::new(row, col))
+    }
 
-        positions.into_iter()
+    /// Returns the corner nearest to this position
+    pub fn nearest_corner(&self) -> Position {
+        let nearest = |i: usize| if i < BOARD_SIZE / 2 { 0 } else { BOARD_SIZE - 1 };
+        Position::new(nearest(self.row), nearest(self.col))
+    }
+
+    /// Checks if this position is on an edge of the board
+    pub fn is_edge(&self) -> bool {
+        [self.row, self.col].iter().any(|&i| i == 0 || i == BOARD_SIZE - 1)
+    }
+
+    /// Checks if this position is diagonally next to a corner, which gives the corner away to the
+    /// opponent while the corner is empty
+    pub fn is_x_square(&self) -> bool {
+        let corner = self.nearest_corner();
+        self.row.abs_diff(corner.row) == 1 && self.col.abs_diff(corner.col) == 1
+    }
+}
+
+#[cfg(feature = "server")]
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for Position {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        Position::parse(field.value.to_string())
+            .map_err(|e| form::Error::validation(e.to_string()).into())
     }
 }
 
@@ -178,18 +274,82 @@ impl Display for Position {
     }
 }
 
-#[derive(Default, Clone, PartialEq, Eq, Hash)]
+/// Representation of the boards in responses
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "server", derive(rocket::FromFormField))]
+pub enum BoardFormat {
+    /// Rows of disk characters joined by newlines
+    #[default]
+    Text,
+    /// Array of rows, where each square is "D", "L" or null
+    Json,
+}
+
+/// Key shared by a board and all of its rotations and reflections, which the evaluation does not
+/// tell apart
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct SymmetryKey(pub(crate) [u64; 3]);
+
+/// Swaps the rows and the columns of the given squares, one bit per square from the top left
+fn transpose(mut bits: u64) -> u64 {
+    let t = 0x0f0f0f0f00000000 & (bits ^ (bits << 28));
+    bits ^= t ^ (t >> 28);
+    let t = 0x3333000033330000 & (bits ^ (bits << 14));
+    bits ^= t ^ (t >> 14);
+    let t = 0x5500550055005500 & (bits ^ (bits << 7));
+    bits ^= t ^ (t >> 7);
+    bits
+}
+
+/// Number of disks of each colour and the sum of the weights of their squares, which are kept
+/// up to date as disks are placed and flipped so that the evaluation does not scan the board
+#[derive(Default, Clone, Copy)]
+struct Tally {
+    num_disks: [i32; 2],
+    placement: [i32; 2],
+}
+
+impl Tally {
+
+    /// Counts the given disk on a square of the given weight, or uncounts it for a negative sign
+    fn add(&mut self, disk: Disk, weight: i32, sign: i32) {
+        self.num_disks[disk as usize] += sign;
+        self.placement[disk as usize] += sign * weight;
+    }
+}
+
+#[derive(Default, Clone)]
 pub struct Board {
     grid: [[Option<Disk>; BOARD_SIZE]; BOARD_SIZE],
+    /// Squares where no disk can ever be placed, for the variants with holes in the board
+    blocked: [[bool; BOARD_SIZE]; BOARD_SIZE],
+    tally: Tally,
+}
+
+// The tally follows from the disks, so boards are compared and hashed without it
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        self.grid == other.grid && self.blocked == other.blocked
+    }
+}
+
+impl Eq for Board {}
+
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.grid.hash(state);
+        self.blocked.hash(state);
+    }
 }
 
 impl Display for Board {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let mut buf = String::with_capacity(BOARD_SIZE * BOARD_SIZE + BOARD_SIZE);
         
-        for row in self.grid.iter() {
-            for cell in row.iter() {
+        for (row, blocked) in self.grid.iter().zip(self.blocked.iter()) {
+            for (cell, blocked) in row.iter().zip(blocked.iter()) {
                 buf.push(match cell {
+                    None if *blocked => BLOCKED_CHAR,
                     None => EMPTY_CHAR,
                     Some(disk) => disk.to_string().chars().nth(0).unwrap(),
                 })
@@ -202,61 +362,346 @@ impl Display for Board {
     }
 }
 
+#[cfg(feature = "server")]
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for Board {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        Board::parse(field.value.to_string())
+            .map_err(|e| form::Error::validation(e.to_string()).into())
+    }
+}
+
 impl Board {
     
     /// Creates a new board
     pub fn new() -> Self {
         assert_eq!(BOARD_SIZE % 2, 0, "Board size must be even");
         
-        let mut board = Board {
-            grid: [[None; BOARD_SIZE]; BOARD_SIZE]
-        };
+        let mut board = Board::new_empty_center();
         
         let mid_pos = Position::new(BOARD_SIZE / 2 - 1, BOARD_SIZE / 2 - 1);
         
-        board.grid[mid_pos.row][mid_pos.col] = Some(Dark);
-        board.grid[mid_pos.row + 1][mid_pos.col] = Some(Light);
-        board.grid[mid_pos.row][mid_pos.col + 1] = Some(Light);
-        board.grid[mid_pos.row + 1][mid_pos.col + 1] = Some(Dark);
+        board.set(mid_pos.row, mid_pos.col, Some(Dark));
+        board.set(mid_pos.row + 1, mid_pos.col, Some(Light));
+        board.set(mid_pos.row, mid_pos.col + 1, Some(Light));
+        board.set(mid_pos.row + 1, mid_pos.col + 1, Some(Dark));
 
         board
     }
     
-    /// Parses the given data to a board
+    /// Creates a board for the classic Reversi start, whose center is empty until the players
+    /// place the first four disks
+    pub fn new_empty_center() -> Self {
+        Board {
+            grid: [[None; BOARD_SIZE]; BOARD_SIZE],
+            blocked: [[false; BOARD_SIZE]; BOARD_SIZE],
+            tally: Tally::default(),
+        }
+    }
+
+    /// Parses the given data to a board, where blocked squares are written as `X`
+    ///
+    /// The data must have exactly `BOARD_SIZE` lines of `BOARD_SIZE` characters each.
     pub fn parse(data: String) -> Result<Self, Error> {
+        let lines = data.lines().collect_vec();
+        if lines.len() != BOARD_SIZE {
+            return Err(ParseError(format!("Expected {} lines in the board but found {}",
+                                          BOARD_SIZE, lines.len())));
+        }
+        if let Some((i, line)) = lines.iter().enumerate()
+            .find(|(_, line)| line.chars().count() != BOARD_SIZE) {
+            return Err(ParseError(format!("Expected {} characters in line {} of the board but \
+                                           found {}", BOARD_SIZE, i + 1, line.chars().count())));
+        }
+
         let mut board = Board::new();
-        for (i, line) in data.lines().enumerate() {
+        for (i, line) in lines.into_iter().enumerate() {
             for (j, ch) in line.chars().enumerate() {
-                let disk = if ch == EMPTY_CHAR {
-                    None
-                } else {
-                    Some(Disk::parse(ch)?)
+                let disk = match ch {
+                    EMPTY_CHAR | BLOCKED_CHAR => None,
+                    ch => Some(Disk::parse(ch)?),
                 };
 
                 board.grid[i][j] = disk;
+                board.blocked[i][j] = ch == BLOCKED_CHAR;
             }
         }
+        // Blocked corners change the weights of the disks next to them, so count them at the end
+        board.recount();
         Ok(board)
     }
     
+    /// Returns this board with its coordinates and Unicode disks, which is easier to read than
+    /// the string representation
+    ///
+    /// Dark disks are drawn as ●, light disks as ○, empty squares as · and blocked squares as ■
+    pub fn pretty(&self) -> String {
+        let mut buf = String::from("  ");
+        for col in 0..BOARD_SIZE {
+            buf.push(' ');
+            buf.push((b'a' + col as u8) as char);
+        }
+
+        for (i, row) in self.grid.iter().enumerate() {
+            buf.push_str(&format!("\n{:>2}", i + 1));
+            for (j, cell) in row.iter().enumerate() {
+                buf.push(' ');
+                buf.push(match cell {
+                    Some(Dark) => '●',
+                    Some(Light) => '○',
+                    None if self.blocked[i][j] => '■',
+                    None => '·',
+                });
+            }
+        }
+
+        buf
+    }
+
+    /// Serializes this board to a json value in the given format
+    pub fn to_json(&self, format: BoardFormat) -> Value {
+        match format {
+            BoardFormat::Text => json!(self.to_string()),
+            BoardFormat::Json => json!(self.grid.iter().zip(self.blocked.iter())
+                .map(|(row, blocked)| row.iter().zip(blocked.iter())
+                    .map(|(cell, &blocked)| match cell {
+                        None if blocked => Some(BLOCKED_CHAR.to_string()),
+                        cell => cell.map(|disk| disk.to_string()),
+                    })
+                    .collect_vec())
+                .collect_vec()),
+        }
+    }
+
     /// Returns the disk at the given position
     pub fn disk(&self, pos: &Position) -> Option<Disk> {
         self.grid[pos.row][pos.col]
     }
     
-    /// Places the disk at the given position
+    /// Checks if no disk can ever be placed at the given position
+    pub fn is_blocked(&self, pos: &Position) -> bool {
+        self.blocked[pos.row][pos.col]
+    }
+
+    /// Checks if a disk can be placed at the given position
+    pub fn is_empty(&self, pos: &Position) -> bool {
+        self.disk(pos).is_none() && !self.is_blocked(pos)
+    }
+
+    /// Returns the weight of the given position on this board for the evaluation
+    ///
+    /// The squares next to a corner are only a liability because they give the corner away, so
+    /// they lose their penalty when the corner is blocked.
+    pub fn weight(&self, pos: &Position) -> i32 {
+        let weight = pos.weight();
+        if weight < 0 && self.is_blocked(&pos.nearest_corner()) { 0 } else { weight }
+    }
+
+    /// Blocks the given position so that no disk can be placed there
     /// Pre-conditions:
     /// * Given position isn't occupied by a disk
-    pub fn place(&mut self, disk: Disk, pos: &Position) -> Result<(), Error> {
+    pub fn block(&mut self, pos: &Position) -> Result<(), Error> {
         if self.disk(pos).is_some() {
+            return Err(InvalidArgument(
+                format!("Given position is not empty to block: {}", pos)));
+        }
+
+        self.blocked[pos.row][pos.col] = true;
+        self.recount();
+        Ok(())
+    }
+
+    /// Places the disk at the given position
+    /// Pre-conditions:
+    /// * Given position is empty and not blocked
+    pub fn place(&mut self, disk: Disk, pos: &Position) -> Result<(), Error> {
+        if !self.is_empty(pos) {
             return Err(InvalidArgument(
                 format!("Given position is not empty to place a disk: {}", pos)));
         }
         
-        self.grid[pos.row][pos.col] = Some(disk);
+        self.set(pos.row, pos.col, Some(disk));
         Ok(())
     }
+
+    /// Puts the given disk at the given square, keeping the tally of the disks up to date
+    fn set(&mut self, row: usize, col: usize, disk: Option<Disk>) {
+        let weight = self.weight(&Position::new(row, col));
+        if let Some(old) = self.grid[row][col] {
+            self.tally.add(old, weight, -1);
+        }
+        if let Some(new) = disk {
+            self.tally.add(new, weight, 1);
+        }
+        self.grid[row][col] = disk;
+    }
+
+    /// Counts the disks of the whole board again
+    fn recount(&mut self) {
+        self.tally = Tally::default();
+        for pos in Position::all() {
+            if let Some(disk) = self.disk(&pos) {
+                let weight = self.weight(&pos);
+                self.tally.add(disk, weight, 1);
+            }
+        }
+    }
+
+    /// Returns the squares of the dark disks, the light disks and the blocked squares, one bit per
+    /// square from the top left
+    fn bits(&self) -> [u64; 3] {
+        let mut bits = [0; 3];
+        for pos in Position::all() {
+            let bit = 1 << (pos.row * BOARD_SIZE + pos.col);
+            match self.disk(&pos) {
+                Some(disk) => bits[disk as usize] |= bit,
+                None if self.is_blocked(&pos) => bits[2] |= bit,
+                None => (),
+            }
+        }
+        bits
+    }
+
+    /// Returns the key of this board up to its rotations and reflections, which is the least key
+    /// among its eight symmetric forms
+    pub fn symmetry_key(&self) -> SymmetryKey {
+        let bits = self.bits();
+        (0..8)
+            .map(|symmetry| SymmetryKey(bits.map(|mut bits| {
+                if symmetry & 1 != 0 {
+                    bits = bits.swap_bytes();
+                }
+                if symmetry & 2 != 0 {
+                    bits = bits.reverse_bits().swap_bytes();
+                }
+                if symmetry & 4 != 0 {
+                    bits = transpose(bits);
+                }
+                bits
+            })))
+            .min()
+            .unwrap()
+    }
+
+    /// Returns the number of squares on which a disk can still be placed
+    pub fn num_empty(&self) -> usize {
+        Position::all().filter(|pos| self.is_empty(pos)).count()
+    }
+
+    /// Returns the number of the given disks on this board
+    pub fn num_disks(&self, disk: Disk) -> i32 {
+        self.tally.num_disks[disk as usize]
+    }
+
+    /// Returns the sum of the weights of the squares of the given disks
+    pub fn placement(&self, disk: Disk) -> i32 {
+        self.tally.placement[disk as usize]
+    }
     
+    /// Returns the disks that no action can flip anymore
+    ///
+    /// A disk is stable when, along each of the four lines through it, the line is full or one of
+    /// its neighbours is the edge, a blocked square or a stable disk of the same colour. This
+    /// misses a few stable disks, but never reports one that can still be flipped.
+    pub fn stable_disks(&self) -> Vec<Position> {
+        const AXES: [(Direction, Direction); 4] =
+            [(North, South), (East, West), (NorthEast, SouthWest), (NorthWest, SouthEast)];
+
+        let mut stable = [[false; BOARD_SIZE]; BOARD_SIZE];
+        // Every disk found stable may anchor its neighbours, so look again until none is found
+        loop {
+            let mut found = false;
+            for pos in Position::all() {
+                let disk = match self.disk(&pos) {
+                    Some(disk) if !stable[pos.row][pos.col] => disk,
+                    _ => continue,
+                };
+                let anchored = |dir: Direction| match self.neighbour(&pos, dir) {
+                    None => true,
+                    Some(neighbour) => self.is_blocked(&neighbour)
+                        || (stable[neighbour.row][neighbour.col]
+                            && self.disk(&neighbour) == Some(disk)),
+                };
+
+                if AXES.iter().all(|&(dir, opposite)| {
+                    anchored(dir) || anchored(opposite) || self.is_line_full(&pos, dir, opposite)
+                }) {
+                    stable[pos.row][pos.col] = true;
+                    found = true;
+                }
+            }
+            if !found {
+                break;
+            }
+        }
+
+        Position::all().filter(|pos| stable[pos.row][pos.col]).collect()
+    }
+
+    /// Checks if no disk can be placed on the line through the given position along the given
+    /// directions
+    fn is_line_full(&self, pos: &Position, dir: Direction, opposite: Direction) -> bool {
+        [dir, opposite].into_iter().all(|dir| {
+            let mut current = pos.clone();
+            while let Some(next) = self.neighbour(&current, dir) {
+                if self.is_empty(&next) {
+                    return false;
+                }
+                current = next;
+            }
+            true
+        })
+    }
+
+    /// Returns the disks next to an empty square, which are the ones the next actions may flip
+    pub fn frontier_disks(&self) -> Vec<Position> {
+        Position::all()
+            .filter(|pos| self.disk(pos).is_some())
+            .filter(|pos| Direction::all().any(|dir| self.neighbour(pos, dir)
+                .is_some_and(|neighbour| self.is_empty(&neighbour))))
+            .collect()
+    }
+
+    /// Describes why this board cannot come from a game, if it cannot
+    ///
+    /// Disks are never removed and each one is placed next to another, so the disks of a real
+    /// game cover the center once any disk is placed around it, and are all connected to it.
+    pub fn inconsistencies(&self) -> Vec<String> {
+        let occupied = |pos: &Position| self.disk(pos).is_some();
+        let mut inconsistencies = Vec::new();
+
+        if Position::all().any(|pos| occupied(&pos) && !Position::center().contains(&pos)) {
+            inconsistencies.extend(Position::center()
+                .filter(|pos| self.is_empty(pos))
+                .map(|pos| format!("The center square {} is empty while disks are placed \
+                                    around it", pos)));
+        }
+
+        let mut reached = [[false; BOARD_SIZE]; BOARD_SIZE];
+        let mut frontier = Position::center().filter(|pos| occupied(pos)).collect_vec();
+        for pos in &frontier {
+            reached[pos.row][pos.col] = true;
+        }
+        while let Some(pos) = frontier.pop() {
+            for next in Direction::all().filter_map(|dir| self.neighbour(&pos, dir)) {
+                if occupied(&next) && !reached[next.row][next.col] {
+                    reached[next.row][next.col] = true;
+                    frontier.push(next);
+                }
+            }
+        }
+        let cut_off = Position::all()
+            .filter(|pos| occupied(pos) && !reached[pos.row][pos.col])
+            .map(|pos| pos.to_string())
+            .collect_vec();
+        if !cut_off.is_empty() {
+            inconsistencies.push(format!("The disks at {} are not connected to the center",
+                                         cut_off.join(" ")));
+        }
+
+        inconsistencies
+    }
+
     /// Returns all positions of the given disk
     pub fn positions(&self, disk: Disk) -> impl Iterator<Item=Position> {
         self.grid.into_iter()
@@ -266,17 +711,16 @@ impl Board {
             .map(|(i, _)| Position::new(i / BOARD_SIZE, i % BOARD_SIZE))
     }
     
-    /// Flips the disk at the given position
-    /// Pre-conditions:
-    /// * pos.is_inbound()
-    /// * The given position must be occupied by a disk
+    /// Flips the disk at the given position, which must be on the board and occupied by a disk
     pub fn flip(&mut self, pos: &Position) -> Result<(), Error> {
-        assert!(pos.is_inbound());
+        if !pos.is_inbound() {
+            return Err(OutOfRange(format!("Position out of the board to flip: {}", pos)));
+        }
 
         match self.disk(pos) {
             None => Err(InvalidArgument(format!("Board is empty at {}", pos))),
             Some(disk) => { 
-                self.grid[pos.row][pos.col] = Some(if disk == Dark { Light } else { Dark });
+                self.set(pos.row, pos.col, Some(if disk == Dark { Light } else { Dark }));
                 Ok(())
             }
         }
@@ -329,15 +773,19 @@ impl Board {
     }
 
     #[cfg(test)]
-    /// Clears this board
+    /// Clears the disks of this board, keeping its blocked squares
     pub fn clear(&mut self) {
         self.grid = [[None; BOARD_SIZE]; BOARD_SIZE];
+        self.tally = Tally::default();
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::board::{Board, BOARD_SIZE, Direction, Disk, Position};
+    use itertools::Itertools;
+    use serde_json::{json, Value};
+
+    use crate::board::{Board, BoardFormat, BOARD_SIZE, Direction, Disk, Position};
     use crate::board::Direction::{East, North, NorthEast, NorthWest, South, SouthEast, SouthWest, West};
     use crate::board::Disk::{Dark, Light};
 
@@ -390,6 +838,8 @@ mod tests {
 
         assert!(board.flip(&pos).is_ok());
         assert_eq!(board.disk(&pos), Some(Dark));
+
+        assert!(board.flip(&Position::new(BOARD_SIZE, 0)).is_err());
     }
     
     #[test]
@@ -484,6 +934,216 @@ mod tests {
         assert_eq!(get_result(Dark), vec!["0,0", "1,1"]);
     }
     
+    #[test]
+    fn to_json() {
+        let board = Board::new();
+        assert_eq!(board.to_json(BoardFormat::Text), json!(board.to_string()));
+
+        let rows = board.to_json(BoardFormat::Json);
+        assert_eq!(rows.as_array().unwrap().len(), BOARD_SIZE);
+        assert_eq!(rows[3][3], json!("D"));
+        assert_eq!(rows[3][4], json!("L"));
+        assert_eq!(rows[0][0], Value::Null);
+    }
+
+    #[test]
+    fn pretty() {
+        let pretty = Board::new().pretty();
+        let lines: Vec<&str> = pretty.lines().collect();
+
+        assert_eq!(lines.len(), BOARD_SIZE + 1);
+        assert_eq!(lines[0], "   a b c d e f g h");
+        assert_eq!(lines[4], " 4 · · · ● ○ · · ·");
+        assert_eq!(lines[5], " 5 · · · ○ ● · · ·");
+    }
+
+    #[test]
+    fn parse_malformed() {
+        let data = Board::new().to_string();
+        assert_eq!(Board::parse(data.clone() + "\n").unwrap().to_string(), data);
+
+        let error = |data: String| Board::parse(data).err().unwrap().to_string();
+        assert_eq!(error(data.lines().take(BOARD_SIZE - 1).join("\n")),
+                   "Expected 8 lines in the board but found 7");
+        assert_eq!(error(data.clone() + "\nEEEEEEEE"), "Expected 8 lines in the board but found 9");
+        assert_eq!(error(data.replacen("EEEEEEEE", "EEEEEEEEE", 1)),
+                   "Expected 8 characters in line 1 of the board but found 9");
+        assert_eq!(error(data.replace("EEEDLEEE", "EEEDLEE")),
+                   "Expected 8 characters in line 4 of the board but found 7");
+        assert!(Board::parse(data.replace("EEEDLEEE", "EEEDLEEQ")).is_err());
+        assert!(Board::parse(data.replace('\n', "")).is_err());
+    }
+
+    #[test]
+    fn inconsistencies() {
+        assert!(Board::new().inconsistencies().is_empty());
+        assert!(Board::new_empty_center().inconsistencies().is_empty());
+        // The first disks of the classic start fill the center one at a time
+        let mut classic = Board::new_empty_center();
+        classic.place(Dark, &Position::new(3, 3)).unwrap();
+        assert!(classic.inconsistencies().is_empty());
+
+        let mut board = Board::new();
+        board.place(Dark, &Position::new(0, 0)).unwrap();
+        assert_eq!(board.inconsistencies(),
+                   vec!["The disks at 0,0 are not connected to the center"]);
+
+        let mut board = Board::new_empty_center();
+        board.place(Dark, &Position::new(3, 3)).unwrap();
+        board.place(Light, &Position::new(2, 2)).unwrap();
+        assert_eq!(board.inconsistencies().len(), 3);
+
+        // A blocked center square stays empty for the whole game
+        let mut board = Board::new_empty_center();
+        board.block(&Position::new(3, 3)).unwrap();
+        for pos in [Position::new(3, 4), Position::new(4, 3), Position::new(4, 4),
+                    Position::new(2, 3)] {
+            board.place(Dark, &pos).unwrap();
+        }
+        assert!(board.inconsistencies().is_empty());
+    }
+
+    #[test]
+    fn blocked() {
+        let data = "XEEEEEEE\nEEEEEEEE\nEEEEEEEE\nEEEDLEEE\nEEELDEEE\nEEEEEEEE\nEEEEEEEE\nEEEEEEEX";
+        let mut board = Board::parse(data.to_string()).unwrap();
+        assert_eq!(board.to_string(), data);
+
+        let corner = Position::new(0, 0);
+        assert!(board.is_blocked(&corner));
+        assert!(!board.is_empty(&corner));
+        assert_eq!(board.disk(&corner), None);
+        assert!(board.place(Dark, &corner).is_err());
+        assert_eq!(board.to_json(BoardFormat::Json)[7][7], json!("X"));
+        assert!(board.pretty().lines().nth(1).unwrap().starts_with(" 1 ■"));
+
+        assert_eq!(board.weight(&Position::new(1, 1)), 0);
+        assert_eq!(board.weight(&Position::new(6, 1)), Position::new(6, 1).weight());
+
+        let pos = Position::new(0, 1);
+        board.block(&pos).unwrap();
+        assert!(board.is_blocked(&pos));
+        assert!(board.block(&Position::new(3, 3)).is_err());
+    }
+
+    #[test]
+    fn symmetry_key() {
+        let with = |positions: &[(usize, usize)]| {
+            let mut board = Board::new_empty_center();
+            for &(row, col) in positions {
+                board.place(Dark, &Position::new(row, col)).unwrap();
+            }
+            board.symmetry_key()
+        };
+
+        // Transposed, rotated and mirrored
+        assert_eq!(with(&[(0, 1), (2, 2)]), with(&[(1, 0), (2, 2)]));
+        assert_eq!(with(&[(0, 1), (2, 2)]), with(&[(7, 6), (5, 5)]));
+        assert_eq!(with(&[(0, 1), (2, 2)]), with(&[(0, 6), (2, 5)]));
+        assert_ne!(with(&[(0, 1), (2, 2)]), with(&[(0, 1), (2, 3)]));
+
+        let mut board = Board::new();
+        board.place(Light, &Position::new(2, 3)).unwrap();
+        board.flip(&Position::new(3, 3)).unwrap();
+        let mut mirrored = Board::new();
+        mirrored.place(Light, &Position::new(3, 2)).unwrap();
+        mirrored.flip(&Position::new(3, 3)).unwrap();
+        assert_eq!(board.symmetry_key(), mirrored.symmetry_key());
+        assert_ne!(board.symmetry_key(), Board::new().symmetry_key());
+    }
+
+    #[test]
+    fn tally() {
+        let scan = |board: &Board, disk: Disk| -> (i32, i32) {
+            (board.positions(disk).count() as i32,
+             board.positions(disk).map(|pos| board.weight(&pos)).sum())
+        };
+        let check = |board: &Board| {
+            for disk in [Dark, Light] {
+                assert_eq!((board.num_disks(disk), board.placement(disk)), scan(board, disk));
+            }
+        };
+
+        let mut board = Board::new();
+        check(&board);
+
+        board.place(Dark, &Position::new(1, 1)).unwrap();
+        board.flip(&Position::new(3, 3)).unwrap();
+        check(&board);
+
+        // The disk next to the corner loses its penalty
+        board.block(&Position::new(0, 0)).unwrap();
+        check(&board);
+        assert_eq!(board.placement(Dark), 0);
+
+        check(&Board::parse(board.to_string()).unwrap());
+    }
+
+    #[test]
+    fn stability() {
+        let board = Board::parse([
+            "LLLEEEEE",
+            "LDEEEEEE",
+            "EEEEEEEE",
+            "EEEDLEEE",
+            "EEELDEEE",
+            "EEEEEEEE",
+            "EEEEEEEE",
+            "EEEEEEED",
+        ].join("\n")).unwrap();
+
+        let stable = board.stable_disks().iter().map(|pos| pos.to_string()).collect::<Vec<_>>();
+        assert_eq!(stable, vec!["0,0", "0,1", "0,2", "1,0", "7,7"]);
+
+        let frontier = board.frontier_disks();
+        assert_eq!(frontier.len(), 9);
+        assert!(!frontier.contains(&Position::new(0, 0)));
+
+        // A full row holds its disks along that line
+        let board = Board::parse(["DLDLDLDL"].into_iter()
+            .chain(["EEEEEEEE"; BOARD_SIZE - 1]).join("\n")).unwrap();
+        assert!(board.stable_disks().iter().all(|pos| pos.row == 0));
+        assert_eq!(board.stable_disks().len(), 8);
+    }
+
+    #[test]
+    fn regions() {
+        assert_eq!(Position::corners().map(|pos| pos.to_string()).collect::<Vec<_>>(),
+                   vec!["0,0", "0,7", "7,0", "7,7"]);
+        assert_eq!(Position::new(2, 6).nearest_corner(), Position::new(0, 7));
+        assert_eq!(Position::all().filter(|pos| pos.is_edge()).count(), 28);
+        assert_eq!(Position::all().filter(|pos| pos.is_x_square()).map(|pos| pos.to_string())
+                       .collect::<Vec<_>>(), vec!["1,1", "1,6", "6,1", "6,6"]);
+    }
+
+    #[test]
+    fn parse_position() {
+        assert_eq!(Position::parse("4,5".to_string()).unwrap(), Position::new(4, 5));
+        assert_eq!(Position::parse("7, 0".to_string()).unwrap(), Position::new(7, 0));
+
+        assert!(Position::parse("8,0".to_string()).is_err());
+        assert!(Position::parse("0,8".to_string()).is_err());
+        assert!(Position::parse("1,2,3".to_string()).is_err());
+        assert!(Position::parse("1,x,2".to_string()).is_err());
+        assert!(Position::parse("-1,2".to_string()).is_err());
+        assert!(Position::parse("1".to_string()).is_err());
+        assert!(Position::parse(String::new()).is_err());
+    }
+
+    #[test]
+    fn notation() {
+        let pos = Position::new(4, 5);
+        assert_eq!(pos.notation(), "f5");
+        assert_eq!(Position::parse_notation("f5").unwrap(), pos);
+        assert_eq!(Position::parse_notation("a1").unwrap(), Position::new(0, 0));
+
+        assert!(Position::parse_notation("i1").is_err());
+        assert!(Position::parse_notation("a9").is_err());
+        assert!(Position::parse_notation("a0").is_err());
+        assert!(Position::parse_notation("5f").is_err());
+        assert!(Position::parse_notation("").is_err());
+    }
+    
     #[test]
     fn direction() {
         let center = Position::new(BOARD_SIZE / 2, BOARD_SIZE / 2);
@@ -0,0 +1,691 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rocket::data::Capped;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Route, State};
+use serde_json::{json, Value};
+
+use crate::admin::Admin;
+use crate::board::{Board, Position};
+use crate::config::EngineConfig;
+use crate::errors::Error;
+use crate::game::{Action, Game, Player};
+use crate::limits;
+use crate::storage::Storage;
+use crate::validation;
+use crate::{ggf, wthor};
+
+const BOOK: &str = "book";
+const BOOK_KEY: &str = "positions";
+
+/// Most games a line added by hand may count for
+const MAX_LINE_GAMES: u32 = 1000;
+
+pub const DATASETS: &str = "datasets";
+pub const WTHOR_DATASET: &str = "wthor";
+
+/// Statistics of a move played from a position of the book
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct MoveStats {
+    /// Number of games in which the move was played
+    pub games: u32,
+    /// Sum of the results of those games for the player of the move: 1 for a win, 0.5 for a draw
+    pub points: f64,
+}
+
+impl MoveStats {
+
+    /// Returns the average result of the games in which the move was played
+    pub fn score(&self) -> f64 {
+        self.points / self.games as f64
+    }
+}
+
+/// Moves played from the opening positions of expert games
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Book {
+    positions: HashMap<String, HashMap<String, MoveStats>>,
+    /// Seconds since the Unix epoch at which games were last imported or the book was last
+    /// edited, if ever
+    #[serde(default)]
+    updated_at: Option<u64>,
+    /// Moves never played from each position, whatever their statistics
+    #[serde(default)]
+    forbidden: HashMap<String, HashSet<String>>,
+    /// Seconds since the Unix epoch at which the line of each first move was last imported or
+    /// edited
+    #[serde(default)]
+    lines_updated_at: HashMap<String, u64>,
+}
+
+/// Returns the key of the given state in the book
+fn key(game: &Game) -> String {
+    format!("{}{}", game.board().to_string().replace('\n', ""), game.current_player())
+}
+
+/// Returns the seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Returns the first move of the given transcript, which names its line
+fn first_move(transcript: &str) -> Option<Position> {
+    let first = transcript.chars().filter(|ch| !ch.is_whitespace()).take(2).collect::<String>();
+    ggf::parse_notation(&first.to_lowercase()).ok()
+}
+
+/// Returns the state reached by playing the given move from the given state, passing for a
+/// player left without moves, or None if the move is illegal
+fn after(game: &Game, placement: &str) -> Option<Game> {
+    let placement = Position::parse(placement.to_string()).ok()?;
+    let result = game.play(&Action::parse(game.current_player(), placement)).ok()?;
+    Some(if result.must_pass() { result.pass() } else { result })
+}
+
+impl Book {
+
+    /// Returns the number of positions in this book
+    pub fn num_positions(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Adds the first plies of a game to this book, together with its result
+    pub fn add(&mut self, states: &[(Game, Action)], winner: Option<Player>, plies: usize) {
+        for (game, action) in states.iter().take(plies) {
+            let stats = self.positions.entry(key(game)).or_default()
+                .entry(action.placement().to_string()).or_default();
+
+            stats.games += 1;
+            stats.points += match winner {
+                Some(winner) if winner == game.current_player() => 1.0,
+                Some(_) => 0.0,
+                None => 0.5,
+            };
+        }
+    }
+
+    /// Marks the line of the given first move as updated at the given time
+    fn touch(&mut self, first: &Position, at: u64) {
+        self.updated_at = Some(at);
+        self.lines_updated_at.insert(first.to_string(), at);
+    }
+
+    /// Returns the move with the best score played at least the given number of times from the
+    /// given state
+    pub fn lookup(&self, game: &Game, min_games: u32) -> Option<Action> {
+        self.positions.get(&key(game))?.iter()
+            .filter(|(_, stats)| stats.games >= min_games)
+            .filter(|(placement, _)| !self.is_forbidden(game, placement))
+            .max_by(|(_, a), (_, b)| a.score().total_cmp(&b.score()))
+            .and_then(|(placement, _)| Position::parse(placement.clone()).ok())
+            .map(|placement| Action::parse(game.current_player(), placement))
+            .filter(|action| game.actions(game.current_player()).any(|a| a == *action))
+    }
+
+    /// Returns a random move played at least the given number of times from the given state,
+    /// chosen as often as it was played
+    pub fn sample(&self, game: &Game, min_games: u32, rng: &mut impl Rng) -> Option<Action> {
+        let player = game.current_player();
+        let moves = self.moves(game).into_iter()
+            .filter(|(_, stats, forbidden)| stats.games >= min_games && !forbidden)
+            .map(|(placement, stats, _)| (Action::parse(player, placement), stats.games))
+            .filter(|(action, _)| game.actions(player).any(|a| a == *action))
+            .collect::<Vec<_>>();
+        moves.choose_weighted(rng, |(_, games)| *games).ok()
+            .map(|(action, _)| action.clone())
+    }
+
+    /// Checks if the move with the given placement is never played from the given state
+    fn is_forbidden(&self, game: &Game, placement: &str) -> bool {
+        self.forbidden.get(&key(game)).is_some_and(|forbidden| forbidden.contains(placement))
+    }
+
+    /// Returns the moves of the book from the given state with their statistics and whether they
+    /// are forbidden, most played first
+    pub fn moves(&self, game: &Game) -> Vec<(Position, MoveStats, bool)> {
+        let mut moves = self.positions.get(&key(game)).into_iter().flatten()
+            .filter_map(|(placement, stats)| Position::parse(placement.clone()).ok()
+                .map(|position| (position, *stats, self.is_forbidden(game, placement))))
+            .collect::<Vec<_>>();
+        moves.sort_by(|(a, a_stats, _), (b, b_stats, _)| b_stats.games.cmp(&a_stats.games)
+            .then_with(|| a.to_string().cmp(&b.to_string())));
+        moves
+    }
+
+    /// Returns the number of plies of the longest line of the book from the given state, where the
+    /// given depths of the states already visited spare searching them again
+    fn depth(&self, game: &Game, depths: &mut HashMap<String, usize>) -> usize {
+        let key = key(game);
+        if let Some(&depth) = depths.get(&key) {
+            return depth;
+        }
+
+        let depth = self.positions.get(&key).into_iter().flatten()
+            .filter_map(|(placement, _)| after(game, placement))
+            .map(|next| 1 + self.depth(&next, depths))
+            .max()
+            .unwrap_or_default();
+        depths.insert(key, depth);
+        depth
+    }
+
+    /// Returns the moves the bot plays from the given state by following the book, each with its
+    /// statistics, until it leaves the book
+    fn main_line(&self, game: &Game, min_games: u32) -> Vec<(Position, MoveStats)> {
+        let mut game = game.clone();
+        let mut line = Vec::new();
+        while let Some(action) = self.lookup(&game, min_games) {
+            let placement = action.placement().clone();
+            let stats = self.positions[&key(&game)][&placement.to_string()];
+            match after(&game, &placement.to_string()) {
+                Some(next) => game = next,
+                None => break,
+            }
+            line.push((placement, stats));
+        }
+        line
+    }
+
+    /// Serializes the statistics of this book: its size, the depth of its lines, the results of
+    /// the first moves and the line the bot follows from the initial board
+    pub fn stats(&self, min_games: u32) -> Value {
+        let initial = Game::parse(Board::new(), Player::Human);
+        let mut depths = HashMap::new();
+        let depth = self.depth(&initial, &mut depths);
+        let first_moves = self.moves(&initial);
+        let games = first_moves.iter().map(|(_, stats, _)| stats.games).sum::<u32>();
+        let points = first_moves.iter().map(|(_, stats, _)| stats.points).sum::<f64>();
+
+        json!({
+            "positions": self.num_positions(),
+            "moves": self.positions.values().map(HashMap::len).sum::<usize>(),
+            "forbidden": self.forbidden.values().map(HashSet::len).sum::<usize>(),
+            "games": games,
+            "dark_score": (games > 0).then(|| points / games as f64),
+            "depth": depth,
+            "updated_at": self.updated_at,
+            "lines": first_moves.iter()
+                .map(|(placement, stats, forbidden)| json!({
+                    "move": ggf::notation(placement),
+                    "games": stats.games,
+                    "score": stats.score(),
+                    "forbidden": forbidden,
+                    "depth": after(&initial, &placement.to_string())
+                        .map_or(1, |next| 1 + self.depth(&next, &mut depths)),
+                    "updated_at": self.lines_updated_at.get(&placement.to_string()),
+                }))
+                .collect::<Vec<_>>(),
+            "main_line": self.main_line(&initial, min_games).into_iter()
+                .map(|(placement, stats)| json!({
+                    "move": ggf::notation(&placement),
+                    "games": stats.games,
+                    "score": stats.score(),
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Removes the move with the given placement from the given state, returning whether the
+    /// book had it
+    pub fn remove(&mut self, game: &Game, placement: &Position) -> bool {
+        let key = key(game);
+        let Some(moves) = self.positions.get_mut(&key) else {
+            return false;
+        };
+        let removed = moves.remove(&placement.to_string()).is_some();
+        if moves.is_empty() {
+            self.positions.remove(&key);
+        }
+        removed
+    }
+
+    /// Forbids or allows again the move with the given placement from the given state, returning
+    /// whether it changed
+    pub fn forbid(&mut self, game: &Game, placement: &Position, forbidden: bool) -> bool {
+        let key = key(game);
+        if forbidden {
+            return self.forbidden.entry(key).or_default().insert(placement.to_string());
+        }
+
+        let Some(moves) = self.forbidden.get_mut(&key) else {
+            return false;
+        };
+        let allowed = moves.remove(&placement.to_string());
+        if moves.is_empty() {
+            self.forbidden.remove(&key);
+        }
+        allowed
+    }
+}
+
+/// Sample of a position and the final disk difference for the tuning datasets
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Sample {
+    pub board: String,
+    pub player: String,
+    /// Disks of the human (dark) minus the disks of the bot (light) at the end of the game
+    pub disk_difference: i32,
+}
+
+/// Replays the given moves from the initial board, returning each state with the action played
+/// from it, or None if a move is illegal
+fn replay(moves: &[Position]) -> Option<(Vec<(Game, Action)>, Game)> {
+    let mut game = Game::parse(Board::new(), Player::Human);
+    let mut states = Vec::new();
+
+    for placement in moves {
+        if game.must_pass() {
+            game = game.pass();
+        }
+
+        let action = Action::parse(game.current_player(), placement.clone());
+        let result = game.play(&action).ok()?;
+        states.push((game, action));
+        game = result;
+    }
+
+    Some((states, game))
+}
+
+/// Parses the given transcript such as "f5d6c3" and replays it from the initial board
+fn parse_line(transcript: &str) -> Result<(Vec<(Game, Action)>, Game), Error> {
+    let transcript = transcript.chars().filter(|ch| !ch.is_whitespace()).collect::<String>();
+    let moves = transcript.as_bytes().chunks(2)
+        .map(|chunk| ggf::parse_notation(&String::from_utf8_lossy(chunk).to_lowercase()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| Error::ParseError(format!("Invalid transcript: {}", transcript)))?;
+
+    replay(&moves)
+        .ok_or_else(|| Error::IllegalMove(format!("Illegal move in the line: {}", transcript)))
+}
+
+/// Returns the state before the last move of the given transcript and that move, failing if the
+/// transcript is empty
+fn last_move(transcript: &str) -> Result<(Game, Position), Error> {
+    let (mut states, _) = parse_line(transcript)?;
+    let (game, action) = states.pop()
+        .ok_or_else(|| Error::InvalidArgument("The line has no moves".to_string()))?;
+    Ok((game, action.placement().clone()))
+}
+
+/// Opening book kept in the storage
+///
+/// Clones share the same book.
+#[derive(Clone)]
+pub struct OpeningBook {
+    storage: Storage,
+    book: Arc<Mutex<Book>>,
+    /// Statistics of the book and the minimum number of games they were computed with, until the
+    /// book changes
+    stats: Arc<Mutex<Option<(u32, Value)>>>,
+}
+
+impl OpeningBook {
+
+    /// Loads the opening book kept in the given storage
+    pub fn new(storage: Storage) -> Self {
+        let book = storage.load(BOOK, BOOK_KEY).unwrap_or_default();
+
+        Self {
+            storage,
+            book: Arc::new(Mutex::new(book)),
+            stats: Arc::default(),
+        }
+    }
+
+    /// Returns the book move from the given state
+    pub fn lookup(&self, game: &Game, min_games: u32) -> Option<Action> {
+        self.book.lock().unwrap().lookup(game, min_games)
+    }
+
+    /// Returns a copy of the book, for the games that play too long to hold its lock
+    pub fn snapshot(&self) -> Book {
+        self.book.lock().unwrap().clone()
+    }
+
+    /// Adds the games of a WTHOR database to the book and the tuning dataset
+    ///
+    /// Returns the number of games imported and skipped
+    pub fn import_wthor(&self, data: &[u8], plies: usize) -> Result<(usize, usize), Error> {
+        let games = wthor::games(data)?;
+        let num_games = games.len();
+        let imported_at = now();
+        let mut book = self.book.lock().unwrap();
+
+        let mut samples = Vec::new();
+        let mut skipped = 0;
+
+        for wthor_game in games {
+            let moves: Vec<Position> = wthor_game.moves().collect();
+            // Only the games played to the end have a winner
+            let replayed = replay(&moves)
+                .and_then(|(states, last)| last.winner().ok().map(|winner| (states, last, winner)));
+            let (states, last, winner) = match replayed {
+                Some(replayed) => replayed,
+                None => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+
+            book.add(&states, winner, plies);
+            if let Some((_, first)) = states.first() {
+                book.touch(first.placement(), imported_at);
+            }
+
+            let dark = last.board().positions(Player::Human.disk()).count() as i32;
+            let light = last.board().positions(Player::Bot.disk()).count() as i32;
+            samples.extend(states.iter().map(|(game, _)| Sample {
+                board: game.board().to_string(),
+                player: game.current_player().to_string(),
+                disk_difference: dark - light,
+            }));
+        }
+
+        book.updated_at = Some(imported_at);
+        let saved = self.storage.append(DATASETS, WTHOR_DATASET, &samples)
+            .and_then(|_| self.storage.save(BOOK, BOOK_KEY, &*book));
+        drop(book);
+        *self.stats.lock().unwrap() = None;
+        saved?;
+
+        Ok((num_games - skipped, skipped))
+    }
+
+    /// Adds the games of the WTHOR database at the given path to the book
+    pub fn import_file(&self, path: &str, plies: usize) -> Result<(usize, usize), Error> {
+        let data = fs::read(path)
+            .map_err(|e| Error::InvalidArgument(format!("Failed to read {}: {}", path, e)))?;
+        self.import_wthor(&data, plies)
+    }
+
+    /// Returns the number of positions in the book
+    pub fn num_positions(&self) -> usize {
+        self.book.lock().unwrap().num_positions()
+    }
+
+    /// Applies the given change to the line of the given transcript and saves the book
+    fn edit<T>(&self, transcript: &str, change: impl FnOnce(&mut Book) -> T)
+        -> Result<T, Error> {
+        let mut book = self.book.lock().unwrap();
+        let changed = change(&mut book);
+        let edited_at = now();
+        book.updated_at = Some(edited_at);
+        if let Some(first) = first_move(transcript) {
+            book.touch(&first, edited_at);
+        }
+        let saved = self.storage.save(BOOK, BOOK_KEY, &*book);
+        // The statistics are dropped once the book is unlocked, so that none computed before the
+        // change outlive it
+        drop(book);
+        *self.stats.lock().unwrap() = None;
+        saved.map(|_| changed)
+    }
+
+    /// Serializes the moves of the book from the position the given transcript reaches, together
+    /// with the move the bot would play there
+    pub fn line(&self, transcript: &str, min_games: u32) -> Result<Value, Error> {
+        let (_, mut game) = parse_line(transcript)?;
+        if game.must_pass() {
+            game = game.pass();
+        }
+        let book = self.book.lock().unwrap();
+
+        Ok(json!({
+            "player": game.current_player().to_string(),
+            "moves": book.moves(&game).into_iter()
+                .map(|(placement, stats, forbidden)| json!({
+                    "move": ggf::notation(&placement),
+                    "games": stats.games,
+                    "points": stats.points,
+                    "score": stats.score(),
+                    "forbidden": forbidden,
+                }))
+                .collect::<Vec<_>>(),
+            "book_move": book.lookup(&game, min_games)
+                .map(|action| ggf::notation(action.placement())),
+        }))
+    }
+
+    /// Adds every move of the given transcript to the book as if it was played in the given
+    /// number of games won by the given player, or drawn if None
+    pub fn add_line(&self, transcript: &str, games: u32, winner: Option<Player>)
+        -> Result<(), Error> {
+        let (states, _) = parse_line(transcript)?;
+        self.edit(transcript, |book| {
+            for _ in 0..games {
+                book.add(&states, winner, states.len());
+            }
+        })
+    }
+
+    /// Removes the last move of the given transcript from the book, returning whether the book
+    /// had it
+    pub fn remove_line(&self, transcript: &str) -> Result<bool, Error> {
+        let (game, placement) = last_move(transcript)?;
+        self.edit(transcript, |book| book.remove(&game, &placement))
+    }
+
+    /// Forbids or allows again the last move of the given transcript, returning whether it
+    /// changed
+    pub fn forbid_line(&self, transcript: &str, forbidden: bool) -> Result<bool, Error> {
+        let (game, placement) = last_move(transcript)?;
+        self.edit(transcript, |book| book.forbid(&game, &placement, forbidden))
+    }
+
+    /// Returns the seconds since the Unix epoch at which games were last imported into the book
+    /// or the book was last edited, if ever
+    pub fn updated_at(&self) -> Option<u64> {
+        self.book.lock().unwrap().updated_at
+    }
+
+    /// Returns a random move of the book from the given state played at least the given number of
+    /// times, chosen as often as it was played
+    pub fn sample(&self, game: &Game, min_games: u32, rng: &mut impl Rng) -> Option<Action> {
+        self.book.lock().unwrap().sample(game, min_games, rng)
+    }
+
+    /// Serializes the statistics of the book, where the bot follows the moves played at least the
+    /// given number of times
+    ///
+    /// They are computed again only once the book changes, since replaying its lines holds the
+    /// book from the bots.
+    pub fn stats(&self, min_games: u32) -> Value {
+        let mut cached = self.stats.lock().unwrap();
+        match &*cached {
+            Some((games, stats)) if *games == min_games => stats.clone(),
+            _ => {
+                let stats = self.book.lock().unwrap().stats(min_games);
+                *cached = Some((min_games, stats.clone()));
+                stats
+            }
+        }
+    }
+}
+
+/// Imports the games of a .wtb file sent as the body
+///
+/// The .trn and .jou files only hold the names of the tournaments and the players, which the
+/// book does not need
+#[post("/admin/book/import", data = "<data>")]
+fn import(_admin: Admin, data: Capped<Vec<u8>>, book: &State<OpeningBook>,
+          config: &State<EngineConfig>) -> Result<String, Custom<String>> {
+    let (imported, skipped) = book.import_wthor(&limits::complete(data)?, config.book_plies)?;
+
+    Ok(json!({
+        "imported": imported,
+        "skipped": skipped,
+        "positions": book.num_positions(),
+    }).to_string())
+}
+
+/// Returns the moves of the book from the position the given transcript reaches
+#[get("/admin/book/line?<transcript>")]
+fn line(_admin: Admin, transcript: &str, book: &State<OpeningBook>,
+        config: &State<EngineConfig>) -> Result<String, Error> {
+    let mut json = book.line(transcript, config.book_min_games)?;
+    json["transcript"] = json!(transcript);
+    Ok(json.to_string())
+}
+
+/// Adds the moves of the given transcript to the book as if they were played in the given number
+/// of games, by default just enough for the bot to play them, won by the given player or drawn
+#[post("/admin/book/line?<transcript>&<games>&<winner>")]
+fn add_line(_admin: Admin, transcript: &str, games: Option<u32>, winner: Option<Player>,
+            book: &State<OpeningBook>, config: &State<EngineConfig>)
+    -> Result<String, Custom<String>> {
+    let games = games.unwrap_or(config.book_min_games.max(1));
+    if games == 0 || games > MAX_LINE_GAMES {
+        return Err(validation::invalid(Status::BadRequest, "games", format!(
+            "The number of games must be between 1 and {}", MAX_LINE_GAMES)));
+    }
+    book.add_line(transcript, games, winner)?;
+
+    Ok(json!({
+        "transcript": transcript,
+        "games": games,
+        "positions": book.num_positions(),
+    }).to_string())
+}
+
+/// Removes the last move of the given transcript from the book
+#[delete("/admin/book/line?<transcript>")]
+fn remove_line(_admin: Admin, transcript: &str, book: &State<OpeningBook>)
+    -> Result<String, Custom<String>> {
+    if !book.remove_line(transcript)? {
+        return Err(Custom(Status::NotFound,
+                          format!("The book has no move ending the line: {}", transcript)));
+    }
+    Ok(json!({
+        "transcript": transcript,
+        "positions": book.num_positions(),
+    }).to_string())
+}
+
+/// Forbids the bot from playing the last move of the given transcript, which survives imports
+#[post("/admin/book/forbidden?<transcript>")]
+fn forbid(_admin: Admin, transcript: &str, book: &State<OpeningBook>)
+    -> Result<String, Error> {
+    let changed = book.forbid_line(transcript, true)?;
+    Ok(json!({ "transcript": transcript, "forbidden": true, "changed": changed }).to_string())
+}
+
+/// Lets the bot play the last move of the given transcript again
+#[delete("/admin/book/forbidden?<transcript>")]
+fn allow(_admin: Admin, transcript: &str, book: &State<OpeningBook>)
+    -> Result<String, Error> {
+    let changed = book.forbid_line(transcript, false)?;
+    Ok(json!({ "transcript": transcript, "forbidden": false, "changed": changed }).to_string())
+}
+
+/// Returns what the book contains: its size, the depth of its lines, the results of the first
+/// moves and the line the bot follows from the initial board
+#[get("/book/stats")]
+fn stats(book: &State<OpeningBook>, config: &State<EngineConfig>) -> String {
+    book.stats(config.book_min_games).to_string()
+}
+
+/// Returns the routes of the opening book
+pub fn routes() -> Vec<Route> {
+    routes![stats, import, line, add_line, remove_line, forbid, allow]
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::board::Position;
+    use crate::book::{first_move, last_move, parse_line, replay, Book, OpeningBook};
+    use crate::game::Player::{Bot, Human};
+    use crate::storage::Storage;
+
+    #[test]
+    fn lookup() {
+        let f5 = Position::new(4, 2);
+        let d6 = Position::new(5, 4);
+        let (states, _) = replay(&[f5.clone(), d6]).unwrap();
+
+        let mut book = Book::default();
+        book.add(&states, Some(Human), 1);
+        assert_eq!(book.num_positions(), 1);
+
+        let (initial, _) = &states[0];
+        assert_eq!(book.lookup(initial, 1).map(|a| a.placement().clone()), Some(f5.clone()));
+        assert_eq!(book.lookup(initial, 2), None);
+        assert_eq!(book.lookup(&states[1].0, 1), None);
+
+        book.add(&states, Some(Bot), 2);
+        assert_eq!(book.num_positions(), 2);
+        assert!(book.lookup(initial, 2).is_some());
+        let mut rng = rand::thread_rng();
+        assert_eq!(book.sample(initial, 2, &mut rng).map(|a| a.placement().clone()), Some(f5));
+        assert_eq!(book.sample(initial, 3, &mut rng), None);
+
+        assert!(replay(&[Position::new(0, 0)]).is_none());
+    }
+
+    #[test]
+    fn edit_lines() {
+        let f5 = Position::new(4, 2);
+        let (states, _) = parse_line("f5 d6").unwrap();
+        assert_eq!(states.len(), 2);
+        assert!(parse_line("a1").is_err());
+        assert!(parse_line("z9").is_err());
+        assert!(last_move("").is_err());
+
+        let mut book = Book::default();
+        book.add(&states, None, 2);
+        let (initial, _) = &states[0];
+        let (game, placement) = last_move("f5").unwrap();
+        assert_eq!(game.board().to_string(), initial.board().to_string());
+        assert_eq!(placement, f5);
+        assert_eq!(book.moves(initial).len(), 1);
+
+        assert!(book.forbid(initial, &f5, true));
+        assert!(!book.forbid(initial, &f5, true));
+        assert_eq!(book.lookup(initial, 1), None);
+        assert!(book.moves(initial)[0].2);
+        assert!(book.forbid(initial, &f5, false));
+        assert!(book.lookup(initial, 1).is_some());
+
+        assert_eq!(book.stats(1)["lines"][0]["updated_at"], Value::Null);
+        book.touch(&first_move("f5 d6").unwrap(), 7);
+        let stats = book.stats(1);
+        assert_eq!((stats["positions"].as_u64(), stats["depth"].as_u64()), (Some(2), Some(2)));
+        assert_eq!(stats["lines"][0]["depth"], 2);
+        assert_eq!((stats["updated_at"].as_u64(), stats["lines"][0]["updated_at"].as_u64()),
+                   (Some(7), Some(7)));
+        assert_eq!(stats["main_line"].as_array().unwrap().len(), 2);
+        assert_eq!(stats["dark_score"], 0.5);
+
+        assert!(book.remove(initial, &f5));
+        assert!(!book.remove(initial, &f5));
+        assert_eq!((book.num_positions(), book.lookup(initial, 1)), (1, None));
+    }
+
+    #[test]
+    fn cached_stats() {
+        let dir = std::env::temp_dir().join(format!("book-stats-{}", std::process::id()));
+        let book = OpeningBook::new(Storage::new(&dir));
+        assert_eq!(book.stats(1)["positions"], 0);
+
+        // The statistics follow the edits of the book
+        book.add_line("f5 d6", 1, None).unwrap();
+        assert_eq!(book.stats(1)["positions"], 2);
+        assert!(book.stats(1)["lines"][0]["updated_at"].is_u64());
+        assert!(book.remove_line("f5 d6").unwrap());
+        assert_eq!(book.stats(1)["depth"], 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,16 +1,387 @@
-use std::cmp::{max, min};
+use std::cmp::{max, min, Reverse};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "server")]
+use rocket::serde::{Deserialize, Serialize};
+use tracing::field;
+
+use crate::board::BOARD_SIZE;
 use crate::errors::Error;
-use crate::errors::Error::InvalidArgument;
-use crate::game::{Action, Game, max_best_evaluation, min_best_evaluation};
+use crate::errors::Error::{IllegalMove, InvalidArgument, ParseError};
+use crate::game::{self, Action, EvaluationKey, Game, max_best_evaluation, weights, Weights};
 use crate::game::Player;
 
+/// Shared flag that asks running searches to stop as soon as possible
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+
+    /// Creates a new token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests every search holding this token to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Checks if this token has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Default number of evaluations a cache keeps before it is cleared
+pub const DEFAULT_CACHE_SIZE: usize = 1 << 20;
+
+/// Number of parts of an evaluation cache, each behind its own lock, so that the bots searching at
+/// the same time rarely wait for each other to store their evaluations
+const CACHE_SHARDS: usize = 64;
+
+/// Start of the saved evaluation caches
+const CACHE_MAGIC: &[u8; 4] = b"DSDC";
+
+/// Version of the format of the saved evaluation caches, which must change with the encoding of
+/// the keys or with the evaluation itself
+const CACHE_FORMAT_VERSION: u8 = 1;
+
+/// Counters of an evaluation cache since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    /// Number of lookups that found an evaluation
+    pub hits: u64,
+    /// Number of lookups that found nothing
+    pub misses: u64,
+    /// Number of evaluations forgotten to make room for new ones
+    pub evictions: u64,
+    /// Number of evaluations currently cached
+    pub len: usize,
+    /// Maximum number of evaluations cached
+    pub size: usize,
+}
+
+impl CacheStats {
+
+    /// Returns the ratio of the lookups that found an evaluation, or 0 before any lookup
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+
+    /// Returns the ratio of the size of the cache in use
+    pub fn fill(&self) -> f64 {
+        self.len as f64 / self.size as f64
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// Evaluations of games that can be shared between bots, so that positions evaluated by one
+/// search are not evaluated again by the next ones
+///
+/// Evaluations are keyed by the symmetry class of the game, so the rotations and reflections of
+/// an evaluated position are not evaluated again either. The keys are spread over shards that
+/// are each cleared when they are full.
+#[derive(Clone)]
+pub struct EvaluationCache {
+    shards: Arc<Vec<RwLock<HashMap<EvaluationKey, i32>>>>,
+    counters: Arc<Counters>,
+    size: usize,
+}
+
+impl Default for EvaluationCache {
+    fn default() -> Self {
+        Self::with_size(DEFAULT_CACHE_SIZE)
+    }
+}
+
+impl EvaluationCache {
+
+    /// Creates a new empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new empty cache keeping at most the given number of evaluations
+    pub fn with_size(size: usize) -> Self {
+        let num_shards = CACHE_SHARDS.clamp(1, size.max(1));
+        Self {
+            shards: Arc::new((0..num_shards).map(|_| RwLock::new(HashMap::new())).collect()),
+            counters: Arc::new(Counters::default()),
+            size,
+        }
+    }
+
+    /// Returns the index of the shard of the given key
+    fn shard(&self, key: &EvaluationKey) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish() as usize % self.shards.len()
+    }
+
+    /// Returns the number of evaluations the shard at the given index keeps, which add up to the
+    /// size of this cache
+    fn shard_size(&self, index: usize) -> usize {
+        let num_shards = self.shards.len();
+        self.size / num_shards + usize::from(index < self.size % num_shards)
+    }
+
+    /// Returns the cached evaluation of the given game, if any
+    fn get(&self, key: &EvaluationKey) -> Option<i32> {
+        let value = self.shards[self.shard(key)].read().unwrap().get(key).copied();
+        let counter = match value {
+            Some(_) => &self.counters.hits,
+            None => &self.counters.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    /// Caches the evaluation of the given key, clearing its shard first when it is full
+    fn insert(&self, key: EvaluationKey, value: i32) {
+        let index = self.shard(&key);
+        let mut evaluations = self.shards[index].write().unwrap();
+        if evaluations.len() >= self.shard_size(index) {
+            self.counters.evictions.fetch_add(evaluations.len() as u64, Ordering::Relaxed);
+            evaluations.clear();
+        }
+        evaluations.insert(key, value);
+    }
+
+    /// Returns the number of cached evaluations
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().unwrap().len()).sum()
+    }
+
+    /// Checks if no evaluation is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the counters of this cache
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            evictions: self.counters.evictions.load(Ordering::Relaxed),
+            len: self.len(),
+            size: self.size,
+        }
+    }
+
+    /// Encodes at most the given number of evaluations together with the weights they were made
+    /// with, so that they can be restored by `load` after a restart
+    pub fn save(&self, limit: usize) -> Vec<u8> {
+        let shards = self.shards.iter().map(|shard| shard.read().unwrap()).collect::<Vec<_>>();
+        let num_entries = min(shards.iter().map(|evaluations| evaluations.len()).sum(), limit);
+
+        let mut buf = Vec::with_capacity(64 + num_entries * (EvaluationKey::SIZE + 4));
+        buf.extend_from_slice(CACHE_MAGIC);
+        buf.push(CACHE_FORMAT_VERSION);
+        for weight in weights_of_cache() {
+            buf.extend_from_slice(&weight.to_le_bytes());
+        }
+        buf.extend_from_slice(&(num_entries as u64).to_le_bytes());
+        for (key, value) in shards.iter().flat_map(|evaluations| evaluations.iter())
+            .take(num_entries) {
+            key.encode(&mut buf);
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        buf
+    }
+
+    /// Adds the evaluations encoded by `save`, returning how many were added
+    ///
+    /// Evaluations saved by another version or under other weights are rejected, since they
+    /// may no longer be right.
+    pub fn load(&self, data: &[u8]) -> Result<usize, Error> {
+        let invalid = || ParseError("Invalid saved evaluation cache".to_string());
+        let header = 4 + 1 + 9 * 4 + 8;
+        if data.len() < header || &data[..4] != CACHE_MAGIC {
+            return Err(invalid());
+        }
+        if data[4] != CACHE_FORMAT_VERSION {
+            return Err(InvalidArgument(format!(
+                "The evaluation cache was saved by the version {} of its format", data[4])));
+        }
+
+        let weights = data[5..41].chunks(4)
+            .map(|chunk| i32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect::<Vec<i32>>();
+        if weights != weights_of_cache() {
+            return Err(InvalidArgument(
+                "The evaluation cache was saved under other weights".to_string()));
+        }
+
+        let num_entries = u64::from_le_bytes(data[41..header].try_into().unwrap()) as usize;
+        let entry_size = EvaluationKey::SIZE + 4;
+        let entries = &data[header..];
+        if entries.len() != num_entries.checked_mul(entry_size).ok_or_else(invalid)? {
+            return Err(invalid());
+        }
+
+        // The evaluations that find their shard full are left out
+        let mut num_loaded = 0;
+        for entry in entries.chunks(entry_size) {
+            let key = EvaluationKey::decode(entry).ok_or_else(invalid)?;
+            let value = i32::from_le_bytes(entry[EvaluationKey::SIZE..].try_into().unwrap());
+            let index = self.shard(&key);
+            let mut evaluations = self.shards[index].write().unwrap();
+            if evaluations.len() < self.shard_size(index) {
+                num_loaded += usize::from(evaluations.insert(key, value).is_none());
+            }
+        }
+        Ok(num_loaded)
+    }
+
+    /// Removes every cached evaluation, which must be done when the evaluation changes
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.write().unwrap().clear();
+        }
+    }
+}
+
+/// Linear fit of the values of deep searches to those of shallow searches in one phase of the
+/// game, where a slope of 0, as of a phase without samples, never cuts
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "server", derive(Serialize, Deserialize), serde(crate = "rocket::serde"))]
+pub struct CutFit {
+    pub slope: f64,
+    pub intercept: f64,
+    /// Standard deviation of the deep values around the fit
+    pub deviation: f64,
+}
+
+/// Depths at which Multi-ProbCut predicts the value of a deep search from a shallow one, with
+/// the fit of each phase of the game
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "server", derive(Serialize, Deserialize), serde(crate = "rocket::serde"))]
+pub struct CutPair {
+    /// Depth left to search at the nodes the pair may cut
+    pub depth: u32,
+    pub shallow_depth: u32,
+    pub fits: [CutFit; 3],
+}
+
+/// Settings of Multi-ProbCut, which prunes the nodes whose deep search is predicted by a shallow
+/// one to fall outside the window
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbCut {
+    pub pairs: Vec<CutPair>,
+    /// Number of standard deviations by which a prediction must clear the window to cut
+    pub confidence: f64,
+}
+
+/// Move lists and lines reused by the nodes of the searches of a bot, one of each per depth, so
+/// that the nodes do not allocate them
+#[derive(Default)]
+struct Buffers {
+    moves: Vec<Vec<Action>>,
+    lines: Vec<Vec<Action>>,
+}
+
+impl Buffers {
+
+    /// Takes the move list of the node at the given depth and the line of its children
+    fn take(&mut self, depth: u32) -> (Vec<Action>, Vec<Action>) {
+        let depth = depth as usize;
+        if self.moves.len() <= depth {
+            self.moves.resize_with(depth + 1, Vec::new);
+            self.lines.resize_with(depth + 1, Vec::new);
+        }
+        (mem::take(&mut self.moves[depth]), mem::take(&mut self.lines[depth]))
+    }
+
+    /// Gives back the buffers taken by the node at the given depth
+    fn give_back(&mut self, depth: u32, moves: Vec<Action>, line: Vec<Action>) {
+        self.moves[depth as usize] = moves;
+        self.lines[depth as usize] = line;
+    }
+}
+
+/// Shuffles the given actions in an order derived from the given seed with the Fisher-Yates
+/// shuffle, drawing from SplitMix64 so that the engine does not need a random number generator
+fn shuffle(actions: &mut [Action], seed: u64) {
+    let mut state = seed;
+    for i in (1..actions.len()).rev() {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        actions.swap(i, (z % (i as u64 + 1)) as usize);
+    }
+}
+
+/// Returns the weights of the standard evaluation, which the saved caches are made under
+fn weights_of_cache() -> Vec<i32> {
+    let weights = weights();
+    [weights.placement, weights.mobility, weights.num_disks].concat()
+}
+
+/// Nodes and time a search spent on one of the actions of its root
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveTiming {
+    pub action: Action,
+    pub nodes: u32,
+    pub elapsed: Duration,
+    /// Value of the action, or None if the search stopped before finishing it
+    pub value: Option<i32>,
+}
+
+/// Nodes and time a search to one depth spent, action by action of its root
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthTiming {
+    pub depth: u32,
+    pub nodes: u32,
+    pub elapsed: Duration,
+    /// Whether every action of the root was searched
+    pub finished: bool,
+    pub moves: Vec<MoveTiming>,
+}
+
 pub struct Bot {
     depth_limit: u32,
-    game: Game,
-    minimax_cache: HashMap<Game, i32>,
+    buffers: Buffers,
+    minimax_cache: EvaluationCache,
+    cancellation: CancellationToken,
+    deadline: Option<Instant>,
+    node_limit: Option<u32>,
+    /// Seed of the order in which the actions of the root are searched, if they are shuffled
+    root_seed: Option<u64>,
+    /// Settings of Multi-ProbCut, which are taken out while a shallow search probes a node
+    probcut: Option<ProbCut>,
+    /// Timings of the depths of the last decision, if they are measured
+    timings: Option<Vec<DepthTiming>>,
     pub num_nodes_expanded: u32,
+    pub timed_out: bool,
+    /// Whether the last search stopped because it expanded as many nodes as it may
+    pub out_of_nodes: bool,
+    /// Weights of the evaluation and the best evaluation they allow, taken when a search starts
+    /// so that its nodes do not read the shared ones
+    weights: Weights,
+    best_evaluation: i32,
+    /// Weights the searches evaluate with instead of those of the variant, with their best
+    /// evaluation, if set
+    fixed_weights: Option<(Weights, i32)>,
+    pub score: i32,
+    pub principal_variation: Vec<Action>,
 }
 
 impl Bot {
@@ -19,115 +390,819 @@ impl Bot {
     pub fn new(intelligence: u32) -> Self {
         Self {
             depth_limit: intelligence,
-            game: Game::new(),
-            minimax_cache: HashMap::new(),
+            buffers: Buffers::default(),
+            minimax_cache: EvaluationCache::new(),
+            cancellation: CancellationToken::new(),
+            deadline: None,
+            node_limit: None,
+            root_seed: None,
+            probcut: None,
+            timings: None,
             num_nodes_expanded: 0,
+            timed_out: false,
+            out_of_nodes: false,
+            weights: Weights::default(),
+            best_evaluation: max_best_evaluation(),
+            fixed_weights: None,
+            score: 0,
+            principal_variation: Vec::new(),
         }
     }
+
+    /// Sets the depth to which this bot searches from now on
+    pub fn set_intelligence(&mut self, intelligence: u32) {
+        self.depth_limit = intelligence;
+    }
+
+    /// Sets the cache in which this bot looks up and stores its evaluations
+    pub fn set_cache(&mut self, cache: EvaluationCache) {
+        self.minimax_cache = cache;
+    }
+
+    /// Sets the token that can abort the searches of this bot
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = token;
+    }
+
+    /// Sets the instant after which the searches of this bot are aborted
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Lets the searches of this bot run until they finish, unless the node limit or the
+    /// cancellation stops them
+    pub fn clear_deadline(&mut self) {
+        self.deadline = None;
+    }
+
+    /// Sets the largest number of nodes each search of this bot may expand, if limited
+    pub fn set_node_limit(&mut self, limit: Option<u32>) {
+        self.node_limit = limit;
+    }
+
+    /// Returns the largest number of nodes each search of this bot may expand, if limited
+    pub fn node_limit(&self) -> Option<u32> {
+        self.node_limit
+    }
+
+    /// Shuffles the actions at the root of the searches of this bot in an order derived from the
+    /// given seed and the position, or searches them in the order they are generated if None
+    ///
+    /// The searches keep the last of the actions with the best score, so without shuffling the
+    /// bot always plays the same move among equally good ones.
+    pub fn set_root_seed(&mut self, seed: Option<u64>) {
+        self.root_seed = seed;
+    }
+
+    /// Makes the searches of this bot evaluate with the given weights whatever the variant of the
+    /// game, or with the weights of the variant if None
+    ///
+    /// Bots evaluating with other weights must not share their cache with the other bots.
+    pub fn set_weights(&mut self, weights: Option<Weights>) -> Result<(), Error> {
+        self.fixed_weights = match weights {
+            Some(weights) => Some((weights, game::validate_weights(&weights)?)),
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Lets the searches of this bot prune the nodes that shallow searches predict to be cut, or
+    /// searches every node if None
+    pub fn set_probcut(&mut self, probcut: Option<ProbCut>) {
+        self.probcut = probcut;
+    }
+
+    /// Measures how long the searches of this bot take on each depth and action of the root from
+    /// now on, or stops measuring
+    ///
+    /// The spans of the searches are traced either way, but only measured timings are logged.
+    pub fn set_timing(&mut self, enabled: bool) {
+        self.timings = enabled.then(Vec::new);
+    }
+
+    /// Returns the timings of the depths searched for the last decision or hint, if measured
+    pub fn timings(&self) -> &[DepthTiming] {
+        self.timings.as_deref().unwrap_or_default()
+    }
+
+    /// Forgets the timings of the last decision before a new one
+    fn clear_timings(&mut self) {
+        if let Some(timings) = self.timings.as_mut() {
+            timings.clear();
+        }
+    }
+
+    /// Checks if the current search must stop, either by cancellation, by the deadline or by the
+    /// node limit
+    fn should_stop(&mut self) -> bool {
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            self.timed_out = true;
+        }
+        if self.node_limit.is_some_and(|limit| self.num_nodes_expanded >= limit) {
+            self.out_of_nodes = true;
+        }
+
+        self.timed_out || self.out_of_nodes || self.cancellation.is_cancelled()
+    }
     
     /// Decides the next action from the given state
-    /// 
-    /// Pre-conditions:
-    /// * self.game.current_player() == Player::Bot
+    ///
+    /// If the search is cancelled or timed out, the best action among the fully searched ones is
+    /// returned
+    ///
+    /// Fails unless it is the turn of the bot in the given state.
     pub fn decide(&mut self, game: &Game) -> Result<(Action, Game), Error> {
-        assert_eq!(self.game.current_player(), Player::Bot);
-        
+        if game.current_player() != Player::Bot {
+            return Err(IllegalMove("The bot can only decide on its own turn".to_string()));
+        }
+
+        self.clear_timings();
+        self.search(game, Player::Bot)
+    }
+
+    /// Decides the next action from the given state by searching one ply deeper at a time, up to
+    /// the given depth, until the deadline, returning the decision of the deepest search that
+    /// finished together with its depth
+    ///
+    /// If not even the search to depth 1 finishes, its best action so far is returned with depth 0.
+    /// The node limit bounds the nodes of all the searches together, which are counted in
+    /// `num_nodes_expanded`.
+    ///
+    /// Fails unless it is the turn of the bot in the given state.
+    pub fn deepen(&mut self, game: &Game, max_depth: u32) -> Result<(Action, Game, u32), Error> {
+        if game.current_player() != Player::Bot {
+            return Err(IllegalMove("The bot can only decide on its own turn".to_string()));
+        }
+
+        self.clear_timings();
+        let limit = self.node_limit;
+        let mut expanded: u32 = 0;
+        let mut deepest = None;
+        for depth in 1..=max_depth.max(1) {
+            self.depth_limit = depth;
+            self.node_limit = limit.map(|limit| limit.saturating_sub(expanded));
+            let searched = self.search(game, Player::Bot);
+            self.node_limit = limit;
+            expanded = expanded.saturating_add(self.num_nodes_expanded);
+            self.num_nodes_expanded = expanded;
+
+            let (action, result) = searched?;
+            if self.should_stop() {
+                if deepest.is_none() {
+                    return Ok((action, result, 0));
+                }
+                break;
+            }
+            deepest = Some((action, result, depth, self.score,
+                            mem::take(&mut self.principal_variation)));
+        }
+
+        let (action, result, depth, score, line) = deepest
+            .expect("The search to depth 1 finished or returned");
+        self.score = score;
+        self.principal_variation = line;
+        self.timed_out = false;
+        self.out_of_nodes = false;
+        Ok((action, result, depth))
+    }
+
+    /// Recommends the next action of the human from the given state
+    ///
+    /// If the search is cancelled or timed out, the best action among the fully searched ones is
+    /// returned
+    pub fn hint(&mut self, game: &Game) -> Result<(Action, Game), Error> {
+        self.clear_timings();
+        self.search(game, Player::Human)
+    }
+
+    /// Scores every action of the given player from the given state, best first
+    ///
+    /// Unlike `decide`, every action is searched with the full window so that the scores of
+    /// the actions can be compared with each other
+    pub fn rank(&mut self, game: &Game, player: Player) -> Vec<(Action, i32)> {
+        self.rank_lines(game, player).into_iter()
+            .map(|(action, value, _)| (action, value))
+            .collect()
+    }
+
+    /// Scores every action of the given player from the given state like `rank`, together with
+    /// the line the search expects after each action
+    pub fn rank_lines(&mut self, game: &Game, player: Player) -> Vec<(Action, i32, Vec<Action>)> {
+        self.load_weights(game);
+        self.num_nodes_expanded = 1;
+        self.timed_out = false;
+        self.out_of_nodes = false;
+
+        let mut ranking = Vec::new();
+        for act in game.actions(player) {
+            let result = game.result(&act);
+            let mut line = Vec::new();
+            let value = match player {
+                Player::Bot => self.min_value(result, -self.best_evaluation,
+                                              self.best_evaluation, 1, &mut line),
+                Player::Human => self.max_value(result, -self.best_evaluation,
+                                                self.best_evaluation, 1, &mut line),
+            };
+            if self.should_stop() {
+                break;
+            }
+            ranking.push((act, value, line));
+        }
+
+        match player {
+            Player::Bot => ranking.sort_by_key(|(_, value, _)| Reverse(*value)),
+            Player::Human => ranking.sort_by_key(|(_, value, _)| *value),
+        }
+        ranking
+    }
+
+    /// Searches the value of the given state to the given depth with the full window, which is
+    /// positive when the bot is ahead, regardless of the intelligence of this bot
+    pub fn value(&mut self, game: &Game, depth: u32) -> i32 {
+        self.load_weights(game);
         self.num_nodes_expanded = 1;
+        self.timed_out = false;
+        self.out_of_nodes = false;
+
+        let depth_limit = mem::replace(&mut self.depth_limit, depth);
+        let value = self.probe(game.clone(), game.current_player(), -self.best_evaluation,
+                               self.best_evaluation, 1);
+        self.depth_limit = depth_limit;
+        value
+    }
+
+    /// Searches the given state within the given window as a node of the given player
+    fn probe(&mut self, game: Game, player: Player, max_best: i32, min_best: i32, depth: u32)
+        -> i32 {
+        let mut line = Vec::new();
+        match player {
+            Player::Bot => self.max_value(game, max_best, min_best, depth, &mut line),
+            Player::Human => self.min_value(game, max_best, min_best, depth, &mut line),
+        }
+    }
+
+    /// Returns the bound of the window beyond which shallow searches of the given node of the
+    /// given player predict its deep search at the given depth to fall, if any pair of
+    /// Multi-ProbCut is confident of it
+    fn probcut(&mut self, game: &Game, player: Player, max_best: i32, min_best: i32, depth: u32)
+        -> Option<i32> {
+        // The shallow searches do not probe their own nodes
+        let probcut = self.probcut.take()?;
+        let remaining = self.depth_limit + 1 - depth;
+        let phase = game.phase().to_index();
+
+        let mut cut = None;
+        for pair in probcut.pairs.iter().filter(|pair| pair.depth == remaining) {
+            let fit = pair.fits[phase];
+            if fit.slope <= 0.0 {
+                continue;
+            }
+            let margin = probcut.confidence * fit.deviation;
+            // Shallow values at the returned bounds predict deep values beyond the window
+            let shallow = |bound: i32, margin: f64| (bound as f64 + margin - fit.intercept)
+                / fit.slope;
+            let depth_limit = mem::replace(&mut self.depth_limit,
+                                           depth - 1 + pair.shallow_depth);
+
+            if min_best < self.best_evaluation {
+                let bound = shallow(min_best, margin).ceil()
+                    .clamp(-self.best_evaluation as f64, self.best_evaluation as f64) as i32;
+                if self.probe(game.clone(), player, bound - 1, bound, depth) >= bound {
+                    cut = Some(min_best);
+                }
+            }
+            if cut.is_none() && max_best > -self.best_evaluation {
+                let bound = shallow(max_best, -margin).floor()
+                    .clamp(-self.best_evaluation as f64, self.best_evaluation as f64) as i32;
+                if self.probe(game.clone(), player, bound, bound + 1, depth) <= bound {
+                    cut = Some(max_best);
+                }
+            }
+
+            self.depth_limit = depth_limit;
+            if cut.is_some() {
+                break;
+            }
+        }
+
+        self.probcut = Some(probcut);
+        cut
+    }
+
+    /// Searches the final disk difference of the given game under perfect play to the end, which
+    /// is positive when the bot wins, or None if the search is cancelled or timed out
+    ///
+    /// Unlike the other searches, this one ignores the depth limit, so it is only affordable for
+    /// the last few empty squares
+    pub fn solve(&mut self, game: &Game) -> Option<i32> {
+        self.num_nodes_expanded = 1;
+        self.timed_out = false;
+        self.out_of_nodes = false;
+
+        let max_difference = (BOARD_SIZE * BOARD_SIZE) as i32;
+        let value = self.solve_value(game.clone(), -max_difference, max_difference);
+        (!self.should_stop()).then_some(value)
+    }
+
+    /// Searches the final disk difference that each action of the given player leads to under
+    /// perfect play like `solve`, best first, or None if the search is cancelled or timed out
+    pub fn solve_actions(&mut self, game: &Game, player: Player) -> Option<Vec<(Action, i32)>> {
+        self.num_nodes_expanded = 1;
+        self.timed_out = false;
+        self.out_of_nodes = false;
+
+        let max_difference = (BOARD_SIZE * BOARD_SIZE) as i32;
+        let mut ranking = Vec::new();
+        for act in game.actions(player) {
+            let value = self.solve_value(game.result(&act), -max_difference, max_difference);
+            if self.should_stop() {
+                return None;
+            }
+            ranking.push((act, value));
+        }
+
+        match player {
+            Player::Bot => ranking.sort_by_key(|(_, value)| Reverse(*value)),
+            Player::Human => ranking.sort_by_key(|(_, value)| *value),
+        }
+        Some(ranking)
+    }
+
+    /// Finds the final disk difference of the given game within the given window
+    fn solve_value(&mut self, game: Game, mut max_best: i32, mut min_best: i32) -> i32 {
+        if game.is_over() {
+            let board = game.board();
+            return board.num_disks(Player::Bot.disk()) - board.num_disks(Player::Human.disk());
+        } else if self.should_stop() {
+            return 0;
+        }
+
+        self.num_nodes_expanded += 1;
+
+        let player = game.current_player();
+        let mut moves = Vec::new();
+        game.actions_into(player, &mut moves);
+        if moves.is_empty() {
+            return self.solve_value(game.pass(), max_best, min_best);
+        }
+
+        let mut best_here = match player {
+            Player::Bot => i32::MIN,
+            Player::Human => i32::MAX,
+        };
+        for act in &moves {
+            let value = self.solve_value(game.result(act), max_best, min_best);
+            match player {
+                Player::Bot => {
+                    best_here = max(best_here, value);
+                    max_best = max(max_best, best_here);
+                }
+                Player::Human => {
+                    best_here = min(best_here, value);
+                    min_best = min(min_best, best_here);
+                }
+            }
+            if max_best >= min_best {
+                break;
+            }
+        }
+
+        best_here
+    }
+
+    /// Searches the best action of the given player from the given state
+    fn search(&mut self, game: &Game, player: Player) -> Result<(Action, Game), Error> {
+        self.load_weights(game);
+        self.num_nodes_expanded = 1;
+        self.timed_out = false;
+        self.out_of_nodes = false;
+        self.principal_variation.clear();
         
-        let mut bot_best = min_best_evaluation();
-        let human_best = max_best_evaluation();
+        let mut bot_best = -self.best_evaluation;
+        let mut human_best = self.best_evaluation;
         
-        let mut minimax_value = bot_best;
-        let mut num_actions = 0;
+        let mut minimax_value = if player == Player::Bot { bot_best } else { human_best };
         let mut decided = false;
         
         let mut best_action = Action::default();
         let mut best_result= Game::default();
         
-        for act in game.actions(Player::Bot) {
-            num_actions += 1;
+        let depth = self.depth_limit;
+        let depth_span = tracing::debug_span!("depth", depth, nodes = field::Empty).entered();
+        let started = self.timings.is_some().then(Instant::now);
+        let mut move_timings = Vec::new();
+        let mut finished = true;
+
+        let (mut moves, mut line) = self.buffers.take(0);
+        game.actions_into(player, &mut moves);
+        if let Some(seed) = self.root_seed {
+            // Every position of a game is shuffled differently under the same seed
+            shuffle(&mut moves, seed ^ game.board().num_empty() as u64);
+        }
+        for act in moves.drain(..) {
+            let move_span = tracing::debug_span!("root_move", action = %act, nodes = field::Empty)
+                .entered();
+            let move_started = started.map(|_| Instant::now());
+            let nodes_before = self.num_nodes_expanded;
+
             let result = game.result(&act);
-            let value = self.min_value(result.clone(), bot_best, human_best, 1);
-            if value >= minimax_value {
+            line.clear();
+            let value = match player {
+                Player::Bot => self.min_value(result.clone(), bot_best, human_best, 1, &mut line),
+                Player::Human => self.max_value(result.clone(), bot_best, human_best, 1, &mut line),
+            };
+
+            let stopped = self.should_stop();
+            let nodes = self.num_nodes_expanded - nodes_before;
+            move_span.record("nodes", nodes);
+            if let Some(move_started) = move_started {
+                let elapsed = move_started.elapsed();
+                tracing::debug!(depth, action = %act, nodes,
+                                elapsed_ms = elapsed.as_millis() as u64, stopped,
+                                "Searched a root action");
+                move_timings.push(MoveTiming {
+                    action: act.clone(),
+                    nodes,
+                    elapsed,
+                    value: (!stopped).then_some(value),
+                });
+            }
+            drop(move_span);
+
+            if stopped {
+                finished = false;
+                if !decided {
+                    self.principal_variation = vec![act.clone()];
+                    minimax_value = self.evaluate(result.clone());
+                    best_action = act;
+                    best_result = result;
+                    decided = true;
+                }
+                break;
+            }
+
+            // The best evaluations are not strict bounds, so the first action is always taken
+            let is_better = !decided || match player {
+                Player::Bot => value >= minimax_value,
+                Player::Human => value <= minimax_value,
+            };
+            if is_better {
                 minimax_value = value;
+                self.principal_variation = vec![act.clone()];
+                self.principal_variation.append(&mut line);
                 best_action = act;
                 best_result = result;
                 decided = true;
             }
-            bot_best = max(bot_best, minimax_value);
+
+            match player {
+                Player::Bot => bot_best = max(bot_best, minimax_value),
+                Player::Human => human_best = min(human_best, minimax_value),
+            }
         }
 
-        if num_actions == 0 {
+        self.buffers.give_back(0, moves, line);
+
+        depth_span.record("nodes", self.num_nodes_expanded);
+        if let (Some(started), Some(timings)) = (started, self.timings.as_mut()) {
+            let elapsed = started.elapsed();
+            tracing::debug!(depth, nodes = self.num_nodes_expanded,
+                            elapsed_ms = elapsed.as_millis() as u64, finished, "Searched a depth");
+            timings.push(DepthTiming {
+                depth,
+                nodes: self.num_nodes_expanded,
+                elapsed,
+                finished,
+                moves: move_timings,
+            });
+        }
+        drop(depth_span);
+
+        if !decided {
             return Err(InvalidArgument(format!("No actions are available from the given game.")));
         }
-        
-        assert!(decided);
-        
+
+        self.score = minimax_value;
         Ok((best_action, best_result))
     }
     
-    /// Finds the min value of the minimax
-    fn min_value(&mut self, game: Game, max_best: i32, mut min_best: i32, depth: u32) -> i32 {
+    /// Finds the min value of the minimax, storing the best continuation into the given line
+    fn min_value(&mut self, game: Game, max_best: i32, mut min_best: i32, depth: u32,
+                 line: &mut Vec<Action>) -> i32 {
         if game.is_over() {
-            return game.utility();
-        } else if depth > self.depth_limit {
+            // Checked first, since the utility of a game that goes on allocates its error
+            return self.utility(&game);
+        } else if depth > self.depth_limit || self.should_stop() {
             return self.evaluate(game);
         }
 
         self.num_nodes_expanded += 1;
+        if let Some(bound) = self.probcut(&game, Player::Human, max_best, min_best, depth) {
+            line.clear();
+            return bound;
+        }
 
-        let mut min_best_here = max_best_evaluation();
+        let mut min_best_here = self.best_evaluation;
 
-        for act in game.actions(Player::Human) {
-            let result = game.result(&act);
-            let value = self.max_value(result, max_best, min_best, depth + 1);
+        let (mut moves, mut child_line) = self.buffers.take(depth);
+        game.actions_into(Player::Human, &mut moves);
+        if moves.is_empty() {
+            self.buffers.give_back(depth, moves, child_line);
+            let passed = game.pass();
+            return self.extended(|bot| bot.max_value(passed, max_best, min_best, depth + 1, line));
+        }
+        let forced = moves.len() == 1;
+        for act in &moves {
+            let result = game.result(act);
+            child_line.clear();
+            let value = if forced {
+                self.extended(|bot| bot.max_value(result, max_best, min_best, depth + 1,
+                                                  &mut child_line))
+            } else {
+                self.max_value(result, max_best, min_best, depth + 1, &mut child_line)
+            };
             if value < min_best_here {
                 min_best_here = value;
+                line.clear();
+                line.push(act.clone());
+                line.append(&mut child_line);
             }
-            if min_best_here <= min_best {
-                return min_best_here;
+            if min_best_here <= max_best {
+                break;
             }
             min_best = min(min_best, min_best_here);
         }
+        self.buffers.give_back(depth, moves, child_line);
 
         return min_best_here;
     }
     
-    /// Finds the max value of the minimax
-    fn max_value(&mut self, game: Game, mut max_best: i32, min_best: i32, depth: u32) -> i32 {
+    /// Finds the max value of the minimax, storing the best continuation into the given line
+    fn max_value(&mut self, game: Game, mut max_best: i32, min_best: i32, depth: u32,
+                 line: &mut Vec<Action>) -> i32 {
         if game.is_over() {
-            return game.utility();
-        } else if depth > self.depth_limit {
+            // Checked first, since the utility of a game that goes on allocates its error
+            return self.utility(&game);
+        } else if depth > self.depth_limit || self.should_stop() {
             return self.evaluate(game);
         }
 
         self.num_nodes_expanded += 1;
+        if let Some(bound) = self.probcut(&game, Player::Bot, max_best, min_best, depth) {
+            line.clear();
+            return bound;
+        }
 
-        let mut max_best_here = min_best_evaluation();
-        
-        for act in game.actions(Player::Bot) {
-            let result = game.result(&act);
-            let value = self.min_value(result, max_best, min_best, depth + 1);
+        let mut max_best_here = -self.best_evaluation;
+
+        let (mut moves, mut child_line) = self.buffers.take(depth);
+        game.actions_into(Player::Bot, &mut moves);
+        if moves.is_empty() {
+            self.buffers.give_back(depth, moves, child_line);
+            let passed = game.pass();
+            return self.extended(|bot| bot.min_value(passed, max_best, min_best, depth + 1, line));
+        }
+        let forced = moves.len() == 1;
+        for act in &moves {
+            let result = game.result(act);
+            child_line.clear();
+            let value = if forced {
+                self.extended(|bot| bot.min_value(result, max_best, min_best, depth + 1,
+                                                  &mut child_line))
+            } else {
+                self.min_value(result, max_best, min_best, depth + 1, &mut child_line)
+            };
             if value > max_best_here {
                 max_best_here = value;
+                line.clear();
+                line.push(act.clone());
+                line.append(&mut child_line);
             }
             if max_best_here >= min_best {
-                return max_best_here;
+                break;
             }
             max_best = max(max_best, max_best_here);
         }
-        
+        self.buffers.give_back(depth, moves, child_line);
+
         return max_best_here;
     }
     
+    /// Runs the given search of a child of the current node one ply deeper than the depth limit,
+    /// since the player to move has no choice but to pass or to play its only action
+    ///
+    /// Otherwise the lines with forced plies, which are the critical ones, are searched less
+    /// deeply than the others.
+    fn extended(&mut self, search: impl FnOnce(&mut Self) -> i32) -> i32 {
+        self.depth_limit += 1;
+        let value = search(self);
+        self.depth_limit -= 1;
+        value
+    }
+
+    /// Takes the weights of the evaluation under the rules of the given game for the search about
+    /// to start from it
+    fn load_weights(&mut self, game: &Game) {
+        (self.weights, self.best_evaluation) = self.fixed_weights
+            .unwrap_or_else(|| (game.variant().weights(), max_best_evaluation()));
+    }
+
+    /// Returns the value of the given finished game for the search, like `Game::utility`
+    fn utility(&self, game: &Game) -> i32 {
+        match game.winner() {
+            Ok(Some(Player::Bot)) => self.best_evaluation,
+            Ok(Some(Player::Human)) => -self.best_evaluation,
+            _ => 0,
+        }
+    }
+
     /// Evaluates the given game to a value
     fn evaluate(&mut self, game: Game) -> i32 {
-        if self.minimax_cache.contains_key(&game) {
-            return self.minimax_cache.get(&game).copied().unwrap();
+        let key = game.evaluation_key();
+        if let Some(value) = self.minimax_cache.get(&key) {
+            return value;
         }
         
-        let value = game.evaluate();
-        self.minimax_cache.insert(game, value);
+        let value = game.evaluate_with(&self.weights);
+        self.minimax_cache.insert(key, value);
         value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::board::Board;
+    use crate::bot::{Bot, EvaluationCache};
+    use crate::errors::Error;
+    use crate::game::{self, max_best_evaluation, Game, Player, Weights};
+
+    #[test]
+    fn cache_size() {
+        // The shards of a cache hold no more evaluations than the cache together
+        let cache = EvaluationCache::with_size(100);
+        let mut bot = Bot::new(4);
+        bot.set_cache(cache.clone());
+        bot.decide(&Game::new()).unwrap();
+
+        let stats = cache.stats();
+        assert!(stats.len <= 100 && stats.evictions > 0);
+        assert_eq!(EvaluationCache::with_size(3).shards.len(), 3);
+    }
+
+    #[test]
+    fn extend_passes() {
+        let board = Board::parse([
+            "EEEEEEEE",
+            "LLLEEEEE",
+            "ELEEDEEE",
+            "LLLDDEEE",
+            "EEEDDEEE",
+            "EEEDEDEE",
+            "EEEEEEDE",
+            "EEEEEEEE",
+        ].join("\n")).unwrap();
+        let game = Game::parse(board, Player::Human);
+        assert!(game.must_pass());
+
+        // The pass of the human leaves the bot the whole depth instead of ending the search
+        let value = Bot::new(1).value(&game, 2);
+        assert_eq!(value, Bot::new(1).value(&game.pass(), 2));
+        assert_ne!(value, max_best_evaluation());
+    }
+
+    #[test]
+    fn deepen() {
+        let game = Game::new();
+        let (action, _) = Bot::new(3).decide(&game).unwrap();
+        let (deepened, _, depth) = Bot::new(3).deepen(&game, 3).unwrap();
+        assert_eq!((deepened, depth), (action, 3));
+
+        // A deadline that has passed stops even the search to depth 1
+        let mut bot = Bot::new(3);
+        bot.set_deadline(Instant::now());
+        let (action, _, depth) = bot.deepen(&game, 3).unwrap();
+        assert_eq!(depth, 0);
+        assert!(game.actions(Player::Bot).any(|legal| legal == action));
+
+        // The node limit bounds every search together, the same way on any hardware
+        let mut unlimited = Bot::new(4);
+        unlimited.deepen(&game, 4).unwrap();
+        let limit = unlimited.num_nodes_expanded - 1;
+        let decisions = (0..2).map(|_| {
+            let mut bot = Bot::new(4);
+            bot.set_node_limit(Some(limit));
+            let (action, _, depth) = bot.deepen(&game, 4).unwrap();
+            assert!(bot.num_nodes_expanded <= limit);
+            (action, depth, bot.num_nodes_expanded)
+        }).collect::<Vec<_>>();
+        assert_eq!(decisions[0], decisions[1]);
+        assert_eq!(decisions[0].1, 3);
+    }
+
+    #[test]
+    fn turns() {
+        let game = Game::parse(Board::new(), Player::Human);
+        assert!(matches!(Bot::new(2).decide(&game), Err(Error::IllegalMove(_))));
+        assert!(matches!(Bot::new(2).deepen(&game, 2), Err(Error::IllegalMove(_))));
+        assert!(Bot::new(2).hint(&game).is_ok());
+    }
+
+    #[test]
+    fn fixed_weights() {
+        let mut bot = Bot::new(2);
+        let invalid = Weights { placement: [-1; 3], mobility: [0; 3], num_disks: [-1; 3] };
+        assert!(bot.set_weights(Some(invalid)).is_err());
+
+        // The searches evaluate with the weights of the bot rather than the shared ones
+        let fixed = Weights { placement: [1; 3], mobility: [0; 3], num_disks: [1; 3] };
+        let best_evaluation = game::validate_weights(&fixed).unwrap();
+        bot.set_weights(Some(fixed)).unwrap();
+        bot.decide(&Game::new()).unwrap();
+        assert_eq!((bot.weights, bot.best_evaluation), (fixed, best_evaluation));
+
+        bot.set_weights(None).unwrap();
+        bot.decide(&Game::new()).unwrap();
+        assert_eq!((bot.weights, bot.best_evaluation), (game::weights(), max_best_evaluation()));
+    }
+
+    #[test]
+    fn timings() {
+        let game = Game::new();
+        let mut bot = Bot::new(3);
+        bot.deepen(&game, 3).unwrap();
+        assert!(bot.timings().is_empty());
+
+        bot.set_timing(true);
+        bot.deepen(&game, 3).unwrap();
+        let timings = bot.timings();
+        assert_eq!(timings.iter().map(|timing| timing.depth).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(timings.iter().map(|timing| timing.nodes).sum::<u32>(), bot.num_nodes_expanded);
+        for timing in timings {
+            assert!(timing.finished);
+            assert_eq!(timing.moves.len(), game.actions(Player::Bot).count());
+            // The root itself is the only node expanded outside of its actions
+            assert_eq!(timing.moves.iter().map(|m| m.nodes).sum::<u32>() + 1, timing.nodes);
+            assert!(timing.moves.iter().all(|m| m.value.is_some()));
+        }
+
+        // A search stopped by its deadline leaves the rest of the actions unmeasured
+        bot.set_deadline(Instant::now());
+        bot.decide(&game).unwrap();
+        let timings = bot.timings();
+        assert_eq!(timings.len(), 1);
+        assert!(!timings[0].finished);
+        assert_eq!(timings[0].moves.len(), 1);
+        assert!(timings[0].moves[0].value.is_none());
+    }
+
+    /// Returns the value of the given state searched to the given depth without pruning, where the
+    /// game neither ends nor passes
+    fn minimax(game: &Game, depth: u32) -> i32 {
+        if depth == 0 {
+            return game.evaluate();
+        }
+
+        let player = game.current_player();
+        let values = game.actions(player).map(|action| minimax(&game.result(&action), depth - 1));
+        match player {
+            Player::Bot => values.max(),
+            Player::Human => values.min(),
+        }.expect("The player to move has actions")
+    }
+
+    #[test]
+    fn rank_scores() {
+        // The scores of the actions are their exact values, whatever order they are searched in
+        let mut game = Game::new();
+        for _ in 0..4 {
+            let player = game.current_player();
+            for (action, value) in Bot::new(3).rank(&game, player) {
+                assert_eq!(value, minimax(&game.result(&action), 3));
+            }
+            let action = game.actions(player).next().unwrap();
+            game = game.result(&action);
+        }
+    }
+
+    #[test]
+    fn evaluations_beyond_best() {
+        let board = Board::parse([
+            "DLDDDDLD",
+            "LLDDDDLL",
+            "DDDDDDDD",
+            "DDDEEDDD",
+            "DDDEEDDD",
+            "DDDDDDDD",
+            "LLDDDDLL",
+            "DLDDDDLD",
+        ].join("\n")).unwrap();
+        let game = Game::parse(board, Player::Bot);
+        // Every action of the bot evaluates below the evaluation of a lost game
+        assert!(game.actions(Player::Bot)
+            .all(|action| game.result(&action).evaluate() < -max_best_evaluation()));
+
+        assert!(Bot::new(0).decide(&game).is_ok());
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+use crate::errors::Error;
+use crate::errors::Error::ParseError;
+use crate::game::Player;
+
+/// Fewest moves the time manager assumes are left, so that it keeps a reserve near the end
+const MIN_MOVES_TO_GO: u32 = 4;
+
+/// Time given to each player and added after every move, such as 5+3 for five minutes with an
+/// increment of three seconds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeControl {
+    pub initial: Duration,
+    pub increment: Duration,
+}
+
+impl TimeControl {
+
+    /// Parses the given minutes and the optional increment in seconds, such as "5+3" or "10"
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let invalid = || ParseError(format!("Invalid time control: {}", s));
+
+        let (minutes, increment) = s.split_once('+').unwrap_or((s, "0"));
+        let minutes: u64 = minutes.trim().parse().map_err(|_| invalid())?;
+        let increment: u64 = increment.trim().parse().map_err(|_| invalid())?;
+
+        if minutes == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            initial: Duration::from_secs(minutes * 60),
+            increment: Duration::from_secs(increment),
+        })
+    }
+}
+
+impl Display for TimeControl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}+{}", self.initial.as_secs() / 60, self.increment.as_secs())
+    }
+}
+
+/// Returns the index of the given player in the clock
+fn index(player: Player) -> usize {
+    match player {
+        Player::Human => 0,
+        Player::Bot => 1,
+    }
+}
+
+/// Clock counting down the time of the player to move
+pub struct Clock {
+    control: TimeControl,
+    remaining: [Duration; 2],
+    running: Option<(Player, Instant)>,
+}
+
+impl Clock {
+
+    /// Creates a clock that runs for the given player
+    pub fn new(control: TimeControl, player: Player) -> Self {
+        Self {
+            control,
+            remaining: [control.initial; 2],
+            running: Some((player, Instant::now())),
+        }
+    }
+
+    /// Creates a clock with the given times left to the human and the bot, which runs for the
+    /// given player, if any
+    pub fn resume(control: TimeControl, human: Duration, bot: Duration, running: Option<Player>)
+        -> Self {
+        Self {
+            control,
+            remaining: [human, bot],
+            running: running.map(|player| (player, Instant::now())),
+        }
+    }
+
+    /// Returns the time control of this clock
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    /// Returns the player whose time is running, if any
+    pub fn running(&self) -> Option<Player> {
+        self.running.map(|(player, _)| player)
+    }
+
+    /// Returns the time left to the given player
+    pub fn remaining(&self, player: Player) -> Duration {
+        match self.running {
+            Some((running, since)) if running == player => {
+                self.remaining[index(player)].saturating_sub(since.elapsed())
+            }
+            _ => self.remaining[index(player)],
+        }
+    }
+
+    /// Returns the player to move if their time ran out
+    pub fn flagged(&self) -> Option<Player> {
+        self.running().filter(|&player| self.remaining(player).is_zero())
+    }
+
+    /// Stops the time of the running player and starts the time of the given player, without
+    /// any increment
+    pub fn start(&mut self, player: Player) {
+        self.stop();
+        self.running = Some((player, Instant::now()));
+    }
+
+    /// Ends the move of the running player, adding the increment to their time, and starts the
+    /// time of the given player
+    pub fn press(&mut self, player: Player) {
+        if let Some(moved) = self.running() {
+            self.remaining[index(moved)] = self.remaining(moved) + self.control.increment;
+        }
+        self.start(player);
+    }
+
+    /// Stops the time of the running player
+    pub fn stop(&mut self) {
+        if let Some(player) = self.running() {
+            self.remaining[index(player)] = self.remaining(player);
+        }
+        self.running = None;
+    }
+
+    /// Returns how long the given player may think about their move, expecting the given number
+    /// of their moves until the end of the game
+    pub fn budget(&self, player: Player, moves_to_go: u32) -> Duration {
+        let remaining = self.remaining(player);
+        let budget = remaining / moves_to_go.max(MIN_MOVES_TO_GO) + self.control.increment * 3 / 4;
+
+        budget.min(remaining / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::clock::{Clock, TimeControl};
+    use crate::game::Player::{Bot, Human};
+
+    #[test]
+    fn time_control() {
+        let control = TimeControl::parse("5+3").unwrap();
+        assert_eq!(control.initial, Duration::from_secs(300));
+        assert_eq!(control.increment, Duration::from_secs(3));
+        assert_eq!(control.to_string(), "5+3");
+
+        assert_eq!(TimeControl::parse("10").unwrap().increment, Duration::ZERO);
+        assert!(TimeControl::parse("0+5").is_err());
+        assert!(TimeControl::parse("5+x").is_err());
+    }
+
+    #[test]
+    fn press() {
+        let control = TimeControl::parse("1+2").unwrap();
+        let mut clock = Clock::new(control, Human);
+        assert_eq!(clock.running(), Some(Human));
+        assert_eq!(clock.remaining(Bot), control.initial);
+
+        clock.press(Bot);
+        assert_eq!(clock.running(), Some(Bot));
+        assert!(clock.remaining(Human) > control.initial);
+        assert!(clock.flagged().is_none());
+
+        assert!(clock.budget(Bot, 30) < clock.remaining(Bot) / 2);
+
+        clock.stop();
+        assert_eq!(clock.running(), None);
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::{Cursor, Write};
+
+use flate2::write::GzEncoder;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Request, Response};
+
+/// Smallest body worth compressing in bytes, below which the headers outweigh the savings
+const MIN_SIZE: usize = 1024;
+
+/// Quality of brotli compression, trading a little size for much faster responses than the
+/// maximum of 11
+const BROTLI_QUALITY: u32 = 5;
+
+/// Base-2 logarithm of the brotli window size
+const BROTLI_WINDOW: u32 = 22;
+
+/// Content codings that the server can compress responses with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Coding {
+    Brotli,
+    Gzip,
+}
+
+impl Coding {
+
+    /// Returns the coding preferred by the given `Accept-Encoding` header, favouring brotli
+    fn negotiate(accept_encoding: &str) -> Option<Self> {
+        let accepted = accept_encoding.split(',')
+            .filter_map(|coding| {
+                let mut params = coding.split(';');
+                let name = params.next()?.trim().to_ascii_lowercase();
+                let rejected = params
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .any(|q| q.trim().parse::<f32>().is_ok_and(|q| q <= 0.0));
+
+                if rejected { None } else { Some(name) }
+            })
+            .collect::<Vec<_>>();
+
+        if accepted.iter().any(|name| name == "br") {
+            Some(Coding::Brotli)
+        } else if accepted.iter().any(|name| name == "gzip" || name == "*") {
+            Some(Coding::Gzip)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the name of this coding in the `Content-Encoding` header
+    fn name(&self) -> &'static str {
+        match self {
+            Coding::Brotli => "br",
+            Coding::Gzip => "gzip",
+        }
+    }
+
+    /// Compresses the given data with this coding
+    fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Coding::Brotli => {
+                let mut compressed = Vec::new();
+                {
+                    let mut writer = brotli::CompressorWriter::new(
+                        &mut compressed, 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+                    writer.write_all(data).ok()?;
+                }
+                Some(compressed)
+            }
+            Coding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).ok()?;
+                encoder.finish().ok()
+            }
+        }
+    }
+}
+
+/// Fairing that compresses json, plain text, MessagePack and CBOR responses with brotli or gzip
+/// when the `Accept-Encoding` header of the request allows it
+///
+/// Attach it after the other response fairings so that it compresses their final bodies.
+pub struct Compression;
+
+#[rocket::async_trait]
+impl Fairing for Compression {
+    fn info(&self) -> Info {
+        Info {
+            name: "Brotli and Gzip Compression",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let compressible = response.content_type().is_some_and(|content_type| {
+            content_type.is_json() || content_type.is_text() || content_type.is_msgpack()
+                || content_type.sub().as_str().eq_ignore_ascii_case("cbor")
+        });
+        if !compressible || response.headers().contains("Content-Encoding") {
+            return;
+        }
+
+        response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+
+        let coding = request.headers().get("Accept-Encoding")
+            .collect::<Vec<_>>()
+            .join(",");
+        let coding = match Coding::negotiate(&coding) {
+            Some(coding) => coding,
+            None => return,
+        };
+
+        let data = match response.body_mut().to_bytes().await {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+
+        let compressed = Some(&data)
+            .filter(|data| data.len() >= MIN_SIZE)
+            .and_then(|data| coding.compress(data));
+
+        match compressed {
+            Some(compressed) => {
+                response.set_header(Header::new("Content-Encoding", coding.name()));
+                response.set_sized_body(compressed.len(), Cursor::new(compressed));
+            }
+            None => response.set_sized_body(data.len(), Cursor::new(data)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use crate::compression::Coding;
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(Coding::negotiate("gzip, deflate, br"), Some(Coding::Brotli));
+        assert_eq!(Coding::negotiate("gzip;q=1.0, br;q=0"), Some(Coding::Gzip));
+        assert_eq!(Coding::negotiate("*"), Some(Coding::Gzip));
+        assert_eq!(Coding::negotiate("identity"), None);
+        assert_eq!(Coding::negotiate(""), None);
+    }
+
+    #[test]
+    fn compress() {
+        let data = "[\"2,3\",\"2,5\",\"4,5\"]".repeat(100).into_bytes();
+
+        let compressed = Coding::Gzip.compress(&data).unwrap();
+        assert!(compressed.len() < data.len());
+        let mut decompressed = Vec::new();
+        GzDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, data);
+
+        let compressed = Coding::Brotli.compress(&data).unwrap();
+        assert!(compressed.len() < data.len());
+        let mut decompressed = Vec::new();
+        brotli::Decompressor::new(compressed.as_slice(), 4096)
+            .read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, data);
+    }
+}
@@ -0,0 +1,377 @@
+use rocket::figment::Figment;
+use rocket::figment::providers::{Env, Serialized};
+use rocket::serde::Deserialize;
+
+use crate::bot::{CutPair, DEFAULT_CACHE_SIZE};
+use crate::errors::Error;
+use crate::errors::Error::InvalidArgument;
+use crate::game::{self, Weights};
+use crate::probcut;
+use crate::session::ReplyPace;
+
+/// Prefix of the environment variables overriding the settings of the engine, such as
+/// `OTHELLO_MAX_DEPTH` for `max_intelligence`
+const ENV_PREFIX: &str = "OTHELLO_";
+
+/// Names of the environment variables, without the prefix, that differ from their settings
+const ENV_ALIASES: [(&str, &str); 1] = [("max_depth", "max_intelligence")];
+
+/// Limits of Rocket on the bodies that the routes read, which `max_body_bytes` sets
+const BODY_LIMITS: [&str; 6] = ["bytes", "data-form", "file", "form", "json", "string"];
+
+/// Settings of the engine loaded from the `engine` section of Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct EngineConfig {
+    /// Maximum time in milliseconds a single /decide request may spend searching
+    pub max_thinking_time_ms: u64,
+
+    /// Maximum time in milliseconds a search may wait for a free thread of the engine before its
+    /// request fails as busy
+    pub max_queue_ms: u64,
+
+    /// Minimum intelligence a request may ask the bot to search with
+    pub min_intelligence: u32,
+
+    /// Maximum intelligence a request may ask the bot to search with
+    pub max_intelligence: u32,
+
+    /// Whether requests outside the range of intelligences are clamped to it instead of rejected
+    pub clamp_intelligence: bool,
+
+    /// Largest number of nodes a /decide request may budget, which is the only bound of its
+    /// search, since a node budget lifts the deadline for the sake of reproducible decisions
+    pub max_nodes: u32,
+
+    /// Whether the bot shuffles the actions it decides between, so that it varies its moves
+    /// among equally good ones from one game to another
+    pub shuffle_root_moves: bool,
+
+    /// Longest query string in bytes a request may have
+    pub max_query_bytes: usize,
+
+    /// Largest body in bytes a request may send, whether it declares its length or is chunked
+    pub max_body_bytes: u64,
+
+    /// Whether boards that cannot come from a game are rejected instead of analysed with a
+    /// warning
+    pub reject_impossible_boards: bool,
+
+    /// Paces at which the bots of game sessions reply by intelligence, where the first paces the
+    /// bots of intelligence 1 and the last any stronger bot, so that weak bots do not answer
+    /// instantly, and where none lets every bot reply as soon as it decides
+    pub reply_pacing: Vec<ReplyPace>,
+
+    /// Default largest difference from an even game, in win probability, that the bot of an
+    /// adaptive game session lets the game drift to before it changes its strength
+    pub adaptive_band: f64,
+
+    /// Default number of moves the human may take back in a game session
+    pub max_takebacks: u32,
+
+    /// Seconds after its last request at which a game session is saved and forgotten
+    pub session_idle_secs: u64,
+
+    /// Seconds after its creation at which a game session is saved and forgotten, however active
+    /// it is
+    pub session_lifetime_secs: u64,
+
+    /// Seconds between two sweeps of the expired game sessions
+    pub session_sweep_secs: u64,
+
+    /// Seconds the records of the expired game sessions are kept in the storage
+    pub session_retention_secs: u64,
+
+    /// Memory in bytes the caches of the bots kept between the moves of game sessions may take
+    /// together, which bounds how many sessions keep their bot
+    pub session_memory_bytes: usize,
+
+    /// Directory where the accounts and games are stored
+    pub storage_dir: String,
+
+    /// Token required by the admin routes, which are disabled when it is missing
+    pub admin_token: Option<String>,
+
+    /// Secret used to sign the results posted to the callbacks of jobs, which are unsigned when
+    /// it is missing
+    pub webhook_secret: Option<String>,
+
+    /// Depth at which the positions of self-play games are searched for puzzles
+    pub puzzle_depth: u32,
+
+    /// Minimum score difference between the best and the second best action of a puzzle
+    pub puzzle_min_gap: i32,
+
+    /// Number of plies of each imported game that are added to the opening book
+    pub book_plies: usize,
+
+    /// Minimum number of games in which a book move must have been played to be chosen
+    pub book_min_games: u32,
+
+    /// File of the XOT openings that sessions and tournaments may start from, which are
+    /// unavailable when it is missing
+    pub xot_path: Option<String>,
+
+    /// Weights the evaluation starts with, which those saved through the admin routes replace
+    pub weights: Option<Weights>,
+
+    /// Whether the weights are nudged towards the outcome of every finished rated game, within
+    /// a small distance of the weights the evaluation starts with
+    pub adapt_weights: bool,
+
+    /// Fraction of a step each finished rated game moves the weights by when they adapt
+    pub adaptation_rate: f64,
+
+    /// Depth pairs of Multi-ProbCut the searches start with, which those fitted through the admin
+    /// routes replace, where none lets the searches visit every node
+    pub probcut: Vec<CutPair>,
+
+    /// Number of standard deviations by which Multi-ProbCut must predict a search to fall outside
+    /// its window to prune it
+    pub probcut_confidence: f64,
+
+    /// WTHOR database imported into the opening book at startup while the book is empty
+    pub book_path: Option<String>,
+
+    /// Maximum number of evaluations shared between searches before they are forgotten
+    pub cache_size: usize,
+
+    /// Maximum number of evaluations saved on shutdown and restored on startup, where 0 keeps
+    /// the cache in memory only
+    pub persisted_cache_size: usize,
+
+    /// Maximum number of responses of the analysis endpoints kept in memory
+    pub analysis_cache_size: usize,
+
+    /// Maximum number of searches running at the same time, where 0 is the number of cores
+    pub threads: usize,
+
+    /// Origins from which browsers may call the API
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            max_thinking_time_ms: 5000,
+            max_queue_ms: 30_000,
+            min_intelligence: 1,
+            max_intelligence: 8,
+            clamp_intelligence: false,
+            max_nodes: 10_000_000,
+            shuffle_root_moves: true,
+            reject_impossible_boards: false,
+            max_query_bytes: 4096,
+            max_body_bytes: 1 << 20,
+            reply_pacing: Vec::new(),
+            adaptive_band: 0.2,
+            max_takebacks: 3,
+            session_idle_secs: 60 * 60,
+            session_lifetime_secs: 24 * 60 * 60,
+            session_sweep_secs: 60,
+            session_retention_secs: 30 * 24 * 60 * 60,
+            session_memory_bytes: 256 << 20,
+            storage_dir: "data".to_string(),
+            admin_token: None,
+            webhook_secret: None,
+            puzzle_depth: 3,
+            puzzle_min_gap: 30,
+            book_plies: 20,
+            book_min_games: 3,
+            xot_path: None,
+            weights: None,
+            adapt_weights: false,
+            adaptation_rate: 0.1,
+            probcut: Vec::new(),
+            probcut_confidence: 1.5,
+            book_path: None,
+            cache_size: DEFAULT_CACHE_SIZE,
+            persisted_cache_size: 0,
+            analysis_cache_size: 4096,
+            threads: 0,
+            allowed_origins: [
+                "http://localhost:443",
+                "https://localhost",
+                "https://localhost:80",
+                "http://localhost:8080",
+                "http://localhost",
+                "http://desdemona.seoulsky.org",
+                "http://desdemona.seoulsky.org:443",
+                "https://desdemona.seoulsky.org",
+            ].iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Layers the environment variables prefixed with `OTHELLO_` over the `engine` section of the
+/// given configuration
+fn with_env(figment: Figment) -> Figment {
+    figment.merge(Env::prefixed(ENV_PREFIX)
+        .map(|key| {
+            let key = ENV_ALIASES.iter()
+                .find(|(alias, _)| key == *alias)
+                .map_or(key.as_str(), |(_, setting)| setting);
+            format!("engine.{}", key).into()
+        })
+        .global())
+}
+
+/// Limits the bodies that Rocket reads to `max_body_bytes`, so that chunked bodies, which do not
+/// declare their length, cannot exceed it either
+fn with_limits(figment: Figment) -> Figment {
+    let max_body_bytes = figment.extract_inner::<u64>("engine.max_body_bytes")
+        .unwrap_or(EngineConfig::default().max_body_bytes);
+    BODY_LIMITS.iter().fold(figment, |figment, limit| {
+        figment.merge(Serialized::global(&format!("limits.{}", limit), max_body_bytes))
+    })
+}
+
+/// Returns the configuration of the server, whose engine settings can be overridden by the
+/// environment so that deployments can change them without rebuilding their images
+pub fn figment() -> Figment {
+    with_limits(with_env(rocket::Config::figment()))
+}
+
+impl EngineConfig {
+
+    /// Checks that the settings allow the engine to search and to play from its book
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.max_thinking_time_ms == 0 {
+            return Err(InvalidArgument("max_thinking_time_ms must be positive".to_string()));
+        }
+        if self.max_queue_ms == 0 {
+            return Err(InvalidArgument("max_queue_ms must be positive".to_string()));
+        }
+        if self.max_intelligence == 0 {
+            return Err(InvalidArgument("max_intelligence must be positive".to_string()));
+        }
+        if self.max_nodes == 0 {
+            return Err(InvalidArgument("max_nodes must be positive".to_string()));
+        }
+        if self.min_intelligence > self.max_intelligence {
+            return Err(InvalidArgument(
+                "min_intelligence must not exceed max_intelligence".to_string()));
+        }
+        if self.max_query_bytes == 0 || self.max_body_bytes == 0 {
+            return Err(InvalidArgument("The sizes of requests must be positive".to_string()));
+        }
+        if self.puzzle_depth == 0 {
+            return Err(InvalidArgument("puzzle_depth must be positive".to_string()));
+        }
+        if self.book_min_games == 0 {
+            return Err(InvalidArgument("book_min_games must be positive".to_string()));
+        }
+        if self.session_idle_secs == 0 || self.session_lifetime_secs == 0 {
+            return Err(InvalidArgument("The lifetimes of sessions must be positive".to_string()));
+        }
+        if self.session_sweep_secs == 0 {
+            return Err(InvalidArgument("session_sweep_secs must be positive".to_string()));
+        }
+        if self.session_retention_secs == 0 {
+            return Err(InvalidArgument("session_retention_secs must be positive".to_string()));
+        }
+        if self.session_memory_bytes == 0 {
+            return Err(InvalidArgument("session_memory_bytes must be positive".to_string()));
+        }
+        if !(self.adaptive_band > 0.0 && self.adaptive_band < 0.5) {
+            return Err(InvalidArgument("adaptive_band must be between 0 and 0.5".to_string()));
+        }
+        if !(self.adaptation_rate > 0.0 && self.adaptation_rate <= 1.0) {
+            return Err(InvalidArgument("adaptation_rate must be between 0 and 1".to_string()));
+        }
+        if !(self.probcut_confidence > 0.0 && self.probcut_confidence.is_finite()) {
+            return Err(InvalidArgument("probcut_confidence must be positive".to_string()));
+        }
+        probcut::validate(&self.probcut)?;
+        if self.cache_size == 0 {
+            return Err(InvalidArgument("cache_size must be positive".to_string()));
+        }
+        if self.analysis_cache_size == 0 {
+            return Err(InvalidArgument("analysis_cache_size must be positive".to_string()));
+        }
+        if let Some(weights) = &self.weights {
+            game::validate_weights(weights)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::Jail;
+    use rocket::figment::Figment;
+    use rocket::figment::providers::{Format, Toml};
+
+    use crate::config::{with_env, with_limits, EngineConfig, BODY_LIMITS};
+    use crate::game::Weights;
+
+    #[test]
+    fn load_config() {
+        let figment = Figment::from(Toml::string(r#"
+            [engine]
+            max_intelligence = 6
+            threads = 2
+            weights = { placement = [5, 4, 2], mobility = [5, 4, 3], num_disks = [-1, -1, 0] }
+        "#));
+        let config: EngineConfig = figment.extract_inner("engine").unwrap();
+
+        assert_eq!(config.max_intelligence, 6);
+        assert_eq!(config.threads, 2);
+        assert_eq!(config.weights, Some(Weights::default()));
+        assert_eq!(config.max_thinking_time_ms, EngineConfig::default().max_thinking_time_ms);
+        assert!(config.validate().is_ok());
+
+        let invalid = EngineConfig {
+            weights: Some(Weights {
+                placement: [-1; 3],
+                mobility: [0; 3],
+                num_disks: [-1; 3],
+            }),
+            ..EngineConfig::default()
+        };
+        assert!(invalid.validate().is_err());
+        assert!(EngineConfig { cache_size: 0, ..EngineConfig::default() }.validate().is_err());
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn env_overrides() {
+        // The jail removes the variables afterwards so that they do not reach the other tests
+        Jail::expect_with(|jail| {
+            jail.set_env("OTHELLO_MAX_DEPTH", "4");
+            jail.set_env("OTHELLO_ALLOWED_ORIGINS", r#"["https://example.com"]"#);
+
+            let figment = with_env(Figment::from(Toml::string(r#"
+                [engine]
+                max_intelligence = 6
+                threads = 2
+            "#)));
+            let config: EngineConfig = figment.extract_inner("engine")?;
+
+            assert_eq!(config.max_intelligence, 4);
+            assert_eq!(config.threads, 2);
+            assert_eq!(config.allowed_origins, vec!["https://example.com".to_string()]);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn body_limits() {
+        let figment = with_limits(Figment::from(rocket::Config::default()).merge(Toml::string(r#"
+            [engine]
+            max_body_bytes = 100
+        "#)));
+        let config: rocket::Config = figment.extract().unwrap();
+
+        for limit in BODY_LIMITS {
+            assert_eq!(config.limits.get(limit).unwrap().as_u64(), 100);
+        }
+
+        let config: rocket::Config = with_limits(Figment::from(rocket::Config::default()))
+            .extract().unwrap();
+        assert_eq!(config.limits.get("string").unwrap().as_u64(),
+                   EngineConfig::default().max_body_bytes);
+    }
+}
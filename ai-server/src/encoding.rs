@@ -0,0 +1,114 @@
+use std::io::Cursor;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header, MediaType};
+use rocket::{Request, Response};
+use serde_json::Value;
+
+/// Binary formats that a client may accept instead of json
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    MessagePack,
+    Cbor,
+}
+
+impl Format {
+
+    /// Returns the format of the given media type, if it is a binary format
+    fn of(media_type: &MediaType) -> Option<Self> {
+        match (media_type.top().as_str(), media_type.sub().as_str()) {
+            ("application", "msgpack") | ("application", "x-msgpack") => Some(Format::MessagePack),
+            ("application", "cbor") => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Returns the content type of this format
+    fn content_type(&self) -> ContentType {
+        match self {
+            Format::MessagePack => ContentType::new("application", "msgpack"),
+            Format::Cbor => ContentType::new("application", "cbor"),
+        }
+    }
+
+    /// Encodes the given json value in this format
+    fn encode(&self, value: &Value) -> Option<Vec<u8>> {
+        match self {
+            Format::MessagePack => rmp_serde::to_vec_named(value).ok(),
+            Format::Cbor => {
+                let mut data = Vec::new();
+                ciborium::ser::into_writer(value, &mut data).ok().map(|_| data)
+            }
+        }
+    }
+}
+
+/// Fairing that re-encodes json responses as MessagePack or CBOR when the `Accept` header of
+/// the request prefers them
+pub struct BinaryEncoding;
+
+#[rocket::async_trait]
+impl Fairing for BinaryEncoding {
+    fn info(&self) -> Info {
+        Info {
+            name: "MessagePack and CBOR Encoding",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let format = request.accept()
+            .and_then(|accept| Format::of(accept.preferred().media_type()));
+        let format = match format {
+            Some(format) => format,
+            None => return,
+        };
+
+        response.adjoin_header(Header::new("Vary", "Accept"));
+
+        // Streams such as the events of a session never end, so they cannot be re-encoded
+        if response.content_type().is_some_and(|content_type| content_type.is_event_stream())
+            || response.body().is_none() {
+            return;
+        }
+
+        let data = match response.body_mut().to_bytes().await {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+
+        let encoded = serde_json::from_slice::<Value>(&data).ok()
+            .and_then(|value| format.encode(&value));
+
+        match encoded {
+            Some(encoded) => {
+                response.set_header(format.content_type());
+                response.set_sized_body(encoded.len(), Cursor::new(encoded));
+            }
+            // Plain text such as error messages is left as it is
+            None => response.set_sized_body(data.len(), Cursor::new(data)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::MediaType;
+    use serde_json::{json, Value};
+
+    use crate::encoding::Format;
+
+    #[test]
+    fn encode() {
+        let value = json!({ "board": "EEE", "dark": 2, "actions": ["2,3"] });
+
+        let data = Format::MessagePack.encode(&value).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Value>(&data).unwrap(), value);
+
+        let data = Format::Cbor.encode(&value).unwrap();
+        assert_eq!(ciborium::de::from_reader::<Value, _>(data.as_slice()).unwrap(), value);
+
+        assert_eq!(Format::of(&MediaType::new("application", "cbor")), Some(Format::Cbor));
+        assert_eq!(Format::of(&MediaType::JSON), None);
+    }
+}
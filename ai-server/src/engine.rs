@@ -0,0 +1,315 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rocket::tokio::sync::{Semaphore, SemaphorePermit};
+use rocket::tokio::task::spawn_blocking;
+use rocket::tokio::time::timeout;
+use rocket::{Route, State};
+use serde_json::{json, Value};
+
+use crate::admin::Admin;
+use crate::book::OpeningBook;
+use crate::bot::{Bot, CancellationToken, CutPair, EvaluationCache, ProbCut};
+use crate::config::EngineConfig;
+use crate::errors::Error;
+use crate::errors::Error::EngineBusy;
+use crate::game::{self, Variant};
+use crate::probability::WinModel;
+use crate::storage::Storage;
+
+const CACHE: &str = "cache";
+const EVALUATIONS_KEY: &str = "evaluations";
+
+/// Resources of the engine shared by every request, which are created once at startup
+///
+/// Bots created from the engine share its evaluation cache, and their searches run on the
+/// blocking thread pool with at most as many searches at a time as the configured threads.
+#[derive(Clone)]
+pub struct Engine {
+    config: EngineConfig,
+    cancellation: CancellationToken,
+    cache: EvaluationCache,
+    threads: usize,
+    searches: Arc<Semaphore>,
+    probcut: Arc<RwLock<Option<ProbCut>>>,
+}
+
+impl Engine {
+
+    /// Creates the resources of the engine with the given settings
+    pub fn new(config: EngineConfig, cancellation: CancellationToken) -> Self {
+        let threads = match config.threads {
+            0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            threads => threads,
+        };
+        let engine = Self {
+            cache: EvaluationCache::with_size(config.cache_size),
+            threads,
+            searches: Arc::new(Semaphore::new(threads)),
+            probcut: Arc::default(),
+            config,
+            cancellation,
+        };
+        engine.set_probcut(engine.config.probcut.clone());
+        engine
+    }
+
+    /// Returns the settings of the engine
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Returns the number of searches the engine runs at a time
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Creates a bot whose searches are bounded by the server's limits
+    pub fn bot(&self, intelligence: u32) -> Bot {
+        let mut bot = Bot::new(intelligence);
+        bot.set_cache(self.cache.clone());
+        bot.set_cancellation(self.cancellation.clone());
+        bot.set_deadline(Instant::now() + Duration::from_millis(self.config.max_thinking_time_ms));
+        bot.set_root_seed(self.config.shuffle_root_moves.then(rand::random));
+        bot.set_probcut(self.probcut());
+        bot.set_timing(true);
+        bot
+    }
+
+    /// Returns the settings of Multi-ProbCut of the bots created from now on, if they prune
+    pub fn probcut(&self) -> Option<ProbCut> {
+        self.probcut.read().unwrap().clone()
+    }
+
+    /// Replaces the depth pairs of Multi-ProbCut of the bots created from now on, where none
+    /// lets them search every node
+    pub fn set_probcut(&self, pairs: Vec<CutPair>) {
+        *self.probcut.write().unwrap() = (!pairs.is_empty()).then_some(ProbCut {
+            pairs,
+            confidence: self.config.probcut_confidence,
+        });
+    }
+
+    /// Creates a bot with a cache of its own, which keeps what it learns about one game from
+    /// one move to the next
+    pub fn session_bot(&self, intelligence: u32, cache_size: usize) -> Bot {
+        let mut bot = self.bot(intelligence);
+        bot.set_cache(EvaluationCache::with_size(cache_size));
+        bot
+    }
+
+    /// Prepares the given bot kept between requests for a new search within the server's limits
+    pub fn rearm(&self, bot: &mut Bot) {
+        bot.set_cancellation(self.cancellation.clone());
+        bot.set_deadline(Instant::now() + Duration::from_millis(self.config.max_thinking_time_ms));
+    }
+
+    /// Waits for one of the threads of the engine to be free, failing once the configured time
+    /// is up
+    async fn acquire(&self) -> Result<SemaphorePermit<'_>, Error> {
+        timeout(Duration::from_millis(self.config.max_queue_ms), self.searches.acquire()).await
+            .map_err(|_| EngineBusy("Every thread of the engine is searching".to_string()))
+            .map(|permit| permit.expect("Search pool closed"))
+    }
+
+    /// Runs the given search once one of the threads of the engine is free
+    ///
+    /// The search is CPU-bound, so keep it off the async executor serving the other requests
+    pub async fn run<T, F>(&self, search: F) -> Result<T, Error>
+        where T: Send + 'static, F: FnOnce() -> T + Send + 'static {
+        let _permit = self.acquire().await?;
+        Ok(spawn_blocking(search).await.expect("Search task panicked"))
+    }
+
+    /// Runs a search of a new bot with the given intelligence once one of the threads of the
+    /// engine is free
+    ///
+    /// Unlike `run`, the thinking time of the bot starts when the search does, so that searches
+    /// queued behind others are not cut short.
+    pub async fn search<T, F>(&self, intelligence: u32, search: F) -> Result<T, Error>
+        where T: Send + 'static, F: FnOnce(&mut Bot) -> T + Send + 'static {
+        let _permit = self.acquire().await?;
+        let mut bot = self.bot(intelligence);
+        Ok(spawn_blocking(move || search(&mut bot)).await.expect("Search task panicked"))
+    }
+
+    /// Forgets every cached evaluation, which must be done when the evaluation weights change
+    pub fn clear_cache(&self) {
+        self.cache.clear();
+    }
+
+    /// Restores the evaluations saved by `save_cache` in the given storage, returning how many
+    /// were restored
+    pub fn load_cache(&self, storage: &Storage) -> Result<usize, Error> {
+        match storage.load_bytes(CACHE, EVALUATIONS_KEY) {
+            Some(data) => self.cache.load(&data),
+            None => Ok(0),
+        }
+    }
+
+    /// Saves the evaluations, up to the configured limit, in the given storage
+    pub fn save_cache(&self, storage: &Storage) -> Result<(), Error> {
+        let data = self.cache.save(self.config.persisted_cache_size);
+        storage.save_bytes(CACHE, EVALUATIONS_KEY, &data)
+    }
+
+    /// Serializes the counters of the evaluation cache
+    pub fn cache_json(&self) -> Value {
+        let stats = self.cache.stats();
+        json!({
+            "hits": stats.hits,
+            "misses": stats.misses,
+            "hit_rate": stats.hit_rate(),
+            "evictions": stats.evictions,
+            "size": stats.len,
+            "capacity": stats.size,
+            "fill": stats.fill(),
+        })
+    }
+}
+
+/// Returns the resources of the engine and how much of them are in use
+#[get("/admin/engine")]
+fn get(_admin: Admin, engine: &State<Engine>) -> String {
+    json!({
+        "threads": engine.threads,
+        "searches": engine.threads - engine.searches.available_permits(),
+        "min_intelligence": engine.config.min_intelligence,
+        "max_intelligence": engine.config.max_intelligence,
+        "max_thinking_time_ms": engine.config.max_thinking_time_ms,
+        "cache": engine.cache_json(),
+    }).to_string()
+}
+
+/// Returns the version of the server and how the engine plays, so that the bots of two
+/// deployments can be told apart
+///
+/// The commit is the one given by the `DESDEMONA_COMMIT` environment variable at build time.
+#[get("/engine")]
+fn describe(engine: &State<Engine>, book: &State<OpeningBook>, model: &State<WinModel>)
+    -> String {
+    let config = &engine.config;
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "commit": option_env!("DESDEMONA_COMMIT").filter(|commit| !commit.is_empty()),
+        "profile": if cfg!(debug_assertions) { "debug" } else { "release" },
+        "evaluator": {
+            "terms": ["placement", "mobility", "num_disks"],
+            "weights": game::weights(),
+            "anti_weights": Variant::Anti.weights(),
+            "adapt_weights": config.adapt_weights,
+            "win_model_slopes": (0..3).map(|phase| model.slope(phase)).collect::<Vec<_>>(),
+        },
+        "probcut": engine.probcut().map(|probcut| json!({
+            "pairs": probcut.pairs,
+            "confidence": probcut.confidence,
+        })),
+        "limits": {
+            "min_intelligence": config.min_intelligence,
+            "max_intelligence": config.max_intelligence,
+            "clamp_intelligence": config.clamp_intelligence,
+            "max_thinking_time_ms": config.max_thinking_time_ms,
+        },
+        "book": {
+            "positions": book.num_positions(),
+            "updated_at": book.updated_at(),
+            "plies": config.book_plies,
+            "min_games": config.book_min_games,
+        },
+        "cache": engine.cache_json(),
+        "threads": engine.threads,
+    }).to_string()
+}
+
+/// Returns the routes of the introspection of the engine
+pub fn routes() -> Vec<Route> {
+    routes![get, describe]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    use crate::bot::CancellationToken;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+    use crate::errors::Error;
+    use crate::game::Game;
+
+    #[rocket::async_test]
+    async fn shared_cache() {
+        let engine = Engine::new(EngineConfig::default(), CancellationToken::new());
+
+        let mut bot = engine.bot(3);
+        assert!(engine.run(move || bot.decide(&Game::new())).await.unwrap().is_ok());
+        let evaluated = engine.cache.len();
+        assert!(evaluated > 0);
+
+        // The second bot finds the evaluations of the first
+        let mut bot = engine.bot(3);
+        assert!(engine.run(move || bot.decide(&Game::new())).await.unwrap().is_ok());
+        assert!(engine.cache.len() >= evaluated);
+
+        let stats = engine.cache.stats();
+        assert!(stats.hits > 0 && stats.misses > 0);
+        assert!(stats.hit_rate() > 0.0 && stats.hit_rate() < 1.0);
+
+        engine.clear_cache();
+        assert!(engine.cache.is_empty());
+    }
+
+    #[rocket::async_test]
+    async fn busy() {
+        let config = EngineConfig { threads: 1, max_queue_ms: 10, ..EngineConfig::default() };
+        let engine = Engine::new(config, CancellationToken::new());
+
+        // A search waiting longer than allowed for the only thread fails instead of queueing
+        let running = engine.clone();
+        let search = rocket::tokio::spawn(async move {
+            running.run(|| std::thread::sleep(Duration::from_millis(500))).await
+        });
+        rocket::tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(matches!(engine.run(|| ()).await, Err(Error::EngineBusy(_))));
+        assert!(search.await.unwrap().is_ok());
+        assert!(engine.run(|| ()).await.is_ok());
+    }
+
+    #[rocket::async_test]
+    async fn persist_cache() {
+        let engine = Engine::new(EngineConfig::default(), CancellationToken::new());
+        let mut bot = engine.bot(3);
+        assert!(engine.run(move || bot.decide(&Game::new())).await.unwrap().is_ok());
+
+        let evaluated = engine.cache.len();
+        let saved = engine.cache.save(usize::MAX);
+        let limited = engine.cache.save(10);
+        engine.clear_cache();
+
+        assert_eq!(engine.cache.load(&saved).unwrap(), evaluated);
+        assert_eq!(engine.cache.len(), evaluated);
+        engine.clear_cache();
+        assert_eq!(engine.cache.load(&limited).unwrap(), 10);
+
+        let mut other_version = saved.clone();
+        other_version[4] += 1;
+        assert!(engine.cache.load(&other_version).is_err());
+        assert!(engine.cache.load(&saved[..saved.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn shuffle_root_moves() {
+        // The four first moves are equally good, so each seed may pick a different one
+        let decisions = |shuffle_root_moves| {
+            let engine = Engine::new(EngineConfig { shuffle_root_moves, ..EngineConfig::default() },
+                                     CancellationToken::new());
+            (0..20).map(|_| engine.bot(2).decide(&Game::new()).unwrap().0.to_string())
+                .collect::<HashSet<_>>()
+        };
+
+        assert!(decisions(true).len() > 1);
+        assert_eq!(decisions(false).len(), 1);
+    }
+}
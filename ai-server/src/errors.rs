@@ -1,5 +1,77 @@
-#[derive(Debug)]
+#[cfg(feature = "server")]
+use rocket::http::Status;
+#[cfg(feature = "server")]
+use rocket::request::Request;
+#[cfg(feature = "server")]
+use rocket::response::{self, status::Custom, Responder};
+
+#[derive(Debug, thiserror::Error)]
 pub enum Error {
+    #[error("{0}")]
     InvalidArgument(String),
-    ParseError(String)
+    #[error("{0}")]
+    ParseError(String),
+    #[error("{0}")]
+    StorageError(String),
+    /// The action is not one the player may take in the state of the game
+    #[error("{0}")]
+    IllegalMove(String),
+    /// The value is well-formed but outside the range the engine or the server accepts
+    #[error("{0}")]
+    OutOfRange(String),
+    /// Every thread of the engine is searching
+    #[error("{0}")]
+    EngineBusy(String),
+    /// The work did not finish in the time it was given
+    #[error("{0}")]
+    Timeout(String),
+}
+
+#[cfg(feature = "server")]
+impl Error {
+
+    /// Returns the HTTP status of the responses to requests that fail with this error
+    pub fn status(&self) -> Status {
+        match self {
+            Error::InvalidArgument(_) | Error::ParseError(_) | Error::IllegalMove(_) => {
+                Status::BadRequest
+            }
+            Error::OutOfRange(_) => Status::UnprocessableEntity,
+            Error::StorageError(_) => Status::InternalServerError,
+            Error::EngineBusy(_) => Status::ServiceUnavailable,
+            Error::Timeout(_) => Status::GatewayTimeout,
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<Error> for Custom<String> {
+    fn from(error: Error) -> Self {
+        Custom(error.status(), error.to_string())
+    }
+}
+
+#[cfg(feature = "server")]
+impl<'r> Responder<'r, 'static> for Error {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        Custom::from(self).respond_to(request)
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use rocket::http::Status;
+    use rocket::response::status::Custom;
+
+    use crate::errors::Error;
+
+    #[test]
+    fn status() {
+        let Custom(status, message) = Custom::from(Error::IllegalMove("Illegal move".into()));
+        assert_eq!((status, message.as_str()), (Status::BadRequest, "Illegal move"));
+        assert_eq!(Error::OutOfRange(String::new()).status(), Status::UnprocessableEntity);
+        assert_eq!(Error::StorageError(String::new()).status(), Status::InternalServerError);
+        assert_eq!(Error::EngineBusy(String::new()).status(), Status::ServiceUnavailable);
+        assert_eq!(Error::Timeout(String::new()).status(), Status::GatewayTimeout);
+    }
 }
@@ -0,0 +1,105 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::response::{self, Responder};
+use rocket::{Request, Response};
+
+/// Body of a response that only depends on the request, which clients and proxies may cache
+///
+/// The response carries a weak ETag of the body and its type, and is answered with 304 Not
+/// Modified when the request already has the body with the same ETag. The ETag is weak, since
+/// the server may compress the body in the encoding the request accepts.
+pub struct Cached {
+    body: Vec<u8>,
+    content_type: ContentType,
+    max_age: u32,
+}
+
+impl Cached {
+
+    /// Creates a response with the given body that may be cached for the given seconds
+    pub fn new(body: String, max_age: u32) -> Self {
+        Self::binary(body.into_bytes(), ContentType::Plain, max_age)
+    }
+
+    /// Creates a response with the given body of the given type that may be cached for the given
+    /// seconds
+    pub fn binary(body: Vec<u8>, content_type: ContentType, max_age: u32) -> Self {
+        Self {
+            body,
+            content_type,
+            max_age,
+        }
+    }
+
+    /// Returns the weak ETag of the body and its type
+    fn etag(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.body.hash(&mut hasher);
+        self.content_type.to_string().hash(&mut hasher);
+        format!("W/\"{:x}\"", hasher.finish())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Cached {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let etag = self.etag();
+        // The weak comparison of the ETags ignores whether they are weak
+        let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+        let not_modified = request.headers().get("If-None-Match")
+            .flat_map(|tags| tags.split(','))
+            .any(|tag| opaque(tag) == opaque(&etag) || tag.trim() == "*");
+
+        let mut response = if not_modified {
+            Response::build().status(Status::NotModified).finalize()
+        } else {
+            (self.content_type, self.body).respond_to(request)?
+        };
+
+        response.set_header(Header::new("ETag", etag));
+        response.set_header(Header::new("Vary", "Accept, Accept-Encoding"));
+        response.set_header(Header::new("Cache-Control", format!("public, max-age={}",
+                                                                 self.max_age)));
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::asynchronous::{Client, LocalRequest};
+    use rocket::response::Responder;
+
+    use crate::etag::Cached;
+
+    #[test]
+    fn etag() {
+        let etag = Cached::new("a".to_string(), 0).etag();
+        assert!(etag.starts_with("W/\"") && etag.ends_with('"'));
+        assert_eq!(etag, Cached::new("a".to_string(), 60).etag());
+        assert_ne!(etag, Cached::new("b".to_string(), 0).etag());
+        assert_ne!(etag, Cached::binary(b"a".to_vec(), ContentType::JSON, 0).etag());
+    }
+
+    #[rocket::async_test]
+    async fn not_modified() {
+        let client = Client::untracked(rocket::custom(rocket::Config::debug_default())).await
+            .unwrap();
+        let respond = |request: LocalRequest| {
+            Cached::new("a".to_string(), 60).respond_to(request.inner()).unwrap()
+        };
+        let etag = Cached::new("a".to_string(), 0).etag();
+
+        let response = respond(client.get("/"));
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("ETag"), Some(etag.as_str()));
+        assert_eq!(response.headers().get_one("Vary"), Some("Accept, Accept-Encoding"));
+
+        // The ETags are compared whether or not the request marks them as weak
+        for tag in [etag.clone(), etag.trim_start_matches("W/").to_string()] {
+            let response = respond(client.get("/").header(Header::new("If-None-Match", tag)));
+            assert_eq!(response.status(), Status::NotModified);
+        }
+    }
+}
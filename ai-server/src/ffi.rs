@@ -0,0 +1,296 @@
+//! C API of the engine, whose header is generated into `include/desdemona.h`
+//!
+//! Ownership rules:
+//! * Games returned by `desdemona_game_new`, `desdemona_game_parse` and `desdemona_game_clone`
+//!   belong to the caller, who must free each of them exactly once with `desdemona_game_free`
+//! * Strings returned by the API belong to the caller, who must free them with
+//!   `desdemona_string_free`
+//! * No function keeps the pointers it is given after it returns, and a game must not be used
+//!   by several threads at the same time
+//!
+//! Functions returning `int` answer 0 on success and a negative value on failure.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+
+use crate::board::{Board, Position, BOARD_SIZE};
+use crate::bot::Bot;
+use crate::game::{Action, Game, Player};
+
+/// Returned when an argument is null or invalid
+pub const DESDEMONA_INVALID_ARGUMENT: c_int = -1;
+
+/// Returned when the move is not allowed in the game
+pub const DESDEMONA_ILLEGAL_MOVE: c_int = -2;
+
+/// Returned when the player to move has no move
+pub const DESDEMONA_NO_MOVES: c_int = -3;
+
+/// Opaque handle of a game state
+pub struct DesdemonaGame(Game);
+
+/// Square of the board, counted from the top left corner
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DesdemonaMove {
+    pub row: u8,
+    pub col: u8,
+}
+
+impl From<&Position> for DesdemonaMove {
+    fn from(pos: &Position) -> Self {
+        Self {
+            row: pos.row() as u8,
+            col: pos.col() as u8,
+        }
+    }
+}
+
+/// Moves the given game onto the heap, handing its ownership to the caller
+fn into_handle(game: Game) -> *mut DesdemonaGame {
+    Box::into_raw(Box::new(DesdemonaGame(game)))
+}
+
+/// Creates a game at the initial board with the human to move
+#[no_mangle]
+pub extern "C" fn desdemona_game_new() -> *mut DesdemonaGame {
+    into_handle(Game::parse(Board::new(), Player::Human))
+}
+
+/// Creates a game from the given board, written as rows of `E`, `D` and `L` joined by newlines,
+/// and the player to move, `H` for the human with the dark disks or `B` for the bot with the
+/// light disks
+///
+/// Returns null when the board or the player is invalid.
+///
+/// # Safety
+/// `board` must be null or point to a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn desdemona_game_parse(board: *const c_char,
+                                              player: c_char) -> *mut DesdemonaGame {
+    if board.is_null() {
+        return ptr::null_mut();
+    }
+    let board = match CStr::from_ptr(board).to_str() {
+        Ok(board) if board.lines().count() == BOARD_SIZE
+            && board.lines().all(|row| row.chars().count() == BOARD_SIZE) => board,
+        _ => return ptr::null_mut(),
+    };
+    let board = match Board::parse(board.to_string()) {
+        Ok(board) => board,
+        Err(_) => return ptr::null_mut(),
+    };
+    match Player::parse(player as u8 as char) {
+        Ok(player) => into_handle(Game::parse(board, player)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Creates a copy of the given game, or returns null when it is null
+///
+/// # Safety
+/// `game` must be null or a live handle returned by this API.
+#[no_mangle]
+pub unsafe extern "C" fn desdemona_game_clone(game: *const DesdemonaGame) -> *mut DesdemonaGame {
+    match game.as_ref() {
+        Some(game) => into_handle(game.0.clone()),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Frees the given game, doing nothing when it is null
+///
+/// # Safety
+/// `game` must be null or a live handle returned by this API, which must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn desdemona_game_free(game: *mut DesdemonaGame) {
+    if !game.is_null() {
+        drop(Box::from_raw(game));
+    }
+}
+
+/// Returns the player to move, `H` or `B`, or 0 when the game is null
+///
+/// # Safety
+/// `game` must be null or a live handle returned by this API.
+#[no_mangle]
+pub unsafe extern "C" fn desdemona_game_current_player(game: *const DesdemonaGame) -> c_char {
+    game.as_ref().map_or(0, |game| game.0.current_player().to_string().as_bytes()[0] as c_char)
+}
+
+/// Returns 1 when the game is over, 0 when it is not and a negative value when it is null
+///
+/// # Safety
+/// `game` must be null or a live handle returned by this API.
+#[no_mangle]
+pub unsafe extern "C" fn desdemona_game_is_over(game: *const DesdemonaGame) -> c_int {
+    game.as_ref().map_or(DESDEMONA_INVALID_ARGUMENT, |game| game.0.is_over() as c_int)
+}
+
+/// Returns the board of the game in the format of `desdemona_game_parse`, or null when the game
+/// is null
+///
+/// # Safety
+/// `game` must be null or a live handle returned by this API.
+#[no_mangle]
+pub unsafe extern "C" fn desdemona_game_board(game: *const DesdemonaGame) -> *mut c_char {
+    game.as_ref()
+        .and_then(|game| CString::new(game.0.board().to_string()).ok())
+        .map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Frees the given string returned by this API, doing nothing when it is null
+///
+/// # Safety
+/// `s` must be null or a string returned by this API, which must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn desdemona_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Writes up to `capacity` moves of the player to move into `moves`, returning how many moves
+/// there are, which may exceed `capacity`, or a negative value when the game is null
+///
+/// # Safety
+/// `game` must be null or a live handle returned by this API, and `moves` must point to
+/// `capacity` writable moves unless `capacity` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn desdemona_game_moves(game: *const DesdemonaGame,
+                                              moves: *mut DesdemonaMove,
+                                              capacity: usize) -> c_int {
+    let game = match game.as_ref() {
+        Some(game) => &game.0,
+        None => return DESDEMONA_INVALID_ARGUMENT,
+    };
+    if moves.is_null() && capacity > 0 {
+        return DESDEMONA_INVALID_ARGUMENT;
+    }
+
+    let actions: Vec<Action> = game.actions(game.current_player()).collect();
+    for (i, action) in actions.iter().take(capacity).enumerate() {
+        *moves.add(i) = DesdemonaMove::from(action.placement());
+    }
+    actions.len() as c_int
+}
+
+/// Plays the given move for the player to move
+///
+/// # Safety
+/// `game` must be null or a live handle returned by this API.
+#[no_mangle]
+pub unsafe extern "C" fn desdemona_game_play(game: *mut DesdemonaGame,
+                                             placement: DesdemonaMove) -> c_int {
+    let game = match game.as_mut() {
+        Some(game) => &mut game.0,
+        None => return DESDEMONA_INVALID_ARGUMENT,
+    };
+
+    let player = game.current_player();
+    let action = Action::parse(player, Position::new(placement.row as usize,
+                                                     placement.col as usize));
+    match game.play(&action) {
+        Ok(result) => {
+            *game = result;
+            0
+        }
+        Err(_) => DESDEMONA_ILLEGAL_MOVE,
+    }
+}
+
+/// Passes the turn of the player to move, which is only allowed when they have no moves
+///
+/// # Safety
+/// `game` must be null or a live handle returned by this API.
+#[no_mangle]
+pub unsafe extern "C" fn desdemona_game_pass(game: *mut DesdemonaGame) -> c_int {
+    match game.as_mut() {
+        Some(game) if game.0.must_pass() => {
+            game.0 = game.0.pass();
+            0
+        }
+        Some(_) => DESDEMONA_ILLEGAL_MOVE,
+        None => DESDEMONA_INVALID_ARGUMENT,
+    }
+}
+
+/// Searches the best move of the player to move with the given intelligence and writes it into
+/// `placement`, without playing it
+///
+/// # Safety
+/// `game` must be null or a live handle returned by this API, and `placement` must be null or
+/// point to a writable move.
+#[no_mangle]
+pub unsafe extern "C" fn desdemona_game_decide(game: *const DesdemonaGame, intelligence: u32,
+                                               placement: *mut DesdemonaMove) -> c_int {
+    let (game, placement) = match (game.as_ref(), placement.as_mut()) {
+        (Some(game), Some(placement)) => (&game.0, placement),
+        _ => return DESDEMONA_INVALID_ARGUMENT,
+    };
+
+    let mut bot = Bot::new(intelligence);
+    let decision = match game.current_player() {
+        Player::Bot => bot.decide(game),
+        Player::Human => bot.hint(game),
+    };
+
+    match decision {
+        Ok((action, _)) => {
+            *placement = DesdemonaMove::from(action.placement());
+            0
+        }
+        Err(_) => DESDEMONA_NO_MOVES,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+
+    use crate::ffi::*;
+
+    #[test]
+    fn game() {
+        unsafe {
+            let game = desdemona_game_new();
+            assert_eq!(desdemona_game_current_player(game), b'H' as c_char);
+            assert_eq!(desdemona_game_is_over(game), 0);
+
+            assert_eq!(desdemona_game_moves(game, ptr::null_mut(), 0), 4);
+            let mut moves = [DesdemonaMove::default(); 2];
+            assert_eq!(desdemona_game_moves(game, moves.as_mut_ptr(), moves.len()), 4);
+
+            assert_eq!(desdemona_game_play(game, DesdemonaMove { row: 0, col: 0 }),
+                       DESDEMONA_ILLEGAL_MOVE);
+            assert_eq!(desdemona_game_pass(game), DESDEMONA_ILLEGAL_MOVE);
+            assert_eq!(desdemona_game_play(game, moves[0]), 0);
+            assert_eq!(desdemona_game_current_player(game), b'B' as c_char);
+
+            let mut decision = DesdemonaMove::default();
+            assert_eq!(desdemona_game_decide(game, 2, &mut decision), 0);
+            assert_eq!(desdemona_game_play(game, decision), 0);
+
+            let board = desdemona_game_board(game);
+            let copy = desdemona_game_parse(board, b'H' as c_char);
+            let copied = desdemona_game_board(copy);
+            assert_eq!(CStr::from_ptr(copied), CStr::from_ptr(board));
+            desdemona_string_free(copied);
+            desdemona_string_free(board);
+            desdemona_game_free(copy);
+            desdemona_game_free(game);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        unsafe {
+            let board = CString::new("EEX").unwrap();
+            assert!(desdemona_game_parse(board.as_ptr(), b'H' as c_char).is_null());
+            assert!(desdemona_game_parse(ptr::null(), b'H' as c_char).is_null());
+            assert_eq!(desdemona_game_is_over(ptr::null()), DESDEMONA_INVALID_ARGUMENT);
+            desdemona_game_free(ptr::null_mut());
+        }
+    }
+}
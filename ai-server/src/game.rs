@@ -1,39 +1,108 @@
-use std::collections::HashSet;
 use std::fmt::{Display, Formatter};
 use std::hash::Hash;
+use std::iter;
+use std::sync::RwLock;
+use itertools::Itertools;
 use lazy_static::lazy_static;
+#[cfg(feature = "server")]
+use rocket::form::{self, FromFormField, ValueField};
+#[cfg(feature = "server")]
+use rocket::serde::{Deserialize, Serialize};
 
-use crate::board::{Board, Direction, Disk, Position};
+use crate::board::{Board, Direction, Disk, Position, SymmetryKey, BOARD_SIZE};
 use crate::board::Disk::{Dark, Light};
 use crate::errors::Error;
-use crate::errors::Error::ParseError;
+use crate::errors::Error::{IllegalMove, InvalidArgument, ParseError};
 use crate::game::Player::{Bot, Human};
 use crate::game::Phase::{Early, Mid, End};
 
 pub const BOT_CHAR: char = 'B';
 pub const HUMAN_CHAR: char = 'H';
 
-lazy_static! {
-    static ref MAX_BEST_EVALUATION: i32 = {
-        assert_eq!(Bot.disk(), Light);
+/// Weights for early, mid and end stage of the game
+const PLACEMENT_WEIGHTS: [i32; 3] = [5, 4, 2];
+const MOBILITY_WEIGHTS: [i32; 3] = [5, 4, 3];
+const NUM_DISKS_WEIGHTS: [i32; 3] = [-1, -1, 0];
 
-        let mut board = Board::new();
-        for pos in Position::all() {
-            match board.disk(&pos) {
-                Some(Dark) => board.flip(&pos).unwrap(),
-                None => board.place(Bot.disk(), &pos).unwrap(),
-                Some(Light) => (),
-            }
+/// Weights of the anti variant, where stable corners and disks in general are liabilities
+const ANTI_WEIGHTS: Weights = Weights {
+    placement: [-5, -4, -2],
+    mobility: [5, 4, 3],
+    num_disks: [-1, -2, -3],
+};
+
+/// Weights of the terms of the evaluation for early, mid and end stage of the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(Serialize, Deserialize), serde(crate = "rocket::serde"))]
+pub struct Weights {
+    pub placement: [i32; 3],
+    pub mobility: [i32; 3],
+    pub num_disks: [i32; 3],
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            placement: PLACEMENT_WEIGHTS,
+            mobility: MOBILITY_WEIGHTS,
+            num_disks: NUM_DISKS_WEIGHTS,
         }
+    }
+}
+
+/// Returns the evaluation of the board filled with the disks of the bot under the given weights
+fn best_evaluation(weights: &Weights) -> i32 {
+    assert_eq!(Bot.disk(), Light);
 
-        let value = Game::parse(board, Player::default()).evaluate();
-        value
+    let mut board = Board::new();
+    for pos in Position::all() {
+        match board.disk(&pos) {
+            Some(Dark) => board.flip(&pos).unwrap(),
+            None => board.place(Bot.disk(), &pos).unwrap(),
+            Some(Light) => (),
+        }
+    }
+
+    Game::parse(board, Default::default()).evaluate_with(weights)
+}
+
+lazy_static! {
+    /// Weights used by the evaluation together with the best evaluation they allow
+    static ref WEIGHTS: RwLock<(Weights, i32)> = {
+        let weights = Weights::default();
+        RwLock::new((weights, best_evaluation(&weights)))
     };
 }
 
+/// Returns the weights currently used by the evaluation
+pub fn weights() -> Weights {
+    WEIGHTS.read().unwrap().0
+}
+
+/// Checks that the evaluation of the board filled with the disks of the bot is positive under
+/// the given weights, returning that evaluation
+pub fn validate_weights(weights: &Weights) -> Result<i32, Error> {
+    let best = best_evaluation(weights);
+    if best <= 0 {
+        return Err(InvalidArgument(
+            "The weights must favour the player owning every disk".to_string()));
+    }
+    Ok(best)
+}
+
+/// Replaces the weights used by the evaluation
+///
+/// Pre-conditions:
+/// * The evaluation of the board filled with the disks of the bot is positive
+pub fn set_weights(weights: Weights) -> Result<(), Error> {
+    let best = validate_weights(&weights)?;
+    *WEIGHTS.write().unwrap() = (weights, best);
+    Ok(())
+}
+
 /// Returns the best evaluation possible for max
 pub fn max_best_evaluation() -> i32 {
-    *MAX_BEST_EVALUATION
+    WEIGHTS.read().unwrap().1
 }
 
 /// Returns the best evaluation possible for min
@@ -41,16 +110,13 @@ pub fn min_best_evaluation() -> i32 {
     -max_best_evaluation()
 }
 
-
-/// Weights for early, mid and end stage of the game
-const PLACEMENT_WEIGHTS: [i32; 3] = [5, 4, 2];
-const MOBILITY_WEIGHTS: [i32; 3] = [5, 4, 3];
-const NUM_DISKS_WEIGHTS: [i32; 3] = [-1, -1, 0];
-
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "server", derive(Serialize, Deserialize), serde(crate = "rocket::serde"))]
 pub enum Player {
     #[default]
+    #[cfg_attr(feature = "server", serde(rename = "B"))]
     Bot,
+    #[cfg_attr(feature = "server", serde(rename = "H"))]
     Human,
 }
 
@@ -82,6 +148,18 @@ impl Player {
     }
 }
 
+#[cfg(feature = "server")]
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for Player {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        match field.value.chars().collect::<Vec<char>>()[..] {
+            [ch] => Player::parse(ch).map_err(|e| form::Error::validation(e.to_string()).into()),
+            _ => Err(form::Error::validation(
+                format!("Invalid string to parse into a player: {}", field.value)).into()),
+        }
+    }
+}
+
 impl Display for Player {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match *self {
@@ -92,7 +170,7 @@ impl Display for Player {
 }
 
 
-#[derive(Default, PartialEq, Hash, Eq)]
+#[derive(Default, Debug, Clone, PartialEq, Hash, Eq)]
 pub struct Action {
     player: Player,
     placement: Position,
@@ -106,6 +184,11 @@ impl Action {
             placement
         }
     }
+
+    /// Returns the position where this action places a disk
+    pub fn placement(&self) -> &Position {
+        &self.placement
+    }
 }
 
 impl Display for Action {
@@ -114,8 +197,8 @@ impl Display for Action {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, Default)]
-enum Phase {
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Phase {
     #[default]
     Early,
     Mid,
@@ -135,6 +218,14 @@ impl Phase {
         }
     }
 
+    /// Returns the stage of the game with the given board, which advances with the disks placed
+    pub fn of(board: &Board) -> Self {
+        const INITIAL_NUM_DISKS: i32 = 4;
+        // The classic start has fewer disks until the center is filled
+        let turn = (board.num_disks(Dark) + board.num_disks(Light) - INITIAL_NUM_DISKS).max(0);
+        Self::new(turn as usize)
+    }
+
     /// Convert this state into index for getting weights
     pub fn to_index(&self) -> usize {
         match *self {
@@ -145,12 +236,144 @@ impl Phase {
     }
 }
 
+impl Display for Phase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match *self {
+            Early => "early",
+            Mid => "mid",
+            End => "end",
+        })
+    }
+}
+
+/// Rules deciding who wins a finished game
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "server", derive(rocket::FromFormField, Serialize, Deserialize),
+           serde(crate = "rocket::serde", rename_all = "lowercase"))]
+pub enum Variant {
+    /// The player with the most disks wins
+    #[default]
+    Standard,
+    /// Anti-Reversi, where the player with the fewest disks wins
+    Anti,
+}
+
+impl Variant {
+
+    /// Returns the weights the evaluation uses under this variant
+    pub fn weights(&self) -> Weights {
+        match *self {
+            Variant::Standard => weights(),
+            Variant::Anti => ANTI_WEIGHTS,
+        }
+    }
+}
+
+impl Display for Variant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match *self {
+            Variant::Standard => "standard",
+            Variant::Anti => "anti",
+        })
+    }
+}
+
+/// Term of the evaluation with the values of each player and its weight in the current phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Term {
+    pub weight: i32,
+    pub bot: i32,
+    pub human: i32,
+}
+
+impl Term {
+
+    /// Returns what this term adds to the evaluation, which is positive when it favours the bot
+    pub fn contribution(&self) -> i32 {
+        self.weight * (self.bot - self.human)
+    }
+}
+
+/// Terms that the evaluation of a game state is the sum of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakdown {
+    pub phase: Phase,
+    pub placement: Term,
+    pub mobility: Term,
+    pub num_disks: Term,
+}
+
+impl Breakdown {
+
+    /// Returns the terms by their names
+    pub fn terms(&self) -> [(&'static str, Term); 3] {
+        [("placement", self.placement), ("mobility", self.mobility), ("num_disks", self.num_disks)]
+    }
+
+    /// Returns the evaluation made of these terms
+    pub fn total(&self) -> i32 {
+        self.terms().iter().map(|(_, term)| term.contribution()).sum()
+    }
+}
+
+/// Key shared by the states whose evaluations are the same, since the evaluation does not change
+/// when the board is rotated or reflected
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct EvaluationKey {
+    board: SymmetryKey,
+    phase: Phase,
+    variant: Variant,
+}
+
+impl EvaluationKey {
+
+    /// Number of bytes of an encoded key
+    pub(crate) const SIZE: usize = 26;
+
+    /// Appends the bytes of this key to the given buffer
+    pub(crate) fn encode(&self, buf: &mut Vec<u8>) {
+        for bits in self.board.0 {
+            buf.extend_from_slice(&bits.to_le_bytes());
+        }
+        buf.push(self.phase.to_index() as u8);
+        buf.push(match self.variant {
+            Variant::Standard => 0,
+            Variant::Anti => 1,
+        });
+    }
+
+    /// Decodes the key encoded at the start of the given bytes
+    pub(crate) fn decode(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..Self::SIZE)?;
+        let mut bits = [0; 3];
+        for (i, chunk) in bytes[..24].chunks(8).enumerate() {
+            bits[i] = u64::from_le_bytes(chunk.try_into().ok()?);
+        }
+
+        Some(Self {
+            board: SymmetryKey(bits),
+            phase: match bytes[24] {
+                0 => Early,
+                1 => Mid,
+                2 => End,
+                _ => return None,
+            },
+            variant: match bytes[25] {
+                0 => Variant::Standard,
+                1 => Variant::Anti,
+                _ => return None,
+            },
+        })
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Default)]
 pub struct Game {
     board: Board,
     current_player: Player,
     phase: Phase,
     winner: Option<Player>,
+    variant: Variant,
 }
 
 impl Game {
@@ -162,19 +385,18 @@ impl Game {
             current_player: Bot,
             phase: Phase::new(0),
             winner: None,
+            variant: Variant::Standard,
         }
     }
     
     /// Parses the given data into a Game
     pub fn parse(board: Board, current_player: Player) -> Self {
-        const INITIAL_NUM_DISKS: usize = 4;
-        let turn = board.positions(Dark).count() + board.positions(Light).count() - INITIAL_NUM_DISKS;
-
         let mut game = Self {
+            phase: Phase::of(&board),
             board,
             current_player,
-            phase: Phase::new(turn),
             winner: None,
+            variant: Variant::Standard,
         };
         
         if game.is_over() {
@@ -184,16 +406,63 @@ impl Game {
         game
     }
     
+    /// Returns this game played under the given variant
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        self.variant = variant;
+        if self.is_over() {
+            self.set_winner();
+        }
+        self
+    }
+
+    /// Returns the variant this game is played under
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
     /// Returns the current player of this turn
     pub fn current_player(&self) -> Player {
         self.current_player
     }
+
+    /// Returns the phase of this game, which selects the weights of the evaluation
+    pub fn phase(&self) -> &Phase {
+        &self.phase
+    }
     
 
+    /// Checks if the players are still placing the first four disks on the center of the board,
+    /// as in the classic Reversi start
+    pub fn is_placing(&self) -> bool {
+        // The center of a standard game is never empty, so this is decided by the first check
+        Position::center().any(|pos| self.board.is_empty(&pos))
+            && self.board.positions(Dark).chain(self.board.positions(Light))
+                .all(|pos| Position::center().any(|center| center == pos))
+    }
+
     /// Returns the possible actions of the given player
+    ///
+    /// While the first four disks are being placed, these are the empty squares of the center.
     pub fn actions(&self, player: Player) -> impl Iterator<Item=Action> + '_ {
-        let mut actions = HashSet::new();
-        
+        let mut actions = Vec::new();
+        self.actions_into(player, &mut actions);
+        actions.into_iter()
+    }
+
+    /// Replaces the content of the given buffer with the possible actions of the given player,
+    /// so that searches can reuse the same buffer at every node
+    pub fn actions_into(&self, player: Player, actions: &mut Vec<Action>) {
+        actions.clear();
+
+        if self.is_placing() {
+            actions.extend(Position::center()
+                .filter(|pos| self.board.is_empty(pos))
+                .map(|placement| Action { player, placement }));
+            return;
+        }
+
+        // One bit per square, so that squares reachable along several lines are listed once
+        let mut placements: u64 = 0;
         for position in self.board.positions(player.disk()) {
             for direction in Direction::all() {
                 let mut distance = 1;
@@ -203,8 +472,9 @@ impl Game {
                     let disk =  self.board.disk(walker.as_ref().unwrap());
                     
                     if disk.is_none() {
-                        if distance > 1 {
-                            actions.insert(Action { player, placement: walker.unwrap() });
+                        let placement = walker.unwrap();
+                        if distance > 1 && !self.board.is_blocked(&placement) {
+                            placements |= 1 << (placement.row() * BOARD_SIZE + placement.col());
                         }
                         break;
                     }
@@ -218,42 +488,82 @@ impl Game {
                 }
             }
         }
-        
-        actions.into_iter()
+
+        while placements != 0 {
+            let square = placements.trailing_zeros() as usize;
+            actions.push(Action {
+                player,
+                placement: Position::new(square / BOARD_SIZE, square % BOARD_SIZE),
+            });
+            placements &= placements - 1;
+        }
     }
     
     
+    /// Returns the disks the given action would flip, grouped by the direction of their line
+    ///
+    /// Pre-conditions:
+    /// * action.placement.is_inbound()
+    pub fn captures(&self, action: &Action) -> Vec<(Direction, Vec<Position>)> {
+        Direction::all()
+            .filter_map(|dir| {
+                let path = self.line(&action.placement, dir)
+                    .take(self.num_captures(action, dir))
+                    .collect_vec();
+                (!path.is_empty()).then_some((dir, path))
+            })
+            .collect()
+    }
+
+    /// Returns the squares after the given one in the given direction, up to the edge
+    fn line(&self, from: &Position, dir: Direction) -> impl Iterator<Item=Position> + '_ {
+        iter::successors(self.board.neighbour(from, dir),
+                         move |pos| self.board.neighbour(pos, dir))
+    }
+
+    /// Returns the number of disks the given action would flip in the given direction
+    fn num_captures(&self, action: &Action, dir: Direction) -> usize {
+        if !self.board.is_empty(&action.placement) {
+            return 0;
+        }
+
+        let mut num_captures = 0;
+        for pos in self.line(&action.placement, dir) {
+            match self.board.disk(&pos) {
+                Some(disk) if disk == action.player.opponent().disk() => num_captures += 1,
+                Some(_) => return num_captures,
+                None => return 0,
+            }
+        }
+        0
+    }
+    
+    /// Returns the number of empty squares next to a disk of the opponent of the given player,
+    /// which are the squares the player may be able to play on later
+    pub fn potential_mobility(&self, player: Player) -> usize {
+        let opponent = player.opponent().disk();
+        Position::all()
+            .filter(|pos| self.board.is_empty(pos))
+            .filter(|pos| Direction::all().any(|dir| self.board.neighbour(pos, dir)
+                .is_some_and(|neighbour| self.board.disk(&neighbour) == Some(opponent))))
+            .count()
+    }
+
     /// Returns the new state with the action applied
+    ///
+    /// Pre-conditions:
+    /// * The action is one of the actions of its player in this state
     pub fn result(&self, action: &Action) -> Self {
         let mut game = self.clone();
 
-        game.board.place(action.player.disk(), &action.placement).unwrap();
-        
+        // Flipped in place rather than collected, since searches apply an action at every node
         for dir in Direction::all() {
-            let neighbour = game.board.neighbour(&action.placement, dir);
-            if neighbour.is_none() {
-                continue;
-            }
-            
-            let mut path = Vec::new();
-            
-            let mut walker = neighbour.unwrap();
-            while game.board.disk(&walker) == Some(action.player.opponent().disk()) {
-                path.push(walker.clone());
-
-                let neighbour = game.board.neighbour(&walker, dir);
-                if neighbour.is_none() {
-                    break;
-                }
-                walker = neighbour.unwrap();
-            }
-            
-            if game.board.disk(&walker) == Some(action.player.disk()) {
-                for pos in path {
-                    game.board.flip(&pos).unwrap();
-                }
+            for pos in self.line(&action.placement, dir).take(self.num_captures(action, dir)) {
+                game.board.flip(&pos).unwrap();
             }
         }
+        game.board.place(action.player.disk(), &action.placement).unwrap();
+        game.phase = Phase::of(&game.board);
 
         game.current_player = action.player.opponent();
         if game.is_over() {
@@ -262,19 +572,47 @@ impl Game {
         game
     }
     
+    /// Returns the new state with the given action applied, or fails unless the player of the
+    /// action may take it in this state
+    ///
+    /// Unlike `result`, this accepts any action, such as one parsed from a request.
+    pub fn play(&self, action: &Action) -> Result<Self, Error> {
+        if !self.actions(action.player).contains(action) {
+            return Err(IllegalMove(format!("Invalid action for the given player: {}", action)));
+        }
+        Ok(self.result(action))
+    }
+
+    /// Returns the new state with the turn passed to the opponent of the current player
+    pub fn pass(&self) -> Self {
+        let mut game = self.clone();
+        game.current_player = self.current_player.opponent();
+        game
+    }
+
+    /// Checks if the current player has no actions and must pass the turn
+    pub fn must_pass(&self) -> bool {
+        !self.is_over() && self.actions(self.current_player).next().is_none()
+    }
+    
     fn set_winner(&mut self) {
-        assert!(self.is_over());
+        debug_assert!(self.is_over());
 
         let num_bot_disks = self.board.positions(Bot.disk()).count();
         let num_human_disks = self.board.positions(Human.disk()).count();
 
-        self.winner = if num_bot_disks > num_human_disks {
+        let most = if num_bot_disks > num_human_disks {
             Some(Bot)
         } else if num_human_disks > num_bot_disks {
             Some(Human)
         } else {
             None
         };
+
+        self.winner = match self.variant {
+            Variant::Standard => most,
+            Variant::Anti => most.map(|player| player.opponent()),
+        };
     }
     
     /// Checks if this game is over
@@ -282,13 +620,14 @@ impl Game {
         self.actions(Bot).next() == None && self.actions(Human).next() == None
     }
     
-    /// Returns the winner of the game
-    /// 
-    /// Pre-conditions
-    /// * self.is_over()
-    pub fn winner(&self) -> Option<Player> {
-        assert!(self.is_over());
-        self.winner
+    /// Returns the winner of the game, which is None for a draw
+    ///
+    /// Fails unless the game is over.
+    pub fn winner(&self) -> Result<Option<Player>, Error> {
+        if !self.is_over() {
+            return Err(InvalidArgument("The game is not over".to_string()));
+        }
+        Ok(self.winner)
     }
     
     /// Returns the board of the game
@@ -297,41 +636,59 @@ impl Game {
     }
     
     /// Returns the utility of this game
-    /// 
-    /// Pre-conditions:
-    /// * self.is_over()
-    pub fn utility(&self) -> i32 {
-        assert!(self.is_over());
-        
-        match self.winner {
+    ///
+    /// Fails unless the game is over.
+    pub fn utility(&self) -> Result<i32, Error> {
+        Ok(match self.winner()? {
             Some(Bot) => max_best_evaluation(),
             Some(_) => min_best_evaluation(),
             None => 0,
-        }
+        })
     }
     
-    /// Evaluates this game state to a value
+    /// Evaluates this game state to a value under the weights of its variant
     pub fn evaluate(&self) -> i32 {
-        let phase_index = self.phase.to_index();
+        self.evaluate_with(&self.variant.weights())
+    }
+
+    /// Returns the key of the evaluation of this game state
+    pub fn evaluation_key(&self) -> EvaluationKey {
+        EvaluationKey {
+            board: self.board.symmetry_key(),
+            phase: self.phase.clone(),
+            variant: self.variant,
+        }
+    }
 
-        PLACEMENT_WEIGHTS[phase_index] * (
-            self.board.positions(Bot.disk())
-                .map(|p| p.weight())
-                .sum::<i32>() -
-            self.board.positions(Human.disk())
-                .map(|p| p.weight())
-                .sum::<i32>()
-        ) + MOBILITY_WEIGHTS[phase_index] * (
-            self.actions(Bot)
-                .count() as i32 -
-            self.actions(Human)
-                .count() as i32
-        ) + NUM_DISKS_WEIGHTS[phase_index] * (
-            self.board.positions(Bot.disk())
-                .count() as i32 -
-            self.board.positions(Human.disk())
-                .count() as i32
-        )
+    /// Evaluates this game state to a value under the given weights
+    pub fn evaluate_with(&self, weights: &Weights) -> i32 {
+        self.breakdown(weights).total()
+    }
+
+    /// Returns the terms of the evaluation of this game state under the given weights
+    pub fn breakdown(&self, weights: &Weights) -> Breakdown {
+        let phase_index = self.phase.to_index();
+        let placement = |player: Player| self.board.placement(player.disk());
+        let num_disks = |player: Player| self.board.num_disks(player.disk());
+
+        Breakdown {
+            phase: self.phase.clone(),
+            placement: Term {
+                weight: weights.placement[phase_index],
+                bot: placement(Bot),
+                human: placement(Human),
+            },
+            mobility: Term {
+                weight: weights.mobility[phase_index],
+                bot: self.actions(Bot).count() as i32,
+                human: self.actions(Human).count() as i32,
+            },
+            num_disks: Term {
+                weight: weights.num_disks[phase_index],
+                bot: num_disks(Bot),
+                human: num_disks(Human),
+            },
+        }
     }
 }
 
@@ -341,7 +698,9 @@ mod tests {
 
     use crate::board::{Board, BOARD_SIZE};
     use crate::board::Position;
-    use crate::game::{Action, Game};
+    use crate::board::Direction::South;
+    use crate::errors::Error;
+    use crate::game::{Action, Game, Phase, Variant, Weights};
     use crate::game::Player::{Bot, Human};
 
     #[test]
@@ -368,11 +727,56 @@ mod tests {
         board.place(Bot.disk(), &Position::new(BOARD_SIZE-1, 0)).unwrap();
         
         let game = Game::parse(board, Bot);
-        assert_eq!(get_result(game).into_iter().sorted().collect_vec(),
+        assert_eq!(get_result(game.clone()).into_iter().sorted().collect_vec(),
                    vec!["0,0"].into_iter()
                        .map(|s| s.to_string())
                        .sorted()
-                       .collect_vec())
+                       .collect_vec());
+
+        // The buffer is emptied before the actions are listed, without duplicates and in the
+        // order of their squares
+        let mut buffer = Game::new().actions(Bot).collect_vec();
+        game.actions_into(Bot, &mut buffer);
+        assert_eq!(buffer, game.actions(Bot).collect_vec());
+        Game::new().actions_into(Human, &mut buffer);
+        assert_eq!(buffer.iter().map(|action| action.placement.to_string()).collect_vec(),
+                   vec!["2,4", "3,5", "4,2", "5,3"]);
+    }
+    
+    #[test]
+    fn captures() {
+        let game = Game::new();
+
+        let captures = game.captures(&Action{player: Human, placement: Position::new(2, 4)});
+        assert_eq!(captures.len(), 1);
+        assert_eq!(captures[0].0, South);
+        assert_eq!(captures[0].1, vec![Position::new(3, 4)]);
+
+        assert!(game.captures(&Action{player: Human, placement: Position::new(0, 0)}).is_empty());
+        assert!(game.captures(&Action{player: Human, placement: Position::new(3, 3)}).is_empty());
+    }
+
+    #[test]
+    fn must_pass() {
+        let game = Game::new();
+        assert!(!game.must_pass());
+
+        let mut board = Board::new();
+        board.clear();
+        board.place(Human.disk(), &Position::new(0, 0)).unwrap();
+        board.place(Bot.disk(), &Position::new(0, 1)).unwrap();
+        board.place(Bot.disk(), &Position::new(BOARD_SIZE - 1, BOARD_SIZE - 2)).unwrap();
+        board.place(Bot.disk(), &Position::new(BOARD_SIZE - 1, BOARD_SIZE - 1)).unwrap();
+
+        let game = Game::parse(board, Bot);
+        assert!(game.must_pass());
+
+        let game = game.pass();
+        assert_eq!(game.current_player(), Human);
+        assert!(!game.must_pass());
+        // Only a finished game has a winner
+        assert!(game.winner().is_err());
+        assert!(game.utility().is_err());
     }
     
     #[test]
@@ -403,4 +807,124 @@ mod tests {
             assert_eq!(game.board.disk(&Position::new(i, i)), Some(Bot.disk()))
         }
     }
+
+    #[test]
+    fn play() {
+        let game = Game::new();
+        let action = Action{player: Human, placement: Position::new(2, 4)};
+        assert_eq!(game.play(&action).unwrap().board().to_string(),
+                   game.result(&action).board().to_string());
+
+        for placement in [Position::new(3, 3), Position::new(0, 0), Position::new(8, 0)] {
+            assert!(matches!(game.play(&Action{player: Human, placement}),
+                             Err(Error::IllegalMove(_))));
+        }
+    }
+
+    #[test]
+    fn potential_mobility() {
+        let game = Game::new();
+        assert_eq!(game.potential_mobility(Human), 10);
+        assert_eq!(game.potential_mobility(Bot), 10);
+
+        let game = game.result(&Action{player: Human, placement: Position::new(2, 4)});
+        assert_eq!(game.potential_mobility(Human), 5);
+        assert_eq!(game.potential_mobility(Bot), 13);
+    }
+
+    #[test]
+    fn phase() {
+        let mut game = Game::new();
+        assert_eq!(game.board().num_empty(), 60);
+
+        // Plays until the board holds the disks of the middle game, which ends the loop even if
+        // the phase of the game does not follow them
+        while *Game::parse(game.board().clone(), Human).phase() == Phase::Early {
+            let action = game.actions(game.current_player()).next();
+            game = match action {
+                Some(action) => game.result(&action),
+                None => game.pass(),
+            };
+        }
+        // The phase follows the disks placed, as if the board were parsed
+        assert_eq!(game.phase(), Game::parse(game.board().clone(), Human).phase());
+        assert_eq!(*game.phase(), Phase::Mid);
+        assert_eq!(game.board().num_empty(), 40);
+    }
+
+    #[test]
+    fn breakdown() {
+        let weights = Weights::default();
+        let game = Game::new().result(&Action{player: Bot, placement: Position::new(2, 3)});
+        let breakdown = game.breakdown(&weights);
+
+        assert_eq!(breakdown.total(), game.evaluate_with(&weights));
+        assert_eq!(breakdown.num_disks.bot, 4);
+        assert_eq!(breakdown.num_disks.human, 1);
+        assert_eq!(breakdown.placement.weight, weights.placement[0]);
+        assert_eq!(breakdown.mobility.contribution(),
+                   weights.mobility[0] * (breakdown.mobility.bot - breakdown.mobility.human));
+
+        // The reflections of a state share its evaluation
+        let mirrored = Game::new().result(&Action{player: Bot, placement: Position::new(3, 2)});
+        assert!(game.evaluation_key() == mirrored.evaluation_key());
+        assert_eq!(game.evaluate_with(&weights), mirrored.evaluate_with(&weights));
+    }
+
+    #[test]
+    fn anti_variant() {
+        let mut board = Board::new();
+        board.clear();
+        board.place(Human.disk(), &Position::new(0, 0)).unwrap();
+        board.place(Human.disk(), &Position::new(0, 1)).unwrap();
+        board.place(Human.disk(), &Position::new(0, 2)).unwrap();
+        board.place(Bot.disk(), &Position::new(BOARD_SIZE - 1, BOARD_SIZE - 1)).unwrap();
+
+        let game = Game::parse(board, Bot);
+        assert_eq!(game.winner().unwrap(), Some(Human));
+        assert!(game.utility().unwrap() < 0);
+
+        let game = game.with_variant(Variant::Anti);
+        assert_eq!(game.variant(), Variant::Anti);
+        assert_eq!(game.winner().unwrap(), Some(Bot));
+        assert!(game.utility().unwrap() > 0);
+
+        let game = Game::new().with_variant(Variant::Anti);
+        assert_eq!(game.evaluate(), game.evaluate_with(&Variant::Anti.weights()));
+        assert_eq!(game.result(&Action{player: Bot, placement: Position::new(2, 3)}).variant(),
+                   Variant::Anti);
+    }
+
+    #[test]
+    fn classic_start() {
+        let game = Game::parse(Board::new_empty_center(), Human);
+        assert!(game.is_placing());
+        assert!(!game.is_over());
+        assert_eq!(game.actions(Human).count(), 4);
+
+        let mut game = game;
+        for player in [Human, Bot, Human, Bot] {
+            assert_eq!(game.current_player(), player);
+            let action = game.actions(player).next().unwrap();
+            game = game.result(&action);
+        }
+
+        assert!(!game.is_placing());
+        assert_eq!(game.board().positions(Human.disk()).count(), 2);
+        assert_eq!(game.board().positions(Bot.disk()).count(), 2);
+        assert!(game.actions(Human).all(|a| Position::center().all(|c| &c != a.placement())));
+        assert!(!Game::new().is_placing());
+    }
+
+    #[test]
+    fn blocked_squares() {
+        let mut board = Board::new();
+        board.block(&Position::new(2, 3)).unwrap();
+        let game = Game::parse(board, Bot);
+
+        let action = Action{player: Bot, placement: Position::new(2, 3)};
+        assert!(!game.actions(Bot).contains(&action));
+        assert!(game.captures(&action).is_empty());
+        assert_eq!(game.actions(Bot).count(), 3);
+    }
 }
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use itertools::Itertools;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::tokio::task::spawn_blocking;
+use rocket::{Route, State};
+use serde_json::{json, Value};
+
+use crate::admin::Admin;
+use crate::archive::Archive;
+use crate::board::{BoardFormat, Position};
+use crate::bot::CancellationToken;
+use crate::engine::Engine;
+use crate::game::{Game, Player};
+use crate::selfplay::{self, GameRecord};
+use crate::session::Finished;
+use crate::validation;
+
+const DEFAULT_GAMES: u32 = 10;
+const MAX_GAMES: u32 = 1000;
+
+/// Intelligence of both sides when a request leaves it out
+const DEFAULT_INTELLIGENCE: u32 = 4;
+
+/// Most plies played at random before each game, beyond which the openings stop being played
+const MAX_OPENING_PLIES: u32 = 20;
+
+/// Self-play games generated in the background together with their progress
+struct Run {
+    games: u32,
+    intelligence: u32,
+    think_time: Duration,
+    opening_plies: u32,
+    /// Keys of the archived games so far
+    keys: Vec<String>,
+    /// Wins of the dark side, wins of the light side and draws
+    results: [u32; 3],
+    error: Option<String>,
+    finished: bool,
+}
+
+impl Run {
+
+    /// Serializes the settings, the progress and the archived games of this run
+    fn to_json(&self, id: u64) -> Value {
+        json!({
+            "id": id,
+            "finished": self.finished,
+            "games_played": self.keys.len(),
+            "games_total": self.games,
+            "intelligence": self.intelligence,
+            "think_time_ms": self.think_time.as_millis() as u64,
+            "opening_plies": self.opening_plies,
+            "dark_wins": self.results[0],
+            "light_wins": self.results[1],
+            "draws": self.results[2],
+            "keys": self.keys,
+            "error": self.error,
+        })
+    }
+}
+
+/// Returns the given self-play game played from the given state as a finished game of the given
+/// intelligence, or None unless it was played to the end
+fn finished(start: Game, record: &GameRecord, intelligence: u32) -> Option<Finished> {
+    let states = selfplay::replay(start.clone(), record)?;
+    let last = states.last().filter(|game| game.is_over())?;
+    let moves = record.moves.iter()
+        .map(|placement| Position::parse(placement.clone()).ok())
+        .collect_vec();
+
+    let mut json = crate::serialize_result(last, BoardFormat::default());
+    json["first"] = json!(start.current_player().to_string());
+    json["initial"] = json!(start.board().to_string());
+    json["moves"] = json!(moves.iter()
+        .map(|m| m.as_ref().map(|pos| pos.to_string()))
+        .collect_vec());
+
+    Some(Finished {
+        account: None,
+        winner: record.winner,
+        takebacks: 0,
+        rated: false,
+        intelligence,
+        initial: start,
+        moves,
+        record: json,
+    })
+}
+
+/// Plays the games of the run with the given id and archives each of them as it finishes
+fn run(id: u64, run: &Mutex<Run>, engine: &Engine, archive: &Archive,
+       cancellation: &CancellationToken) {
+    let (games, intelligence, think_time, opening_plies) = {
+        let run = run.lock().unwrap();
+        (run.games, run.intelligence, run.think_time, run.opening_plies)
+    };
+
+    let mut rng = rand::thread_rng();
+    for index in 0..games {
+        if cancellation.is_cancelled() {
+            break;
+        }
+
+        let mut human = engine.bot(intelligence);
+        let mut bot = engine.bot(intelligence);
+        let start = selfplay::random_opening(opening_plies, &mut rng);
+        let record = selfplay::play(start.clone(), &mut human, &mut bot, think_time,
+                                    cancellation);
+        let Some(game) = finished(start, &record, intelligence) else {
+            break;
+        };
+
+        let saved = archive.save_selfplay(id, index, &game);
+        let mut run = run.lock().unwrap();
+        match saved {
+            Ok(key) => run.keys.push(key),
+            Err(e) => {
+                run.error = Some(e.to_string());
+                break;
+            }
+        }
+        run.results[match record.winner {
+            Some(Player::Human) => 0,
+            Some(Player::Bot) => 1,
+            None => 2,
+        }] += 1;
+    }
+
+    run.lock().unwrap().finished = true;
+}
+
+/// Registry of the self-play runs of the server
+#[derive(Default)]
+pub struct Runs {
+    next_id: AtomicU64,
+    runs: Mutex<HashMap<u64, Arc<Mutex<Run>>>>,
+}
+
+/// Starts generating the given number of games the bot plays against itself with the given
+/// intelligence and thinking time, each from a random opening of the given number of plies, and
+/// archives them as self-play games
+///
+/// The progress of the run is polled at /admin/selfplay/<id>.
+#[post("/admin/selfplay?<games>&<intelligence>&<think_time_ms>&<opening_plies>")]
+#[allow(clippy::too_many_arguments)]
+fn create(_admin: Admin, games: Option<u32>, intelligence: Option<u32>,
+          think_time_ms: Option<u64>, opening_plies: Option<u32>, runs: &State<Runs>,
+          engine: &State<Engine>, archive: &State<Archive>,
+          cancellation: &State<CancellationToken>) -> Result<String, Custom<String>> {
+    let config = engine.config();
+    let games = games.unwrap_or(DEFAULT_GAMES);
+    if games == 0 || games > MAX_GAMES {
+        return Err(validation::invalid(Status::BadRequest, "games",
+                          format!("The number of games must be between 1 and {}", MAX_GAMES)));
+    }
+    let intelligence = validation::intelligence(
+        intelligence.unwrap_or(validation::default_intelligence(DEFAULT_INTELLIGENCE, config)),
+        config)?;
+    let think_time = validation::think_time(
+        think_time_ms.unwrap_or(config.max_thinking_time_ms), "think_time_ms", config)?;
+    let opening_plies = opening_plies.unwrap_or_default();
+    if opening_plies > MAX_OPENING_PLIES {
+        return Err(validation::invalid(Status::BadRequest, "opening_plies", format!(
+            "The opening must have at most {} plies", MAX_OPENING_PLIES)));
+    }
+
+    let progress = Arc::new(Mutex::new(Run {
+        games,
+        intelligence,
+        think_time,
+        opening_plies,
+        keys: Vec::new(),
+        results: [0; 3],
+        error: None,
+        finished: false,
+    }));
+    let id = runs.next_id.fetch_add(1, Ordering::Relaxed);
+    runs.runs.lock().unwrap().insert(id, progress.clone());
+
+    let engine = engine.inner().clone();
+    let archive = archive.inner().clone();
+    let cancellation = cancellation.inner().clone();
+    spawn_blocking(move || run(id, &progress, &engine, &archive, &cancellation));
+
+    Ok(json!({ "id": id }).to_string())
+}
+
+#[get("/admin/selfplay/<id>")]
+fn get(_admin: Admin, id: u64, runs: &State<Runs>) -> Result<String, Custom<String>> {
+    let run = runs.runs.lock().unwrap().get(&id).cloned()
+        .ok_or_else(|| Custom(Status::NotFound,
+                              format!("No self-play run with the id: {}", id)))?;
+
+    let json = run.lock().unwrap().to_json(id);
+    Ok(json.to_string())
+}
+
+/// Returns the routes of the self-play runs
+pub fn routes() -> Vec<Route> {
+    routes![create, get]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bot::{Bot, CancellationToken};
+    use crate::game::Game;
+    use crate::generation::finished;
+    use crate::selfplay;
+
+    #[test]
+    fn finished_games() {
+        let start = selfplay::random_opening(4, &mut rand::thread_rng());
+        let record = selfplay::play(start.clone(), &mut Bot::new(1), &mut Bot::new(1),
+                                    Duration::from_secs(1), &CancellationToken::default());
+
+        let game = finished(start.clone(), &record, 1).unwrap();
+        assert_eq!(game.moves.len(), record.moves.len());
+        assert_eq!(game.winner, record.winner);
+        assert_eq!(game.record["moves"].as_array().unwrap().len(), record.moves.len());
+        assert_eq!(game.record["initial"], start.board().to_string());
+
+        // A game cut short is not archived
+        let unfinished = selfplay::GameRecord { moves: Vec::new(), ..record };
+        assert!(finished(Game::new(), &unfinished, 1).is_none());
+    }
+}
@@ -0,0 +1,304 @@
+//! Reader and writer of the Generic Game Format used by GGS and most Othello tools
+//!
+//! Boards are written as in the `BO` property with `*` for the dark disks, `O` for the light
+//! disks and `-` for the empty squares, and moves as in `B[f5]` or `W[PA]` for a pass.
+//!
+//! Boards and moves are in the standard orientation, whose initial board is the mirror image of
+//! the one of this game, so they are mirrored on the way in and out.
+
+use itertools::Itertools;
+
+use crate::board::{Board, Disk, Position, BOARD_SIZE, DARK_CHAR, EMPTY_CHAR, LIGHT_CHAR};
+use crate::errors::Error;
+use crate::errors::Error::{IllegalMove, InvalidArgument, ParseError};
+use crate::game::{Action, Game, Player};
+
+/// Notation of a pass
+const PASS: &str = "PA";
+
+/// Game read from a GGF record
+#[derive(Clone)]
+pub struct Record {
+    /// State the game started from
+    pub initial: Game,
+    /// Placements played in order, where None is a pass
+    pub moves: Vec<Option<Position>>,
+    /// State reached after the moves
+    pub last: Game,
+    /// Name of the player with the dark disks
+    pub black: Option<String>,
+    /// Name of the player with the light disks
+    pub white: Option<String>,
+}
+
+/// Converts the given position between the standard orientation and the one of this game by
+/// mirroring its column
+pub fn mirror(pos: &Position) -> Position {
+    Position::new(pos.row(), BOARD_SIZE - 1 - pos.col())
+}
+
+/// Returns the standard notation of the given position such as "f5"
+pub fn notation(pos: &Position) -> String {
+    mirror(pos).notation()
+}
+
+/// Parses the given standard notation such as "f5" into a position of this game
+pub fn parse_notation(s: &str) -> Result<Position, Error> {
+    Position::parse_notation(s).map(|pos| mirror(&pos))
+}
+
+/// Returns the GGF character of the given cell
+fn cell(disk: Option<Disk>) -> char {
+    match disk {
+        None => '-',
+        Some(Disk::Dark) => '*',
+        Some(Disk::Light) => 'O',
+    }
+}
+
+/// Returns the GGF colour of the given player, where dark is black and light is white
+fn colour(player: Player) -> char {
+    match player.disk() {
+        Disk::Dark => 'B',
+        Disk::Light => 'W',
+    }
+}
+
+/// Returns the player owning the disks of the given GGF colour such as "B" or "*"
+pub fn player_of(colour: char) -> Result<Player, Error> {
+    let disk = match colour {
+        'B' | '*' => Disk::Dark,
+        'W' | 'O' => Disk::Light,
+        _ => return Err(ParseError(format!("Invalid colour: {}", colour))),
+    };
+    Ok(if disk == Player::Human.disk() { Player::Human } else { Player::Bot })
+}
+
+/// Returns the properties such as `PB[name]` of the given GGF record in order
+pub fn tags(record: &str) -> Vec<(String, String)> {
+    let mut tags = Vec::new();
+    let mut rest = record;
+
+    while let Some(open) = rest.find('[') {
+        let name: String = rest[..open].chars().rev()
+            .take_while(|ch| ch.is_ascii_uppercase())
+            .collect::<Vec<char>>()
+            .into_iter()
+            .rev()
+            .collect();
+        let close = match rest[open..].find(']') {
+            Some(close) => open + close,
+            None => break,
+        };
+
+        tags.push((name, rest[open + 1..close].to_string()));
+        rest = &rest[close + 1..];
+    }
+    tags
+}
+
+/// Parses the given GGF move such as "f5", or "PA" for a pass, ignoring the evaluation and the
+/// time that may follow it after a slash
+pub fn parse_move(placement: &str) -> Result<Option<Position>, Error> {
+    let placement = placement.split('/').next().unwrap_or_default().trim();
+    if placement.eq_ignore_ascii_case(PASS) {
+        return Ok(None);
+    }
+    parse_notation(&placement.to_lowercase()).map(Some)
+}
+
+/// Plays the given GGF move for the player to move
+pub fn apply(game: &Game, placement: &str) -> Result<Game, Error> {
+    let player = game.current_player();
+
+    match parse_move(placement)? {
+        None if game.must_pass() => Ok(game.pass()),
+        None => Err(InvalidArgument("Passing is only allowed without moves".to_string())),
+        Some(pos) => {
+            let action = Action::parse(player, pos);
+            if !game.actions(player).any(|a| a == action) {
+                return Err(IllegalMove(format!("Illegal move: {}", placement)));
+            }
+            Ok(game.result(&action))
+        }
+    }
+}
+
+/// Parses the given `BO` property such as "8 ---...--- *"
+fn parse_board(board: &str) -> Result<Game, Error> {
+    let (squares, to_move) = match board.split_whitespace().collect::<Vec<&str>>()[..] {
+        ["8", squares, to_move] if squares.len() == BOARD_SIZE * BOARD_SIZE => (squares, to_move),
+        _ => return Err(ParseError(format!("Invalid board: {}", board))),
+    };
+
+    let rows = squares.chars()
+        .map(|ch| match ch {
+            '*' => Ok(DARK_CHAR),
+            'O' => Ok(LIGHT_CHAR),
+            '-' => Ok(EMPTY_CHAR),
+            _ => Err(ParseError(format!("Invalid square of the board: {}", ch))),
+        })
+        .collect::<Result<Vec<char>, Error>>()?
+        .chunks(BOARD_SIZE)
+        .map(|row| row.iter().rev().collect::<String>())
+        .join("\n");
+    let to_move = player_of(to_move.chars().next().unwrap_or_default())?;
+    Ok(Game::parse(Board::parse(rows)?, to_move))
+}
+
+/// Reads the given GGF record, replaying its moves
+///
+/// Passes the record leaves out are inserted when the colour of a move shows that the other
+/// player had to pass.
+pub fn parse(record: &str) -> Result<Record, Error> {
+    let record = record.trim();
+    if !record.starts_with("(;") || !record.ends_with(";)") {
+        return Err(ParseError("A GGF record must be enclosed in \"(;\" and \";)\"".to_string()));
+    }
+
+    let tags = tags(record);
+    let tag = |name: &str| tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value);
+
+    if tag("GM").is_some_and(|game| !game.eq_ignore_ascii_case("othello")) {
+        return Err(InvalidArgument("The record is not a game of Othello".to_string()));
+    }
+    // Without a board, the game starts from the standard one with black to move
+    let initial = match tag("BO") {
+        Some(board) => parse_board(board)?,
+        None => Game::parse(Board::new(), player_of('B')?),
+    };
+
+    let mut game = initial.clone();
+    let mut moves = Vec::new();
+    for (name, value) in tags.iter().filter(|(tag, _)| tag == "B" || tag == "W") {
+        let player = player_of(name.chars().next().unwrap_or_default())?;
+        if player != game.current_player() && game.must_pass() && !game.is_over() {
+            game = game.pass();
+            moves.push(None);
+        }
+        if player != game.current_player() {
+            return Err(InvalidArgument(format!("{}[{}] is played out of turn", name, value)));
+        }
+
+        game = apply(&game, value)?;
+        moves.push(parse_move(value)?);
+    }
+
+    Ok(Record {
+        initial,
+        moves,
+        last: game,
+        black: tag("PB").cloned(),
+        white: tag("PW").cloned(),
+    })
+}
+
+/// Writes the game that started from the initial state and played the given placements, where
+/// None is a pass, in the Generic Game Format
+pub fn write(initial: &Game, moves: &[Option<Position>], last: &Game) -> String {
+    let board = initial.board();
+    let cells: String = Position::all()
+        .map(|pos| cell(board.disk(&mirror(&pos))))
+        .collect();
+    let to_move = cell(Some(initial.current_player().disk()));
+
+    let mut ggf = format!("(;GM[Othello]PC[Desdemona]PB[{}]PW[{}]TY[8]BO[8 {} {}]",
+                          Player::Human, Player::Bot, cells, to_move);
+
+    let mut player = initial.current_player();
+    for placement in moves {
+        let coordinate = placement.as_ref()
+            .map(notation)
+            .unwrap_or_else(|| PASS.to_string());
+        ggf.push_str(&format!("{}[{}]", colour(player), coordinate));
+        player = player.opponent();
+    }
+
+    if last.is_over() {
+        let dark = last.board().positions(Disk::Dark).count() as i32;
+        let light = last.board().positions(Disk::Light).count() as i32;
+        ggf.push_str(&format!("RE[{:+}]", dark - light));
+    }
+
+    ggf.push_str(";)");
+    ggf
+}
+
+/// Writes the given placements as a compact transcript such as "f5d6c3", skipping passes
+pub fn transcript(moves: &[Option<Position>]) -> String {
+    moves.iter()
+        .flatten()
+        .map(notation)
+        .join("")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::{Board, Position};
+    use crate::game::{Game, Player};
+    use crate::ggf::{notation, parse, parse_move, tags, transcript, write};
+
+    const RECORD: &str = "(;GM[Othello]PC[GGS/os]PB[alice]PW[desdemona]TY[8]\
+                          BO[8 ---------------------------O*------*O--------------------------- *]\
+                          B[f5//1.2]W[f6//0.5];)";
+
+    #[test]
+    fn read() {
+        assert_eq!(tags("PB[alice]B[f5//1.2]"), vec![("PB".to_string(), "alice".to_string()),
+                                                    ("B".to_string(), "f5//1.2".to_string())]);
+        assert_eq!(parse_move("f5//1.2").unwrap(), Some(Position::new(4, 2)));
+        assert_eq!(parse_move("PA").unwrap(), None);
+
+        let record = parse(RECORD).unwrap();
+        assert_eq!(record.black.as_deref(), Some("alice"));
+        assert_eq!(record.initial.current_player(), Player::Human);
+        assert_eq!(record.moves, vec![Some(Position::new(4, 2)), Some(Position::new(5, 2))]);
+        assert_eq!(record.last.current_player(), Player::Human);
+
+        assert!(parse("GM[Othello]").is_err());
+        assert!(parse("(;GM[Chess];)").is_err());
+        assert!(parse(&RECORD.replace("W[f6", "W[a1")).is_err());
+        assert!(parse(&RECORD.replace("W[f6", "B[f6")).is_err());
+
+        // The board of the record is the initial one of this game
+        assert!(record.initial.board() == &Board::new());
+        let record = parse("(;GM[Othello]B[f5]W[d6];)").unwrap();
+        assert!(record.initial.board() == &Board::new());
+        assert_eq!(record.moves.len(), 2);
+    }
+
+    #[test]
+    fn standard_orientation() {
+        // The standard opening square of black is a legal move from the initial board
+        let game = Game::parse(Board::new(), Player::Human);
+        let f5 = game.actions(Player::Human)
+            .map(|action| action.placement().clone())
+            .find(|pos| notation(pos) == "f5")
+            .unwrap();
+        assert_eq!(parse_move("f5").unwrap(), Some(f5.clone()));
+        assert_eq!(transcript(&[Some(f5), None]), "f5");
+    }
+
+    #[test]
+    fn round_trip() {
+        let initial = Game::parse(Board::new(), Player::Human);
+        let mut game = initial.clone();
+        let mut moves = Vec::new();
+        while !game.is_over() {
+            if game.must_pass() {
+                game = game.pass();
+                moves.push(None);
+                continue;
+            }
+            let action = game.actions(game.current_player()).next().unwrap();
+            moves.push(Some(action.placement().clone()));
+            game = game.result(&action);
+        }
+
+        let ggf = write(&initial, &moves, &game);
+        let record = parse(&ggf).unwrap();
+        assert_eq!(record.moves, moves);
+        assert!(record.last.board() == game.board());
+        assert_eq!(write(&record.initial, &record.moves, &record.last), ggf);
+    }
+}
@@ -0,0 +1,184 @@
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject};
+use itertools::Itertools;
+use rocket::data::Capped;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::{Route, State};
+
+use crate::account::Accounts;
+use crate::board::Board;
+use crate::engine::Engine;
+use crate::errors::Error::ParseError;
+use crate::game::{Game, Player};
+use crate::limits;
+use crate::session::Sessions;
+use crate::validation;
+
+/// Schema of the GraphQL API
+pub type ApiSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// Game session as seen through the GraphQL API
+#[derive(SimpleObject)]
+struct GameSession {
+    id: String,
+    board: String,
+    player: String,
+    first: String,
+    /// Placements in the order they were played, where null is a pass
+    moves: Vec<Option<String>>,
+    over: bool,
+    winner: Option<String>,
+    takebacks_left: u32,
+}
+
+/// Statistics of a player account
+#[derive(SimpleObject)]
+struct PlayerStats {
+    id: String,
+    games_played: u32,
+    wins: u32,
+    losses: u32,
+    draws: u32,
+    win_rate: f64,
+    rating: f64,
+}
+
+/// Action of a position together with its score, which is positive when good for the bot
+#[derive(SimpleObject)]
+struct RankedAction {
+    placement: String,
+    score: i32,
+}
+
+/// Parses the board and player arguments of a query into a game
+fn parse_game(board: String, player: &str) -> Result<Game, async_graphql::Error> {
+    let board = Board::parse(board)?;
+    let player = player.chars().exactly_one()
+        .map_err(|_| ParseError(format!("Invalid player: {}", player)))?;
+
+    Ok(Game::parse(board, Player::parse(player)?))
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+
+    /// Returns the game session with the given id
+    async fn game(&self, ctx: &Context<'_>, id: String) -> Option<GameSession> {
+        ctx.data_unchecked::<Sessions>().with(id.parse().ok()?, |s| GameSession {
+            id,
+            board: s.game().board().to_string(),
+            player: s.game().current_player().to_string(),
+            first: s.first().to_string(),
+            moves: s.moves().iter()
+                .map(|m| m.as_ref().map(|pos| pos.to_string()))
+                .collect(),
+            over: s.is_over(),
+            winner: s.winner().ok().flatten().map(|p| p.to_string()),
+            takebacks_left: s.takebacks_left(),
+        })
+    }
+
+    /// Returns the statistics of the player with the given id
+    async fn player(&self, ctx: &Context<'_>, id: String) -> Option<PlayerStats> {
+        ctx.data_unchecked::<Accounts>().get(&id).map(|account| PlayerStats {
+            win_rate: account.win_rate(),
+            id: account.id,
+            games_played: account.games_played,
+            wins: account.wins,
+            losses: account.losses,
+            draws: account.draws,
+            rating: account.rating,
+        })
+    }
+
+    /// Returns the possible placements of the given player
+    async fn actions(&self, board: String, player: String)
+        -> Result<Vec<String>, async_graphql::Error> {
+        let game = parse_game(board, &player)?;
+        Ok(game.actions(game.current_player()).map(|a| a.to_string()).collect())
+    }
+
+    /// Ranks the actions of the given player by searching them with the given intelligence
+    async fn analysis(&self, ctx: &Context<'_>, board: String, player: String,
+                      intelligence: u32) -> Result<Vec<RankedAction>, async_graphql::Error> {
+        let game = parse_game(board, &player)?;
+        let engine = ctx.data_unchecked::<Engine>();
+        let intelligence = validation::intelligence(intelligence, engine.config())
+            .map_err(|Custom(_, message)| async_graphql::Error::new(message))?;
+
+        let mut bot = engine.bot(intelligence);
+        let ranking = engine.run(move || bot.rank(&game, game.current_player())).await?;
+
+        Ok(ranking.into_iter()
+            .map(|(action, score)| RankedAction {
+                placement: action.to_string(),
+                score,
+            })
+            .collect())
+    }
+}
+
+/// Creates the schema of the GraphQL API that reads from the given state of the server
+pub fn schema(sessions: Sessions, accounts: Accounts, engine: Engine) -> ApiSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(sessions)
+        .data(accounts)
+        .data(engine)
+        .finish()
+}
+
+#[post("/graphql", data = "<request>")]
+async fn graphql(request: Capped<String>, schema: &State<ApiSchema>) -> Result<String, Custom<String>> {
+    let request = limits::complete(request)?;
+    let request: async_graphql::Request = serde_json::from_str(&request)
+        .map_err(|e| validation::invalid(Status::BadRequest, "body",
+                                         format!("Invalid GraphQL request: {}", e)))?;
+
+    let response = schema.execute(request).await;
+    serde_json::to_string(&response)
+        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))
+}
+
+/// Returns the routes of the GraphQL API
+pub fn routes() -> Vec<Route> {
+    routes![graphql]
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{Request, Variables};
+    use serde_json::json;
+
+    use crate::account::Accounts;
+    use crate::board::Board;
+    use crate::bot::CancellationToken;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+    use crate::graphql::schema;
+    use crate::session::Sessions;
+    use crate::storage::Storage;
+
+    #[rocket::async_test]
+    async fn query_actions() {
+        let engine = Engine::new(EngineConfig::default(), CancellationToken::new());
+        let sessions = Sessions::new(&EngineConfig::default());
+        let schema = schema(sessions, Accounts::new(Storage::new("unused")), engine);
+
+        let request = Request::new(r#"query($board: String!) {
+            actions(board: $board, player: "B")
+            game(id: "0") { board }
+        }"#).variables(Variables::from_json(json!({ "board": Board::new().to_string() })));
+
+        let response = schema.execute(request).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        let data = response.data.into_json().unwrap();
+        assert!(data["game"].is_null());
+
+        let mut actions = data["actions"].as_array().unwrap().clone();
+        actions.sort_by_key(|a| a.to_string());
+        assert_eq!(actions, vec![json!("2,3"), json!("3,2"), json!("4,5"), json!("5,4")]);
+    }
+}
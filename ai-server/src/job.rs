@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rocket::form;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::tokio::spawn;
+use rocket::{Route, State};
+use serde_json::{json, Value};
+
+use crate::board::{Board, BoardFormat};
+use crate::book::OpeningBook;
+use crate::engine::Engine;
+use crate::game::{Game, Player};
+use crate::validation;
+use crate::webhook;
+
+/// How long the result of a finished job is kept for polling
+const RETENTION: Duration = Duration::from_secs(600);
+
+/// Background search whose result is polled by the client
+struct Job {
+    started: Instant,
+    time_budget: Duration,
+    finished: Option<(Instant, Value)>,
+    callback: Option<&'static str>,
+}
+
+impl Job {
+
+    /// Serializes the progress of this job and its result once finished
+    fn to_json(&self, id: u64) -> Value {
+        let elapsed = match &self.finished {
+            Some((finished, _)) => finished.duration_since(self.started),
+            None => self.started.elapsed(),
+        };
+
+        json!({
+            "id": id,
+            "status": if self.finished.is_some() { "done" } else { "running" },
+            "elapsed_ms": elapsed.as_millis() as u64,
+            "time_budget_ms": self.time_budget.as_millis() as u64,
+            "result": self.finished.as_ref().map(|(_, result)| result),
+            "callback": self.callback,
+        })
+    }
+}
+
+/// Registry of the background jobs
+///
+/// Clones share the same jobs.
+#[derive(Default, Clone)]
+pub struct Jobs {
+    next_id: Arc<AtomicU64>,
+    jobs: Arc<Mutex<HashMap<u64, Job>>>,
+}
+
+impl Jobs {
+
+    /// Registers a running job that searches for at most the given time and returns its id
+    ///
+    /// Jobs that finished longer than the retention period ago are forgotten.
+    pub fn start(&self, time_budget: Duration, callback: bool) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, job| job.finished.as_ref()
+            .is_none_or(|(finished, _)| finished.elapsed() < RETENTION));
+        jobs.insert(id, Job {
+            started: Instant::now(),
+            time_budget,
+            finished: None,
+            callback: callback.then_some("pending"),
+        });
+        id
+    }
+
+    /// Records the result of the job with the given id
+    pub fn finish(&self, id: u64, result: Value) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            job.finished = Some((Instant::now(), result));
+        }
+    }
+
+    /// Records whether the result of the job with the given id was delivered to its callback
+    pub fn set_delivered(&self, id: u64, delivered: bool) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            job.callback = Some(if delivered { "delivered" } else { "failed" });
+        }
+    }
+
+    /// Serializes the job with the given id
+    pub fn to_json(&self, id: u64) -> Option<Value> {
+        self.jobs.lock().unwrap().get(&id).map(|job| job.to_json(id))
+    }
+}
+
+/// Starts a search for the decision of the bot, whose result is polled at /jobs/<id> or posted
+/// to the callback url once ready
+#[post("/decide/async?<board>&<intelligence>&<callback>")]
+async fn decide(board: form::Result<'_, Board>, intelligence: form::Result<'_, u32>,
+          callback: Option<String>, jobs: &State<Jobs>, engine: &State<Engine>,
+          book: &State<OpeningBook>)
+    -> Result<String, Custom<String>> {
+    let board = validation::param(board, "board")?;
+    let config = engine.config();
+    let intelligence = validation::param(intelligence, "intelligence")?;
+    let intelligence = validation::intelligence(intelligence, config)?;
+    if let Some(url) = callback.as_deref() {
+        webhook::validate(url).await?;
+    }
+
+    let game = Game::parse(board, Player::Bot);
+    let book_action = book.lookup(&game, config.book_min_games);
+
+    let id = jobs.start(Duration::from_millis(config.max_thinking_time_ms), callback.is_some());
+    let jobs = jobs.inner().clone();
+    let secret = config.webhook_secret.clone();
+    let engine = engine.inner().clone();
+
+    spawn(async move {
+        let unsearched = book_action.map(crate::Unsearched::Book);
+        let result = engine.search(intelligence, move |bot| {
+            crate::decision(bot, game, unsearched, intelligence, false, BoardFormat::default(),
+                            false, false)
+        }).await.unwrap_or_else(|e| json!({ "error": e.to_string() }));
+        jobs.finish(id, result.clone());
+
+        if let Some(url) = callback {
+            let delivered = webhook::deliver(&url, id, &result, secret.as_deref()).await;
+            jobs.set_delivered(id, delivered);
+        }
+    });
+
+    Ok(json!({ "id": id }).to_string())
+}
+
+#[get("/jobs/<id>")]
+fn get(id: u64, jobs: &State<Jobs>) -> Result<String, Custom<String>> {
+    jobs.to_json(id)
+        .map(|json| json.to_string())
+        .ok_or_else(|| Custom(Status::NotFound, format!("No job with the id: {}", id)))
+}
+
+/// Returns the routes of the background jobs
+pub fn routes() -> Vec<Route> {
+    routes![decide, get]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::job::Jobs;
+
+    #[test]
+    fn finish() {
+        let jobs = Jobs::default();
+        let id = jobs.start(Duration::from_secs(1), true);
+        assert_eq!(jobs.to_json(id).unwrap()["status"], "running");
+        assert!(jobs.to_json(id + 1).is_none());
+
+        jobs.finish(id, json!({ "decision": "2,3" }));
+        let job = jobs.to_json(id).unwrap();
+        assert_eq!(job["status"], "done");
+        assert_eq!(job["result"]["decision"], "2,3");
+        assert_eq!(job["callback"], "pending");
+
+        jobs.set_delivered(id, true);
+        assert_eq!(jobs.to_json(id).unwrap()["callback"], "delivered");
+    }
+}
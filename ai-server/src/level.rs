@@ -0,0 +1,135 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::config::EngineConfig;
+use crate::game::{Action, Game, Player};
+
+/// Named strength of the bot, which players choose instead of the depth of its search
+#[derive(Clone, Copy, PartialEq, Eq, Debug, FromFormField, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Level {
+    Beginner,
+    Casual,
+    Club,
+    Expert,
+    /// Strongest bot the server allows
+    Maximum,
+}
+
+impl Level {
+
+    /// Returns how the bot plays at this level, within the limits of the given configuration
+    pub fn preset(&self, config: &EngineConfig) -> Preset {
+        let (intelligence, think_time_ms, node_limit, error_rate, use_book) = match self {
+            Level::Beginner => (1, 200, Some(200), 0.3, false),
+            Level::Casual => (2, 500, Some(2_000), 0.15, false),
+            Level::Club => (4, 1_000, Some(50_000), 0.05, true),
+            Level::Expert => (6, 3_000, Some(1_000_000), 0.0, true),
+            Level::Maximum => (config.max_intelligence, config.max_thinking_time_ms, None, 0.0,
+                               true),
+        };
+
+        Preset {
+            level: *self,
+            intelligence: intelligence.clamp(config.min_intelligence, config.max_intelligence),
+            think_time: Duration::from_millis(think_time_ms.min(config.max_thinking_time_ms)),
+            node_limit,
+            error_rate,
+            use_book,
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Level::Beginner => "beginner",
+            Level::Casual => "casual",
+            Level::Club => "club",
+            Level::Expert => "expert",
+            Level::Maximum => "maximum",
+        })
+    }
+}
+
+/// How the bot plays at a level
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Preset {
+    pub level: Level,
+    pub intelligence: u32,
+    /// Longest time the bot thinks about a move
+    pub think_time: Duration,
+    /// Largest number of positions the bot expands to decide a move, if limited
+    pub node_limit: Option<u32>,
+    /// Probability that the bot plays a random action instead of searching one
+    pub error_rate: f64,
+    /// Whether the bot plays the moves of the opening book
+    pub use_book: bool,
+}
+
+impl Preset {
+
+    /// Returns a random action of the given player in the given state for the bot to play
+    /// instead of searching one, as often as the error rate of this preset
+    pub fn mistake(&self, game: &Game, player: Player, rng: &mut impl Rng) -> Option<Action> {
+        if self.error_rate <= 0.0 || !rng.gen_bool(self.error_rate) {
+            return None;
+        }
+        game.actions(player).collect::<Vec<_>>().choose(rng).cloned()
+    }
+
+    /// Serializes this preset
+    pub fn to_json(self) -> Value {
+        json!({
+            "level": self.level.to_string(),
+            "intelligence": self.intelligence,
+            "think_time_ms": self.think_time.as_millis() as u64,
+            "node_limit": self.node_limit,
+            "error_rate": self.error_rate,
+            "book": self.use_book,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::config::EngineConfig;
+    use crate::game::{Game, Player};
+    use crate::level::Level;
+
+    #[test]
+    fn presets() {
+        let config = EngineConfig {
+            min_intelligence: 2,
+            max_intelligence: 5,
+            max_thinking_time_ms: 2_000,
+            ..EngineConfig::default()
+        };
+
+        let beginner = Level::Beginner.preset(&config);
+        assert_eq!((beginner.intelligence, beginner.use_book), (2, false));
+        let expert = Level::Expert.preset(&config);
+        assert_eq!((expert.intelligence, expert.think_time), (5, Duration::from_millis(2_000)));
+        let maximum = Level::Maximum.preset(&config);
+        assert_eq!((maximum.intelligence, maximum.node_limit), (5, None));
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let game = Game::new();
+        assert!((0..100).all(|_| maximum.mistake(&game, Player::Bot, &mut rng).is_none()));
+        let mistakes = (0..1000)
+            .filter_map(|_| beginner.mistake(&game, Player::Bot, &mut rng))
+            .inspect(|action| assert!(game.actions(Player::Bot).any(|a| a == *action)))
+            .count();
+        assert!((200..400).contains(&mistakes));
+    }
+}
@@ -0,0 +1,13 @@
+//! Othello engine behind the Desdemona server, which also compiles to WebAssembly with the
+//! `wasm` feature so that browsers can search low depths locally
+
+pub mod board;
+pub mod bot;
+pub mod errors;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod game;
+pub mod ggf;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod wthor;
@@ -0,0 +1,144 @@
+use std::io::Cursor;
+
+use rocket::data::{Capped, Data};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{ContentType, Status};
+use rocket::response::status::Custom;
+use rocket::{Request, Response};
+
+use crate::board::BOARD_SIZE;
+use crate::config::EngineConfig;
+use crate::validation::invalid;
+
+/// Longest board parameter, in characters, which leaves room for a line break of two characters
+/// after each row
+const MAX_BOARD_CHARS: usize = BOARD_SIZE * (BOARD_SIZE + 2);
+
+/// Path that no route serves, where the rejected requests are sent so that none of their inputs
+/// reach the parsers of the routes
+const REJECTED: &str = "/rejected";
+
+/// Error of a request rejected before it reaches its route, kept in the cache of the request
+struct Rejection(Option<(Status, String)>);
+
+/// Fairing that rejects requests with oversized queries or bodies, or with a board parameter that
+/// cannot be a board, before their route parses them
+///
+/// Chunked bodies, which do not declare their length, are instead cut off by the limits that
+/// `config::figment` gives Rocket as the routes read them, which then reject them with `complete`.
+///
+/// Attach it before the other response fairings so that they encode and compress its errors.
+pub struct InputLimits {
+    max_query_bytes: usize,
+    max_body_bytes: u64,
+}
+
+impl InputLimits {
+
+    /// Creates the fairing enforcing the limits of the given configuration
+    pub fn new(config: &EngineConfig) -> Self {
+        Self {
+            max_query_bytes: config.max_query_bytes,
+            max_body_bytes: config.max_body_bytes,
+        }
+    }
+
+    /// Returns why a request with a query of the given length, the given decoded board parameter
+    /// and a body of the given length is rejected, if it is
+    fn check(&self, query_bytes: usize, board: Option<&str>, body_bytes: Option<u64>)
+        -> Result<(), Custom<String>> {
+        if query_bytes > self.max_query_bytes {
+            return Err(invalid(Status::UriTooLong, "query", format!(
+                "The query must be at most {} bytes but has {}", self.max_query_bytes,
+                query_bytes)));
+        }
+        if let Some(body_bytes) = body_bytes.filter(|&bytes| bytes > self.max_body_bytes) {
+            return Err(invalid(Status::PayloadTooLarge, "body", format!(
+                "The body must be at most {} bytes but has {}", self.max_body_bytes, body_bytes)));
+        }
+        match board {
+            Some(board) if !board.is_ascii() => Err(invalid(Status::BadRequest, "board",
+                "The board must only contain ASCII characters")),
+            Some(board) if board.len() > MAX_BOARD_CHARS => Err(invalid(Status::BadRequest,
+                "board", format!("The board must be at most {} characters but has {}",
+                                 MAX_BOARD_CHARS, board.len()))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Unwraps the given body, or rejects it if Rocket cut it off at its limit because it was chunked
+/// and longer than the largest body a request may send
+pub fn complete<T>(body: Capped<T>) -> Result<T, Custom<String>> {
+    if !body.is_complete() {
+        return Err(invalid(Status::PayloadTooLarge, "body",
+                           "The body is longer than the largest one a request may send"));
+    }
+    Ok(body.into_inner())
+}
+
+#[rocket::async_trait]
+impl Fairing for InputLimits {
+    fn info(&self) -> Info {
+        Info {
+            name: "Input Limits",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        let query_bytes = request.uri().query().map_or(0, |query| query.as_str().len());
+        let body_bytes = request.headers().get_one("Content-Length")
+            .and_then(|length| length.parse().ok());
+        let board = match request.query_value::<&str>("board") {
+            Some(Ok(board)) => Ok(Some(board)),
+            Some(Err(_)) => Err(invalid(Status::BadRequest, "board",
+                                        "The board must be valid percent-encoded UTF-8")),
+            None => Ok(None),
+        };
+
+        if let Err(Custom(status, body)) = board
+            .and_then(|board| self.check(query_bytes, board, body_bytes)) {
+            request.local_cache(|| Rejection(Some((status, body))));
+            request.set_uri(Origin::parse(REJECTED).unwrap());
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if let Rejection(Some((status, body))) = request.local_cache(|| Rejection(None)) {
+            response.set_status(*status);
+            response.set_header(ContentType::JSON);
+            response.set_sized_body(body.len(), Cursor::new(body.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+
+    use crate::config::EngineConfig;
+    use crate::limits::{InputLimits, MAX_BOARD_CHARS};
+
+    #[test]
+    fn limits() {
+        let limits = InputLimits::new(&EngineConfig {
+            max_query_bytes: 100,
+            max_body_bytes: 10,
+            ..EngineConfig::default()
+        });
+        let board = "EEEEEEEE\n".repeat(8);
+
+        assert!(limits.check(100, Some(&board), Some(10)).is_ok());
+        assert!(limits.check(0, None, None).is_ok());
+        assert_eq!(limits.check(101, None, None).unwrap_err().0, Status::UriTooLong);
+        assert_eq!(limits.check(0, None, Some(11)).unwrap_err().0, Status::PayloadTooLarge);
+
+        let error = limits.check(0, Some("EEEEEEEÉ"), None).unwrap_err();
+        assert_eq!(error.0, Status::BadRequest);
+        assert!(error.1.contains("ASCII"));
+        let long = "E".repeat(MAX_BOARD_CHARS + 1);
+        assert_eq!(limits.check(0, Some(&long), None).unwrap_err().0, Status::BadRequest);
+    }
+}
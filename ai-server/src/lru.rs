@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde_json::{json, Value};
+
+/// Map that forgets its least recently used entry once it holds more than its capacity
+pub struct LruCache<K, V> {
+    capacity: usize,
+    evictions: u64,
+    entries: HashMap<K, (V, u64)>,
+    /// Keys of the entries by the time they were last used
+    recency: BTreeMap<u64, K>,
+    time: u64,
+}
+
+impl<K: Clone + Eq + Hash, V> LruCache<K, V> {
+
+    /// Creates a new empty cache holding at most the given number of entries
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            evictions: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            time: 0,
+        }
+    }
+
+    /// Inserts the given value, evicting the least recently used entry when the cache is full
+    pub fn insert(&mut self, key: K, value: V) {
+        self.time += 1;
+        if let Some((_, used)) = self.entries.remove(&key) {
+            self.recency.remove(&used);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+                self.evictions += 1;
+            }
+        }
+        self.recency.insert(self.time, key.clone());
+        self.entries.insert(key, (value, self.time));
+    }
+
+    /// Removes the entry of the given key, returning its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, used) = self.entries.remove(key)?;
+        self.recency.remove(&used);
+        Some(value)
+    }
+
+    /// Returns the number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Removes every entry
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> LruCache<K, V> {
+
+    /// Returns the value of the given key, marking it as the most recently used
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.time += 1;
+        let (value, used) = self.entries.get_mut(key)?;
+        self.recency.remove(used);
+        *used = self.time;
+        self.recency.insert(self.time, key.clone());
+        Some(value.clone())
+    }
+}
+
+/// Bodies of the responses of the analysis endpoints, which UIs request for the same positions
+/// over and over
+///
+/// Clones share the same responses.
+#[derive(Clone)]
+pub struct ResponseCache {
+    responses: Arc<Mutex<LruCache<String, String>>>,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+impl ResponseCache {
+
+    /// Creates a new empty cache holding at most the given number of responses
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            responses: Arc::new(Mutex::new(LruCache::new(capacity))),
+            hits: Arc::default(),
+            misses: Arc::default(),
+        }
+    }
+
+    /// Returns the cached response of the given key, or caches the one made by the given function
+    pub fn get_or_insert_with(&self, key: String, f: impl FnOnce() -> String) -> String {
+        if let Some(response) = self.responses.lock().unwrap().get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return response;
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let response = f();
+        self.responses.lock().unwrap().insert(key, response.clone());
+        response
+    }
+
+    /// Forgets every response, which must be done when the evaluation changes
+    pub fn clear(&self) {
+        self.responses.lock().unwrap().clear();
+    }
+
+    /// Serializes the counters of this cache
+    pub fn to_json(&self) -> Value {
+        let responses = self.responses.lock().unwrap();
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let hit_rate = match hits + misses {
+            0 => 0.0,
+            lookups => hits as f64 / lookups as f64,
+        };
+        json!({
+            "hits": hits,
+            "misses": misses,
+            "hit_rate": hit_rate,
+            "evictions": responses.evictions,
+            "size": responses.len(),
+            "capacity": responses.capacity,
+            "fill": responses.len() as f64 / responses.capacity as f64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lru::{LruCache, ResponseCache};
+
+    #[test]
+    fn evict_least_recent() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        cache.insert("c", 3);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"c"), Some(3));
+
+        cache.insert("a", 4);
+        cache.insert("d", 5);
+        assert_eq!(cache.get(&"a"), Some(4));
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.evictions, 2);
+
+        assert_eq!(cache.remove(&"a"), Some(4));
+        assert_eq!(cache.remove(&"a"), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn count_hits() {
+        let cache = ResponseCache::new(8);
+        assert_eq!(cache.get_or_insert_with("a".to_string(), || "1".to_string()), "1");
+        assert_eq!(cache.get_or_insert_with("a".to_string(), || "2".to_string()), "1");
+
+        let json = cache.to_json();
+        assert_eq!(json["hits"], 1);
+        assert_eq!(json["misses"], 1);
+        assert_eq!(json["size"], 1);
+        assert_eq!(json["hit_rate"], 0.5);
+    }
+}
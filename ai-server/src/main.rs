@@ -1,33 +1,109 @@
 #[macro_use] extern crate rocket;
 
 use std::collections::HashSet;
+use std::iter;
+use std::time::{Duration, Instant};
 
+use desdemona::{board, bot, errors, game, ggf, wthor};
 use game::{max_best_evaluation, min_best_evaluation};
 use itertools::Itertools;
-use rocket::fairing::{Fairing, Info, Kind};
+use rand::seq::IteratorRandom;
+use rocket::data::Capped;
+use rocket::fairing::{AdHoc, Fairing, Info, Kind};
+use rocket::form;
 
 use rocket::http::Header;
-use rocket::{Request, Response};
-use rocket::response::status::BadRequest;
+use rocket::{Request, Response, State};
+use rocket::http::Status;
+use rocket::response::status::Custom;
 use serde_json::{json, Value};
 
-use crate::board::{Board, Position};
-use crate::bot::Bot;
-use crate::game::{Action, Game, Player};
+use crate::account::Accounts;
+use crate::archive::Archive;
+use crate::board::{Board, BoardFormat, Disk, Position};
+use crate::book::OpeningBook;
+use crate::bot::{Bot, CancellationToken};
+use crate::compression::Compression;
+use crate::config::EngineConfig;
+use crate::encoding::BinaryEncoding;
+use crate::engine::Engine;
+use crate::errors::Error::Timeout;
+use crate::etag::Cached;
+use crate::generation::Runs;
+use crate::game::{Action, Game, Player, Variant};
+use crate::job::Jobs;
+use crate::level::Level;
+use crate::limits::InputLimits;
+use crate::lru::ResponseCache;
+use crate::predict::Calibration;
+use crate::probability::WinModel;
+use crate::rating::BotRatings;
+use crate::selfplay::Quality;
+use crate::render::Thumbnails;
+use crate::session::Sessions;
+use crate::storage::Storage;
+use crate::tournament::Tournaments;
+use crate::validation::param;
+use crate::weights::Adaptation;
+use crate::xot::Xot;
 
-mod board;
-mod errors;
-mod game;
-mod bot;
+// The modules with routes are public, so that the URI macros Rocket exports for their routes are
+// not reported as unused imports
+pub mod account;
+mod admin;
+pub mod analysis;
+pub mod archive;
+pub mod book;
+mod clock;
+mod compression;
+mod config;
+mod encoding;
+pub mod engine;
+mod etag;
+pub mod generation;
+pub mod graphql;
+pub mod job;
+mod level;
+mod limits;
+mod lru;
+pub mod metrics;
+pub mod predict;
+pub mod probability;
+pub mod probcut;
+pub mod puzzle;
+pub mod rating;
+pub mod render;
+mod selfplay;
+pub mod session;
+mod storage;
+pub mod tournament;
+mod validation;
+mod webhook;
+pub mod weights;
+mod xot;
 
 
-fn serialize_result(game: &Game) -> Value {
+fn serialize_result(game: &Game, format: BoardFormat) -> Value {
+    let dark = game.board().positions(Disk::Dark).count();
+    let light = game.board().positions(Disk::Light).count();
+
     let mut json = json!({
-        "board": game.board().to_string(),
+        "board": game.board().to_json(format),
+        "player": game.current_player().to_string(),
+        "dark": dark,
+        "light": light,
+        "margin": dark as i32 - light as i32,
+        "actions": game.actions(game.current_player())
+            .map(|a| a.to_string())
+            .sorted()
+            .collect_vec(),
+        "must_pass": game.must_pass(),
+        "phase": game.phase().to_string(),
+        "empties": game.board().num_empty(),
     });
 
-    if game.is_over() {
-        json["winner"] = serde_json::to_value(game.winner().map(|p| p.to_string()))
+    if let Ok(winner) = game.winner() {
+        json["winner"] = serde_json::to_value(winner.map(|p| p.to_string()))
             .unwrap_or_else(|_| Value::Null);
     }
     
@@ -40,129 +116,887 @@ fn index() -> &'static str {
     "Hello World!"
 }
 
-#[get("/initial-board")]
-fn initial_board() -> String {
-    Board::new().to_string()
-}
+/// Seconds for which clients may cache the responses that never change
+const IMMUTABLE_MAX_AGE: u32 = 86400;
 
-#[get("/evaluate?<board>")]
-fn evaluate(board: String) -> Result<String, BadRequest<String>> {
-    let board = Board::parse(board);
-    if board.is_err() {
-        return Err(BadRequest(Some("Invalid board".to_string())));
-    }
+/// Seconds for which clients may cache evaluations, which change with the weights at runtime
+const EVALUATION_MAX_AGE: u32 = 60;
 
-    let evaluation = Game::parse(board.unwrap(), Player::default()).evaluate();
+#[get("/initial-board?<format>")]
+fn initial_board(format: Option<BoardFormat>) -> Cached {
+    let board = match format.unwrap_or_default() {
+        BoardFormat::Text => Board::new().to_string(),
+        format => Board::new().to_json(format).to_string(),
+    };
+    Cached::new(board, IMMUTABLE_MAX_AGE)
+}
 
+/// Normalizes the given evaluation to the range from 0 for the player it is relative to losing to
+/// 1 for that player winning
+fn normalize(evaluation: i32) -> f32 {
     let range = max_best_evaluation() - min_best_evaluation();
-    let normalized = (evaluation - min_best_evaluation()) as f32 / range as f32;
-    Ok(normalized.to_string())
+    (evaluation - min_best_evaluation()) as f32 / range as f32
 }
 
-#[get("/result?<board>&<position>&<player>")]
-fn result(board: String, position: String, player: String) -> Result<String, BadRequest<String>> {
-    let board = Board::parse(board);
-    if board.is_err() {
-        return Err(BadRequest(Some("Invalid board".to_string())));
-    }
+/// Evaluates the given board relative to the given player to move, who is the bot by default,
+/// together with the evaluation relative to the bot
+#[get("/evaluate?<board>&<player>&<variant>")]
+fn evaluate(board: form::Result<'_, Board>, player: form::Result<'_, Player>,
+            variant: Option<Variant>, cache: &State<ResponseCache>, model: &State<WinModel>,
+            config: &State<EngineConfig>) -> Result<Cached, Custom<String>> {
+    let board = param(board, "board")?;
+    let warnings = validation::consistency(&board, config)?;
+    let player = validation::optional(player, "player")?.unwrap_or_default();
+    let variant = variant.unwrap_or_default();
+
+    let key = format!("evaluate {} {} {}", board, player, variant);
+    let body = cache.get_or_insert_with(key, || {
+        let game = Game::parse(board, player).with_variant(variant);
+        let evaluation = game.evaluate();
+        let probability = model.probability(&game, evaluation);
+        // The human stands to gain what the bot stands to lose
+        let (relative, probability) = match player {
+            Player::Bot => (evaluation, probability),
+            Player::Human => (-evaluation, 1.0 - probability),
+        };
+        json!({
+            "player": player.to_string(),
+            "evaluation": relative,
+            "bot_evaluation": evaluation,
+            "normalized": normalize(relative),
+            "win_probability": probability,
+            "phase": game.phase().to_string(),
+            "empties": game.board().num_empty(),
+            "warnings": warnings,
+        }).to_string()
+    });
+    Ok(Cached::new(body, EVALUATION_MAX_AGE))
+}
+
+/// Summarizes who owns the corners and the edges of the given board, and which disks of each
+/// player give an empty corner away
+fn ownership(board: &Board) -> Value {
+    let owner = |pos: &Position| [Player::Human, Player::Bot].into_iter()
+        .find(|player| board.disk(pos) == Some(player.disk()));
+    let edges = Position::all().filter(|pos| pos.is_edge()).collect_vec();
+    let players = |f: &dyn Fn(Player) -> Value| json!({
+        Player::Human.to_string(): f(Player::Human),
+        Player::Bot.to_string(): f(Player::Bot),
+    });
+
+    json!({
+        "corners": Position::corners()
+            .map(|pos| (pos.to_string(), json!(owner(&pos).map(|p| p.to_string()))))
+            .collect::<serde_json::Map<String, Value>>(),
+        // Percentages of the edge squares owned by each player
+        "edges": players(&|player| json!(
+            100.0 * edges.iter().filter(|pos| owner(pos) == Some(player)).count() as f64
+                / edges.len() as f64)),
+        "x_squares": players(&|player| json!(Position::all()
+            .filter(|pos| pos.is_x_square() && owner(pos) == Some(player))
+            .filter(|pos| board.is_empty(&pos.nearest_corner()))
+            .map(|pos| pos.to_string())
+            .collect_vec())),
+    })
+}
+
+#[get("/evaluate/detail?<board>")]
+fn evaluate_detail(board: form::Result<'_, Board>, model: &State<WinModel>,
+                   config: &State<EngineConfig>) -> Result<Cached, Custom<String>> {
+    let board = param(board, "board")?;
+    let warnings = validation::consistency(&board, config)?;
+
+    let game = Game::parse(board, Player::default());
+    let breakdown = game.breakdown(&game::weights());
+    let terms: serde_json::Map<String, Value> = breakdown.terms().iter()
+        .map(|(name, term)| (name.to_string(), json!({
+            "weight": term.weight,
+            "bot": term.bot,
+            "human": term.human,
+            "contribution": term.contribution(),
+        })))
+        .collect();
+
+    let json = json!({
+        "phase": breakdown.phase.to_string(),
+        "terms": terms,
+        "evaluation": breakdown.total(),
+        "normalized": normalize(breakdown.total()),
+        "win_probability": model.probability(&game, breakdown.total()),
+        "summary": ownership(game.board()),
+        "warnings": warnings,
+    });
+    Ok(Cached::new(json.to_string(), EVALUATION_MAX_AGE))
+}
+
+#[get("/result?<board>&<position>&<player>&<variant>&<format>")]
+fn result(board: form::Result<'_, Board>, position: form::Result<'_, Position>,
+          player: form::Result<'_, Player>, variant: Option<Variant>,
+          format: Option<BoardFormat>) -> Result<String, Custom<String>> {
+    let board = param(board, "board")?;
+    let position = param(position, "position")?;
+    let player = param(player, "player")?;
+
+    let game = Game::parse(board, player).with_variant(variant.unwrap_or_default());
+    let action = validation::action(&game, player, position)?;
+    let game = game.result(&action);
     
-    let player = player.chars().next();
-    if player.is_none() {
-        return Err(BadRequest(Some("Invalid player".to_string())));
+    Ok(serialize_result(&game, format.unwrap_or_default()).to_string())
+}
+
+/// Passes the turn of the given player, who has no actions, to the opponent
+#[get("/pass?<board>&<player>&<variant>&<format>")]
+fn pass(board: form::Result<'_, Board>, player: form::Result<'_, Player>,
+        variant: Option<Variant>, format: Option<BoardFormat>) -> Result<String, Custom<String>> {
+    let board = param(board, "board")?;
+    let player = param(player, "player")?;
+
+    let game = Game::parse(board, player).with_variant(variant.unwrap_or_default());
+    validation::pass(&game)?;
+
+    Ok(serialize_result(&game.pass(), format.unwrap_or_default()).to_string())
+}
+
+#[get("/actions?<board>&<player>")]
+fn actions(board: form::Result<'_, Board>, player: form::Result<'_, Player>,
+           cache: &State<ResponseCache>) -> Result<Cached, Custom<String>> {
+    let board = param(board, "board")?;
+    let player = param(player, "player")?;
+
+    let key = format!("actions {} {}", board, player);
+    let body = cache.get_or_insert_with(key, || {
+        let game = Game::parse(board, player);
+        // Sorted so that the ETag of the same position does not change between requests
+        Value::Array(
+            game.actions(player)
+                .map(|a| a.to_string())
+                .sorted()
+                .map(Value::String)
+                .collect_vec()
+        ).to_string()
+    });
+    Ok(Cached::new(body, IMMUTABLE_MAX_AGE))
+}
+
+/// Serializes the nodes and the time the last decision of the given bot spent on each depth and
+/// on each action of its root
+fn timings(bot: &Bot) -> Value {
+    json!(bot.timings().iter().map(|depth| json!({
+        "depth": depth.depth,
+        "nodes": depth.nodes,
+        "elapsed_ms": depth.elapsed.as_secs_f64() * 1000.0,
+        "finished": depth.finished,
+        "moves": depth.moves.iter().map(|timing| json!({
+            "move": timing.action.to_string(),
+            "nodes": timing.nodes,
+            "elapsed_ms": timing.elapsed.as_secs_f64() * 1000.0,
+            "value": timing.value,
+        })).collect_vec(),
+    })).collect_vec())
+}
+
+/// Number of alternatives to the decision of the bot listed by its explanation
+const NUM_ALTERNATIVES: usize = 3;
+
+/// Explains why the bot decided the given action from the given state, comparing it with the
+/// other actions and the terms of the evaluation with those of the best of them
+fn explanation(bot: &mut Bot, game: &Game, decision: &Action) -> Value {
+    let ranking = bot.rank_lines(game, Player::Bot);
+    let alternatives = ranking.iter()
+        .filter(|(action, _, _)| action != decision)
+        .collect_vec();
+
+    // Positive differences are the terms in which the decision is better than the alternative
+    let weights = game.variant().weights();
+    let terms = alternatives.first().map(|(alternative, _, _)| {
+        let chosen = game.result(decision).breakdown(&weights);
+        let other = game.result(alternative).breakdown(&weights);
+        chosen.terms().iter().zip(other.terms())
+            .map(|((name, chosen), (_, other))| {
+                (name.to_string(), json!(chosen.contribution() - other.contribution()))
+            })
+            .collect::<serde_json::Map<String, Value>>()
+    });
+
+    json!({
+        "score": ranking.iter()
+            .find(|(action, _, _)| action == decision)
+            .map(|(_, value, _)| value),
+        "alternatives": alternatives.iter()
+            .take(NUM_ALTERNATIVES)
+            .map(|(action, value, _)| json!({"action": action.to_string(), "score": value}))
+            .collect_vec(),
+        "terms": terms,
+        "refutation": alternatives.first()
+            .map(|(_, _, line)| line.iter().map(|a| a.to_string()).collect_vec()),
+    })
+}
+
+/// Returns the number of actions of each player and of the empty squares next to the disks of
+/// their opponents
+#[get("/mobility?<board>")]
+fn mobility(board: form::Result<'_, Board>, cache: &State<ResponseCache>)
+    -> Result<Cached, Custom<String>> {
+    let board = param(board, "board")?;
+
+    let key = format!("mobility {}", board);
+    let body = cache.get_or_insert_with(key, || {
+        let game = Game::parse(board, Player::default());
+        let count = |count: &dyn Fn(Player) -> usize| json!({
+            Player::Human.to_string(): count(Player::Human),
+            Player::Bot.to_string(): count(Player::Bot),
+        });
+        json!({
+            "mobility": count(&|player| game.actions(player).count()),
+            "potential_mobility": count(&|player| game.potential_mobility(player)),
+        }).to_string()
+    });
+    Ok(Cached::new(body, IMMUTABLE_MAX_AGE))
+}
+
+/// Returns the stable disks of each player, which no action can flip anymore, and their frontier
+/// disks, which are next to an empty square
+#[get("/stability?<board>")]
+fn stability(board: form::Result<'_, Board>, cache: &State<ResponseCache>)
+    -> Result<Cached, Custom<String>> {
+    let board = param(board, "board")?;
+
+    let key = format!("stability {}", board);
+    let body = cache.get_or_insert_with(key, || {
+        let (stable, frontier) = (board.stable_disks(), board.frontier_disks());
+        let disks = |positions: &[Position], player: Player| positions.iter()
+            .filter(|pos| board.disk(pos) == Some(player.disk()))
+            .map(|pos| pos.to_string())
+            .collect_vec();
+        let metrics = |player: Player| {
+            let (stable, frontier) = (disks(&stable, player), disks(&frontier, player));
+            json!({
+                "stable": stable.len(),
+                "frontier": frontier.len(),
+                "stable_disks": stable,
+                "frontier_disks": frontier,
+            })
+        };
+        json!({
+            Player::Human.to_string(): metrics(Player::Human),
+            Player::Bot.to_string(): metrics(Player::Bot),
+        }).to_string()
+    });
+    Ok(Cached::new(body, IMMUTABLE_MAX_AGE))
+}
+
+/// Action the bot plays without searching
+pub enum Unsearched {
+    /// Move of the opening book
+    Book(Action),
+    /// Random action of a level at which the bot makes mistakes
+    Mistake(Action),
+}
+
+/// Serializes the decision of the bot from the given state, searching it unless the bot plays
+/// the given action, and explains the searched decision if asked to
+///
+/// A search that reaches the end of the game within the last few empty squares is solved after
+/// it, so that the decision comes with the final disk difference it proves instead of a score,
+/// unless the solve runs out of time or nodes. A deepened search goes one ply deeper
+/// at a time up to the intelligence until the deadline or the node limit of the bot. A verbose
+/// decision also lists the nodes and the time spent on each depth and each action searched.
+#[allow(clippy::too_many_arguments)]
+fn decision(bot: &mut Bot, game: Game, unsearched: Option<Unsearched>, intelligence: u32,
+            deepen: bool, format: BoardFormat, explain: bool, verbose: bool) -> Value {
+    if let Some(unsearched) = unsearched {
+        let (action, book) = match unsearched {
+            Unsearched::Book(action) => (action, true),
+            Unsearched::Mistake(action) => (action, false),
+        };
+        return json!({
+            "decision": action.to_string(),
+            "result": serialize_result(&game.result(&action), format),
+            "timed_out": false,
+            "out_of_nodes": false,
+            "intelligence": intelligence,
+            "book": book,
+            "mistake": !book,
+            "score": Value::Null,
+            "proven": false,
+            "disk_difference": Value::Null,
+            "depth": Value::Null,
+            "nodes": 0,
+            "explanation": Value::Null,
+            "timings": Value::Null,
+        });
     }
-    
-    let player = Player::parse(player.unwrap());
-    if player.is_err() {
-        return Err(BadRequest(Some("Invalid player".to_string())));
+
+    let decided = match deepen {
+        true => bot.deepen(&game, intelligence)
+            .map(|(action, result, depth)| (action, result, Some(depth))),
+        false => bot.decide(&game).map(|(action, result)| (action, result, None)),
+    };
+    let (action, result, depth) = match decided {
+        Ok((action, result, depth)) => (Some(action), result, depth),
+        Err(_) => (None, game.clone(), None), // No available actions
+    };
+    let (timed_out, out_of_nodes) = (bot.timed_out, bot.out_of_nodes);
+    let score = action.as_ref().map(|_| bot.score);
+    let mut nodes = bot.num_nodes_expanded;
+    // Explaining searches again, which measures the timings anew
+    let timings = match verbose {
+        true => timings(bot),
+        false => Value::Null,
+    };
+
+    // Only a search that went as deep as the empty squares may be replaced by the perfect play
+    let empties = game.board().num_empty();
+    let reached_end = !timed_out && !out_of_nodes
+        && depth.unwrap_or(intelligence) as usize >= empties;
+    let solved = match action.is_some() && reached_end && empties <= predict::SOLVE_EMPTIES {
+        true => {
+            // The solve spends what is left of the node limit of the search
+            let limit = bot.node_limit();
+            bot.set_node_limit(limit.map(|limit| limit.saturating_sub(nodes)));
+            let solved = bot.solve_actions(&game, Player::Bot)
+                .and_then(|ranking| ranking.into_iter().next());
+            bot.set_node_limit(limit);
+            nodes = nodes.saturating_add(bot.num_nodes_expanded);
+            solved
+        }
+        false => None,
+    };
+    let (action, result, score, difference) = match solved {
+        Some((action, difference)) => {
+            let result = game.result(&action);
+            (Some(action), result, None, Some(difference))
+        }
+        None => (action, result, score, None),
+    };
+
+    // The deadline of a deepened search is over once it decides
+    let explanation = match &action {
+        Some(action) if explain && !bot.timed_out && !deepen => explanation(bot, &game, action),
+        _ => Value::Null,
+    };
+
+    json!({
+        "decision": action.map(|a| a.to_string()),
+        "result": serialize_result(&result, format),
+        "timed_out": timed_out,
+        "out_of_nodes": out_of_nodes,
+        "intelligence": intelligence,
+        "book": false,
+        "mistake": false,
+        "score": score,
+        "proven": difference.is_some(),
+        "disk_difference": difference,
+        "depth": depth,
+        "nodes": nodes,
+        "explanation": explanation,
+        "timings": timings,
+    })
+}
+
+#[get("/decide?<board>&<intelligence>&<level>&<time_ms>&<max_nodes>&<variant>&<format>&\
+         <explain>&<verbose>")]
+#[allow(clippy::too_many_arguments)]
+async fn decide(board: form::Result<'_, Board>, intelligence: form::Result<'_, u32>,
+                level: form::Result<'_, Level>, time_ms: form::Result<'_, u64>,
+                max_nodes: form::Result<'_, u32>,
+                variant: Option<Variant>, format: Option<BoardFormat>, explain: Option<bool>,
+                verbose: Option<bool>, engine: &State<Engine>, book: &State<OpeningBook>,
+                model: &State<WinModel>)
+    -> Result<String, Custom<String>> {
+    let board = param(board, "board")?;
+    let config = engine.config();
+    let warnings = validation::consistency(&board, config)?;
+    let time_ms = validation::optional(time_ms, "time_ms")?;
+    let max_nodes = validation::node_budget(max_nodes, config)?;
+    // A budget of time or of nodes alone deepens the search as far as the budget allows
+    let budgeted = time_ms.is_some() || (max_nodes.is_some()
+        && validation::is_missing(&intelligence) && validation::is_missing(&level));
+    let (intelligence, preset, budget) = match budgeted {
+        true => (config.max_intelligence, None,
+                 validation::time_budget(time_ms, intelligence, level, config)?),
+        false => {
+            let (intelligence, preset) = validation::strength(intelligence, level, config)?;
+            (intelligence, preset, None)
+        }
+    };
+
+    let deepen = budget.is_some() || max_nodes.is_some();
+    let game = Game::parse(board, Player::Bot).with_variant(variant.unwrap_or_default());
+    let mistake = preset
+        .and_then(|preset| preset.mistake(&game, Player::Bot, &mut rand::thread_rng()));
+    // The book is built from standard games, whose moves lose under the other variants
+    let book_action = match game.variant() {
+        Variant::Standard if preset.is_none_or(|preset| preset.use_book) => {
+            book.lookup(&game, config.book_min_games)
+        }
+        _ => None,
+    };
+    let unsearched = mistake.map(Unsearched::Mistake).or(book_action.map(Unsearched::Book));
+
+    let format = format.unwrap_or_default();
+    let explain = explain.unwrap_or_default();
+    let verbose = verbose.unwrap_or_default();
+    let searched = game.clone();
+    let mut json = engine.search(intelligence, move |bot| {
+        // The budgets start with the search rather than while it waits for a thread
+        if let Some(budget) = budget {
+            bot.set_deadline(Instant::now() + budget);
+        }
+        if let Some(preset) = preset {
+            bot.set_deadline(Instant::now() + preset.think_time);
+            bot.set_node_limit(preset.node_limit);
+        }
+        if max_nodes.is_some() {
+            bot.set_node_limit(max_nodes);
+            // The deadline and the shuffled order would change which nodes the budget reaches
+            // from one request or one machine to the next
+            bot.clear_deadline();
+            bot.set_root_seed(None);
+        }
+        decision(bot, game, unsearched, intelligence, deepen, format, explain, verbose)
+    }).await?;
+    json["win_probability"] = match json["disk_difference"].as_i64() {
+        // The outcome of a proven decision is certain
+        Some(difference) => json!(match difference.signum() {
+            1 => 1.0,
+            0 => 0.5,
+            _ => 0.0,
+        }),
+        None => json!(json["score"].as_i64()
+            .map(|score| model.probability(&searched, score as i32))),
+    };
+    // The scale of the score changes with the phase of the searched position
+    json["phase"] = json!(searched.phase().to_string());
+    json["empties"] = json!(searched.board().num_empty());
+    json["warnings"] = json!(warnings);
+    json["level"] = json!(preset.map(|preset| preset.to_json()));
+
+    Ok(json.to_string())
+}
+
+#[get("/hint?<board>&<intelligence>&<variant>")]
+async fn hint(board: form::Result<'_, Board>, intelligence: form::Result<'_, u32>,
+              variant: Option<Variant>, engine: &State<Engine>)
+    -> Result<String, Custom<String>> {
+    let board = param(board, "board")?;
+    let warnings = validation::consistency(&board, engine.config())?;
+    let intelligence = param(intelligence, "intelligence")?;
+    let intelligence = validation::intelligence(intelligence, engine.config())?;
+
+    let mut bot = engine.bot(intelligence);
+    let game = Game::parse(board, Player::Human).with_variant(variant.unwrap_or_default());
+
+    let (bot, hint) = engine.run(move || {
+        let hint = bot.hint(&game);
+        (bot, hint)
+    }).await?;
+
+    let action = match hint {
+        Ok((action, _)) => action,
+        Err(_) => return Ok(json!({ // No available actions
+            "hint": Value::Null,
+            "timed_out": false,
+            "intelligence": intelligence,
+            "warnings": warnings,
+        }).to_string()),
+    };
+
+    // The score of the bot is positive when the bot is winning
+    let score = -bot.score;
+    let line = bot.principal_variation.iter()
+        .map(|a| a.to_string())
+        .collect_vec();
+
+    let json = json!({
+        "hint": action.to_string(),
+        "score": score,
+        "line": line,
+        "justification": format!("Playing {} leads to a score of {} for you after the line {}",
+                                 action, score, line.join(" ")),
+        "timed_out": bot.timed_out,
+        "intelligence": intelligence,
+        "warnings": warnings,
+    });
+
+    Ok(json.to_string())
+}
+
+/// Number of best moves listed when a request leaves it out, which the hints show side by side
+const DEFAULT_BEST_MOVES: usize = 3;
+
+/// Intelligence of the search of the best moves when a request leaves out the depth
+const BEST_MOVES_DEPTH: u32 = 4;
+
+/// Number of plies of the line listed with each of the best moves, counting the move itself
+const BEST_MOVE_LINE_LENGTH: usize = 6;
+
+/// Lists the given number of best actions of the given player, best first, with their scores for
+/// that player and the lines the search expects after them
+///
+/// Every action is searched with the full window so that their scores can be compared.
+#[get("/best-moves?<board>&<player>&<n>&<depth>&<variant>")]
+async fn best_moves(board: form::Result<'_, Board>, player: form::Result<'_, Player>,
+                    n: Option<usize>, depth: Option<u32>, variant: Option<Variant>,
+                    engine: &State<Engine>) -> Result<String, Custom<String>> {
+    let board = param(board, "board")?;
+    let warnings = validation::consistency(&board, engine.config())?;
+    let player = param(player, "player")?;
+    let n = n.unwrap_or(DEFAULT_BEST_MOVES);
+    if n == 0 {
+        return Err(validation::invalid(Status::UnprocessableEntity, "n",
+                                       "The number of moves must be positive"));
     }
-    let player = player.unwrap();
+    let depth = validation::intelligence(
+        depth.unwrap_or(validation::default_intelligence(BEST_MOVES_DEPTH, engine.config())),
+        engine.config())?;
 
-    let game = Game::parse(board.unwrap(), player.clone());
-    let action = Action::parse(player, Position::parse(position).unwrap());
-    
-    if !game.actions(player).contains(&action) {
-        return Err(BadRequest(Some("Invalid action for the given player".to_string())));
+    let game = Game::parse(board, player).with_variant(variant.unwrap_or_default());
+    let searched = game.clone();
+    let (ranking, timed_out) = engine.search(depth, move |bot| {
+        (bot.rank_lines(&searched, player), bot.timed_out)
+    }).await?;
+    if timed_out && ranking.is_empty() && !game.must_pass() {
+        return Err(Timeout(format!(
+            "No move was searched to depth {} in time", depth)).into());
     }
 
-    let game = game.result(&action);
-    
-    Ok(serialize_result(&game).to_string())
+    // The scores of the search are positive when the bot is ahead
+    let sign = match player {
+        Player::Bot => 1,
+        Player::Human => -1,
+    };
+    let moves = ranking.into_iter()
+        .take(n)
+        .map(|(action, value, line)| json!({
+            "move": action.to_string(),
+            "score": sign * value,
+            "line": iter::once(&action).chain(&line)
+                .take(BEST_MOVE_LINE_LENGTH)
+                .map(|a| a.to_string())
+                .collect_vec(),
+        }))
+        .collect_vec();
+
+    Ok(json!({
+        "player": player.to_string(),
+        "moves": moves,
+        "must_pass": game.must_pass(),
+        "timed_out": timed_out,
+        "depth": depth,
+        "warnings": warnings,
+    }).to_string())
 }
 
-#[get("/actions?<board>&<player>")]
-fn actions(board: String, player: String) -> Result<String, BadRequest<String>> {
-    let board = Board::parse(board);
-    if board.is_err() {
-        return Err(BadRequest(Some("Invalid board".to_string())));
+/// Checks if the given player may place a disk at the given position, and why not otherwise
+#[get("/legal?<board>&<player>&<position>&<variant>")]
+fn legal(board: form::Result<'_, Board>, player: form::Result<'_, Player>,
+         position: form::Result<'_, &str>, variant: Option<Variant>)
+    -> Result<String, Custom<String>> {
+    let board = param(board, "board")?;
+    let player = param(player, "player")?;
+    let position = param(position, "position")?;
+
+    let game = Game::parse(board, player).with_variant(variant.unwrap_or_default());
+    let reason = validation::illegality(&game, player, position)?;
+    Ok(json!({
+        "legal": reason.is_none(),
+        "reason": reason,
+    }).to_string())
+}
+
+#[get("/explain?<board>&<player>&<position>")]
+fn explain(board: form::Result<'_, Board>, player: form::Result<'_, Player>,
+           position: form::Result<'_, Position>) -> Result<String, Custom<String>> {
+    let board = param(board, "board")?;
+    let player = param(player, "player")?;
+    let position = param(position, "position")?;
+
+    let occupied = board.disk(&position).is_some();
+    let game = Game::parse(board, player);
+    let captures = game.captures(&Action::parse(player, position));
+
+    let json = json!({
+        "legal": !captures.is_empty(),
+        "occupied": occupied,
+        "directions": captures.iter()
+            .map(|(dir, _)| dir.to_string())
+            .collect_vec(),
+        "flips": captures.iter()
+            .flat_map(|(_, path)| path.iter().map(|p| p.to_string()))
+            .collect_vec(),
+    });
+
+    Ok(json.to_string())
+}
+
+/// Number of plies of a random position when a request leaves it out
+const DEFAULT_RANDOM_PLIES: u32 = 20;
+
+/// Number of empty squares of the initial board, which bounds the plies of a game
+const MAX_RANDOM_PLIES: u32 = 60;
+
+/// Intelligence of the bot playing the moves of random positions, which only need to be sensible
+const RANDOM_POSITION_INTELLIGENCE: u32 = 2;
+
+/// Thinking time of the bot for each move of a random position
+const RANDOM_POSITION_THINK_TIME: Duration = Duration::from_millis(100);
+
+/// Generates the position reached by playing the given number of plies from the initial board,
+/// chosen at random, from the opening book or by the bot, together with the moves leading to it
+///
+/// Every move is legal, so the position is reached in fewer plies if the game ends first.
+#[get("/random-position?<plies>&<quality>&<format>")]
+async fn random_position(plies: Option<u32>, quality: Option<Quality>,
+                         format: Option<BoardFormat>, engine: &State<Engine>,
+                         book: &State<OpeningBook>) -> Result<String, Custom<String>> {
+    let plies = plies.unwrap_or(DEFAULT_RANDOM_PLIES);
+    if plies > MAX_RANDOM_PLIES {
+        return Err(validation::invalid(Status::UnprocessableEntity, "plies", format!(
+            "The number of plies must be at most {}", MAX_RANDOM_PLIES)));
     }
+    let quality = quality.unwrap_or_default();
+    let config = engine.config();
 
-    let player = player.chars().next();
-    if player.is_none() {
-        return Err(BadRequest(Some("Invalid player".to_string())));
+    let mut game = Game::parse(Board::new(), Player::Human);
+    let mut moves = Vec::new();
+    {
+        let mut rng = rand::thread_rng();
+        match quality {
+            Quality::Random => selfplay::extend(&mut game, &mut moves, plies, |game| {
+                game.actions(game.current_player()).choose(&mut rng)
+            }),
+            Quality::Book => selfplay::extend(&mut game, &mut moves, plies, |game| {
+                book.sample(game, config.book_min_games, &mut rng)
+            }),
+            Quality::Engine => {}
+        }
+    }
+    // The bot plays on once the book runs out of moves
+    if quality != Quality::Random {
+        let intelligence = validation::default_intelligence(RANDOM_POSITION_INTELLIGENCE, config);
+        let mut bot = engine.bot(intelligence);
+        (game, moves) = engine.run(move || {
+            selfplay::extend(&mut game, &mut moves, plies, |game| {
+                selfplay::decide(&mut bot, game, RANDOM_POSITION_THINK_TIME)
+            });
+            (game, moves)
+        }).await?;
     }
 
-    let player = Player::parse(player.unwrap());
-    if player.is_err() {
-        return Err(BadRequest(Some("Invalid player".to_string())));
+    let mut json = serialize_result(&game, format.unwrap_or_default());
+    json["quality"] = json!(quality.to_string());
+    json["plies"] = json!(moves.iter().flatten().count());
+    json["moves"] = json!(moves.iter()
+        .map(|m| m.as_ref().map(|pos| pos.to_string()))
+        .collect_vec());
+    json["transcript"] = json!(ggf::transcript(&moves));
+    Ok(json.to_string())
+}
+
+/// Replays the given GGF record, where an illegal move fails the whole record
+fn replay_ggf(record: &str, format: BoardFormat) -> Result<String, Custom<String>> {
+    let record = ggf::parse(record)
+        .map_err(|e| validation::invalid(Status::BadRequest, "transcript", e))?;
+
+    let mut game = record.last;
+    if game.must_pass() {
+        game = game.pass();
     }
-    let player = player.unwrap();
-    
-    let game = Game::parse(board.unwrap(), player);
-    Ok(Value::Array(
-        game.actions(player)
-            .map(|a| Value::String(a.to_string()))
-            .collect_vec()
-    ).to_string())
+
+    let mut json = serialize_result(&game, format);
+    json["illegal_move"] = Value::Null;
+    Ok(json.to_string())
 }
 
-#[get("/decide?<board>&<intelligence>")]
-fn decide(board: String, intelligence: u32) -> Result<String, BadRequest<String>> {
-    let mut bot = Bot::new(intelligence);
-    let board = Board::parse(board);
-    if board.is_err() {
-        return Err(BadRequest(Some("Invalid board".to_string())));
+#[post("/replay?<format>", data = "<transcript>")]
+fn replay(transcript: Capped<String>, format: Option<BoardFormat>)
+    -> Result<String, Custom<String>> {
+    let transcript = limits::complete(transcript)?;
+    if transcript.trim_start().starts_with("(;") {
+        return replay_ggf(&transcript, format.unwrap_or_default());
     }
-    
-    let game = Game::parse(board.unwrap(), Player::Bot);
-    
-    let decision = bot.decide(&game);
-    
-    if decision.is_err() { // No available actions
-        let json = json!({
-            "decision": Value::Null,
-            "result": serialize_result(&game),
-        });
-        return Ok(json.to_string());
+
+    let transcript: Value = serde_json::from_str(&transcript)
+        .map_err(|_| validation::invalid(Status::BadRequest, "transcript", "Invalid transcript"))?;
+
+    let first = match transcript["first"].as_str() {
+        None => Player::Human,
+        Some(first) => Player::parse(first.chars().next().unwrap_or_default())
+            .map_err(|_| validation::invalid(Status::BadRequest, "first", "Invalid first player"))?,
+    };
+
+    let moves = transcript["moves"].as_array()
+        .ok_or_else(|| validation::invalid(Status::BadRequest, "moves", "Invalid moves"))?;
+
+    let mut game = Game::parse(Board::new(), first);
+    let mut illegal_move = None;
+
+    for (i, placement) in moves.iter().enumerate() {
+        if game.must_pass() {
+            game = game.pass();
+        }
+
+        let player = game.current_player();
+        let action = placement.as_str()
+            .and_then(|p| Position::parse(p.to_string()).ok())
+            .map(|p| Action::parse(player, p));
+
+        match action {
+            Some(action) if game.actions(player).contains(&action) => game = game.result(&action),
+            _ => {
+                illegal_move = Some(i);
+                break;
+            }
+        }
     }
-    
-    let (action, game) = decision.unwrap();
-    
-    let json = json!({
-        "decision": action.to_string(),
-        "result": serialize_result(&game),
-    });
-    
+
+    if game.must_pass() {
+        game = game.pass();
+    }
+
+    let mut json = serialize_result(&game, format.unwrap_or_default());
+    json["illegal_move"] = serde_json::to_value(illegal_move).unwrap_or(Value::Null);
+
     Ok(json.to_string())
 }
 
+/// Stops the server before it starts with invalid engine settings
+fn invalid_config(message: String) -> ! {
+    error!("Invalid engine configuration: {}", message);
+    std::process::exit(1)
+}
+
 #[rocket::main]
 async fn main() -> Result<(), rocket::Error> {
-    let allowed_origins: HashSet<String> = [
-        "http://localhost:443",
-        "https://localhost",
-        "https://localhost:80",  
-        "http://localhost:8080",
-        "http://localhost",
-        "http://desdemona.seoulsky.org",
-        "http://desdemona.seoulsky.org:443",
-        "https://desdemona.seoulsky.org",
-        ].iter()
-        .map(|s| s.to_string())
-        .collect();
+    let rocket = rocket::custom(config::figment());
+    let engine_config: EngineConfig = match rocket.figment().find_value("engine") {
+        Ok(_) => rocket.figment().extract_inner("engine")
+            .unwrap_or_else(|e| invalid_config(e.to_string())),
+        Err(_) => EngineConfig::default(),
+    };
+    if let Err(e) = engine_config.validate() {
+        invalid_config(e.to_string());
+    }
+
+    let storage = Storage::new(&engine_config.storage_dir);
+    let cancellation = CancellationToken::new();
+    let engine = Engine::new(engine_config.clone(), cancellation.clone());
+    let sessions = Sessions::new(&engine_config);
+    let accounts = Accounts::new(storage.clone());
+    if let Some(weights) = engine_config.weights {
+        game::set_weights(weights).unwrap_or_else(|e| invalid_config(e.to_string()));
+    }
+    if let Err(e) = weights::load(&storage) {
+        warn!("Ignoring the saved evaluation weights: {}", e);
+    }
+    if let Err(e) = probcut::load(&storage, &engine) {
+        warn!("Ignoring the saved ProbCut pairs: {}", e);
+    }
+    let xot = match &engine_config.xot_path {
+        Some(path) => match Xot::load(path) {
+            Ok(xot) => {
+                info!("Loaded {} XOT openings", xot.len());
+                xot
+            }
+            Err(e) => {
+                warn!("Starting without the XOT openings: {}", e);
+                Xot::default()
+            }
+        },
+        None => Xot::default(),
+    };
+
+    if engine_config.persisted_cache_size > 0 {
+        match engine.load_cache(&storage) {
+            Ok(restored) => info!("Restored {} cached evaluations", restored),
+            Err(e) => warn!("Starting with an empty evaluation cache: {}", e),
+        }
+    }
+    let analysis = ResponseCache::new(engine_config.analysis_cache_size);
+    let adaptation = Adaptation::new(&engine_config, storage.clone(), engine.clone(),
+                                     analysis.clone(), sessions.clone());
+    let book = OpeningBook::new(storage.clone());
+    if let Some(path) = engine_config.book_path.as_deref().filter(|_| book.num_positions() == 0) {
+        match book.import_file(path, engine_config.book_plies) {
+            Ok((imported, _)) => info!("Imported {} games into the opening book", imported),
+            Err(e) => warn!("Starting with an empty opening book: {}", e),
+        }
+    }
 
-    rocket::build()
-        .mount("/api", routes![index, initial_board, evaluate, result, actions, decide])
-        .attach(Cors::new(allowed_origins))
+    rocket
+        .mount("/api", routes![index, initial_board, evaluate, evaluate_detail, result, pass,
+                        actions, mobility, stability, decide, hint, best_moves, replay, legal,
+                        explain, random_position])
+        .mount("/api", session::routes())
+        .mount("/api", account::routes())
+        .mount("/api", rating::routes())
+        .mount("/api", archive::routes())
+        .mount("/api", tournament::routes())
+        .mount("/api", generation::routes())
+        .mount("/api", puzzle::routes())
+        .mount("/api", book::routes())
+        .mount("/api", weights::routes())
+        .mount("/api", graphql::routes())
+        .mount("/api", job::routes())
+        .mount("/api", render::routes())
+        .mount("/api", metrics::routes())
+        .mount("/api", engine::routes())
+        .mount("/api", analysis::routes())
+        .mount("/api", predict::routes())
+        .mount("/api", probability::routes())
+        .mount("/api", probcut::routes())
+        .attach(InputLimits::new(&engine_config))
+        .attach(Cors::new(engine_config.allowed_origins.iter().cloned().collect()))
+        .attach(BinaryEncoding)
+        .attach(Compression)
+        .manage(graphql::schema(sessions.clone(), accounts.clone(), engine.clone()))
+        .manage(cancellation)
+        .manage(engine)
+        .manage(analysis)
+        .manage(adaptation)
+        .manage(Calibration::load(&storage))
+        .manage(WinModel::load(&storage))
+        .manage(engine_config)
+        .manage(sessions)
+        .manage(accounts)
+        .manage(BotRatings::new(storage.clone()))
+        .manage(Archive::new(storage.clone()))
+        .manage(book)
+        .manage(storage)
+        .manage(Tournaments::default())
+        .manage(Runs::default())
+        .manage(Jobs::default())
+        .manage(xot)
+        .manage(Thumbnails::default())
+        .attach(AdHoc::on_liftoff("Session Sweeper", |rocket| Box::pin(async move {
+            let sessions = rocket.state::<Sessions>();
+            let storage = rocket.state::<Storage>();
+            let accounts = rocket.state::<Accounts>();
+            let ratings = rocket.state::<BotRatings>();
+            let config = rocket.state::<EngineConfig>();
+            if let (Some(sessions), Some(storage), Some(accounts), Some(ratings), Some(config)) =
+                (sessions, storage, accounts, ratings, config) {
+                rocket::tokio::spawn(session::sweep(sessions.clone(), storage.clone(),
+                                                    accounts.clone(), ratings.clone(),
+                                                    config.clone(), rocket.shutdown()));
+            }
+        })))
+        .attach(session::Persistence)
+        .attach(AdHoc::on_shutdown("Search Drainer", |rocket| Box::pin(async move {
+            // In-flight searches return their best action so far within the grace period
+            if let Some(cancellation) = rocket.state::<CancellationToken>() {
+                cancellation.cancel();
+            }
+            if let (Some(engine), Some(storage)) = (rocket.state::<Engine>(),
+                                                    rocket.state::<Storage>()) {
+                if engine.config().persisted_cache_size > 0 {
+                    if let Err(e) = engine.save_cache(storage) {
+                        warn!("Failed to save the evaluation cache: {}", e);
+                    }
+                }
+            }
+        })))
         .launch()
         .await?;
 
@@ -193,7 +1027,8 @@ impl Fairing for Cors {
 
         if self.allowed_origins.contains(origin) {
             response.set_header(Header::new("Access-Control-Allow-Origin", origin));
-            response.set_header(Header::new("Access-Control-Allow-Methods", "GET"));
+            response.set_header(Header::new("Access-Control-Allow-Methods",
+                                            "GET, POST, PUT, PATCH"));
             response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
             response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
         }
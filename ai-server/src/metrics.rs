@@ -0,0 +1,20 @@
+use rocket::{Route, State};
+use serde_json::json;
+
+use crate::admin::Admin;
+use crate::engine::Engine;
+use crate::lru::ResponseCache;
+
+/// Returns the counters of the caches of the server
+#[get("/admin/metrics")]
+fn get(_admin: Admin, analysis: &State<ResponseCache>, engine: &State<Engine>) -> String {
+    json!({
+        "analysis_cache": analysis.to_json(),
+        "evaluation_cache": engine.cache_json(),
+    }).to_string()
+}
+
+/// Returns the routes of the metrics of the server
+pub fn routes() -> Vec<Route> {
+    routes![get]
+}
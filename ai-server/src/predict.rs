@@ -0,0 +1,237 @@
+use std::sync::RwLock;
+
+use rocket::form;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::{Route, State};
+use serde_json::json;
+
+use crate::admin::Admin;
+use crate::board::{Board, BOARD_SIZE};
+use crate::book::{Sample, DATASETS, WTHOR_DATASET};
+use crate::engine::Engine;
+use crate::game::{self, Game, Player};
+use crate::storage::Storage;
+use crate::validation;
+
+const CONFIG: &str = "config";
+const CALIBRATION_KEY: &str = "calibration";
+
+/// Number of empty squares from which the final disk difference is searched to the end
+pub const SOLVE_EMPTIES: usize = 10;
+
+/// Intelligence of the search whose score is scaled into a disk difference before the endgame
+const DEFAULT_INTELLIGENCE: u32 = 4;
+
+/// Returns the disk difference per point of evaluation before any calibration, which scales the
+/// best evaluation to a game won with every disk
+fn default_scale() -> f64 {
+    (BOARD_SIZE * BOARD_SIZE) as f64 / game::max_best_evaluation().max(1) as f64
+}
+
+/// Fits the scale of each phase of the game by least squares to the final disk differences of the
+/// given samples, keeping the default scale of the phases without samples
+fn fit(samples: &[Sample]) -> [f64; 3] {
+    let weights = game::weights();
+    // Sums of the products of the evaluations and the disk differences, and of the squares of
+    // the evaluations
+    let mut sums = [(0.0, 0.0); 3];
+
+    for sample in samples {
+        let board = match Board::parse(sample.board.clone()) {
+            Ok(board) => board,
+            Err(_) => continue,
+        };
+        let player = match Player::parse(sample.player.chars().next().unwrap_or_default()) {
+            Ok(player) => player,
+            Err(_) => continue,
+        };
+
+        let breakdown = Game::parse(board, player).breakdown(&weights);
+        let value = breakdown.total() as f64;
+        // The samples count the disks of the human first, unlike the evaluation
+        let difference = -sample.disk_difference as f64;
+
+        let (product, square) = &mut sums[breakdown.phase.to_index()];
+        *product += value * difference;
+        *square += value * value;
+    }
+
+    sums.map(|(product, square)| match square {
+        square if square > 0.0 => product / square,
+        _ => default_scale(),
+    })
+}
+
+/// Scales of the evaluations into final disk differences in each phase of the game
+pub struct Calibration {
+    scales: RwLock<Option<[f64; 3]>>,
+}
+
+impl Calibration {
+
+    /// Loads the calibration saved in the given storage, if any
+    pub fn load(storage: &Storage) -> Self {
+        Self {
+            scales: RwLock::new(storage.load(CONFIG, CALIBRATION_KEY)),
+        }
+    }
+
+    /// Predicts the final disk difference of a position of the given phase from its score
+    fn predict(&self, phase: usize, value: i32) -> i32 {
+        let scale = self.scales.read().unwrap().map_or_else(default_scale, |scales| scales[phase]);
+        let max_difference = (BOARD_SIZE * BOARD_SIZE) as i32;
+        ((scale * value as f64).round() as i32).clamp(-max_difference, max_difference)
+    }
+}
+
+/// Predicts the final disk difference of the given position, which is positive when the bot wins
+///
+/// The last empty squares are searched to the end of the game, while the score of the search of
+/// an earlier position is scaled by the calibration.
+#[get("/predict?<board>&<player>&<intelligence>")]
+async fn predict(board: form::Result<'_, Board>, player: form::Result<'_, Player>,
+                 intelligence: Option<u32>, engine: &State<Engine>,
+                 calibration: &State<Calibration>) -> Result<String, Custom<String>> {
+    let board = validation::param(board, "board")?;
+    let player = validation::param(player, "player")?;
+    let intelligence = validation::intelligence(
+        intelligence.unwrap_or(validation::default_intelligence(DEFAULT_INTELLIGENCE,
+                                                                engine.config())),
+        engine.config())?;
+
+    let mut game = Game::parse(board, player);
+    if game.must_pass() {
+        game = game.pass();
+    }
+    let empties = game.board().num_empty();
+
+    let solved = match game.is_over() || empties <= SOLVE_EMPTIES {
+        true => {
+            let game = game.clone();
+            engine.search(intelligence, move |bot| bot.solve(&game)).await?
+        }
+        false => None,
+    };
+    // The search runs out of time on positions with many moves left
+    let (difference, exact) = match solved {
+        Some(difference) => (difference, true),
+        None => {
+            let phase = game.phase().to_index();
+            let searched = game.clone();
+            let value = engine.search(intelligence, move |bot| {
+                bot.rank(&searched, searched.current_player()).first().map(|&(_, value)| value)
+            }).await?;
+            (calibration.predict(phase, value.unwrap_or_else(|| game.evaluate())), false)
+        }
+    };
+
+    Ok(json!({
+        "disk_difference": difference,
+        "winner": match difference {
+            0 => None,
+            difference if difference > 0 => Some(Player::Bot.to_string()),
+            _ => Some(Player::Human.to_string()),
+        },
+        "margin": difference.abs(),
+        "exact": exact,
+        "empties": empties,
+        "intelligence": intelligence,
+    }).to_string())
+}
+
+/// Fits the calibration to the positions of the imported WTHOR games, which must be done again
+/// after the evaluation weights change
+#[post("/admin/predict/calibrate")]
+async fn calibrate(_admin: Admin, storage: &State<Storage>, engine: &State<Engine>,
+                   calibration: &State<Calibration>) -> Result<String, Custom<String>> {
+    let samples: Vec<Sample> = storage.load_lines(DATASETS, WTHOR_DATASET);
+    if samples.is_empty() {
+        return Err(Custom(Status::Conflict,
+                          "No imported games to calibrate the prediction with".to_string()));
+    }
+
+    let num_samples = samples.len();
+    let scales = engine.run(move || fit(&samples)).await?;
+    storage.save(CONFIG, CALIBRATION_KEY, &scales)?;
+    *calibration.scales.write().unwrap() = Some(scales);
+
+    Ok(json!({
+        "samples": num_samples,
+        "scales": scales,
+    }).to_string())
+}
+
+/// Returns the routes of the prediction of final scores
+pub fn routes() -> Vec<Route> {
+    routes![predict, calibrate]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::book::Sample;
+    use crate::bot::Bot;
+    use crate::game::{self, Game, Player};
+    use crate::predict::{default_scale, fit};
+
+    /// Finds the final disk difference by searching every line to the end
+    fn exhaustive(game: &Game) -> i32 {
+        if game.is_over() {
+            let board = game.board();
+            return board.num_disks(Player::Bot.disk()) - board.num_disks(Player::Human.disk());
+        }
+
+        let player = game.current_player();
+        let values = game.actions(player).map(|a| exhaustive(&game.result(&a))).collect::<Vec<_>>();
+        match (player, values.is_empty()) {
+            (_, true) => exhaustive(&game.pass()),
+            (Player::Bot, false) => values.into_iter().max().unwrap(),
+            (Player::Human, false) => values.into_iter().min().unwrap(),
+        }
+    }
+
+    #[test]
+    fn solve_endgame() {
+        let mut game = Game::new();
+        while game.board().num_disks(Player::Bot.disk())
+            + game.board().num_disks(Player::Human.disk()) < 57 {
+            let action = game.actions(game.current_player()).last();
+            game = match action {
+                Some(action) => game.result(&action),
+                None => game.pass(),
+            };
+        }
+
+        assert_eq!(Bot::new(1).solve(&game), Some(exhaustive(&game)));
+
+        // The best action leads to the proven outcome of the position
+        let player = game.current_player();
+        let ranking = Bot::new(1).solve_actions(&game, player).unwrap();
+        assert_eq!(ranking.len(), game.actions(player).count());
+        for (action, difference) in &ranking {
+            assert_eq!(*difference, exhaustive(&game.result(action)));
+        }
+        assert_eq!(ranking[0].1, exhaustive(&game));
+    }
+
+    #[test]
+    fn fit_scales() {
+        let mut game = Game::new();
+        let mut samples = Vec::new();
+        for _ in 0..12 {
+            let player = game.current_player();
+            let evaluation = game.breakdown(&game::weights()).total();
+            samples.push(Sample {
+                board: game.board().to_string(),
+                player: player.to_string(),
+                disk_difference: -2 * evaluation,
+            });
+            let action = game.actions(player).next().unwrap();
+            game = game.result(&action);
+        }
+
+        let scales = fit(&samples);
+        assert!((scales[0] - 2.0).abs() < 1e-9);
+        assert_eq!(scales[2], default_scale());
+    }
+}
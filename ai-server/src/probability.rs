@@ -0,0 +1,211 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::{Route, State};
+use serde_json::json;
+
+use crate::admin::Admin;
+use crate::bot::CancellationToken;
+use crate::engine::Engine;
+use crate::game::{self, Game, Player};
+use crate::lru::ResponseCache;
+use crate::selfplay::{self, GameRecord};
+use crate::storage::Storage;
+use crate::validation;
+
+const CONFIG: &str = "config";
+const WIN_MODEL_KEY: &str = "win-model";
+
+/// Plies played at random before each self-play game, so that the games differ
+const OPENING_PLIES: u32 = 8;
+
+const DEFAULT_GAMES: u32 = 100;
+const MAX_GAMES: u32 = 1000;
+const DEFAULT_INTELLIGENCE: u32 = 2;
+
+/// Thinking time of each move of the self-play games
+const THINK_TIME: Duration = Duration::from_millis(500);
+
+const NEWTON_ITERATIONS: usize = 50;
+
+/// Largest slope a fit may reach as a multiple of the default one, since the outcomes of a phase
+/// in which the evaluation never misjudges a game would push the slope to infinity
+const MAX_SLOPE_FACTOR: f64 = 100.0;
+
+/// Returns the slope of the logistic curve before any fit, under which the best evaluation is
+/// almost a sure win
+fn default_slope() -> f64 {
+    4.0 / game::max_best_evaluation().max(1) as f64
+}
+
+pub fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Position of a self-play game: its phase, its evaluation and the result of the bot, which is 1
+/// for a win, 0.5 for a draw and 0 for a loss
+type Sample = (usize, i32, f64);
+
+/// Replays the given record of a self-play game from the given state, returning the samples of
+/// its positions, or none if the game was not played to the end or by the rules
+fn game_samples(start: Game, record: &GameRecord) -> Vec<Sample> {
+    let Some(states) = selfplay::replay(start, record) else {
+        return Vec::new();
+    };
+    let (last, played) = states.split_last().expect("A replay starts from a state");
+
+    let outcome = match last.winner() {
+        Ok(Some(Player::Bot)) => 1.0,
+        Ok(Some(Player::Human)) => 0.0,
+        Ok(None) => 0.5,
+        Err(_) => return Vec::new(),
+    };
+    played.iter().map(|game| (game.phase().to_index(), game.evaluate(), outcome)).collect()
+}
+
+/// Fits the slope of each phase of the game by Newton's method on the log loss of the given
+/// samples, keeping the default slope of the phases without samples
+fn fit(samples: &[Sample]) -> [f64; 3] {
+    let default = default_slope();
+    let mut slopes = [default; 3];
+
+    for (phase, slope) in slopes.iter_mut().enumerate() {
+        for _ in 0..NEWTON_ITERATIONS {
+            let (mut gradient, mut hessian) = (0.0, 0.0);
+            for &(_, value, outcome) in samples.iter().filter(|sample| sample.0 == phase) {
+                let value = value as f64;
+                let probability = sigmoid(*slope * value);
+                gradient += (probability - outcome) * value;
+                hessian += probability * (1.0 - probability) * value * value;
+            }
+            if hessian <= 0.0 {
+                break;
+            }
+
+            let previous = *slope;
+            *slope = (*slope - gradient / hessian).clamp(0.0, default * MAX_SLOPE_FACTOR);
+            if (*slope - previous).abs() <= f64::EPSILON * previous.abs() {
+                break;
+            }
+        }
+    }
+
+    slopes
+}
+
+/// Logistic curves mapping the evaluations of each phase of the game to the probability that the
+/// bot wins
+///
+/// Clones share the same curves.
+#[derive(Clone)]
+pub struct WinModel {
+    slopes: Arc<RwLock<Option<[f64; 3]>>>,
+}
+
+impl WinModel {
+
+    /// Loads the model saved in the given storage, if any
+    pub fn load(storage: &Storage) -> Self {
+        Self {
+            slopes: Arc::new(RwLock::new(storage.load(CONFIG, WIN_MODEL_KEY))),
+        }
+    }
+
+    /// Returns the probability that the bot wins the given game, whose value is the given
+    /// evaluation or score of a search
+    pub fn probability(&self, game: &Game, value: i32) -> f64 {
+        sigmoid(self.slope(game.phase().to_index()) * value as f64)
+    }
+
+    /// Returns the slope of the logistic curve of the phase of the game with the given index
+    pub fn slope(&self, phase: usize) -> f64 {
+        self.slopes.read().unwrap().map_or_else(default_slope, |slopes| slopes[phase])
+    }
+}
+
+/// Fits the model to the outcomes of self-play games between two bots of the given intelligence,
+/// which must be done again after the evaluation weights change
+#[post("/admin/win-model/fit?<games>&<intelligence>")]
+#[allow(clippy::too_many_arguments)]
+async fn train(_admin: Admin, games: Option<u32>, intelligence: Option<u32>,
+               storage: &State<Storage>, engine: &State<Engine>, model: &State<WinModel>,
+               analysis: &State<ResponseCache>, cancellation: &State<CancellationToken>)
+    -> Result<String, Custom<String>> {
+    let games = games.unwrap_or(DEFAULT_GAMES);
+    if games == 0 || games > MAX_GAMES {
+        return Err(validation::invalid(Status::BadRequest, "games",
+                          format!("The number of games must be between 1 and {}", MAX_GAMES)));
+    }
+    let intelligence = validation::intelligence(
+        intelligence.unwrap_or(validation::default_intelligence(DEFAULT_INTELLIGENCE,
+                                                                engine.config())),
+        engine.config())?;
+
+    let mut human = engine.bot(intelligence);
+    let mut bot = engine.bot(intelligence);
+    let cancellation = cancellation.inner().clone();
+    let samples = engine.run(move || {
+        let mut rng = rand::thread_rng();
+        let mut samples = Vec::new();
+        for _ in 0..games {
+            let start = selfplay::random_opening(OPENING_PLIES, &mut rng);
+            let record = selfplay::play(start.clone(), &mut human, &mut bot, THINK_TIME,
+                                        &cancellation);
+            samples.extend(game_samples(start, &record));
+        }
+        samples
+    }).await?;
+
+    let slopes = fit(&samples);
+    storage.save(CONFIG, WIN_MODEL_KEY, &slopes)?;
+    *model.slopes.write().unwrap() = Some(slopes);
+    // The cached evaluations hold the probabilities of the previous model
+    analysis.clear();
+
+    Ok(json!({
+        "games": games,
+        "samples": samples.len(),
+        "slopes": slopes,
+    }).to_string())
+}
+
+/// Returns the routes of the win probability model
+pub fn routes() -> Vec<Route> {
+    routes![train]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bot::{Bot, CancellationToken};
+    use crate::probability::{default_slope, fit, game_samples, sigmoid};
+    use crate::selfplay;
+
+    #[test]
+    fn fit_slopes() {
+        // Outcomes that are exactly as likely as a known curve predicts
+        let slope = 0.01;
+        let samples = (-50..=50)
+            .map(|value| (0, value * 10, sigmoid(slope * (value * 10) as f64)))
+            .collect::<Vec<_>>();
+
+        let slopes = fit(&samples);
+        assert!((slopes[0] - slope).abs() < 1e-9);
+        assert_eq!(slopes[1], default_slope());
+    }
+
+    #[test]
+    fn sample_self_play() {
+        let start = selfplay::random_opening(4, &mut rand::thread_rng());
+        let record = selfplay::play(start.clone(), &mut Bot::new(1), &mut Bot::new(1),
+                                    Duration::from_secs(1), &CancellationToken::new());
+
+        let samples = game_samples(start, &record);
+        assert_eq!(samples.len(), record.moves.len());
+        let outcome = samples[0].2;
+        assert!(samples.iter().all(|sample| sample.2 == outcome));
+    }
+}
@@ -0,0 +1,267 @@
+use std::time::Duration;
+
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::{Route, State};
+use serde_json::json;
+
+use crate::admin::Admin;
+use crate::bot::{Bot, CancellationToken, CutFit, CutPair};
+use crate::engine::Engine;
+use crate::errors::Error;
+use crate::errors::Error::InvalidArgument;
+use crate::game::Game;
+use crate::lru::ResponseCache;
+use crate::selfplay;
+use crate::session::Sessions;
+use crate::storage::Storage;
+use crate::validation;
+
+const CONFIG: &str = "config";
+const PROBCUT_KEY: &str = "probcut";
+
+/// Plies played at random before each self-play game, so that the games differ
+const OPENING_PLIES: u32 = 8;
+
+const DEFAULT_GAMES: u32 = 10;
+const MAX_GAMES: u32 = 200;
+
+/// Shallowest depth a pair cuts at, below which the shallow searches save nothing
+const MIN_DEPTH: u32 = 3;
+const DEFAULT_MAX_DEPTH: u32 = 4;
+
+/// Difference between the depths of the deep and the shallow searches of each pair
+const DEPTH_GAP: u32 = 2;
+
+/// Intelligence of the bots playing the self-play games
+const PLAYER_INTELLIGENCE: u32 = 2;
+
+/// Thinking time of each move of the self-play games
+const THINK_TIME: Duration = Duration::from_millis(200);
+
+/// Fewest positions of a phase that a fit is made from, below which the phase is never cut
+const MIN_SAMPLES: usize = 10;
+
+/// Position of a self-play game: its phase and its values searched to each depth from 0
+type Sample = (usize, Vec<i32>);
+
+/// Checks that the shallow search of each of the given pairs is shallower than its deep one and
+/// that their fits are finite
+pub fn validate(pairs: &[CutPair]) -> Result<(), Error> {
+    if pairs.iter().any(|pair| pair.shallow_depth >= pair.depth) {
+        return Err(InvalidArgument(
+            "The shallow depth of each ProbCut pair must be below its depth".to_string()));
+    }
+    let finite = |fit: &CutFit| fit.slope.is_finite() && fit.intercept.is_finite()
+        && fit.deviation.is_finite() && fit.deviation >= 0.0;
+    if !pairs.iter().flat_map(|pair| &pair.fits).all(finite) {
+        return Err(InvalidArgument(
+            "The fits of ProbCut must be finite with a non-negative deviation".to_string()));
+    }
+    Ok(())
+}
+
+/// Replaces the depth pairs of Multi-ProbCut with the ones fitted and saved in the given storage,
+/// if any
+pub fn load(storage: &Storage, engine: &Engine) -> Result<(), Error> {
+    if let Some(pairs) = storage.load::<Vec<CutPair>>(CONFIG, PROBCUT_KEY) {
+        validate(&pairs)?;
+        engine.set_probcut(pairs);
+    }
+    Ok(())
+}
+
+/// Drops the depth pairs of Multi-ProbCut, which were fitted to the previous evaluation weights,
+/// until they are fitted again
+pub fn reset(storage: &Storage, engine: &Engine) -> Result<(), Error> {
+    if engine.probcut().is_some() {
+        info!("Dropped the ProbCut pairs fitted to the previous evaluation weights");
+    }
+    engine.set_probcut(Vec::new());
+    storage.remove(CONFIG, PROBCUT_KEY)
+}
+
+/// Fits the deep values of the given pairs of shallow and deep values to the shallow ones by
+/// least squares
+fn fit(values: &[(i32, i32)]) -> CutFit {
+    if values.len() < MIN_SAMPLES {
+        return CutFit::default();
+    }
+
+    let n = values.len() as f64;
+    let mean_shallow = values.iter().map(|&(shallow, _)| shallow as f64).sum::<f64>() / n;
+    let mean_deep = values.iter().map(|&(_, deep)| deep as f64).sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for &(shallow, deep) in values {
+        let difference = shallow as f64 - mean_shallow;
+        covariance += difference * (deep as f64 - mean_deep);
+        variance += difference * difference;
+    }
+    if variance <= 0.0 {
+        return CutFit::default();
+    }
+
+    let slope = covariance / variance;
+    let intercept = mean_deep - slope * mean_shallow;
+    let squared_error = values.iter()
+        .map(|&(shallow, deep)| (deep as f64 - slope * shallow as f64 - intercept).powi(2))
+        .sum::<f64>();
+    CutFit {
+        slope,
+        intercept,
+        deviation: (squared_error / n).sqrt(),
+    }
+}
+
+/// Fits the pair of the given depths to the given samples, phase by phase
+fn fit_pair(samples: &[Sample], depth: u32, shallow_depth: u32) -> CutPair {
+    let fits = [0, 1, 2].map(|phase| {
+        let values = samples.iter()
+            .filter(|(sample_phase, _)| *sample_phase == phase)
+            .map(|(_, values)| (values[shallow_depth as usize], values[depth as usize]))
+            .collect::<Vec<_>>();
+        fit(&values)
+    });
+    CutPair { depth, shallow_depth, fits }
+}
+
+/// Searches the given states of a self-play game to every depth up to the given one, leaving out
+/// those over or to be passed, and stops once the searches are cancelled
+fn game_samples(states: &[Game], searcher: &mut Bot, max_depth: u32,
+                cancellation: &CancellationToken) -> Vec<Sample> {
+    states.iter()
+        .filter(|game| !game.is_over() && !game.must_pass())
+        .map_while(|game| {
+            let values = (0..=max_depth).map(|depth| searcher.value(game, depth))
+                .collect::<Vec<_>>();
+            (!cancellation.is_cancelled()).then(|| (game.phase().to_index(), values))
+        })
+        .collect()
+}
+
+/// Fits the depth pairs of Multi-ProbCut up to the given depth to the positions of self-play
+/// games, which are dropped when the evaluation weights change
+#[post("/admin/probcut/fit?<games>&<max_depth>")]
+#[allow(clippy::too_many_arguments)]
+async fn train(_admin: Admin, games: Option<u32>, max_depth: Option<u32>,
+               storage: &State<Storage>, engine: &State<Engine>, analysis: &State<ResponseCache>,
+               sessions: &State<Sessions>, cancellation: &State<CancellationToken>)
+    -> Result<String, Custom<String>> {
+    let games = games.unwrap_or(DEFAULT_GAMES);
+    if games == 0 || games > MAX_GAMES {
+        return Err(validation::invalid(Status::BadRequest, "games",
+                          format!("The number of games must be between 1 and {}", MAX_GAMES)));
+    }
+    let max_depth = max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+    let deepest = engine.config().max_intelligence;
+    if !(MIN_DEPTH..=deepest).contains(&max_depth) {
+        return Err(validation::invalid(Status::BadRequest, "max_depth", format!(
+            "The deepest depth must be between {} and {}", MIN_DEPTH, deepest)));
+    }
+
+    let mut human = engine.bot(PLAYER_INTELLIGENCE);
+    let mut bot = engine.bot(PLAYER_INTELLIGENCE);
+    // The values are fitted without the pruning they are fitted for
+    let mut searcher = Bot::new(max_depth);
+    let cancellation = cancellation.inner().clone();
+    searcher.set_cancellation(cancellation.clone());
+    let samples = engine.run(move || {
+        let mut rng = rand::thread_rng();
+        let mut samples = Vec::new();
+        for _ in 0..games {
+            let start = selfplay::random_opening(OPENING_PLIES, &mut rng);
+            let record = selfplay::play(start.clone(), &mut human, &mut bot, THINK_TIME,
+                                        &cancellation);
+            if let Some(states) = selfplay::replay(start, &record) {
+                samples.extend(game_samples(&states, &mut searcher, max_depth, &cancellation));
+            }
+        }
+        samples
+    }).await?;
+
+    let pairs = (MIN_DEPTH..=max_depth)
+        .map(|depth| fit_pair(&samples, depth, depth - DEPTH_GAP))
+        .collect::<Vec<_>>();
+    storage.save(CONFIG, PROBCUT_KEY, &pairs)?;
+    engine.set_probcut(pairs.clone());
+    // The analyses and the bots of the sessions were made with the previous pairs
+    analysis.clear();
+    sessions.clear_bots();
+
+    Ok(json!({
+        "games": games,
+        "samples": samples.len(),
+        "pairs": pairs,
+    }).to_string())
+}
+
+/// Returns the routes of Multi-ProbCut
+pub fn routes() -> Vec<Route> {
+    routes![train]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bot::{Bot, CancellationToken, CutFit, CutPair, ProbCut};
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+    use crate::game::{Game, Player};
+    use crate::probcut::{fit_pair, load, reset, validate, CONFIG, PROBCUT_KEY};
+    use crate::storage::Storage;
+
+    #[test]
+    fn fit_pairs() {
+        // Deep values of the early phase are twice the shallow ones plus 5, off by 3 either way
+        let samples = (0..100)
+            .map(|x| (0, vec![0, x, 0, 2 * x + 5 + if x % 2 == 0 { 3 } else { -3 }]))
+            .collect::<Vec<_>>();
+
+        let pair = fit_pair(&samples, 3, 1);
+        let early = pair.fits[0];
+        assert!((early.slope - 2.0).abs() < 0.01);
+        assert!((early.intercept - 5.0).abs() < 1.0);
+        assert!((early.deviation - 3.0).abs() < 0.01);
+        // The other phases have no samples, so they never cut
+        assert_eq!(pair.fits[1], CutFit::default());
+        let invalid = CutPair { shallow_depth: 3, ..pair.clone() };
+        assert!(validate(&[pair]).is_ok());
+        assert!(validate(&[invalid]).is_err());
+    }
+
+    #[test]
+    fn cuts() {
+        let fit = CutFit { slope: 1.0, intercept: 0.0, deviation: 0.0 };
+        let probcut = ProbCut {
+            pairs: vec![CutPair { depth: 3, shallow_depth: 1, fits: [fit; 3] }],
+            confidence: 1.0,
+        };
+
+        let mut full = Bot::new(4);
+        assert!(full.decide(&Game::new()).is_ok());
+        let mut pruned = Bot::new(4);
+        pruned.set_probcut(Some(probcut));
+        let (action, _) = pruned.decide(&Game::new()).unwrap();
+
+        assert!(pruned.num_nodes_expanded < full.num_nodes_expanded);
+        assert!(Game::new().actions(Player::Bot).any(|legal| legal == action));
+    }
+
+    #[test]
+    fn reset_pairs() {
+        let dir = std::env::temp_dir().join(format!("probcut-{}", std::process::id()));
+        let storage = Storage::new(&dir);
+        let engine = Engine::new(EngineConfig::default(), CancellationToken::new());
+        let fit = CutFit { slope: 1.0, intercept: 0.0, deviation: 1.0 };
+        let pairs = vec![CutPair { depth: 3, shallow_depth: 1, fits: [fit; 3] }];
+        storage.save(CONFIG, PROBCUT_KEY, &pairs).unwrap();
+        load(&storage, &engine).unwrap();
+        assert!(engine.probcut().is_some());
+
+        // The pairs are dropped for good, so that a restart does not bring them back
+        reset(&storage, &engine).unwrap();
+        load(&storage, &engine).unwrap();
+        assert!(engine.probcut().is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
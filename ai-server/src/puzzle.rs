@@ -0,0 +1,235 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use rand::seq::SliceRandom;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Route, State};
+use serde_json::json;
+
+use crate::admin::Admin;
+use crate::bot::{Bot, CancellationToken};
+use crate::board::Board;
+use crate::engine::Engine;
+use crate::errors::Error;
+use crate::game::{Action, Game, Player};
+use crate::selfplay;
+use crate::storage::Storage;
+
+const PUZZLES: &str = "puzzles";
+
+/// Number of random plies played before mining a self-play game, so that games differ
+const OPENING_PLIES: u32 = 6;
+
+/// A position where one action is clearly better than all the others
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Puzzle {
+    pub id: String,
+    pub board: String,
+    pub player: String,
+    pub solution: String,
+    pub score_gap: i32,
+}
+
+/// Returns a puzzle if the best action of the ranking is better than the second best by at
+/// least the given gap
+fn to_puzzle(game: &Game, ranking: &[(Action, i32)], min_gap: i32) -> Option<Puzzle> {
+    if ranking.len() < 2 {
+        return None;
+    }
+
+    let gap = (ranking[0].1 - ranking[1].1).abs();
+    if gap < min_gap {
+        return None;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    game.board().hash(&mut hasher);
+    game.current_player().hash(&mut hasher);
+
+    Some(Puzzle {
+        id: format!("{:x}", hasher.finish()),
+        board: game.board().to_string(),
+        player: game.current_player().to_string(),
+        solution: ranking[0].0.to_string(),
+        score_gap: gap,
+    })
+}
+
+/// Plays a self-play game from a random opening with the given bot and returns the puzzles found
+/// along the way
+///
+/// Every position is ranked by the bot and the best action is played, until the game is over or
+/// the search of the bot is stopped.
+pub fn mine(bot: &mut Bot, min_gap: i32) -> Vec<Puzzle> {
+    let mut game = selfplay::random_opening(OPENING_PLIES, &mut rand::thread_rng());
+
+    let mut puzzles = Vec::new();
+    while !game.is_over() {
+        if game.must_pass() {
+            game = game.pass();
+        }
+
+        let ranking = bot.rank(&game, game.current_player());
+        if bot.timed_out {
+            break;
+        }
+
+        if let Some(puzzle) = to_puzzle(&game, &ranking, min_gap) {
+            puzzles.push(puzzle);
+        }
+
+        match ranking.into_iter().next() {
+            Some((action, _)) => game = game.result(&action),
+            None => break,
+        }
+    }
+
+    puzzles
+}
+
+/// Verifies the given puzzle by searching it again with the given bot and saves it, unless the
+/// search is stopped before it confirms the solution
+fn save_verified(storage: &Storage, puzzle: &Puzzle, bot: &mut Bot) -> Result<bool, Error> {
+    let board = Board::parse(puzzle.board.clone())?;
+    let player = Player::parse(puzzle.player.chars().next().unwrap_or_default())?;
+
+    let ranking = bot.rank(&Game::parse(board, player), player);
+
+    if bot.timed_out
+        || ranking.first().map(|(action, _)| action.to_string()) != Some(puzzle.solution.clone()) {
+        return Ok(false);
+    }
+
+    storage.save(PUZZLES, &puzzle.id, puzzle)?;
+    Ok(true)
+}
+
+/// Mines a self-play game for puzzles on one of the threads of the engine and saves the ones
+/// verified at a greater depth, so that their solutions don't change with more search, returning
+/// the first of them
+///
+/// The game is mined until the given time, and the puzzles are verified until the given later
+/// time.
+async fn mine_verified(engine: &Engine, storage: &Storage, mining: Duration, verifying: Duration)
+    -> Result<Option<Puzzle>, Error> {
+    let (engine, storage) = (engine.clone(), storage.clone());
+    let config = engine.config();
+    let (depth, min_gap) = (config.puzzle_depth, config.puzzle_min_gap);
+
+    engine.clone().run(move || {
+        let mut bot = engine.bot(depth);
+        bot.set_deadline(Instant::now() + mining);
+        let mut verifier = engine.bot(depth + 1);
+        verifier.set_deadline(Instant::now() + mining + verifying);
+
+        let verified: Vec<Puzzle> = mine(&mut bot, min_gap).into_iter()
+            .filter(|puzzle| save_verified(&storage, puzzle, &mut verifier).unwrap_or(false))
+            .collect();
+        verified.into_iter().next()
+    }).await
+}
+
+/// Returns a random saved puzzle, or mines one within the thinking time of the server when none
+/// is saved yet
+#[get("/puzzle")]
+async fn puzzle(storage: &State<Storage>, engine: &State<Engine>)
+    -> Result<String, Custom<String>> {
+    let ids = storage.keys(PUZZLES);
+    let stored = ids.choose(&mut rand::thread_rng())
+        .and_then(|id| storage.load::<Puzzle>(PUZZLES, id));
+
+    let puzzle = match stored {
+        Some(puzzle) => puzzle,
+        None => {
+            // Half of the thinking time is left to verify the puzzles
+            let half = Duration::from_millis(engine.config().max_thinking_time_ms) / 2;
+            mine_verified(engine, storage, half, half).await?
+                .ok_or_else(|| Custom(Status::ServiceUnavailable,
+                                      "No puzzles are available yet".to_string()))?
+        }
+    };
+
+    Ok(json!(puzzle).to_string())
+}
+
+/// Mines the given number of self-play games for puzzles in the background, one at a time on
+/// the threads of the engine
+#[post("/admin/puzzles/mine?<games>")]
+fn mine_games(_admin: Admin, games: u32, storage: &State<Storage>, engine: &State<Engine>,
+              cancellation: &State<CancellationToken>) -> String {
+    let (storage, engine) = (storage.inner().clone(), engine.inner().clone());
+    let cancellation = cancellation.inner().clone();
+    let time_per_game = Duration::from_millis(engine.config().max_thinking_time_ms) * 60;
+
+    rocket::tokio::spawn(async move {
+        for _ in 0..games {
+            if cancellation.is_cancelled() {
+                break;
+            }
+            if let Err(e) = mine_verified(&engine, &storage, time_per_game, time_per_game).await {
+                warn!("Stopped mining puzzles: {}", e);
+                break;
+            }
+        }
+    });
+
+    json!({ "games": games }).to_string()
+}
+
+/// Returns the routes of the puzzles
+pub fn routes() -> Vec<Route> {
+    routes![puzzle, mine_games]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::board::Board;
+    use crate::bot::Bot;
+    use crate::game::{Action, Game, Player};
+    use crate::puzzle::{save_verified, to_puzzle, PUZZLES};
+    use crate::storage::Storage;
+
+    #[test]
+    fn score_gap() {
+        let game = Game::parse(Board::new(), Player::Human);
+        let actions: Vec<Action> = game.actions(Player::Human).collect();
+        let ranking = vec![(actions[0].clone(), 10), (actions[1].clone(), 4)];
+
+        let puzzle = to_puzzle(&game, &ranking, 6).unwrap();
+        assert_eq!((puzzle.solution, puzzle.score_gap), (actions[0].to_string(), 6));
+        assert!(to_puzzle(&game, &ranking, 7).is_none());
+
+        // A position with a single action has nothing to solve
+        assert!(to_puzzle(&game, &ranking[..1], 0).is_none());
+    }
+
+    #[test]
+    fn verify() {
+        let dir = std::env::temp_dir().join(format!("puzzles-{}", std::process::id()));
+        let storage = Storage::new(&dir);
+        let game = Game::parse(Board::new(), Player::Human);
+        let ranking = Bot::new(3).rank(&game, Player::Human);
+
+        let mut wrong = to_puzzle(&game, &ranking, i32::MIN).unwrap();
+        wrong.solution = ranking[1].0.to_string();
+        assert!(!save_verified(&storage, &wrong, &mut Bot::new(3)).unwrap());
+
+        // The search must confirm the solution before the deadline of the bot
+        let puzzle = to_puzzle(&game, &ranking, i32::MIN).unwrap();
+        let mut late = Bot::new(3);
+        late.set_deadline(Instant::now());
+        assert!(!save_verified(&storage, &puzzle, &mut late).unwrap());
+        assert!(storage.keys(PUZZLES).is_empty());
+
+        assert!(save_verified(&storage, &puzzle, &mut Bot::new(3)).unwrap());
+        assert_eq!(storage.keys(PUZZLES), vec![puzzle.id]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use rocket::{Route, State};
+use serde_json::json;
+
+use crate::config::EngineConfig;
+use crate::errors::Error;
+use crate::storage::Storage;
+
+const RATINGS: &str = "ratings";
+const BOTS_KEY: &str = "bots";
+
+pub const INITIAL_RATING: f64 = 1200.0;
+const K_FACTOR: f64 = 32.0;
+
+/// Returns the rating of the bot playing with the given intelligence before any rated game
+pub fn initial_bot_rating(intelligence: u32) -> f64 {
+    1000.0 + 150.0 * intelligence as f64
+}
+
+/// Returns the score a player with the given rating is expected to make against the opponent
+pub fn expected_score(rating: f64, opponent_rating: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent_rating - rating) / 400.0))
+}
+
+/// Returns the new rating of a player after a game
+///
+/// The score is 1 for a win, 0.5 for a draw and 0 for a loss
+pub fn updated_rating(rating: f64, opponent_rating: f64, score: f64) -> f64 {
+    rating + K_FACTOR * (score - expected_score(rating, opponent_rating))
+}
+
+/// Ratings of the bot at each intelligence kept in the storage, which rated games move like the
+/// ratings of the humans
+///
+/// Clones share the same lock on the storage.
+#[derive(Clone)]
+pub struct BotRatings {
+    storage: Storage,
+    lock: Arc<Mutex<()>>,
+}
+
+impl BotRatings {
+
+    /// Creates the ratings kept in the given storage
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            storage,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Returns the ratings of the intelligences that played rated games
+    fn load(&self) -> BTreeMap<u32, f64> {
+        self.storage.load(RATINGS, BOTS_KEY).unwrap_or_default()
+    }
+
+    /// Returns the rating of the bot playing with the given intelligence
+    pub fn get(&self, intelligence: u32) -> f64 {
+        self.load().get(&intelligence).copied()
+            .unwrap_or_else(|| initial_bot_rating(intelligence))
+    }
+
+    /// Records a rated game of the bot with the given intelligence against a human with the
+    /// given rating, who made the given score
+    pub fn record(&self, intelligence: u32, human_rating: f64, human_score: f64)
+        -> Result<(), Error> {
+        let _guard = self.lock.lock().unwrap();
+
+        let mut ratings = self.load();
+        let rating = ratings.get(&intelligence).copied()
+            .unwrap_or_else(|| initial_bot_rating(intelligence));
+        ratings.insert(intelligence, updated_rating(rating, human_rating, 1.0 - human_score));
+        self.storage.save(RATINGS, BOTS_KEY, &ratings)
+    }
+}
+
+/// Returns the rating of the bot at each intelligence the server allows
+#[get("/ratings/bots")]
+fn bots(ratings: &State<BotRatings>, config: &State<EngineConfig>) -> String {
+    let ratings = (config.min_intelligence..=config.max_intelligence)
+        .map(|intelligence| (intelligence.to_string(), json!(ratings.get(intelligence))))
+        .collect::<serde_json::Map<_, _>>();
+    json!(ratings).to_string()
+}
+
+/// Returns the routes of the ratings
+pub fn routes() -> Vec<Route> {
+    routes![bots]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rating::{initial_bot_rating, updated_rating, BotRatings};
+    use crate::storage::Storage;
+
+    #[test]
+    fn rating_update() {
+        assert_eq!(updated_rating(1500.0, 1500.0, 1.0), 1516.0);
+        assert_eq!(updated_rating(1500.0, 1500.0, 0.0), 1484.0);
+        assert_eq!(updated_rating(1500.0, 1500.0, 0.5), 1500.0);
+    }
+
+    #[test]
+    fn bot_ratings() {
+        let dir = std::env::temp_dir().join(format!("bot-ratings-{}", std::process::id()));
+        let ratings = BotRatings::new(Storage::new(&dir));
+        assert_eq!(ratings.get(2), initial_bot_rating(2));
+
+        // The bot loses rating points when a human wins
+        ratings.record(2, initial_bot_rating(2), 1.0).unwrap();
+        assert_eq!(ratings.get(2), initial_bot_rating(2) - 16.0);
+        assert_eq!(ratings.get(3), initial_bot_rating(3));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use lazy_static::lazy_static;
+use resvg::{tiny_skia, usvg};
+use rocket::form;
+use rocket::http::{ContentType, Status};
+use rocket::response::status::Custom;
+use rocket::{Route, State};
+
+use crate::board::{Board, Disk, Position, BOARD_SIZE};
+use crate::errors::Error;
+use crate::errors::Error::InvalidArgument;
+use crate::etag::Cached;
+use crate::session::{SessionId, Sessions};
+use crate::validation;
+
+/// Length of the side of a square in pixels
+const SQUARE: usize = 48;
+
+/// Width of the margin holding the coordinates in pixels
+const MARGIN: usize = 24;
+
+/// Width of the thumbnails shown in link previews in pixels
+const THUMBNAIL_WIDTH: u32 = 200;
+
+/// Number of thumbnails kept in memory
+const THUMBNAIL_CACHE_CAPACITY: usize = 1024;
+
+/// Seconds for which clients may cache the thumbnail of a game in progress
+const GAME_THUMBNAIL_MAX_AGE: u32 = 60;
+
+lazy_static! {
+    /// Fonts installed on the system, used for the coordinates of rasterized boards
+    static ref FONTS: Arc<usvg::fontdb::Database> = {
+        let mut fonts = usvg::fontdb::Database::new();
+        fonts.load_system_fonts();
+        Arc::new(fonts)
+    };
+}
+
+/// Draws the given board as an SVG image with its coordinates, highlighting the last placement
+///
+/// Columns are labelled with letters and rows with numbers, as in the notation of positions
+pub fn svg(board: &Board, last: Option<&Position>) -> String {
+    let size = SQUARE * BOARD_SIZE + 2 * MARGIN;
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" \
+                           height=\"{size}\" viewBox=\"0 0 {size} {size}\">\
+                           <rect width=\"{size}\" height=\"{size}\" fill=\"#1b4d2b\"/>\
+                           <rect x=\"{MARGIN}\" y=\"{MARGIN}\" width=\"{0}\" height=\"{0}\" \
+                           fill=\"#2e7d32\"/>", SQUARE * BOARD_SIZE);
+
+    for i in 0..BOARD_SIZE {
+        let center = MARGIN + SQUARE * i + SQUARE / 2;
+        let _ = write!(svg, "<text x=\"{center}\" y=\"{0}\" font-family=\"sans-serif\" \
+                             font-size=\"14\" fill=\"#ffffff\" text-anchor=\"middle\">{1}</text>\
+                             <text x=\"{2}\" y=\"{3}\" font-family=\"sans-serif\" \
+                             font-size=\"14\" fill=\"#ffffff\" text-anchor=\"middle\">{4}</text>",
+                       MARGIN * 2 / 3, (b'a' + i as u8) as char, MARGIN / 2, center + 5, i + 1);
+    }
+
+    for i in 0..=BOARD_SIZE {
+        let offset = MARGIN + SQUARE * i;
+        let end = MARGIN + SQUARE * BOARD_SIZE;
+        let _ = write!(svg, "<line x1=\"{offset}\" y1=\"{MARGIN}\" x2=\"{offset}\" y2=\"{end}\" \
+                             stroke=\"#0d3318\" stroke-width=\"2\"/>\
+                             <line x1=\"{MARGIN}\" y1=\"{offset}\" x2=\"{end}\" y2=\"{offset}\" \
+                             stroke=\"#0d3318\" stroke-width=\"2\"/>");
+    }
+
+    for pos in Position::all() {
+        let x = MARGIN + SQUARE * pos.col();
+        let y = MARGIN + SQUARE * pos.row();
+
+        if Some(&pos) == last {
+            let _ = write!(svg, "<rect x=\"{x}\" y=\"{y}\" width=\"{SQUARE}\" height=\"{SQUARE}\" \
+                                 fill=\"#fdd835\" fill-opacity=\"0.5\"/>");
+        }
+
+        if board.is_blocked(&pos) {
+            let _ = write!(svg, "<rect x=\"{x}\" y=\"{y}\" width=\"{SQUARE}\" \
+                                 height=\"{SQUARE}\" fill=\"#424242\"/>");
+            continue;
+        }
+
+        let fill = match board.disk(&pos) {
+            Some(Disk::Dark) => "#111111",
+            Some(Disk::Light) => "#f5f5f5",
+            None => continue,
+        };
+        let _ = write!(svg, "<circle cx=\"{0}\" cy=\"{1}\" r=\"{2}\" fill=\"{fill}\" \
+                             stroke=\"#000000\" stroke-width=\"1\"/>",
+                       x + SQUARE / 2, y + SQUARE / 2, SQUARE * 2 / 5);
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Rasterizes the given SVG image into a PNG image
+pub fn png(svg: &str) -> Result<Vec<u8>, Error> {
+    rasterize(svg, None)
+}
+
+/// Rasterizes the given SVG image into a PNG image, scaled to the given width if any
+fn rasterize(svg: &str, width: Option<u32>) -> Result<Vec<u8>, Error> {
+    let options = usvg::Options {
+        fontdb: FONTS.clone(),
+        ..Default::default()
+    };
+
+    let tree = usvg::Tree::from_str(svg, &options)
+        .map_err(|e| InvalidArgument(format!("Invalid SVG image: {}", e)))?;
+    let size = tree.size().to_int_size();
+    let scale = width.map_or(1.0, |width| width as f32 / size.width() as f32);
+    let size = size.scale_by(scale)
+        .ok_or_else(|| InvalidArgument("The image is empty".to_string()))?;
+
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| InvalidArgument("The image is empty".to_string()))?;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    pixmap.encode_png()
+        .map_err(|e| InvalidArgument(format!("Failed to encode the image: {}", e)))
+}
+
+/// Thumbnails rendered so far by their board and last placement
+///
+/// The cache is emptied once it holds THUMBNAIL_CACHE_CAPACITY thumbnails, which is simpler than
+/// evicting the least recently used ones and good enough for link previews.
+#[derive(Default)]
+pub struct Thumbnails {
+    cache: Mutex<HashMap<String, Arc<Vec<u8>>>>,
+}
+
+impl Thumbnails {
+
+    /// Returns the PNG thumbnail of the given board, rendering it unless it is cached
+    pub fn get(&self, board: &Board, last: Option<&Position>) -> Result<Arc<Vec<u8>>, Error> {
+        let key = format!("{}{}", board, last.map(|pos| pos.to_string()).unwrap_or_default());
+        if let Some(thumbnail) = self.cache.lock().unwrap().get(&key) {
+            return Ok(thumbnail.clone());
+        }
+
+        let thumbnail = Arc::new(rasterize(&svg(board, last), Some(THUMBNAIL_WIDTH))?);
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= THUMBNAIL_CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(key, thumbnail.clone());
+        Ok(thumbnail)
+    }
+}
+
+#[get("/render?<board>&<format>&<last>")]
+fn render(board: form::Result<'_, Board>, format: Option<&str>,
+          last: form::Result<'_, Position>) -> Result<(ContentType, Vec<u8>), Custom<String>> {
+    let board = validation::param(board, "board")?;
+    let last = validation::optional(last, "last")?;
+
+    let svg = svg(&board, last.as_ref());
+
+    match format.unwrap_or("svg") {
+        "svg" => Ok((ContentType::SVG, svg.into_bytes())),
+        "png" => png(&svg)
+            .map(|png| (ContentType::PNG, png))
+            .map_err(|e| Custom(Status::InternalServerError, e.to_string())),
+        format => Err(validation::invalid(Status::BadRequest, "format",
+                                          format!("Invalid format: {}", format))),
+    }
+}
+
+/// Answers the given thumbnail, which clients may cache for the given seconds
+fn thumbnail_response(thumbnail: Result<Arc<Vec<u8>>, Error>, max_age: u32)
+    -> Result<Cached, Custom<String>> {
+    thumbnail
+        .map(|png| Cached::binary(png.to_vec(), ContentType::PNG, max_age))
+        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))
+}
+
+#[get("/thumbnail?<board>")]
+fn thumbnail(board: form::Result<'_, Board>, thumbnails: &State<Thumbnails>)
+    -> Result<Cached, Custom<String>> {
+    let board = validation::param(board, "board")?;
+    thumbnail_response(thumbnails.get(&board, None), crate::IMMUTABLE_MAX_AGE)
+}
+
+#[get("/games/<id>/thumbnail")]
+fn game_thumbnail(id: SessionId, sessions: &State<Sessions>, thumbnails: &State<Thumbnails>)
+    -> Result<Cached, Custom<String>> {
+    let (board, last) = sessions
+        .with(id, |s| (s.game().board().clone(), s.moves().iter().flatten().last().cloned()))
+        .ok_or_else(|| Custom(Status::NotFound, format!("No game with the id: {}", id)))?;
+
+    // The game goes on, so clients revalidate the thumbnail with its ETag after a minute
+    thumbnail_response(thumbnails.get(&board, last.as_ref()), GAME_THUMBNAIL_MAX_AGE)
+}
+
+#[get("/pretty?<board>")]
+fn pretty(board: form::Result<'_, Board>) -> Result<String, Custom<String>> {
+    Ok(validation::param(board, "board")?.pretty())
+}
+
+/// Returns the routes of the board renderer
+pub fn routes() -> Vec<Route> {
+    routes![render, pretty, thumbnail, game_thumbnail]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::{Board, Position};
+    use crate::render::{png, svg, Thumbnails};
+
+    #[test]
+    fn render() {
+        let board = Board::new();
+        let image = svg(&board, Some(&Position::new(2, 4)));
+        assert_eq!(image.matches("<circle").count(), 4);
+        assert_eq!(image.matches("fill-opacity").count(), 1);
+
+        let image = png(&image).unwrap();
+        assert_eq!(&image[1..4], b"PNG");
+    }
+
+    #[test]
+    fn thumbnail() {
+        let thumbnails = Thumbnails::default();
+        let board = Board::new();
+
+        let thumbnail = thumbnails.get(&board, None).unwrap();
+        assert_eq!(&thumbnail[1..4], b"PNG");
+        // The width is stored big-endian at the start of the IHDR chunk
+        assert_eq!(&thumbnail[16..20], &200u32.to_be_bytes());
+        assert!(std::sync::Arc::ptr_eq(&thumbnail, &thumbnails.get(&board, None).unwrap()));
+    }
+}
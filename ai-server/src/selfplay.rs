@@ -0,0 +1,191 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, Instant};
+
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use serde_json::{json, Value};
+
+use crate::bot::{Bot, CancellationToken};
+use crate::board::{Board, Position};
+use crate::game::{Action, Game, Player};
+
+/// How the moves leading to a generated position are chosen
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, FromFormField)]
+pub enum Quality {
+    /// Random legal moves
+    Random,
+    /// Moves of the opening book, weighted by how often they were played, then moves of the bot
+    #[default]
+    Book,
+    /// Moves of the bot from the first one
+    Engine,
+}
+
+impl Display for Quality {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Quality::Random => "random",
+            Quality::Book => "book",
+            Quality::Engine => "engine",
+        })
+    }
+}
+
+/// Record of a game played between two bots
+pub struct GameRecord {
+    pub moves: Vec<String>,
+    pub winner: Option<Player>,
+    pub board: String,
+}
+
+impl GameRecord {
+
+    /// Serializes this record to a json value
+    pub fn to_json(&self) -> Value {
+        json!({
+            "moves": self.moves,
+            "winner": self.winner.map(|p| p.to_string()),
+            "board": self.board,
+        })
+    }
+}
+
+/// Returns the states of the given record of a game played from the given state, before each of
+/// its moves and at its end, or None if one of its moves breaks the rules
+pub fn replay(start: Game, record: &GameRecord) -> Option<Vec<Game>> {
+    let mut states = vec![start];
+    for placement in &record.moves {
+        let game = states.last()?;
+        let next = match Position::parse(placement.clone()) {
+            Ok(placement) => game.play(&Action::parse(game.current_player(), placement)).ok()?,
+            Err(_) => game.pass(),
+        };
+        states.push(next);
+    }
+    Some(states)
+}
+
+/// Returns the state reached by playing the given number of random plies from the initial board
+///
+/// Stops early when the game is over
+pub fn random_opening(plies: u32, rng: &mut impl Rng) -> Game {
+    let mut game = Game::parse(Board::new(), Player::Human);
+
+    for _ in 0..plies {
+        if game.is_over() {
+            break;
+        }
+        if game.must_pass() {
+            game = game.pass();
+        }
+
+        let action = game.actions(game.current_player()).choose(rng)
+            .expect("A player who must not pass has actions");
+        game = game.result(&action);
+    }
+
+    game
+}
+
+/// Plays the actions the given function chooses from the given state until the given moves hold
+/// the given number of placements, the game is over or the function chooses none
+///
+/// Passes are played for players left without actions and recorded as None.
+pub fn extend(game: &mut Game, moves: &mut Vec<Option<Position>>, plies: u32,
+              mut choose: impl FnMut(&Game) -> Option<Action>) {
+    while moves.iter().flatten().count() < plies as usize && !game.is_over() {
+        if game.must_pass() {
+            *game = game.pass();
+            moves.push(None);
+            continue;
+        }
+
+        let Some(action) = choose(game) else {
+            break;
+        };
+        moves.push(Some(action.placement().clone()));
+        *game = game.result(&action);
+    }
+}
+
+/// Returns the action the given bot decides for the player to move in the given state within the
+/// given time, or None if the player has no actions
+pub fn decide(bot: &mut Bot, game: &Game, think_time: Duration) -> Option<Action> {
+    bot.set_deadline(Instant::now() + think_time);
+    let decision = match game.current_player() {
+        Player::Human => bot.hint(game),
+        Player::Bot => bot.decide(game),
+    };
+    decision.ok().map(|(action, _)| action)
+}
+
+/// Plays a game from the given state until it is over
+///
+/// The human side is played by the first bot and the bot side by the second one. Passes are
+/// recorded as "pass".
+pub fn play(game: Game, human: &mut Bot, bot: &mut Bot, think_time: Duration,
+            cancellation: &CancellationToken) -> GameRecord {
+    play_with(game, cancellation, |game| {
+        let searcher = match game.current_player() {
+            Player::Human => &mut *human,
+            Player::Bot => &mut *bot,
+        };
+        decide(searcher, game, think_time)
+    })
+}
+
+/// Plays a game from the given state until it is over, with the actions the given function
+/// chooses for the player to move
+///
+/// Passes are recorded as "pass".
+pub fn play_with(game: Game, cancellation: &CancellationToken,
+                 mut choose: impl FnMut(&Game) -> Option<Action>) -> GameRecord {
+    let mut game = game;
+    let mut moves = Vec::new();
+
+    while !game.is_over() && !cancellation.is_cancelled() {
+        if game.must_pass() {
+            game = game.pass();
+            moves.push("pass".to_string());
+            continue;
+        }
+
+        let action = choose(&game).expect("A player who must not pass has actions");
+        moves.push(action.to_string());
+        game = game.result(&action);
+    }
+
+    GameRecord {
+        moves,
+        winner: game.winner().ok().flatten(),
+        board: game.board().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::seq::IteratorRandom;
+
+    use crate::board::{Board, Disk};
+    use crate::game::{Game, Player};
+    use crate::selfplay::extend;
+
+    #[test]
+    fn extend_moves() {
+        let mut rng = rand::thread_rng();
+        let mut game = Game::parse(Board::new(), Player::Human);
+        let mut moves = Vec::new();
+        extend(&mut game, &mut moves, 20,
+               |game| game.actions(game.current_player()).choose(&mut rng));
+
+        assert_eq!(moves.iter().flatten().count(), 20);
+        let disks = game.board().positions(Disk::Dark).count()
+            + game.board().positions(Disk::Light).count();
+        assert_eq!(disks, 24);
+
+        // Nothing is played once the function chooses no action
+        let before = moves.len();
+        extend(&mut game, &mut moves, 30, |_| None);
+        assert!(moves.len() <= before + 1);
+    }
+}
@@ -0,0 +1,2040 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::form;
+use rocket::http::Status;
+use rocket::request::{FromParam, FromRequest, Outcome};
+use rocket::serde::{Deserialize, Serialize, Serializer};
+use rocket::response::status::Custom;
+use rocket::response::stream::{Event, EventStream};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::{channel, Receiver, Sender};
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::tokio::time::sleep;
+use rocket::{Orbit, Request, Rocket, Route, Shutdown, State};
+use serde_json::{json, Value};
+
+use crate::account::{self, Accounts, Authenticated};
+use crate::archive::Archive;
+use crate::board::{Board, BoardFormat, Position};
+use crate::book::OpeningBook;
+use crate::bot::Bot;
+use crate::clock::{Clock, TimeControl};
+use crate::config::EngineConfig;
+use crate::engine::Engine;
+use crate::errors::Error;
+use crate::errors::Error::{IllegalMove, InvalidArgument, ParseError};
+use crate::game::{Action, EvaluationKey, Game, Player, Variant};
+use crate::ggf;
+use crate::level::{Level, Preset};
+use crate::lru::LruCache;
+use crate::probability::WinModel;
+use crate::rating::BotRatings;
+use crate::storage::Storage;
+use crate::validation;
+use crate::weights::Adaptation;
+use crate::xot::{self, Xot};
+
+/// Maximum number of events kept for a spectator who falls behind, and for a player who
+/// reconnects to resume watching
+const EVENT_CAPACITY: usize = 16;
+
+/// Length of the tokens with which players resume watching their sessions
+const RESUME_TOKEN_LENGTH: usize = 24;
+
+/// Number of the latest positions over which the bot of an adaptive session follows the trend of
+/// the game
+const TREND_LENGTH: usize = 3;
+
+/// Largest fraction by which the reply time of the bot varies from one move to the next
+const REPLY_JITTER: f64 = 0.25;
+
+/// Pace at which the bot of a game session replies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ReplyPace {
+    /// Shortest time in milliseconds the bot takes to reply
+    pub min_reply_ms: u64,
+
+    /// Time in milliseconds the bot takes to reply for each action it could play, so that it
+    /// replies faster to forced moves than to complex positions
+    pub reply_ms_per_action: u64,
+}
+
+/// Returns how long the bot of the given intelligence takes to reply in a position where it can
+/// play the given number of actions, varying at random so that its pace feels natural
+fn reply_time(config: &EngineConfig, intelligence: u32, num_actions: usize, rng: &mut impl Rng)
+    -> Duration {
+    let index = (intelligence.max(1) - 1) as usize;
+    let Some(pace) = config.reply_pacing.get(index).or(config.reply_pacing.last()) else {
+        return Duration::ZERO;
+    };
+    let ms = pace.min_reply_ms + pace.reply_ms_per_action * num_actions as u64;
+    Duration::from_millis(ms).mul_f64(rng.gen_range(1.0 - REPLY_JITTER..=1.0 + REPLY_JITTER))
+        .max(Duration::from_millis(pace.min_reply_ms))
+}
+
+/// Maximum number of evaluations the bot of a session keeps
+const SESSION_CACHE_SIZE: usize = 1 << 16;
+
+/// Estimated memory in bytes an evaluation takes in the cache of a bot, including the control
+/// byte and the spare capacity of its map
+const EVALUATION_BYTES: usize = size_of::<(EvaluationKey, i32)>() * 8 / 7 + 1;
+
+/// Returns how many sessions keep their bot between moves within the memory the configuration
+/// gives them, beyond which the bots of the least recently played sessions are dropped
+///
+/// At least one session keeps its bot, even if its cache alone exceeds the memory.
+fn max_session_bots(config: &EngineConfig) -> usize {
+    (config.session_memory_bytes / (SESSION_CACHE_SIZE * EVALUATION_BYTES)).max(1)
+}
+
+/// Collection of the records of the expired sessions
+const SESSIONS: &str = "sessions";
+
+/// Collection of the sessions that were live when the server shut down, which it resumes once it
+/// starts again
+const LIVE_SESSIONS: &str = "live-sessions";
+
+/// Identifier of a game session, which is random so that nobody finds the sessions of the others
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionId(u128);
+
+impl SessionId {
+
+    /// Generates a new random id
+    fn random() -> Self {
+        Self(rand::random())
+    }
+}
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for SessionId {
+    type Err = Error;
+
+    /// Parses an id written as 32 hexadecimal digits
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 32 {
+            return Err(ParseError(format!("Invalid game id: {}", s)));
+        }
+        u128::from_str_radix(s, 16)
+            .map(Self)
+            .map_err(|_| ParseError(format!("Invalid game id: {}", s)))
+    }
+}
+
+impl<'a> FromParam<'a> for SessionId {
+    type Error = Error;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        param.parse()
+    }
+}
+
+impl Serialize for SessionId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Request guard of the credentials with which a player changes a game session, which are the
+/// account of the `Authorization: Bearer <token>` header and the resume token of the session in
+/// the `X-Resume-Token` header
+pub struct Credentials {
+    account: Option<String>,
+    resume_token: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Credentials {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let account = request.guard::<Authenticated>().await
+            .succeeded()
+            .map(|Authenticated(account)| account.id);
+        let resume_token = request.headers().get_one("X-Resume-Token").map(str::to_string);
+        Outcome::Success(Credentials { account, resume_token })
+    }
+}
+
+/// When a move of a session was played, and the time both players had left right after it
+#[derive(Clone)]
+struct Stamp {
+    time: SystemTime,
+    /// Remaining times of the human and the bot, if the session is timed
+    clocks: Option<(Duration, Duration)>,
+}
+
+/// State of a session that can be restored by undo and redo
+struct Snapshot {
+    game: Game,
+    moves: Vec<Option<Position>>,
+    stamps: Vec<Stamp>,
+}
+
+/// Strength state of a session whose bot changes its intelligence to keep the game close
+struct Adaptive {
+    /// Largest difference from an even game, in win probability, that the game may drift to
+    band: f64,
+    /// Win probabilities of the bot in the latest positions it moved from
+    trend: VecDeque<f64>,
+}
+
+/// State of the last takeback the human requested in a session
+#[derive(Clone, Debug, PartialEq)]
+pub enum Takeback {
+    /// Waiting for the answer of the bot
+    Requested,
+    Accepted,
+    /// Refused by the bot for the given reason
+    Declined(String),
+}
+
+impl Takeback {
+
+    /// Serializes this takeback to a json value
+    fn to_json(&self) -> Value {
+        let (state, reason) = match self {
+            Takeback::Requested => ("requested", None),
+            Takeback::Accepted => ("accepted", None),
+            Takeback::Declined(reason) => ("declined", Some(reason)),
+        };
+        json!({
+            "state": state,
+            "reason": reason,
+        })
+    }
+}
+
+/// Outcome of a finished game session
+pub struct Finished {
+    /// Account of the human, if any
+    pub account: Option<String>,
+    pub winner: Option<Player>,
+    /// Number of moves the human took back
+    pub takebacks: u32,
+    pub rated: bool,
+    /// Intelligence of the bot at the end of the game
+    pub intelligence: u32,
+    /// State the game started from, which the moves are played from
+    pub initial: Game,
+    pub moves: Vec<Option<Position>>,
+    pub record: Value,
+}
+
+/// State of a live session kept in the storage while the server restarts, without the moves
+/// undone, the pending takeback and the last analysis
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Saved {
+    first: Player,
+    initial: String,
+    variant: Variant,
+    /// Placements played so far, where None is a pass
+    moves: Vec<Option<String>>,
+    /// Times the moves were played in milliseconds since the Unix epoch, with the times in
+    /// milliseconds the human and the bot had left right after them if the session is timed
+    stamps: Vec<(u64, Option<(u64, u64)>)>,
+    intelligence: u32,
+    think_time_ms: Option<u64>,
+    level: Option<Level>,
+    /// Band of an adaptive session
+    band: Option<f64>,
+    takeback_limit: u32,
+    num_takebacks: u32,
+    account: Option<String>,
+    rated: bool,
+    recorded: bool,
+    resume_token: String,
+    seed: u64,
+    /// Time control of the clock with the times in milliseconds the human and the bot have left
+    clock: Option<(String, u64, u64)>,
+    flagged: Option<Player>,
+}
+
+/// A game played between the human and the bot that lives on the server
+pub struct Session {
+    game: Game,
+    initial: Game,
+    first: Player,
+    moves: Vec<Option<Position>>,
+    /// Stamps of the moves, in the same order
+    stamps: Vec<Stamp>,
+    /// Last analysis of the game and the moves it reviewed
+    analysis: Option<(Vec<Option<Position>>, Value)>,
+    intelligence: u32,
+    /// Longest time the bot thinks about a move, if shorter than the limit of the server
+    think_time: Option<Duration>,
+    /// Level the bot plays at, until the strength is changed
+    preset: Option<Preset>,
+    adaptive: Option<Adaptive>,
+    undo_stack: Vec<Snapshot>,
+    redo_stack: Vec<Snapshot>,
+    takeback_limit: u32,
+    num_takebacks: u32,
+    takeback: Option<Takeback>,
+    version: u64,
+    account: Option<String>,
+    rated: bool,
+    recorded: bool,
+    /// Events sent so far, with the versions of the session they announce
+    events: Sender<(u64, String)>,
+    backlog: VecDeque<(u64, String)>,
+    resume_token: String,
+    /// Seed of the order in which the bot searches its actions, which varies the game from the
+    /// others where equally good moves are available
+    seed: u64,
+    clock: Option<Clock>,
+    flagged: Option<Player>,
+    created: Instant,
+    last_active: Instant,
+}
+
+impl Session {
+
+    /// Creates a new session starting from the initial board, timed by a clock if a time
+    /// control is given
+    pub fn new(first: Player, intelligence: u32, takeback_limit: u32, account: Option<String>,
+               time_control: Option<TimeControl>) -> Self {
+        Self {
+            game: Game::parse(Board::new(), first),
+            initial: Game::parse(Board::new(), first),
+            first,
+            moves: Vec::new(),
+            stamps: Vec::new(),
+            analysis: None,
+            intelligence,
+            think_time: None,
+            preset: None,
+            adaptive: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            takeback_limit,
+            num_takebacks: 0,
+            takeback: None,
+            version: 0,
+            rated: account.is_some(),
+            account,
+            recorded: false,
+            events: channel(EVENT_CAPACITY).0,
+            backlog: VecDeque::with_capacity(EVENT_CAPACITY),
+            resume_token: account::random_string(RESUME_TOKEN_LENGTH),
+            seed: rand::random(),
+            clock: time_control.map(|control| Clock::new(control, first)),
+            flagged: None,
+            created: Instant::now(),
+            last_active: Instant::now(),
+        }
+    }
+
+    /// Starts this session from the given state instead of the initial board, such as an opening
+    /// or the classic start, where the first player is to move
+    pub fn with_opening(mut self, opening: Game) -> Self {
+        self.game = opening.clone();
+        self.initial = opening;
+        self
+    }
+
+    /// Keeps the result of this session out of the rating of the human, who played it from an
+    /// account
+    pub fn unrated(mut self) -> Self {
+        self.rated = false;
+        self
+    }
+
+    /// Plays this session under the given variant
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        self.game = self.game.with_variant(variant);
+        self.initial = self.initial.with_variant(variant);
+        self
+    }
+
+    /// Lets the bot of this session play at the level of the given preset
+    pub fn with_preset(mut self, preset: Preset) -> Self {
+        self.intelligence = preset.intelligence;
+        self.think_time = Some(preset.think_time);
+        self.preset = Some(preset);
+        self
+    }
+
+    /// Lets the bot of this session change its intelligence to keep the win probabilities within
+    /// the given band around an even game
+    pub fn with_adaptive(mut self, band: f64) -> Self {
+        self.adaptive = Some(Adaptive {
+            band,
+            trend: VecDeque::with_capacity(TREND_LENGTH),
+        });
+        self
+    }
+
+    /// Returns the current state of the game
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Returns the state this session started from
+    pub fn initial(&self) -> &Game {
+        &self.initial
+    }
+
+    /// Returns the player who moved first in this session
+    pub fn first(&self) -> Player {
+        self.first
+    }
+
+    /// Returns the placements played so far in order, where None is a pass
+    pub fn moves(&self) -> &[Option<Position>] {
+        &self.moves
+    }
+
+    /// Returns the intelligence of the bot playing this session
+    pub fn intelligence(&self) -> u32 {
+        self.intelligence
+    }
+
+    /// Returns the longest time the bot thinks about a move, if shorter than the limit of the
+    /// server
+    pub fn think_time(&self) -> Option<Duration> {
+        self.think_time
+    }
+
+    /// Returns the preset of the level the bot plays at, if any
+    pub fn preset(&self) -> Option<Preset> {
+        self.preset
+    }
+
+    /// Changes the strength of the bot from its next move on, keeping what it learned about the
+    /// game so far, which leaves the level it played at
+    ///
+    /// The strength of the bot in rated games is fixed, since the rating of the human depends
+    /// on it.
+    pub fn set_strength(&mut self, intelligence: Option<u32>, think_time: Option<Duration>)
+        -> Result<(), Error> {
+        if self.is_rated() {
+            return Err(InvalidArgument(
+                "The strength of the bot cannot change in a rated game".to_string()));
+        }
+
+        self.intelligence = intelligence.unwrap_or(self.intelligence);
+        self.think_time = think_time.or(self.think_time);
+        self.preset = None;
+        Ok(())
+    }
+
+    /// Follows the trend of an adaptive session with the given win probability of the bot in the
+    /// position it moves from, weakening the bot when the game drifts its way and strengthening
+    /// it when the game drifts the other way, within the given range of intelligences
+    pub fn adapt(&mut self, probability: f64, intelligences: RangeInclusive<u32>) {
+        let Some(adaptive) = self.adaptive.as_mut() else {
+            return;
+        };
+
+        if adaptive.trend.len() == TREND_LENGTH {
+            adaptive.trend.pop_front();
+        }
+        adaptive.trend.push_back(probability);
+        if adaptive.trend.len() < TREND_LENGTH {
+            return;
+        }
+
+        let mean = adaptive.trend.iter().sum::<f64>() / TREND_LENGTH as f64;
+        let intelligence = if mean > 0.5 + adaptive.band {
+            self.intelligence.saturating_sub(1).max(*intelligences.start())
+        } else if mean < 0.5 - adaptive.band {
+            (self.intelligence + 1).min(*intelligences.end())
+        } else {
+            self.intelligence
+        };
+        // The new strength needs a few moves to show in the trend
+        if intelligence != self.intelligence {
+            self.intelligence = intelligence;
+            adaptive.trend.clear();
+        }
+    }
+
+    /// Returns the number of changes made to this session so far
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Checks if the game is over on the board or because a player ran out of time
+    pub fn is_over(&mut self) -> bool {
+        self.check_flag();
+        self.game.is_over() || self.flagged.is_some()
+    }
+
+    /// Returns the winner of the finished game, who is the opponent of a player out of time
+    ///
+    /// Fails unless the game is over.
+    pub fn winner(&self) -> Result<Option<Player>, Error> {
+        match self.flagged {
+            Some(flagged) => Ok(Some(flagged.opponent())),
+            None => self.game.winner(),
+        }
+    }
+
+    /// Ends the game if the player to move ran out of time
+    pub fn check_flag(&mut self) {
+        let flagged = self.clock.as_ref().and_then(|clock| clock.flagged());
+
+        if let Some(player) = flagged {
+            self.flagged = Some(player);
+            if let Some(clock) = self.clock.as_mut() {
+                clock.stop();
+            }
+            self.changed();
+        }
+    }
+
+    /// Returns how long the bot may think about its move according to its clock, if timed
+    pub fn bot_budget(&self) -> Option<Duration> {
+        let empties = Position::all()
+            .filter(|pos| self.game.board().is_empty(pos))
+            .count() as u32;
+
+        self.clock.as_ref().map(|clock| clock.budget(Player::Bot, empties.div_ceil(2)))
+    }
+
+    /// Returns the number of takebacks the human can still request
+    pub fn takebacks_left(&self) -> u32 {
+        self.takeback_limit.saturating_sub(self.num_takebacks)
+    }
+
+    /// Checks if the result of this session counts towards the rating of the human
+    pub fn is_rated(&self) -> bool {
+        self.rated
+    }
+
+    /// Plays the placement of the human, remembering the previous state for undo
+    pub fn play(&mut self, placement: Position) -> Result<(), Error> {
+        if self.is_over() {
+            return Err(InvalidArgument("The game is over".to_string()));
+        }
+        if self.game.current_player() != Player::Human {
+            return Err(InvalidArgument("It is not the turn of the human".to_string()));
+        }
+
+        let action = Action::parse(Player::Human, placement.clone());
+        if !self.game.actions(Player::Human).any(|a| a == action) {
+            return Err(IllegalMove(format!("Invalid action for the human: {}", action)));
+        }
+
+        self.undo_stack.push(self.snapshot());
+        self.redo_stack.clear();
+        // Playing on withdraws any takeback request
+        self.takeback = None;
+        let result = self.game.result(&action);
+        self.advance(placement, result);
+        Ok(())
+    }
+
+    /// Replaces the current state with the given result of the placement, skipping the turn of
+    /// a player who must pass
+    pub fn advance(&mut self, placement: Position, game: Game) {
+        self.moves.push(Some(placement));
+
+        self.game = if game.must_pass() {
+            self.moves.push(None);
+            game.pass()
+        } else {
+            game
+        };
+
+        if let Some(clock) = self.clock.as_mut() {
+            clock.press(self.game.current_player());
+            if self.game.is_over() {
+                clock.stop();
+            }
+        }
+        let stamp = Stamp {
+            time: SystemTime::now(),
+            clocks: self.clock.as_ref()
+                .map(|clock| (clock.remaining(Player::Human), clock.remaining(Player::Bot))),
+        };
+        self.stamps.resize(self.moves.len(), stamp);
+        self.changed();
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            game: self.game.clone(),
+            moves: self.moves.clone(),
+            stamps: self.stamps.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) -> Snapshot {
+        let current = Snapshot {
+            game: std::mem::replace(&mut self.game, snapshot.game),
+            moves: std::mem::replace(&mut self.moves, snapshot.moves),
+            stamps: std::mem::replace(&mut self.stamps, snapshot.stamps),
+        };
+
+        if let Some(clock) = self.clock.as_mut() {
+            clock.start(self.game.current_player());
+        }
+        current
+    }
+
+    /// Marks this session as changed and notifies the spectators
+    fn changed(&mut self) {
+        self.version += 1;
+
+        let mut event = crate::serialize_result(&self.game, BoardFormat::default());
+        event["evaluation"] = json!(self.game.evaluate());
+        let event = (self.version, event.to_string());
+
+        if self.backlog.len() == EVENT_CAPACITY {
+            self.backlog.pop_front();
+        }
+        self.backlog.push_back(event.clone());
+        // Sending only fails when nobody is watching
+        let _ = self.events.send(event);
+    }
+
+    /// Returns a receiver of the changes made to this session from now on, with the versions
+    /// they lead to
+    pub fn subscribe(&self) -> Receiver<(u64, String)> {
+        self.events.subscribe()
+    }
+
+    /// Returns the token with which the player of this session plays it and resumes watching it
+    /// after losing the connection
+    pub fn resume_token(&self) -> &str {
+        &self.resume_token
+    }
+
+    /// Checks if the given credentials are those of the player of this session, who either
+    /// created it from their account or holds its resume token
+    pub fn is_player(&self, credentials: &Credentials) -> bool {
+        let account = self.account.is_some() && credentials.account == self.account;
+        account || credentials.resume_token.as_deref() == Some(&self.resume_token)
+    }
+
+    /// Returns the events sent after the given version of this session, or None if some of them
+    /// are no longer kept
+    fn missed(&self, after: u64) -> Option<Vec<(u64, String)>> {
+        if after > self.version {
+            return None;
+        }
+        let oldest = self.backlog.front().map_or(self.version + 1, |&(version, _)| version);
+        if oldest > after + 1 {
+            return None;
+        }
+
+        Some(self.backlog.iter().filter(|(version, _)| *version > after).cloned().collect())
+    }
+
+    /// Restores the state before the last move of the human
+    ///
+    /// The moves of rated games are only taken back through takeback requests.
+    pub fn undo(&mut self) -> Result<(), Error> {
+        if self.is_rated() {
+            return Err(InvalidArgument(
+                "Moves of rated games are taken back by requesting a takeback".to_string()));
+        }
+        if self.takebacks_left() == 0 {
+            return Err(InvalidArgument("No takebacks are left in this game".to_string()));
+        }
+
+        let snapshot = self.undo_stack.pop()
+            .ok_or_else(|| InvalidArgument("No moves to undo".to_string()))?;
+
+        self.take_back(snapshot);
+        Ok(())
+    }
+
+    /// Restores the given state from before the last move of the human, counting a takeback
+    fn take_back(&mut self, snapshot: Snapshot) {
+        let current = self.restore(snapshot);
+        self.redo_stack.push(current);
+        self.num_takebacks += 1;
+        self.changed();
+    }
+
+    /// Requests to take back the last move of the human, which stays pending until the bot
+    /// answers it with `answer_takeback`
+    pub fn request_takeback(&mut self) -> Result<(), Error> {
+        if self.takeback == Some(Takeback::Requested) {
+            return Err(InvalidArgument("A takeback is already requested".to_string()));
+        }
+
+        self.takeback = Some(Takeback::Requested);
+        self.changed();
+        Ok(())
+    }
+
+    /// Answers the pending takeback request for the bot, which takes back the last move of the
+    /// human unless the game does not allow it
+    pub fn answer_takeback(&mut self) -> Result<&Takeback, Error> {
+        if self.takeback != Some(Takeback::Requested) {
+            return Err(InvalidArgument("No takeback is requested".to_string()));
+        }
+
+        let reason = if self.flagged.is_some() {
+            Some("The game was lost on time")
+        } else if self.is_rated() && self.is_over() {
+            Some("The result of a rated game is final")
+        } else if self.takebacks_left() == 0 {
+            Some("No takebacks are left in this game")
+        } else if self.undo_stack.is_empty() {
+            Some("No moves to take back")
+        } else {
+            None
+        };
+
+        self.takeback = Some(match reason {
+            Some(reason) => Takeback::Declined(reason.to_string()),
+            None => {
+                let snapshot = self.undo_stack.pop().unwrap();
+                self.take_back(snapshot);
+                Takeback::Accepted
+            }
+        });
+        self.changed();
+        Ok(self.takeback.as_ref().unwrap())
+    }
+
+    /// Restores the state undone last
+    pub fn redo(&mut self) -> Result<(), Error> {
+        let snapshot = self.redo_stack.pop()
+            .ok_or_else(|| InvalidArgument("No moves to redo".to_string()))?;
+
+        let current = self.restore(snapshot);
+        self.undo_stack.push(current);
+        self.changed();
+        Ok(())
+    }
+
+    /// Returns the outcome of the game once it is over, only the first time
+    pub fn finish(&mut self) -> Option<Finished> {
+        if self.recorded || !self.is_over() {
+            return None;
+        }
+
+        self.recorded = true;
+        Some(Finished {
+            account: self.account.clone(),
+            winner: self.winner().ok().flatten(),
+            takebacks: self.num_takebacks,
+            rated: self.rated,
+            intelligence: self.intelligence,
+            initial: self.initial.clone(),
+            moves: self.moves.clone(),
+            record: self.record(),
+        })
+    }
+
+    /// Ends the unfinished rated game as a loss of the human who abandoned it, returning it to be
+    /// recorded unless it already was
+    pub fn forfeit(&mut self) -> Option<Finished> {
+        if self.recorded || !self.rated || self.is_over() {
+            return None;
+        }
+
+        self.recorded = true;
+        Some(Finished {
+            account: self.account.clone(),
+            winner: Some(Player::Bot),
+            takebacks: self.num_takebacks,
+            rated: self.rated,
+            intelligence: self.intelligence,
+            initial: self.initial.clone(),
+            moves: self.moves.clone(),
+            record: self.record(),
+        })
+    }
+
+    /// Returns the state of this session to keep in the storage while the server restarts
+    fn save(&self) -> Saved {
+        let millis = |time: Duration| time.as_millis() as u64;
+        Saved {
+            first: self.first,
+            initial: self.initial.board().to_string(),
+            variant: self.initial.variant(),
+            moves: self.moves.iter().map(|m| m.as_ref().map(|pos| pos.to_string())).collect(),
+            stamps: self.stamps.iter()
+                .map(|stamp| (millis(stamp.time.duration_since(UNIX_EPOCH).unwrap_or_default()),
+                              stamp.clocks.map(|(human, bot)| (millis(human), millis(bot)))))
+                .collect(),
+            intelligence: self.intelligence,
+            think_time_ms: self.think_time.map(millis),
+            level: self.preset.map(|preset| preset.level),
+            band: self.adaptive.as_ref().map(|adaptive| adaptive.band),
+            takeback_limit: self.takeback_limit,
+            num_takebacks: self.num_takebacks,
+            account: self.account.clone(),
+            rated: self.rated,
+            recorded: self.recorded,
+            resume_token: self.resume_token.clone(),
+            seed: self.seed,
+            clock: self.clock.as_ref().map(|clock| (clock.control().to_string(),
+                                                     millis(clock.remaining(Player::Human)),
+                                                     millis(clock.remaining(Player::Bot)))),
+            flagged: self.flagged,
+        }
+    }
+
+    /// Restores the session saved by `save`, replaying its moves so that the human can still undo
+    /// them, at the levels of the given configuration
+    ///
+    /// The clock runs again from the time the player to move had left.
+    fn resume(saved: Saved, config: &EngineConfig) -> Result<Self, Error> {
+        if saved.moves.len() != saved.stamps.len() {
+            return Err(ParseError("Every move of a saved game needs a stamp".to_string()));
+        }
+        let time_control = saved.clock.as_ref()
+            .map(|(control, _, _)| TimeControl::parse(control))
+            .transpose()?;
+        let initial = Game::parse(Board::parse(saved.initial)?, saved.first);
+
+        let mut session = Session::new(saved.first, saved.intelligence, saved.takeback_limit,
+                                       saved.account, None)
+            .with_opening(initial)
+            .with_variant(saved.variant);
+        if let Some(level) = saved.level {
+            session = session.with_preset(level.preset(config));
+        }
+        if let Some(band) = saved.band {
+            session = session.with_adaptive(band);
+        }
+
+        for (placement, &(time, clocks)) in saved.moves.into_iter().zip(&saved.stamps) {
+            let placement = placement.map(Position::parse).transpose()?;
+            session.game = match &placement {
+                None => session.game.pass(),
+                Some(placement) => {
+                    let player = session.game.current_player();
+                    if player == Player::Human {
+                        session.undo_stack.push(session.snapshot());
+                    }
+                    session.game.play(&Action::parse(player, placement.clone()))?
+                }
+            };
+            session.moves.push(placement);
+            session.stamps.push(Stamp {
+                time: UNIX_EPOCH + Duration::from_millis(time),
+                clocks: clocks.map(|(human, bot)| {
+                    (Duration::from_millis(human), Duration::from_millis(bot))
+                }),
+            });
+        }
+
+        session.intelligence = saved.intelligence;
+        session.think_time = saved.think_time_ms.map(Duration::from_millis);
+        session.num_takebacks = saved.num_takebacks;
+        session.rated = saved.rated;
+        session.recorded = saved.recorded;
+        session.resume_token = saved.resume_token;
+        session.seed = saved.seed;
+        session.flagged = saved.flagged;
+        let running = (session.flagged.is_none() && !session.game.is_over())
+            .then(|| session.game.current_player());
+        session.clock = time_control.zip(saved.clock).map(|(control, (_, human, bot))| {
+            Clock::resume(control, Duration::from_millis(human), Duration::from_millis(bot),
+                          running)
+        });
+        Ok(session)
+    }
+
+    /// Checks if this session was idle or alive for at least the given lifetimes at the given time
+    fn is_expired(&self, now: Instant, idle: Duration, lifetime: Duration) -> bool {
+        now.saturating_duration_since(self.last_active) >= idle
+            || now.saturating_duration_since(self.created) >= lifetime
+    }
+
+    /// Serializes the record of this game, which is its current state, the board it started from
+    /// and the moves played since
+    pub fn record(&self) -> Value {
+        let mut json = crate::serialize_result(&self.game, BoardFormat::default());
+        json["first"] = json!(self.first.to_string());
+        json["initial"] = json!(self.initial.board().to_string());
+        json["moves"] = json!(self.moves.iter()
+            .map(|m| m.as_ref().map(|pos| pos.to_string()))
+            .collect::<Vec<_>>());
+        json
+    }
+
+    /// Remembers the given analysis of the given moves, whose reviews the history reports for as
+    /// long as the moves are played in this session
+    pub fn set_analysis(&mut self, moves: Vec<Option<Position>>, analysis: Value) {
+        self.analysis = Some((moves, analysis));
+    }
+
+    /// Serializes every move played so far with when it was played, the clocks right after it
+    /// and its review if the game was analyzed, where passes have no position
+    pub fn history(&self) -> Value {
+        let review = |ply: usize| {
+            let (moves, analysis) = self.analysis.as_ref()?;
+            if moves.get(..ply)? != &self.moves[..ply] {
+                return None;
+            }
+            analysis["moves"].as_array()?.iter().find(|review| review["ply"] == ply).cloned()
+        };
+
+        let mut player = self.initial.current_player();
+        let moves = self.moves.iter().zip(&self.stamps).enumerate()
+            .map(|(index, (placement, stamp))| {
+                let json = json!({
+                    "ply": index + 1,
+                    "player": player.to_string(),
+                    "pass": placement.is_none(),
+                    "position": placement.as_ref().map(|pos| pos.to_string()),
+                    "timestamp_ms": stamp.time.duration_since(UNIX_EPOCH)
+                        .unwrap_or_default().as_millis() as u64,
+                    "clock": stamp.clocks.map(|(human, bot)| json!({
+                        "human_ms": human.as_millis() as u64,
+                        "bot_ms": bot.as_millis() as u64,
+                    })),
+                    "review": review(index + 1),
+                });
+                player = player.opponent();
+                json
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "first": self.first.to_string(),
+            "initial": self.initial.board().to_string(),
+            "time_control": self.clock.as_ref().map(|clock| clock.control().to_string()),
+            "moves": moves,
+        })
+    }
+
+    /// Serializes this session to a json value with the board in the given format
+    pub fn to_json(&self, id: SessionId, format: BoardFormat) -> Value {
+        let mut json = crate::serialize_result(&self.game, format);
+        json["id"] = json!(id);
+        json["can_undo"] = json!(!self.is_rated() && !self.undo_stack.is_empty()
+            && self.takebacks_left() > 0);
+        json["can_redo"] = json!(!self.redo_stack.is_empty());
+        json["takebacks_left"] = json!(self.takebacks_left());
+        json["intelligence"] = json!(self.intelligence);
+        json["think_time_ms"] = json!(self.think_time.map(|time| time.as_millis() as u64));
+        json["level"] = json!(self.preset.map(|preset| preset.to_json()));
+        json["adaptive"] = json!(self.adaptive.as_ref().map(|adaptive| json!({
+            "band": adaptive.band,
+            "trend": adaptive.trend,
+        })));
+        json["takeback"] = json!(self.takeback.as_ref().map(Takeback::to_json));
+        json["rated"] = json!(self.is_rated());
+        json["variant"] = json!(self.game.variant().to_string());
+
+        if let Some(flagged) = self.flagged {
+            json["flagged"] = json!(flagged.to_string());
+            json["winner"] = json!(flagged.opponent().to_string());
+        }
+        if let Some(clock) = &self.clock {
+            json["clock"] = json!({
+                "time_control": clock.control().to_string(),
+                "human_ms": clock.remaining(Player::Human).as_millis() as u64,
+                "bot_ms": clock.remaining(Player::Bot).as_millis() as u64,
+                "running": clock.running().map(|p| p.to_string()),
+            });
+        }
+        json
+    }
+}
+
+/// Registry of all sessions on the server
+///
+/// Clones share the same sessions.
+#[derive(Clone)]
+pub struct Sessions {
+    sessions: Arc<Mutex<HashMap<SessionId, Session>>>,
+    /// Bots of the recently played sessions, so that a bot keeps its cache for the whole game
+    /// without unrelated games polluting it
+    bots: Arc<Mutex<LruCache<SessionId, Bot>>>,
+}
+
+impl Sessions {
+
+    /// Creates an empty registry keeping as many bots as the given configuration allows
+    pub fn new(config: &EngineConfig) -> Self {
+        Self {
+            sessions: Arc::default(),
+            bots: Arc::new(Mutex::new(LruCache::new(max_session_bots(config)))),
+        }
+    }
+
+    /// Takes the bot of the session with the given id, or creates one if it has none
+    fn take_bot(&self, id: SessionId, intelligence: u32, engine: &Engine) -> Bot {
+        let bot = self.bots.lock().unwrap().remove(&id);
+        let mut bot = bot.unwrap_or_else(|| engine.session_bot(intelligence, SESSION_CACHE_SIZE));
+        engine.rearm(&mut bot);
+        bot.set_intelligence(intelligence);
+        if engine.config().shuffle_root_moves {
+            bot.set_root_seed(self.with(id, |s| s.seed));
+        }
+        bot
+    }
+
+    /// Keeps the given bot of the session with the given id for its next moves
+    fn put_bot(&self, id: SessionId, bot: Bot) {
+        self.bots.lock().unwrap().insert(id, bot);
+    }
+
+    /// Drops the bots of all sessions, which must be done when the evaluation or the pruning
+    /// changes
+    pub fn clear_bots(&self) {
+        self.bots.lock().unwrap().clear();
+    }
+
+    /// Removes every session along with its bot, returning them with their ids
+    fn drain(&self) -> Vec<(SessionId, Session)> {
+        self.bots.lock().unwrap().clear();
+        self.sessions.lock().unwrap().drain().collect()
+    }
+
+    /// Registers the given session under a new random id and returns the id
+    pub fn insert(&self, session: Session) -> SessionId {
+        let mut sessions = self.sessions.lock().unwrap();
+        let id = std::iter::repeat_with(SessionId::random)
+            .find(|id| !sessions.contains_key(id))
+            .unwrap();
+        sessions.insert(id, session);
+        id
+    }
+
+    /// Runs the given function with the session of the given id, which keeps it alive
+    pub fn with<T>(&self, id: SessionId, f: impl FnOnce(&mut Session) -> T) -> Option<T> {
+        self.sessions.lock().unwrap().get_mut(&id).map(|session| {
+            session.last_active = Instant::now();
+            f(session)
+        })
+    }
+
+    /// Removes the sessions idle or alive for at least the given lifetimes at the given time,
+    /// along with their bots, returning them with their ids
+    fn expire(&self, now: Instant, idle: Duration, lifetime: Duration)
+        -> Vec<(SessionId, Session)> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let expired = sessions.iter()
+            .filter(|(_, session)| session.is_expired(now, idle, lifetime))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+
+        let mut bots = self.bots.lock().unwrap();
+        expired.into_iter()
+            .filter_map(|id| {
+                bots.remove(&id);
+                sessions.remove(&id).map(|session| (id, session))
+            })
+            .collect()
+    }
+}
+
+/// Records the ratings of the finished game
+fn rate(finished: &Finished, accounts: &Accounts, ratings: &BotRatings) -> Result<(), Error> {
+    if let Some(account) = &finished.account {
+        let bot_rating = finished.rated.then(|| ratings.get(finished.intelligence));
+        let rated = accounts.record(account, finished.winner, finished.takebacks, bot_rating)?;
+        // The bot at the intelligence it played with is rated against the human
+        if let Some((human_rating, score)) = rated {
+            ratings.record(finished.intelligence, human_rating, score)?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the records of the sessions that expired more than the given seconds before the given
+/// time, in seconds since the epoch
+fn prune(storage: &Storage, now: u64, retention: u64) {
+    for key in storage.keys(SESSIONS) {
+        let expired = key.split('-').next().and_then(|secs| secs.parse::<u64>().ok());
+        if expired.is_some_and(|secs| secs.saturating_add(retention) <= now) {
+            if let Err(e) = storage.remove(SESSIONS, &key) {
+                warn!("Failed to remove the expired game {}: {}", key, e);
+            }
+        }
+    }
+}
+
+/// Rates and saves the expired sessions of the given registry at the given time, and removes the
+/// records that outlived the configured retention
+///
+/// The records of the sessions are kept in the storage under the time they expired and their id.
+/// A rated game abandoned before its end is lost by the human.
+fn retire(sessions: &Sessions, storage: &Storage, accounts: &Accounts, ratings: &BotRatings,
+          config: &EngineConfig, now: Instant) {
+    let idle = Duration::from_secs(config.session_idle_secs);
+    let lifetime = Duration::from_secs(config.session_lifetime_secs);
+
+    let mut expired = sessions.expire(now, idle, lifetime);
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    for (id, session) in &mut expired {
+        if let Some(finished) = session.finish().or_else(|| session.forfeit()) {
+            if let Err(e) = rate(&finished, accounts, ratings) {
+                warn!("Failed to rate the expired game {}: {}", id, e);
+            }
+        }
+        if let Err(e) = storage.save(SESSIONS, &format!("{}-{}", secs, id), &session.record()) {
+            warn!("Failed to save the expired game {}: {}", id, e);
+        }
+    }
+    if !expired.is_empty() {
+        info!("Expired {} game sessions", expired.len());
+    }
+    prune(storage, secs, config.session_retention_secs);
+}
+
+/// Saves and forgets the expired sessions at the configured interval until the server shuts down
+pub async fn sweep(sessions: Sessions, storage: Storage, accounts: Accounts, ratings: BotRatings,
+                   config: EngineConfig, mut shutdown: Shutdown) {
+    let interval = Duration::from_secs(config.session_sweep_secs);
+
+    loop {
+        select! {
+            _ = sleep(interval) => {}
+            _ = &mut shutdown => break,
+        }
+        retire(&sessions, &storage, &accounts, &ratings, &config, Instant::now());
+    }
+}
+
+/// Saves every live session in the storage, once the expired ones are retired like the sweep
+/// does, and removes them from the registry so that no reply changes them afterwards
+fn save_live(sessions: &Sessions, storage: &Storage, accounts: &Accounts, ratings: &BotRatings,
+             config: &EngineConfig) {
+    retire(sessions, storage, accounts, ratings, config, Instant::now());
+
+    let live = sessions.drain();
+    for (id, session) in &live {
+        if let Err(e) = storage.save(LIVE_SESSIONS, &id.to_string(), &session.save()) {
+            warn!("Failed to save the live game {}: {}", id, e);
+        }
+    }
+    info!("Saved {} live game sessions", live.len());
+}
+
+/// Registers the sessions saved by `save_live` under their ids again and removes them from the
+/// storage, returning the ids of the sessions whose bot is to move
+fn resume(sessions: &Sessions, storage: &Storage, config: &EngineConfig) -> Vec<SessionId> {
+    let mut waiting = Vec::new();
+    let mut num_resumed = 0;
+    for key in storage.keys(LIVE_SESSIONS) {
+        let resumed = key.parse::<SessionId>().and_then(|id| {
+            let saved = storage.load::<Saved>(LIVE_SESSIONS, &key)
+                .ok_or_else(|| ParseError("The saved game cannot be read".to_string()))?;
+            Session::resume(saved, config).map(|session| (id, session))
+        });
+        match resumed {
+            Ok((id, mut session)) => {
+                if session.game().current_player() == Player::Bot && !session.is_over() {
+                    waiting.push(id);
+                }
+                sessions.sessions.lock().unwrap().insert(id, session);
+                num_resumed += 1;
+            }
+            Err(e) => warn!("Failed to resume the game {}: {}", key, e),
+        }
+        if let Err(e) = storage.remove(LIVE_SESSIONS, &key) {
+            warn!("Failed to remove the saved game {}: {}", key, e);
+        }
+    }
+    if num_resumed > 0 {
+        info!("Resumed {} live game sessions", num_resumed);
+    }
+    waiting
+}
+
+/// Fairing that saves the live sessions when the server shuts down and resumes them once it
+/// lifts off again, so that a redeploy loses no game
+///
+/// The bots of the resumed sessions play the moves they were thinking about when the server shut
+/// down.
+pub struct Persistence;
+
+#[rocket::async_trait]
+impl Fairing for Persistence {
+    fn info(&self) -> Info {
+        Info {
+            name: "Session Persistence",
+            kind: Kind::Liftoff | Kind::Shutdown,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (Some(sessions), Some(storage), Some(engine)) =
+            (rocket.state::<Sessions>(), rocket.state::<Storage>(), rocket.state::<Engine>())
+        else {
+            return;
+        };
+        let waiting = resume(sessions, storage, engine.config());
+
+        let (Some(accounts), Some(ratings), Some(archive), Some(model), Some(adaptation),
+             Some(book)) =
+            (rocket.state::<Accounts>(), rocket.state::<BotRatings>(), rocket.state::<Archive>(),
+             rocket.state::<WinModel>(), rocket.state::<Adaptation>(),
+             rocket.state::<OpeningBook>())
+        else {
+            return;
+        };
+        for id in waiting {
+            let (sessions, accounts, ratings, archive, engine, model, adaptation, book) =
+                (sessions.clone(), accounts.clone(), ratings.clone(), archive.clone(),
+                 engine.clone(), model.clone(), adaptation.clone(), book.clone());
+            rocket::tokio::spawn(async move {
+                if let Err(Custom(_, e)) = reply(id, &sessions, &accounts, &ratings, &archive,
+                                                 &engine, &model, &adaptation, &book,
+                                                 BoardFormat::default()).await {
+                    warn!("Failed to reply in the resumed game {}: {}", id, e);
+                }
+            });
+        }
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        if let (Some(sessions), Some(storage), Some(accounts), Some(ratings), Some(engine)) =
+            (rocket.state::<Sessions>(), rocket.state::<Storage>(), rocket.state::<Accounts>(),
+             rocket.state::<BotRatings>(), rocket.state::<Engine>()) {
+            save_live(sessions, storage, accounts, ratings, engine.config());
+        }
+    }
+}
+
+fn not_found(id: SessionId) -> Custom<String> {
+    Custom(Status::NotFound, format!("No game with the id: {}", id))
+}
+
+/// Runs the given function with the session of the given id on behalf of the player with the
+/// given credentials, failing if the session belongs to someone else
+fn with_player<T>(sessions: &Sessions, id: SessionId, credentials: &Credentials,
+                  f: impl FnOnce(&mut Session) -> T) -> Result<T, Custom<String>> {
+    sessions.with(id, |s| {
+        if !s.is_player(credentials) {
+            return Err(Custom(Status::Forbidden,
+                              "Only the player of the game may change it".to_string()));
+        }
+        Ok(f(s))
+    }).ok_or_else(|| not_found(id))?
+}
+
+/// Lets the bot play its turns of the session, if any, and archives the game once it is over
+#[allow(clippy::too_many_arguments)]
+async fn reply(id: SessionId, sessions: &Sessions, accounts: &Accounts, ratings: &BotRatings,
+               archive: &Archive, engine: &Engine, model: &WinModel, adaptation: &Adaptation,
+               book: &OpeningBook, format: BoardFormat)
+    -> Result<Value, Custom<String>> {
+    loop {
+        sessions.with(id, |s| {
+            if s.game().current_player() == Player::Bot && !s.is_over() {
+                let probability = model.probability(s.game(), s.game().evaluate());
+                let config = engine.config();
+                s.adapt(probability, config.min_intelligence..=config.max_intelligence);
+            }
+        });
+        let (game, over, intelligence, version, budget, think_time, preset) =
+            sessions.with(id, |s| {
+                (s.game().clone(), s.is_over(), s.intelligence(), s.version(), s.bot_budget(),
+                 s.think_time(), s.preset())
+            }).ok_or_else(|| not_found(id))?;
+
+        if over || game.current_player() != Player::Bot {
+            break;
+        }
+
+        let num_actions = game.actions(Player::Bot).count();
+        let started = Instant::now();
+        let mistake = preset
+            .and_then(|preset| preset.mistake(&game, Player::Bot, &mut rand::thread_rng()));
+        // The book is built from standard games, whose moves lose under the other variants
+        let book_action = match game.variant() {
+            Variant::Standard if preset.is_none_or(|preset| preset.use_book) => {
+                book.lookup(&game, engine.config().book_min_games)
+            }
+            _ => None,
+        };
+        let (action, result) = match mistake.or(book_action) {
+            Some(action) => {
+                let result = game.result(&action);
+                (action, result)
+            }
+            None => {
+                let mut bot = sessions.take_bot(id, intelligence, engine);
+                // The bot thinks no longer than its settings and its clock allow, within the
+                // limit of the server
+                let limit = [think_time, budget].into_iter().flatten()
+                    .fold(Duration::from_millis(engine.config().max_thinking_time_ms),
+                          Duration::min);
+                bot.set_deadline(Instant::now() + limit);
+                bot.set_node_limit(preset.and_then(|preset| preset.node_limit));
+                let (bot, decision) = engine.run(move || {
+                    let decision = bot.decide(&game);
+                    (bot, decision)
+                }).await?;
+                sessions.put_bot(id, bot);
+                decision?
+            }
+        };
+
+        // The bot takes its time on the moves it decides faster than a human would, without
+        // spending more than its clock allows
+        let mut delay = reply_time(engine.config(), intelligence, num_actions,
+                                   &mut rand::thread_rng())
+            .saturating_sub(started.elapsed());
+        if let Some(budget) = budget {
+            delay = delay.min(budget.saturating_sub(started.elapsed()));
+        }
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+
+        let applied = sessions.with(id, |s| {
+            // Another request changed the session while the bot was thinking
+            if s.version() != version || s.is_over() {
+                return false;
+            }
+            s.advance(action.placement().clone(), result);
+            true
+        }).ok_or_else(|| not_found(id))?;
+
+        if !applied {
+            break;
+        }
+    }
+
+    let (json, finished) = sessions
+        .with(id, |s| (s.to_json(id, format), s.finish()))
+        .ok_or_else(|| not_found(id))?;
+
+    if let Some(finished) = finished {
+        // The game is finished only once, so its ratings are recorded before anything that may
+        // fail to keep them
+        rate(&finished, accounts, ratings)
+            .map_err(|e| Custom(Status::InternalServerError, e.to_string()))?;
+        // A game missing from the archive does not undo the move that finished it
+        if let Err(e) = archive.save(id, &finished) {
+            warn!("Failed to archive the game {}: {}", id, e);
+        }
+        // The weights adapt to the next game if this one cannot be learnt from
+        if let Err(e) = adaptation.record(&finished, model) {
+            warn!("Failed to adapt the weights to the game {}: {}", id, e);
+        }
+    }
+
+    Ok(json)
+}
+
+#[post("/games?<intelligence>&<level>&<first>&<takebacks>&<time_control>&<xot>&<classic>\
+         &<variant>&<adaptive>&<band>&<rated>&<format>")]
+#[allow(clippy::too_many_arguments)]
+async fn create(intelligence: form::Result<'_, u32>, level: form::Result<'_, Level>,
+                first: form::Result<'_, Player>,
+                takebacks: Option<u32>, time_control: Option<&str>, xot: Option<bool>,
+                classic: Option<bool>, variant: Option<Variant>, adaptive: Option<bool>,
+                band: Option<f64>, rated: Option<bool>, format: Option<BoardFormat>,
+                account: Option<Authenticated>,
+                sessions: &State<Sessions>, accounts: &State<Accounts>,
+                ratings: &State<BotRatings>, archive: &State<Archive>, engine: &State<Engine>,
+                openings: &State<Xot>, model: &State<WinModel>, adaptation: &State<Adaptation>,
+                book: &State<OpeningBook>)
+    -> Result<String, Custom<String>> {
+    let first = validation::optional(first, "first")?.unwrap_or(Player::Human);
+    let config = engine.config();
+    let (intelligence, preset) = validation::strength(intelligence, level, config)?;
+    let takebacks = takebacks.unwrap_or(config.max_takebacks);
+    let time_control = time_control.map(TimeControl::parse).transpose()?;
+
+    let account = account.map(|Authenticated(account)| account.id);
+
+    let mut session = Session::new(first, intelligence, takebacks, account, time_control);
+    if xot.unwrap_or_default() && classic.unwrap_or_default() {
+        return Err(validation::invalid(Status::BadRequest, "xot",
+                          "XOT openings cannot be played from the classic start"));
+    }
+
+    if classic.unwrap_or_default() {
+        session = session.with_opening(Game::parse(Board::new_empty_center(), first));
+    }
+    if xot.unwrap_or_default() {
+        let opening = openings.choose(&mut rand::thread_rng())?;
+        session = session.with_opening(xot::start(opening, first)?);
+    }
+    let mut session = session.with_variant(variant.unwrap_or_default());
+
+    // Games of players with an account are rated unless they ask otherwise
+    match rated {
+        Some(true) if !session.is_rated() => return Err(Custom(Status::Unauthorized,
+            "Only players with an account can play rated games".to_string())),
+        Some(false) => session = session.unrated(),
+        _ => {}
+    }
+
+    if let Some(preset) = preset {
+        // The bots are rated by intelligence, which the other settings of a level would skew
+        if session.is_rated() {
+            return Err(validation::invalid(Status::BadRequest, "level",
+                              "Rated games cannot be played at a level"));
+        }
+        if adaptive.unwrap_or_default() {
+            return Err(validation::invalid(Status::BadRequest, "adaptive",
+                              "The bot cannot both play at a level and adapt"));
+        }
+        session = session.with_preset(preset);
+    }
+
+    if adaptive.unwrap_or_default() {
+        if session.is_rated() {
+            return Err(validation::invalid(Status::BadRequest, "adaptive",
+                              "The strength of the bot cannot adapt in a rated game"));
+        }
+        let band = band.unwrap_or(config.adaptive_band);
+        if !(band > 0.0 && band < 0.5) {
+            return Err(validation::invalid(Status::BadRequest, "band",
+                              "The band must be between 0 and 0.5"));
+        }
+        session = session.with_adaptive(band);
+    }
+
+    let resume_token = session.resume_token().to_string();
+    let id = sessions.insert(session);
+    let mut json = reply(id, sessions, accounts, ratings, archive, engine, model, adaptation,
+                         book, format.unwrap_or_default()).await?;
+    // Only the player who creates the session learns the token to play and resume watching it
+    json["resume_token"] = json!(resume_token);
+    Ok(json.to_string())
+}
+
+#[get("/games/<id>?<format>")]
+fn get(id: SessionId, format: Option<BoardFormat>, sessions: &State<Sessions>)
+    -> Result<String, Custom<String>> {
+    sessions.with(id, |s| {
+        s.check_flag();
+        s.to_json(id, format.unwrap_or_default()).to_string()
+    }).ok_or_else(|| not_found(id))
+}
+
+#[post("/games/<id>/moves?<position>&<format>")]
+#[allow(clippy::too_many_arguments)]
+async fn play(id: SessionId, position: form::Result<'_, Position>, format: Option<BoardFormat>,
+              credentials: Credentials, sessions: &State<Sessions>, accounts: &State<Accounts>,
+              ratings: &State<BotRatings>, archive: &State<Archive>, engine: &State<Engine>,
+              model: &State<WinModel>, adaptation: &State<Adaptation>, book: &State<OpeningBook>)
+    -> Result<String, Custom<String>> {
+    let position = validation::param(position, "position")?;
+
+    with_player(sessions, id, &credentials, |s| s.play(position))??;
+
+    reply(id, sessions, accounts, ratings, archive, engine, model, adaptation, book,
+          format.unwrap_or_default())
+        .await
+        .map(|json| json.to_string())
+}
+
+#[post("/games/<id>/undo?<format>")]
+fn undo(id: SessionId, format: Option<BoardFormat>, credentials: Credentials,
+        sessions: &State<Sessions>) -> Result<String, Custom<String>> {
+    let format = format.unwrap_or_default();
+    with_player(sessions, id, &credentials, |s| {
+        s.undo().map(|_| s.to_json(id, format).to_string())
+    })?
+        .map_err(Custom::from)
+}
+
+/// Requests to take back the last move of the human, which the bot accepts as long as the
+/// takeback limit of the game is not reached and the result of a rated game is not final
+#[post("/games/<id>/takeback?<format>")]
+fn takeback(id: SessionId, format: Option<BoardFormat>, credentials: Credentials,
+            sessions: &State<Sessions>) -> Result<String, Custom<String>> {
+    let format = format.unwrap_or_default();
+    with_player(sessions, id, &credentials, |s| -> Result<String, Error> {
+        s.request_takeback()?;
+        s.answer_takeback()?;
+        Ok(s.to_json(id, format).to_string())
+    })?
+        .map_err(Custom::from)
+}
+
+/// Changes the strength of the bot of a game session between its moves, such as its intelligence
+/// or the longest time it thinks about a move
+#[patch("/games/<id>/settings?<intelligence>&<think_time_ms>&<format>")]
+fn settings(id: SessionId, intelligence: Option<u32>, think_time_ms: Option<u64>,
+            format: Option<BoardFormat>, credentials: Credentials, sessions: &State<Sessions>,
+            engine: &State<Engine>)
+    -> Result<String, Custom<String>> {
+    let config = engine.config();
+    let intelligence = intelligence
+        .map(|intelligence| validation::intelligence(intelligence, config))
+        .transpose()?;
+    let think_time = think_time_ms
+        .map(|ms| validation::think_time(ms, "think_time_ms", config))
+        .transpose()?;
+
+    let format = format.unwrap_or_default();
+    with_player(sessions, id, &credentials, |s| {
+        s.set_strength(intelligence, think_time)
+            .map(|_| s.to_json(id, format).to_string())
+    })?
+        .map_err(Custom::from)
+}
+
+#[post("/games/<id>/redo?<format>")]
+fn redo(id: SessionId, format: Option<BoardFormat>, credentials: Credentials,
+        sessions: &State<Sessions>) -> Result<String, Custom<String>> {
+    let format = format.unwrap_or_default();
+    with_player(sessions, id, &credentials, |s| {
+        s.redo().map(|_| s.to_json(id, format).to_string())
+    })?
+        .map_err(Custom::from)
+}
+
+/// Request guard of the id of the last event a reconnecting client received, which browsers send
+/// in the `Last-Event-ID` header
+struct LastEventId(Option<u64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let id = request.headers().get_one("Last-Event-ID").and_then(|id| id.parse().ok());
+        Outcome::Success(LastEventId(id))
+    }
+}
+
+/// Streams the changes of a game session, starting with its current state
+///
+/// Events are identified by the version of the session they lead to. A player who reconnects
+/// with the resume token of the session and the id of the last event received, in the query or
+/// in the `Last-Event-ID` header, receives the events missed in between instead, as long as they
+/// are still kept. The bot of the session keeps what it learned about the game meanwhile.
+#[get("/games/<id>/watch?<resume>&<after>")]
+fn watch(id: SessionId, resume: Option<&str>, after: Option<u64>, last_event: LastEventId,
+         sessions: &State<Sessions>, mut shutdown: Shutdown)
+    -> Result<EventStream![], Custom<String>> {
+    let after = after.or(last_event.0);
+    let (backlog, mut events) = sessions.with(id, |s| {
+        if resume.is_some_and(|token| token != s.resume_token()) {
+            return Err(Custom(Status::Forbidden, "Invalid resume token".to_string()));
+        }
+
+        let missed = resume.and(after).and_then(|after| s.missed(after));
+        let backlog = missed.unwrap_or_else(|| {
+            vec![(s.version(), s.to_json(id, BoardFormat::default()).to_string())]
+        });
+        Ok((backlog, s.subscribe()))
+    }).ok_or_else(|| not_found(id))??;
+
+    Ok(EventStream! {
+        for (version, event) in backlog {
+            yield Event::data(event).id(version.to_string());
+        }
+
+        loop {
+            select! {
+                event = events.recv() => match event {
+                    Ok((version, event)) => yield Event::data(event).id(version.to_string()),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            }
+        }
+    })
+}
+
+/// Returns every move of a game session with its time, clocks and review, for move lists and
+/// archives
+#[get("/games/<id>/history")]
+fn history(id: SessionId, sessions: &State<Sessions>) -> Result<String, Custom<String>> {
+    sessions.with(id, |s| {
+        let mut json = s.history();
+        json["id"] = json!(id);
+        json.to_string()
+    }).ok_or_else(|| not_found(id))
+}
+
+#[get("/games/<id>/export?<format>")]
+fn export(id: SessionId, format: Option<&str>, sessions: &State<Sessions>)
+    -> Result<String, Custom<String>> {
+    sessions.with(id, |s| {
+        match format.unwrap_or("json") {
+            "ggf" => Ok(ggf::write(s.initial(), s.moves(), s.game())),
+            "transcript" => Ok(ggf::transcript(s.moves())),
+            "json" => Ok(s.record().to_string()),
+            format => Err(validation::invalid(Status::BadRequest, "format",
+                                              format!("Invalid format: {}", format))),
+        }
+    }).ok_or_else(|| not_found(id))?
+}
+
+/// Returns the routes of the game sessions
+pub fn routes() -> Vec<Route> {
+    routes![create, get, play, undo, takeback, redo, settings, watch, history, export]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use rocket::http::{Header, Status};
+    use rocket::local::asynchronous::Client;
+    use serde_json::{json, Value};
+
+    use crate::account::Accounts;
+    use crate::archive::Archive;
+    use crate::board::{BoardFormat, Position};
+    use crate::book::OpeningBook;
+    use crate::bot::{self, CancellationToken};
+    use crate::clock::TimeControl;
+    use crate::config::EngineConfig;
+    use crate::engine::Engine;
+    use crate::game::Player::{Bot, Human};
+    use crate::game::Variant;
+    use crate::ggf;
+    use crate::level::Level;
+    use crate::lru::ResponseCache;
+    use crate::probability::WinModel;
+    use crate::rating::BotRatings;
+    use crate::session::{reply, with_player, Credentials, Persistence, ReplyPace, Session, SessionId,
+                         Sessions, Takeback, EVALUATION_BYTES, EVENT_CAPACITY, LIVE_SESSIONS,
+                         SESSIONS, SESSION_CACHE_SIZE, TREND_LENGTH};
+    use crate::storage::Storage;
+    use crate::weights::Adaptation;
+
+    #[test]
+    fn undo_redo() {
+        let mut session = Session::new(Human, 1, 1, None, None);
+        let initial = session.game().clone();
+
+        assert!(session.undo().is_err());
+        assert!(session.redo().is_err());
+
+        session.play(Position::new(2, 4)).unwrap();
+        let played = session.game().clone();
+        assert!(played != initial);
+
+        session.undo().unwrap();
+        assert!(*session.game() == initial);
+        assert_eq!(session.takebacks_left(), 0);
+
+        session.redo().unwrap();
+        assert!(*session.game() == played);
+
+        // The takeback limit is reached
+        assert!(session.undo().is_err());
+    }
+
+    #[test]
+    fn takeback() {
+        let mut session = Session::new(Human, 1, 1, None, None);
+        assert!(session.answer_takeback().is_err());
+
+        session.request_takeback().unwrap();
+        assert!(session.request_takeback().is_err());
+        assert_eq!(session.answer_takeback().unwrap(),
+                   &Takeback::Declined("No moves to take back".to_string()));
+
+        let initial = session.game().clone();
+        session.play(Position::new(2, 4)).unwrap();
+        assert!(session.takeback.is_none());
+        session.request_takeback().unwrap();
+        assert_eq!(session.answer_takeback().unwrap(), &Takeback::Accepted);
+        assert!(*session.game() == initial);
+        assert_eq!(session.takebacks_left(), 0);
+
+        // The takeback limit is reached
+        session.play(Position::new(2, 4)).unwrap();
+        session.request_takeback().unwrap();
+        assert!(matches!(session.answer_takeback().unwrap(), Takeback::Declined(_)));
+
+        // Rated games only take moves back through requests
+        let mut rated = Session::new(Human, 1, 1, Some("account".to_string()), None);
+        rated.play(Position::new(2, 4)).unwrap();
+        assert!(rated.undo().is_err());
+        rated.request_takeback().unwrap();
+        assert_eq!(rated.answer_takeback().unwrap(), &Takeback::Accepted);
+        assert!(rated.finish().is_none());
+    }
+
+    #[test]
+    fn history() {
+        let mut session = Session::new(Human, 1, 1, None, None);
+        session.play(Position::new(2, 4)).unwrap();
+        let action = session.game().actions(Bot).next().unwrap();
+        let result = session.game().result(&action);
+        session.advance(action.placement().clone(), result);
+
+        let history = session.history();
+        let moves = history["moves"].as_array().unwrap();
+        assert_eq!(moves.len(), 2);
+        assert_eq!((&moves[0]["player"], &moves[0]["position"]), (&json!("H"), &json!("2,4")));
+        assert_eq!(moves[1]["player"], "B");
+        assert!(moves[0]["timestamp_ms"].as_u64() <= moves[1]["timestamp_ms"].as_u64());
+        assert!(moves.iter().all(|m| m["clock"].is_null() && m["review"].is_null()));
+
+        let analysis = json!({"moves": [{"ply": 1, "score": 3}, {"ply": 2, "score": -1}]});
+        session.set_analysis(session.moves().to_vec(), analysis);
+        assert_eq!(session.history()["moves"][1]["review"]["score"], -1);
+
+        // The reviews of moves taken back no longer apply
+        session.undo().unwrap();
+        session.play(Position::new(3, 5)).unwrap();
+        let moves = session.history()["moves"].as_array().unwrap().clone();
+        assert_eq!(moves.len(), 1);
+        assert!(moves[0]["review"].is_null());
+    }
+
+    #[test]
+    fn resume() {
+        let mut session = Session::new(Human, 1, EVENT_CAPACITY as u32 + 1, None, None);
+        assert_eq!(session.missed(0), Some(Vec::new()));
+        assert_eq!(session.missed(1), None);
+
+        session.play(Position::new(2, 4)).unwrap();
+        session.undo().unwrap();
+        let missed = session.missed(0).unwrap();
+        assert_eq!(missed.iter().map(|(version, _)| *version).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(session.missed(1).unwrap().len(), 1);
+
+        // Only the latest events are kept
+        for _ in 0..EVENT_CAPACITY {
+            session.redo().unwrap();
+            session.undo().unwrap();
+        }
+        assert_eq!(session.missed(0), None);
+        assert_eq!(session.missed(session.version() - 3).unwrap().len(), 3);
+
+        let other = Session::new(Human, 1, 1, None, None);
+        assert!(session.resume_token() != other.resume_token());
+    }
+
+    #[test]
+    fn reply_time() {
+        let mut rng = rand::thread_rng();
+        assert_eq!(super::reply_time(&EngineConfig::default(), 1, 10, &mut rng), Duration::ZERO);
+
+        let config = EngineConfig {
+            reply_pacing: vec![
+                ReplyPace { min_reply_ms: 400, reply_ms_per_action: 100 },
+                ReplyPace { min_reply_ms: 100, reply_ms_per_action: 0 },
+            ],
+            ..EngineConfig::default()
+        };
+        for _ in 0..100 {
+            let time = super::reply_time(&config, 1, 6, &mut rng);
+            assert!(Duration::from_millis(750) <= time && time <= Duration::from_millis(1250));
+
+            // The jitter never lets the bot reply sooner than its pace allows
+            for intelligence in [2, 8] {
+                let time = super::reply_time(&config, intelligence, 6, &mut rng);
+                assert!(Duration::from_millis(100) <= time && time <= Duration::from_millis(125));
+            }
+            let time = super::reply_time(&config, 1, 0, &mut rng);
+            assert!(Duration::from_millis(400) <= time);
+        }
+    }
+
+    #[test]
+    fn set_strength() {
+        let mut session = Session::new(Human, 1, 1, None, None);
+        session.set_strength(Some(4), None).unwrap();
+        assert_eq!((session.intelligence(), session.think_time()), (4, None));
+
+        session.set_strength(None, Some(Duration::from_millis(500))).unwrap();
+        assert_eq!(session.intelligence(), 4);
+        assert_eq!(session.to_json(SessionId(0), BoardFormat::default())["think_time_ms"], 500);
+
+        let config = EngineConfig::default();
+        let mut leveled = Session::new(Human, 1, 1, None, None)
+            .with_preset(Level::Casual.preset(&config));
+        assert_eq!(leveled.intelligence(), 2);
+        assert_eq!(leveled.to_json(SessionId(0), BoardFormat::default())["level"]["level"], "casual");
+        leveled.set_strength(Some(5), None).unwrap();
+        assert!(leveled.preset().is_none());
+
+        let mut rated = Session::new(Human, 1, 1, Some("account".to_string()), None);
+        assert!(rated.set_strength(Some(4), None).is_err());
+        assert_eq!(rated.intelligence(), 1);
+    }
+
+    #[test]
+    fn adapt() {
+        let mut session = Session::new(Human, 3, 1, None, None).with_adaptive(0.2);
+        session.adapt(0.9, 1..=8);
+        session.adapt(0.9, 1..=8);
+        assert_eq!(session.intelligence(), 3);
+
+        // The bot weakens once the game drifts its way
+        session.adapt(0.9, 1..=8);
+        assert_eq!(session.intelligence(), 2);
+
+        // and strengthens once it drifts the other way, up to the given intelligence
+        for _ in 0..3 * TREND_LENGTH {
+            session.adapt(0.1, 1..=4);
+        }
+        assert_eq!(session.intelligence(), 4);
+
+        // A close game keeps the strength
+        for _ in 0..TREND_LENGTH {
+            session.adapt(0.6, 1..=4);
+        }
+        assert_eq!(session.intelligence(), 4);
+
+        let mut fixed = Session::new(Human, 3, 1, None, None);
+        for _ in 0..TREND_LENGTH {
+            fixed.adapt(0.9, 1..=8);
+        }
+        assert_eq!(fixed.intelligence(), 3);
+    }
+
+    #[test]
+    fn flag() {
+        let control = TimeControl {
+            initial: Duration::ZERO,
+            increment: Duration::ZERO,
+        };
+        let mut session = Session::new(Human, 1, 1, None, Some(control));
+
+        assert!(session.is_over());
+        assert_eq!(session.winner().unwrap(), Some(Bot));
+        assert!(session.play(Position::new(2, 4)).is_err());
+        assert_eq!(session.to_json(SessionId(0), BoardFormat::default())["flagged"], "H");
+    }
+
+    #[test]
+    fn expire() {
+        let engine = Engine::new(EngineConfig::default(), CancellationToken::new());
+        let sessions = Sessions::new(&EngineConfig::default());
+        let (idle, lifetime) = (Duration::from_secs(60), Duration::from_secs(600));
+
+        let active = sessions.insert(Session::new(Human, 1, 1, None, None));
+        let forgotten = sessions.insert(Session::new(Human, 1, 1, None, None));
+        let bot = sessions.take_bot(forgotten, 1, &engine);
+        sessions.put_bot(forgotten, bot);
+
+        let start = Instant::now();
+        assert!(sessions.expire(start, idle, lifetime).is_empty());
+
+        sessions.with(active, |s| s.last_active = start + Duration::from_secs(50));
+        let expired = sessions.expire(start + Duration::from_secs(90), idle, lifetime);
+        assert_eq!(expired.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![forgotten]);
+        assert!(sessions.with(forgotten, |_| ()).is_none());
+        assert_eq!(sessions.bots.lock().unwrap().len(), 0);
+
+        // Even an active session expires at the end of its lifetime
+        sessions.with(active, |s| s.last_active = start + lifetime);
+        assert_eq!(sessions.expire(start + lifetime, idle, lifetime).len(), 1);
+    }
+
+    #[test]
+    fn forfeit() {
+        // An abandoned rated game is lost by the human, only once
+        let mut rated = Session::new(Human, 1, 1, Some("account".to_string()), None);
+        let finished = rated.forfeit().unwrap();
+        assert_eq!(finished.winner, Some(Bot));
+        assert!(finished.rated);
+        assert!(rated.forfeit().is_none());
+
+        let mut unrated = Session::new(Human, 1, 1, Some("account".to_string()), None).unrated();
+        assert!(unrated.forfeit().is_none());
+    }
+
+    #[test]
+    fn player() {
+        let sessions = Sessions::new(&EngineConfig::default());
+        let session = Session::new(Human, 1, 1, Some("account".to_string()), None);
+        let resume_token = Some(session.resume_token().to_string());
+        let id = sessions.insert(session);
+        let initial = sessions.with(id, |s| s.game().clone()).unwrap();
+
+        // A stranger who guesses the id of the game cannot play it
+        let stranger = Credentials { account: Some("stranger".to_string()), resume_token: None };
+        let error = with_player(&sessions, id, &stranger, |s| s.play(Position::new(2, 4)))
+            .unwrap_err();
+        assert_eq!(error.0, Status::Forbidden);
+        assert!(sessions.with(id, |s| *s.game() == initial).unwrap());
+        let missing = SessionId(u128::MAX);
+        assert_eq!(with_player(&sessions, missing, &stranger, |_| ()).unwrap_err().0,
+                   Status::NotFound);
+
+        // The player plays from their account or with the resume token of the game
+        let account = Credentials { account: Some("account".to_string()), resume_token: None };
+        let resume = Credentials { account: None, resume_token };
+        assert!(with_player(&sessions, id, &account, |s| s.play(Position::new(2, 4))).is_ok());
+        assert!(with_player(&sessions, id, &resume, |_| ()).is_ok());
+
+        let anonymous = Session::new(Human, 1, 1, None, None);
+        assert!(!anonymous.is_player(&Credentials { account: None, resume_token: None }));
+    }
+
+    #[rocket::async_test]
+    async fn think_time() {
+        let config = EngineConfig { max_thinking_time_ms: 1_000, ..EngineConfig::default() };
+        let sessions = Sessions::new(&config);
+        let session = Session::new(Human, 1, 1, None, None);
+        let resume_token = session.resume_token().to_string();
+        let id = sessions.insert(session);
+
+        let rocket = rocket::custom(rocket::Config::debug_default())
+            .manage(sessions.clone())
+            .manage(Engine::new(config, CancellationToken::new()))
+            .mount("/", routes![super::settings]);
+        let client = Client::tracked(rocket).await.unwrap();
+        let patch = |ms: u64| client.patch(format!("/games/{}/settings?think_time_ms={}", id, ms))
+            .header(Header::new("X-Resume-Token", resume_token.clone()));
+
+        // The thinking time is kept within that of the server
+        let response = patch(1_001).dispatch().await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let json: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        assert_eq!(json["field"], "think_time_ms");
+
+        assert_eq!(patch(500).dispatch().await.status(), Status::Ok);
+        assert_eq!(sessions.with(id, |s| s.think_time()).unwrap(),
+                   Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn session_id() {
+        let sessions = Sessions::new(&EngineConfig::default());
+        let id = sessions.insert(Session::new(Human, 1, 1, None, None));
+        assert!(id != sessions.insert(Session::new(Human, 1, 1, None, None)));
+
+        let text = id.to_string();
+        assert_eq!(text.len(), 32);
+        assert_eq!(text.parse::<SessionId>().unwrap(), id);
+        assert_eq!(json!(SessionId(255)), json!(format!("{:0>32}", "ff")));
+        assert!("1".parse::<SessionId>().is_err());
+        assert!("g".repeat(32).parse::<SessionId>().is_err());
+    }
+
+    #[test]
+    fn prune() {
+        let dir = std::env::temp_dir().join(format!("expired-sessions-{}", std::process::id()));
+        let storage = Storage::new(&dir);
+        storage.save(SESSIONS, "100-1", &json!({})).unwrap();
+        storage.save(SESSIONS, "200-2", &json!({})).unwrap();
+
+        // Only the records older than the retention are removed
+        super::prune(&storage, 250, 100);
+        assert_eq!(storage.keys(SESSIONS), vec!["200-2".to_string()]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn persistence() {
+        let dir = std::env::temp_dir().join(format!("live-sessions-{}", std::process::id()));
+        let storage = Storage::new(&dir);
+        let config = EngineConfig::default();
+        let sessions = Sessions::new(&config);
+
+        let control = TimeControl::parse("5+3").unwrap();
+        let mut session = Session::new(Human, 3, 2, Some("account".to_string()), Some(control))
+            .with_variant(Variant::Anti)
+            .with_adaptive(0.2);
+        session.play(Position::new(2, 4)).unwrap();
+        let action = session.game().actions(Bot).next().unwrap();
+        let result = session.game().result(&action);
+        session.advance(action.placement().clone(), result);
+        let (record, resume_token) = (session.record(), session.resume_token().to_string());
+        let id = sessions.insert(session);
+
+        let rocket = rocket::custom(rocket::Config::debug_default())
+            .manage(sessions.clone())
+            .manage(storage.clone())
+            .manage(Accounts::new(storage.clone()))
+            .manage(BotRatings::new(storage.clone()))
+            .manage(Engine::new(config.clone(), CancellationToken::new()))
+            .attach(Persistence);
+        Client::tracked(rocket).await.unwrap().terminate().await;
+
+        // The live session waits in the storage for the server to start again
+        assert!(sessions.with(id, |_| ()).is_none());
+        assert_eq!(storage.keys(LIVE_SESSIONS), vec![id.to_string()]);
+
+        let resumed = Sessions::new(&config);
+        assert!(super::resume(&resumed, &storage, &config).is_empty());
+        assert!(storage.keys(LIVE_SESSIONS).is_empty());
+        resumed.with(id, |s| {
+            assert_eq!((s.record(), s.resume_token()), (record, resume_token.as_str()));
+            assert_eq!(s.game().variant(), Variant::Anti);
+            assert!(s.is_rated() && s.adaptive.is_some());
+            let clock = s.clock.as_ref().unwrap();
+            assert_eq!((clock.control(), clock.running()), (control, Some(Human)));
+
+            // The move of the human can still be taken back
+            s.request_takeback().unwrap();
+            assert_eq!(s.answer_takeback().unwrap(), &Takeback::Accepted);
+            assert!(s.moves().is_empty());
+        }).unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn book_reply() {
+        let dir = std::env::temp_dir().join(format!("session-book-{}", std::process::id()));
+        let storage = Storage::new(&dir);
+        let config = EngineConfig::default();
+        let engine = Engine::new(config.clone(), CancellationToken::new());
+        let sessions = Sessions::new(&config);
+
+        let mut session = Session::new(Human, 1, 0, None, None);
+        session.play(ggf::parse_notation("f5").unwrap()).unwrap();
+        // The book holds the reply the search likes least
+        let ranking = bot::Bot::new(1).rank(session.game(), Bot);
+        let (worst, best) = (ranking.iter().min_by_key(|(_, score)| *score).unwrap(),
+                             ranking.iter().max_by_key(|(_, score)| *score).unwrap());
+        assert!(worst.1 < best.1);
+        let book = OpeningBook::new(storage.clone());
+        let line = format!("f5 {}", ggf::notation(worst.0.placement()));
+        book.add_line(&line, config.book_min_games, None).unwrap();
+
+        let id = sessions.insert(session);
+        let model = WinModel::load(&storage);
+        let adaptation = Adaptation::new(&config, storage.clone(), engine.clone(),
+                                         ResponseCache::new(1), sessions.clone());
+        reply(id, &sessions, &Accounts::new(storage.clone()), &BotRatings::new(storage.clone()),
+              &Archive::new(storage.clone()), &engine, &model, &adaptation, &book,
+              BoardFormat::default()).await.unwrap();
+        assert_eq!(sessions.with(id, |s| s.moves()[1].clone()).unwrap(),
+                   Some(worst.0.placement().clone()));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn session_bots() {
+        let max_bots = 4;
+        let config = EngineConfig {
+            session_memory_bytes: max_bots * SESSION_CACHE_SIZE * EVALUATION_BYTES + 1,
+            ..EngineConfig::default()
+        };
+        let engine = Engine::new(config.clone(), CancellationToken::new());
+        let sessions = Sessions::new(&config);
+
+        for id in 0..max_bots as u128 + 1 {
+            let bot = sessions.take_bot(SessionId(id), 1, &engine);
+            sessions.put_bot(SessionId(id), bot);
+        }
+        // The bot of the least recently played session is dropped
+        assert_eq!(sessions.bots.lock().unwrap().len(), max_bots);
+        assert!(sessions.bots.lock().unwrap().remove(&SessionId(0)).is_none());
+
+        let bot = sessions.take_bot(SessionId(1), 1, &engine);
+        assert_eq!(sessions.bots.lock().unwrap().len(), max_bots - 1);
+        sessions.put_bot(SessionId(1), bot);
+
+        sessions.clear_bots();
+        assert_eq!(sessions.bots.lock().unwrap().len(), 0);
+
+        let config = EngineConfig { session_memory_bytes: 1, ..EngineConfig::default() };
+        assert_eq!(super::max_session_bots(&config), 1);
+    }
+}
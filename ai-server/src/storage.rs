@@ -0,0 +1,122 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+use rocket::serde::de::DeserializeOwned;
+use rocket::serde::Serialize;
+
+use crate::errors::Error;
+use crate::errors::Error::{InvalidArgument, StorageError};
+
+/// Persists json documents as files, grouped into collections by directory
+#[derive(Clone)]
+pub struct Storage {
+    root: PathBuf,
+}
+
+impl Storage {
+
+    /// Creates a new storage that keeps its files under the given directory
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+        }
+    }
+
+    /// Returns the path of the document with the given key and extension
+    fn path_with(&self, collection: &str, key: &str, extension: &str) -> Result<PathBuf, Error> {
+        if key.is_empty() || !key.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-') {
+            return Err(InvalidArgument(format!("Invalid key of a document: {}", key)));
+        }
+
+        Ok(self.root.join(collection).join(format!("{}.{}", key, extension)))
+    }
+
+    /// Returns the path of the document with the given key
+    fn path(&self, collection: &str, key: &str) -> Result<PathBuf, Error> {
+        self.path_with(collection, key, "json")
+    }
+
+    /// Loads the document with the given key, if it exists
+    pub fn load<T: DeserializeOwned>(&self, collection: &str, key: &str) -> Option<T> {
+        let data = fs::read_to_string(self.path(collection, key).ok()?).ok()?;
+        serde_json::from_str(&data).ok()
+    }
+
+    /// Saves the given document under the given key, replacing the previous one
+    pub fn save<T: Serialize>(&self, collection: &str, key: &str, value: &T) -> Result<(), Error> {
+        let path = self.path(collection, key)?;
+        let data = serde_json::to_string(value)
+            .map_err(|e| StorageError(format!("Failed to serialize {}: {}", key, e)))?;
+
+        fs::create_dir_all(self.root.join(collection))
+            .and_then(|_| fs::write(&path, data))
+            .map_err(|e| StorageError(format!("Failed to write {}: {}", path.display(), e)))
+    }
+
+    /// Loads the binary document with the given key, if it exists
+    pub fn load_bytes(&self, collection: &str, key: &str) -> Option<Vec<u8>> {
+        fs::read(self.path_with(collection, key, "bin").ok()?).ok()
+    }
+
+    /// Saves the given binary document under the given key, replacing the previous one
+    pub fn save_bytes(&self, collection: &str, key: &str, data: &[u8]) -> Result<(), Error> {
+        let path = self.path_with(collection, key, "bin")?;
+
+        fs::create_dir_all(self.root.join(collection))
+            .and_then(|_| fs::write(&path, data))
+            .map_err(|e| StorageError(format!("Failed to write {}: {}", path.display(), e)))
+    }
+
+    /// Appends the given values as json lines to the log with the given key
+    pub fn append<T: Serialize>(&self, collection: &str, key: &str, values: &[T])
+        -> Result<(), Error> {
+        let path = self.path_with(collection, key, "jsonl")?;
+
+        let mut data = String::new();
+        for value in values {
+            data += &serde_json::to_string(value)
+                .map_err(|e| StorageError(format!("Failed to serialize {}: {}", key, e)))?;
+            data.push('\n');
+        }
+
+        fs::create_dir_all(self.root.join(collection))
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(&path))
+            .and_then(|mut file| file.write_all(data.as_bytes()))
+            .map_err(|e| StorageError(format!("Failed to write {}: {}", path.display(), e)))
+    }
+
+    /// Loads the values of the log with the given key, skipping the lines that fail to parse
+    pub fn load_lines<T: DeserializeOwned>(&self, collection: &str, key: &str) -> Vec<T> {
+        let data = match self.path_with(collection, key, "jsonl").map(fs::read_to_string) {
+            Ok(Ok(data)) => data,
+            _ => return Vec::new(),
+        };
+
+        data.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+    }
+
+    /// Removes the document with the given key, if it exists
+    pub fn remove(&self, collection: &str, key: &str) -> Result<(), Error> {
+        let path = self.path(collection, key)?;
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound =>
+                Err(StorageError(format!("Failed to remove {}: {}", path.display(), e))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the keys of all documents in the given collection
+    pub fn keys(&self, collection: &str) -> Vec<String> {
+        let entries = match fs::read_dir(self.root.join(collection)) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        entries.filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str()
+                .and_then(|name| name.strip_suffix(".json"))
+                .map(|key| key.to_string()))
+            .collect()
+    }
+}
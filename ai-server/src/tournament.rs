@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use itertools::Itertools;
+use rocket::data::Capped;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::Deserialize;
+use rocket::tokio::task::spawn_blocking;
+use rocket::{Route, State};
+use serde_json::{json, Value};
+
+use crate::admin::Admin;
+use crate::board::Board;
+use crate::book::{Book, OpeningBook};
+use crate::bot::{Bot, CancellationToken};
+use crate::config::EngineConfig;
+use crate::game::{self, Game, Player, Weights};
+use crate::limits;
+use crate::selfplay::{self, GameRecord};
+use crate::validation;
+use crate::xot::{self, Xot};
+
+/// How the engines of a tournament are paired
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "kebab-case")]
+pub enum Format {
+    /// Every engine plays every other engine
+    RoundRobin,
+    /// The first engine plays every other engine
+    Gauntlet,
+}
+
+/// Named configuration of the engine taking part in a tournament
+#[derive(Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct EngineSpec {
+    pub name: String,
+    /// Depth of the searches of the engine
+    #[serde(alias = "depth")]
+    pub intelligence: u32,
+    /// Weights the engine evaluates with, or those of the server if missing
+    #[serde(default)]
+    pub weights: Option<Weights>,
+    /// Whether the engine plays the moves of the opening book while it has some
+    #[serde(default)]
+    pub use_book: bool,
+}
+
+impl EngineSpec {
+
+    /// Creates the bot playing as this engine, which the given token can stop
+    fn bot(&self, cancellation: &CancellationToken) -> Bot {
+        let mut bot = Bot::new(self.intelligence);
+        bot.set_cancellation(cancellation.clone());
+        bot.set_weights(self.weights).expect("The weights of the engines are validated");
+        bot
+    }
+}
+
+/// Definition of a tournament sent by the operator
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TournamentSpec {
+    pub format: Format,
+    pub engines: Vec<EngineSpec>,
+    pub games_per_pairing: u32,
+    /// Whether each pair of games starts from a random XOT opening instead of the initial board
+    #[serde(default)]
+    pub xot: bool,
+}
+
+/// Returns the pairs of engine indices that play each other
+fn pairings(format: Format, num_engines: usize) -> Vec<(usize, usize)> {
+    match format {
+        Format::RoundRobin => (0..num_engines).tuple_combinations().collect(),
+        Format::Gauntlet => (1..num_engines).map(|i| (0, i)).collect(),
+    }
+}
+
+/// A tournament together with the games played so far
+pub struct Tournament {
+    spec: TournamentSpec,
+    games: Vec<(usize, usize, GameRecord)>,
+    finished: bool,
+}
+
+impl Tournament {
+
+    /// Creates a new tournament that has not played any games
+    pub fn new(spec: TournamentSpec) -> Self {
+        Self {
+            spec,
+            games: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Returns the number of games this tournament plays in total
+    fn num_games(&self) -> usize {
+        pairings(self.spec.format, self.spec.engines.len()).len()
+            * self.spec.games_per_pairing as usize
+    }
+
+    /// Serializes the standings and the game records of this tournament to a json value
+    pub fn to_json(&self) -> Value {
+        let mut standings = self.spec.engines.iter()
+            .map(|e| (e, 0.0_f64, 0, 0, 0))
+            .collect_vec();
+
+        for (human, bot, record) in self.games.iter() {
+            match record.winner {
+                Some(Player::Human) => {
+                    standings[*human].1 += 1.0;
+                    standings[*human].2 += 1;
+                    standings[*bot].3 += 1;
+                }
+                Some(Player::Bot) => {
+                    standings[*bot].1 += 1.0;
+                    standings[*bot].2 += 1;
+                    standings[*human].3 += 1;
+                }
+                None => {
+                    for i in [*human, *bot] {
+                        standings[i].1 += 0.5;
+                        standings[i].4 += 1;
+                    }
+                }
+            }
+        }
+
+        json!({
+            "finished": self.finished,
+            "games_played": self.games.len(),
+            "games_total": self.num_games(),
+            "standings": standings.iter()
+                .sorted_by(|a, b| b.1.total_cmp(&a.1))
+                .map(|(engine, points, wins, losses, draws)| json!({
+                    "name": engine.name,
+                    "intelligence": engine.intelligence,
+                    "weights": engine.weights,
+                    "use_book": engine.use_book,
+                    "points": points,
+                    "wins": wins,
+                    "losses": losses,
+                    "draws": draws,
+                }))
+                .collect_vec(),
+            "games": self.games.iter()
+                .map(|(human, bot, record)| {
+                    let mut json = record.to_json();
+                    json["dark"] = json!(self.spec.engines[*human].name);
+                    json["light"] = json!(self.spec.engines[*bot].name);
+                    json
+                })
+                .collect_vec(),
+        })
+    }
+}
+
+/// Plays all games of the given tournament, alternating the colours within each pairing
+///
+/// With XOT openings, both games of a pair with swapped colours start from the same opening. The
+/// engines using the book play its moves played at least the given number of times.
+fn run(tournament: &Mutex<Tournament>, think_time: Duration, cancellation: &CancellationToken,
+       openings: &Xot, book: &Book, min_games: u32) {
+    let (engines, matches, use_xot) = {
+        let tournament = tournament.lock().unwrap();
+        let spec = &tournament.spec;
+
+        let matches = pairings(spec.format, spec.engines.len()).into_iter()
+            .flat_map(|(a, b)| (0..spec.games_per_pairing)
+                .map(move |i| if i % 2 == 0 { (a, b, i) } else { (b, a, i) }))
+            .collect_vec();
+
+        (spec.engines.clone(), matches, spec.xot)
+    };
+
+    let mut rng = rand::thread_rng();
+    let mut start = Game::parse(Board::new(), Player::Human);
+
+    for (human, bot, i) in matches {
+        if cancellation.is_cancelled() {
+            break;
+        }
+
+        let mut human_bot = engines[human].bot(cancellation);
+        let mut bot_bot = engines[bot].bot(cancellation);
+
+        if use_xot && i % 2 == 0 {
+            start = openings.choose(&mut rng)
+                .and_then(|opening| xot::start(opening, Player::Human))
+                .unwrap_or_else(|_| Game::parse(Board::new(), Player::Human));
+        }
+        let record = selfplay::play_with(start.clone(), cancellation, |game| {
+            let (engine, searcher) = match game.current_player() {
+                Player::Human => (&engines[human], &mut human_bot),
+                Player::Bot => (&engines[bot], &mut bot_bot),
+            };
+            engine.use_book.then(|| book.lookup(game, min_games)).flatten()
+                .or_else(|| selfplay::decide(searcher, game, think_time))
+        });
+
+        tournament.lock().unwrap().games.push((human, bot, record));
+    }
+
+    tournament.lock().unwrap().finished = true;
+}
+
+/// Registry of the tournaments run on the server
+#[derive(Default)]
+pub struct Tournaments {
+    next_id: AtomicU64,
+    tournaments: Mutex<HashMap<u64, Arc<Mutex<Tournament>>>>,
+}
+
+#[post("/admin/tournaments", data = "<spec>")]
+fn create(_admin: Admin, spec: Capped<String>, tournaments: &State<Tournaments>,
+          cancellation: &State<CancellationToken>, config: &State<EngineConfig>,
+          openings: &State<Xot>, book: &State<OpeningBook>)
+    -> Result<String, Custom<String>> {
+    let mut spec: TournamentSpec = serde_json::from_str(&limits::complete(spec)?)
+        .map_err(|e| validation::invalid(Status::BadRequest, "body",
+                                         format!("Invalid tournament: {}", e)))?;
+
+    if spec.engines.len() < 2 {
+        return Err(validation::invalid(Status::BadRequest, "engines",
+                          "A tournament needs at least two engines"));
+    }
+    if spec.xot && openings.is_empty() {
+        return Err(validation::invalid(Status::BadRequest, "xot", "No XOT openings are loaded"));
+    }
+    for engine in &mut spec.engines {
+        engine.intelligence = validation::intelligence(engine.intelligence, config)?;
+        if let Some(weights) = &engine.weights {
+            game::validate_weights(weights)
+                .map_err(|e| validation::invalid(Status::BadRequest, "weights", e))?;
+        }
+    }
+    // The games play on their own copy of the book, which its edits do not reach
+    let book = if spec.engines.iter().any(|engine| engine.use_book) {
+        book.snapshot()
+    } else {
+        Book::default()
+    };
+
+    let tournament = Arc::new(Mutex::new(Tournament::new(spec)));
+    let id = tournaments.next_id.fetch_add(1, Ordering::Relaxed);
+    tournaments.tournaments.lock().unwrap().insert(id, tournament.clone());
+
+    let think_time = Duration::from_millis(config.max_thinking_time_ms);
+    let cancellation = cancellation.inner().clone();
+    let openings = openings.inner().clone();
+    let min_games = config.book_min_games;
+    spawn_blocking(move || run(&tournament, think_time, &cancellation, &openings, &book,
+                               min_games));
+
+    Ok(json!({ "id": id }).to_string())
+}
+
+#[get("/admin/tournaments/<id>")]
+fn get(_admin: Admin, id: u64, tournaments: &State<Tournaments>)
+    -> Result<String, Custom<String>> {
+    let tournament = tournaments.tournaments.lock().unwrap().get(&id).cloned()
+        .ok_or_else(|| Custom(Status::NotFound, format!("No tournament with the id: {}", id)))?;
+
+    let json = tournament.lock().unwrap().to_json();
+    Ok(json.to_string())
+}
+
+/// Returns the routes of the tournaments
+pub fn routes() -> Vec<Route> {
+    routes![create, get]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Weights;
+    use crate::tournament::{EngineSpec, Format, pairings};
+
+    #[test]
+    fn pairings_of_formats() {
+        assert_eq!(pairings(Format::RoundRobin, 3), vec![(0, 1), (0, 2), (1, 2)]);
+        assert_eq!(pairings(Format::Gauntlet, 3), vec![(0, 1), (0, 2)]);
+        assert!(pairings(Format::RoundRobin, 1).is_empty());
+    }
+
+    #[test]
+    fn engine_specs() {
+        let engine: EngineSpec = serde_json::from_str(r#"{"name": "a", "depth": 3}"#).unwrap();
+        assert_eq!((engine.intelligence, engine.weights, engine.use_book), (3, None, false));
+
+        let engine: EngineSpec = serde_json::from_str(r#"{
+            "name": "b",
+            "intelligence": 4,
+            "weights": {"placement": [5, 4, 2], "mobility": [5, 4, 3], "num_disks": [-1, -1, 0]},
+            "use_book": true
+        }"#).unwrap();
+        assert_eq!(engine.intelligence, 4);
+        assert_eq!(engine.weights, Some(Weights::default()));
+        assert!(engine.use_book);
+    }
+}
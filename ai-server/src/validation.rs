@@ -0,0 +1,344 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+use itertools::Itertools;
+use rocket::form;
+use rocket::form::error::ErrorKind;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use serde_json::json;
+
+use crate::board::{Board, Position};
+use crate::config::EngineConfig;
+use crate::errors::Error;
+use crate::game::{Action, Game, Player};
+use crate::level::{Level, Preset};
+
+/// Returns the error of the given field of a request, whose body names the field so that clients
+/// can point at the wrong input
+pub fn invalid(status: Status, field: &str, message: impl Display) -> Custom<String> {
+    Custom(status, json!({
+        "error": message.to_string(),
+        "field": field,
+    }).to_string())
+}
+
+/// Unwraps the given parsed query parameter or describes why it is invalid
+///
+/// The board, the players and the positions are checked as they are parsed, such as the shape of
+/// the board and the bounds of the positions.
+pub fn param<T>(value: form::Result<'_, T>, field: &str) -> Result<T, Custom<String>> {
+    value.map_err(|errors| invalid(Status::BadRequest, field, format!(
+        "Invalid {}: {}", field, errors.iter().map(|e| &e.kind).join(", "))))
+}
+
+/// Unwraps the given parsed query parameter, which may be left out, or describes why it is
+/// invalid
+pub fn optional<T>(value: form::Result<'_, T>, field: &str) -> Result<Option<T>, Custom<String>> {
+    match is_missing(&value) {
+        true => Ok(None),
+        false => param(value, field).map(Some),
+    }
+}
+
+/// Checks if the given query parameter is left out of the request
+pub fn is_missing<T>(value: &form::Result<'_, T>) -> bool {
+    matches!(value, Err(e) if e.iter().all(|e| matches!(e.kind, ErrorKind::Missing)))
+}
+
+/// Returns the intelligence the bot may search with, clamping the requested one to the range of
+/// the server or rejecting it with 422 Unprocessable Entity, depending on the configuration
+///
+/// The deadline of the searches bounds their time whatever the intelligence, while the range
+/// keeps requests from asking for searches that could never finish in time.
+pub fn intelligence(intelligence: u32, config: &EngineConfig) -> Result<u32, Custom<String>> {
+    let (min, max) = (config.min_intelligence, config.max_intelligence);
+    if (min..=max).contains(&intelligence) {
+        return Ok(intelligence);
+    }
+    if config.clamp_intelligence {
+        return Ok(intelligence.clamp(min, max));
+    }
+
+    Err(Custom(Status::UnprocessableEntity, json!({
+        "error": format!("The intelligence must be between {} and {}", min, max),
+        "field": "intelligence",
+        "min": min,
+        "max": max,
+    }).to_string()))
+}
+
+/// Returns the intelligence the bot searches with and the preset of the level, if the request
+/// gives a level instead of an intelligence
+pub fn strength(intelligence: form::Result<'_, u32>, level: form::Result<'_, Level>,
+                config: &EngineConfig) -> Result<(u32, Option<Preset>), Custom<String>> {
+    match (optional(intelligence, "intelligence")?, optional(level, "level")?) {
+        (Some(intelligence), None) => Ok((self::intelligence(intelligence, config)?, None)),
+        (None, Some(level)) => {
+            let preset = level.preset(config);
+            Ok((preset.intelligence, Some(preset)))
+        }
+        (Some(_), Some(_)) => Err(invalid(Status::BadRequest, "level",
+                                          "Give either an intelligence or a level, not both")),
+        (None, None) => Err(invalid(Status::BadRequest, "intelligence",
+                                    "Give either an intelligence or a level")),
+    }
+}
+
+/// Returns the time the bot may search a decision for, deepening its search until then, if the
+/// request gives a time budget instead of an intelligence or a level
+pub fn time_budget(time_ms: Option<u64>, intelligence: form::Result<'_, u32>,
+                   level: form::Result<'_, Level>, config: &EngineConfig)
+    -> Result<Option<Duration>, Custom<String>> {
+    let Some(time_ms) = time_ms else {
+        return Ok(None);
+    };
+    if optional(intelligence, "intelligence")?.is_some() || optional(level, "level")?.is_some() {
+        return Err(invalid(Status::BadRequest, "time_ms",
+                           "Give either an intelligence, a level or a time budget"));
+    }
+    think_time(time_ms, "time_ms", config).map(Some)
+}
+
+/// Returns the time in the given field the bot may think about a move, rejecting a time that is
+/// empty or beyond that of the server with 422 Unprocessable Entity
+pub fn think_time(time_ms: u64, field: &str, config: &EngineConfig)
+    -> Result<Duration, Custom<String>> {
+    if time_ms == 0 || time_ms > config.max_thinking_time_ms {
+        return Err(invalid(Status::UnprocessableEntity, field, format!(
+            "The thinking time must be between 1 and {} ms", config.max_thinking_time_ms)));
+    }
+    Ok(Duration::from_millis(time_ms))
+}
+
+/// Returns the largest number of nodes the bot may expand for a decision, if the request limits
+/// them, rejecting a budget that is empty or beyond that of the server with 422 Unprocessable
+/// Entity
+pub fn node_budget(max_nodes: form::Result<'_, u32>, config: &EngineConfig)
+    -> Result<Option<u32>, Custom<String>> {
+    match optional(max_nodes, "max_nodes")? {
+        Some(max_nodes) if max_nodes == 0 || max_nodes > config.max_nodes => {
+            Err(invalid(Status::UnprocessableEntity, "max_nodes", format!(
+                "The node budget must be between 1 and {}", config.max_nodes)))
+        }
+        max_nodes => Ok(max_nodes),
+    }
+}
+
+/// Returns the intelligence the bot searches with when a request leaves it out, within the range
+/// of the server
+pub fn default_intelligence(default: u32, config: &EngineConfig) -> u32 {
+    default.clamp(config.min_intelligence, config.max_intelligence)
+}
+
+/// Returns why the given board cannot come from a game, or rejects it with 422 Unprocessable
+/// Entity if the server is configured to
+pub fn consistency(board: &Board, config: &EngineConfig) -> Result<Vec<String>, Custom<String>> {
+    let inconsistencies = board.inconsistencies();
+    if config.reject_impossible_boards && !inconsistencies.is_empty() {
+        return Err(invalid(Status::UnprocessableEntity, "board", inconsistencies.join(", ")));
+    }
+    Ok(inconsistencies)
+}
+
+/// Returns the action of placing a disk of the given player at the given position, or rejects it
+/// unless the player may take it in the given state
+pub fn action(game: &Game, player: Player, position: Position) -> Result<Action, Custom<String>> {
+    let action = Action::parse(player, position);
+    if game.actions(player).contains(&action) {
+        Ok(action)
+    } else {
+        Err(invalid(Status::BadRequest, "position",
+                    format!("Invalid action for the given player: {}", action)))
+    }
+}
+
+/// Returns why the given player may not place a disk at the given position in the given state,
+/// or None if the placement is one of the actions of the player, rejecting a position that
+/// cannot be parsed
+///
+/// The reason is one of "out_of_bounds", "occupied", "blocked", "outside_center" while the
+/// players fill the center of a classic start, and "no_bracketing_line".
+pub fn illegality(game: &Game, player: Player, position: &str)
+    -> Result<Option<&'static str>, Custom<String>> {
+    let position = match Position::parse(position.to_string()) {
+        Ok(position) => position,
+        Err(Error::OutOfRange(_)) => return Ok(Some("out_of_bounds")),
+        Err(e) => return Err(invalid(Status::BadRequest, "position",
+                                     format!("Invalid position: {}", e))),
+    };
+    // Legality is decided by the actions, like the placements of /result, and only explained here
+    if game.actions(player).contains(&Action::parse(player, position.clone())) {
+        return Ok(None);
+    }
+
+    let board = game.board();
+    Ok(Some(if board.disk(&position).is_some() {
+        "occupied"
+    } else if board.is_blocked(&position) {
+        "blocked"
+    } else if game.is_placing() {
+        "outside_center"
+    } else {
+        "no_bracketing_line"
+    }))
+}
+
+/// Rejects passing the turn of the current player of the given state unless the player has no
+/// actions while the game goes on
+pub fn pass(game: &Game) -> Result<(), Custom<String>> {
+    if game.is_over() {
+        return Err(invalid(Status::BadRequest, "board", "The game is over"));
+    }
+    if !game.must_pass() {
+        return Err(invalid(Status::BadRequest, "player",
+                           format!("The player {} has actions and cannot pass",
+                                   game.current_player())));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rocket::form::error::ErrorKind;
+    use rocket::form::{self, Errors};
+    use rocket::http::Status;
+    use serde_json::{json, Value};
+
+    use crate::board::{Board, Position};
+    use crate::config::EngineConfig;
+    use crate::game::{Game, Player};
+    use crate::level::Level;
+    use crate::validation::{
+        action, illegality, intelligence, node_budget, optional, param, pass, strength,
+        think_time, time_budget,
+    };
+
+    fn body(error: rocket::response::status::Custom<String>) -> (Status, Value) {
+        (error.0, serde_json::from_str(&error.1).unwrap())
+    }
+
+    #[test]
+    fn fields() {
+        let errors = Errors::from(form::Error::validation("Expected 8 lines"));
+        let (status, json) = body(param::<u32>(Err(errors), "board").unwrap_err());
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(json, json!({"error": "Invalid board: Expected 8 lines", "field": "board"}));
+
+        let missing = || Err(Errors::from(ErrorKind::Missing));
+        assert_eq!(body(param::<u32>(missing(), "intelligence").unwrap_err()).1["field"],
+                   "intelligence");
+        assert_eq!(optional::<u32>(missing(), "last").unwrap(), None);
+        assert_eq!(optional::<u32>(Ok(3), "last").unwrap(), Some(3));
+
+        let config = EngineConfig { min_intelligence: 2, max_intelligence: 6,
+                                    ..EngineConfig::default() };
+        let (status, json) = body(intelligence(u32::MAX, &config).unwrap_err());
+        assert_eq!(status, Status::UnprocessableEntity);
+        assert_eq!(json, json!({"error": "The intelligence must be between 2 and 6",
+                                "field": "intelligence", "min": 2, "max": 6}));
+        assert!(intelligence(1, &config).is_err());
+        assert_eq!(intelligence(6, &config).unwrap(), 6);
+        let clamped = EngineConfig { clamp_intelligence: true, ..config.clone() };
+        assert_eq!(intelligence(u32::MAX, &clamped).unwrap(), 6);
+        assert_eq!(intelligence(0, &clamped).unwrap(), 2);
+
+        let game = Game::parse(Board::new(), Player::Human);
+        assert!(action(&game, Player::Human, Position::new(2, 4)).is_ok());
+        let (status, json) = body(action(&game, Player::Human, Position::new(0, 0)).unwrap_err());
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(json["field"], "position");
+
+        let reason = |board: Board, position| {
+            illegality(&Game::parse(board, Player::Human), Player::Human, position).unwrap()
+        };
+        assert_eq!(reason(Board::new(), "2,4"), None);
+        assert_eq!(reason(Board::new(), "3,3"), Some("occupied"));
+        assert_eq!(reason(Board::new(), "0,0"), Some("no_bracketing_line"));
+        assert_eq!(reason(Board::new(), "8,2"), Some("out_of_bounds"));
+        let (status, json) = body(illegality(&Game::new(), Player::Human, "c4").unwrap_err());
+        assert_eq!((status, json["field"].as_str()), (Status::BadRequest, Some("position")));
+
+        // A blocked square is never legal, even where it would bracket a line
+        let blocked = Board::parse([
+            "EEEEEEEE",
+            "EEEEEEEE",
+            "EEEEXEEE",
+            "EEEDLEEE",
+            "EEELDEEE",
+            "EEEEEEEE",
+            "EEEEEEEE",
+            "EEEEEEEE",
+        ].join("\n")).unwrap();
+        assert_eq!(reason(blocked, "2,4"), Some("blocked"));
+        // The first disks of a classic start fill the center without bracketing anything
+        assert_eq!(reason(Board::new_empty_center(), "3,3"), None);
+        assert_eq!(reason(Board::new_empty_center(), "0,0"), Some("outside_center"));
+    }
+
+    #[test]
+    fn passes() {
+        let board = Board::parse([
+            "EEEEEEEE",
+            "LLLEEEEE",
+            "ELEEDEEE",
+            "LLLDDEEE",
+            "EEEDDEEE",
+            "EEEDEDEE",
+            "EEEEEEDE",
+            "EEEEEEEE",
+        ].join("\n")).unwrap();
+        assert!(pass(&Game::parse(board.clone(), Player::Human)).is_ok());
+
+        let (status, json) = body(pass(&Game::parse(board, Player::Bot)).unwrap_err());
+        assert_eq!((status, json["field"].as_str()), (Status::BadRequest, Some("player")));
+        let over = Board::parse(["DDDDDDDD"; 8].join("\n")).unwrap();
+        assert_eq!(body(pass(&Game::parse(over, Player::Human)).unwrap_err()).1["field"], "board");
+    }
+
+    #[test]
+    fn levels() {
+        fn missing<T>() -> form::Result<'static, T> {
+            Err(Errors::from(ErrorKind::Missing))
+        }
+        let config = EngineConfig { min_intelligence: 1, max_intelligence: 6,
+                                    ..EngineConfig::default() };
+
+        assert_eq!(strength(Ok(3), missing(), &config).unwrap(), (3, None));
+        let (intelligence, preset) = strength(missing(), Ok(Level::Club), &config).unwrap();
+        assert_eq!((intelligence, preset), (4, Some(Level::Club.preset(&config))));
+
+        let (status, json) = body(strength(Ok(3), Ok(Level::Club), &config).unwrap_err());
+        assert_eq!((status, json["field"].as_str()), (Status::BadRequest, Some("level")));
+        let (status, json) = body(strength(missing(), missing(), &config).unwrap_err());
+        assert_eq!((status, json["field"].as_str()), (Status::BadRequest, Some("intelligence")));
+        assert_eq!(body(strength(Ok(7), missing(), &config).unwrap_err()).0,
+                   Status::UnprocessableEntity);
+
+        let config = EngineConfig { max_thinking_time_ms: 1_000, ..config };
+        assert_eq!(time_budget(None, Ok(3), missing(), &config).unwrap(), None);
+        assert_eq!(time_budget(Some(300), missing(), missing(), &config).unwrap(),
+                   Some(Duration::from_millis(300)));
+        let (status, json) = body(time_budget(Some(300), Ok(3), missing(), &config).unwrap_err());
+        assert_eq!((status, json["field"].as_str()), (Status::BadRequest, Some("time_ms")));
+        assert_eq!(body(time_budget(Some(0), missing(), missing(), &config).unwrap_err()).0,
+                   Status::UnprocessableEntity);
+        assert!(time_budget(Some(1_001), missing(), missing(), &config).is_err());
+        assert_eq!(think_time(1_000, "think_time_ms", &config).unwrap(), Duration::from_secs(1));
+        let (status, json) = body(think_time(1_001, "think_time_ms", &config).unwrap_err());
+        assert_eq!((status, json["field"].as_str()),
+                   (Status::UnprocessableEntity, Some("think_time_ms")));
+
+        let config = EngineConfig { max_nodes: 1_000, ..config };
+        assert_eq!(node_budget(missing(), &config).unwrap(), None);
+        assert_eq!(node_budget(Ok(500), &config).unwrap(), Some(500));
+        let (status, json) = body(node_budget(Ok(0), &config).unwrap_err());
+        assert_eq!((status, json["field"].as_str()),
+                   (Status::UnprocessableEntity, Some("max_nodes")));
+        assert!(node_budget(Ok(1_001), &config).is_err());
+        let malformed = Err(Errors::from(form::Error::validation("invalid digit")));
+        assert_eq!(body(node_budget(malformed, &config).unwrap_err()).0, Status::BadRequest);
+    }
+}
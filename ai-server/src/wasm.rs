@@ -0,0 +1,81 @@
+use itertools::Itertools;
+use serde_json::{json, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::board::{Board, Position};
+use crate::bot::Bot;
+use crate::errors::Error;
+use crate::game::{Action, Game, Player};
+
+/// Converts the given error of the engine into a JavaScript error
+fn js_error(error: Error) -> JsError {
+    JsError::new(&error.to_string())
+}
+
+/// Parses the given player such as "H" or "B"
+fn parse_player(player: &str) -> Result<Player, Error> {
+    match player.chars().collect::<Vec<char>>()[..] {
+        [ch] => Player::parse(ch),
+        _ => Err(Error::ParseError(format!("Invalid string to parse into a player: {}", player))),
+    }
+}
+
+/// Serializes the given game state to json, in the same shape as the results of the server
+fn serialize(game: &Game) -> Value {
+    json!({
+        "board": game.board().to_string(),
+        "player": game.current_player().to_string(),
+        "actions": game.actions(game.current_player())
+            .map(|a| a.to_string())
+            .sorted()
+            .collect_vec(),
+        "must_pass": game.must_pass(),
+        "winner": game.winner().ok().flatten().map(|p| p.to_string()),
+    })
+}
+
+/// Returns the positions where the given player can place a disk
+#[wasm_bindgen]
+pub fn actions(board: &str, player: &str) -> Result<Vec<String>, JsError> {
+    let board = Board::parse(board.to_string()).map_err(js_error)?;
+    let player = parse_player(player).map_err(js_error)?;
+
+    Ok(Game::parse(board, player).actions(player)
+        .map(|a| a.to_string())
+        .sorted()
+        .collect_vec())
+}
+
+/// Returns the json of the state after the given player places a disk at the given position
+#[wasm_bindgen]
+pub fn result(board: &str, position: &str, player: &str) -> Result<String, JsError> {
+    let board = Board::parse(board.to_string()).map_err(js_error)?;
+    let position = Position::parse(position.to_string()).map_err(js_error)?;
+    let player = parse_player(player).map_err(js_error)?;
+
+    let game = Game::parse(board, player).play(&Action::parse(player, position))
+        .map_err(js_error)?;
+
+    Ok(serialize(&game).to_string())
+}
+
+/// Returns the json of the decision of the bot on the given board and the state it leads to
+///
+/// The search runs on the calling thread without a deadline, so keep the intelligence low
+#[wasm_bindgen]
+pub fn decide(board: &str, intelligence: u32) -> Result<String, JsError> {
+    let board = Board::parse(board.to_string()).map_err(js_error)?;
+    let game = Game::parse(board, Player::Bot);
+
+    let mut bot = Bot::new(intelligence);
+    let (action, game) = match bot.decide(&game) {
+        Ok((action, result)) => (Some(action), result),
+        Err(_) => (None, game), // No available actions
+    };
+
+    Ok(json!({
+        "decision": action.map(|a| a.to_string()),
+        "result": serialize(&game),
+        "score": bot.score,
+    }).to_string())
+}
@@ -0,0 +1,179 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use rocket::tokio::net::lookup_host;
+use rocket::tokio::time::sleep;
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::errors::Error;
+use crate::errors::Error::InvalidArgument;
+
+/// Number of times the delivery of a callback is attempted
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the second attempt, doubled after every further attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Time allowed for the receiver to answer a single attempt
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Checks if the given address is reachable from the public internet, unlike the server itself,
+/// its private network and the metadata services of cloud providers
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            // 100.64.0.0/10 is shared by the carrier-grade NATs of providers
+            let shared = first == 100 && (64..128).contains(&second);
+            !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()
+                || ip.is_broadcast() || ip.is_multicast() || ip.is_documentation() || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                // fc00::/7 holds the unique local addresses and fe80::/10 the link-local ones
+                let local = first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80;
+                !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || local)
+            }
+        },
+    }
+}
+
+/// Resolves the host of the given callback url to the addresses it is posted to
+///
+/// The callback must use http or https and every address of its host must be public, so that
+/// callbacks cannot make the server post to itself or to its private network.
+pub async fn resolve(url: &str) -> Result<(Url, Vec<SocketAddr>), Error> {
+    let invalid = || InvalidArgument(format!("Invalid callback: {}", url));
+    let parsed = Url::parse(url).map_err(|_| invalid())?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(invalid());
+    }
+    let port = parsed.port_or_known_default().ok_or_else(invalid)?;
+    // Addresses are resolved as they are, without their brackets for IPv6
+    let host = parsed.host_str().ok_or_else(invalid)?.trim_start_matches('[').trim_end_matches(']');
+    let addrs = lookup_host((host, port)).await
+        .map_err(|_| InvalidArgument(format!("Unknown host of the callback: {}", host)))?
+        .collect::<Vec<_>>();
+
+    if addrs.is_empty() || !addrs.iter().all(|addr| is_public(addr.ip())) {
+        return Err(InvalidArgument(format!("The callback must be a public address: {}", url)));
+    }
+    Ok((parsed, addrs))
+}
+
+/// Checks that the given callback url can be posted to
+pub async fn validate(url: &str) -> Result<(), Error> {
+    resolve(url).await.map(|_| ())
+}
+
+/// Returns the hex encoded HMAC-SHA256 signature of the given body
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Posts the result of the job with the given id to the given url, retrying with exponential
+/// backoff until the receiver answers with a success status
+///
+/// The body is signed in the `X-Desdemona-Signature` header when a secret is given. The host is
+/// resolved and checked again before posting, and the request goes to the checked addresses
+/// without following redirects, so that the host cannot turn private after the validation.
+/// Returns whether the result was delivered.
+pub async fn deliver(url: &str, job: u64, result: &Value, secret: Option<&str>) -> bool {
+    let (url, addrs) = match resolve(url).await {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            warn!("Callback of job {} was refused: {}", job, e);
+            return false;
+        }
+    };
+    let mut client = reqwest::Client::builder().redirect(Policy::none());
+    if let Some(domain) = url.domain() {
+        client = client.resolve_to_addrs(domain, &addrs);
+    }
+    let client = match client.build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Callback of job {} failed: {}", job, e);
+            return false;
+        }
+    };
+
+    let body = result.to_string();
+    let mut backoff = INITIAL_BACKOFF;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let mut request = client.post(url.clone())
+            .timeout(ATTEMPT_TIMEOUT)
+            .header("Content-Type", "application/json")
+            .header("X-Desdemona-Job", job)
+            .header("X-Desdemona-Attempt", attempt)
+            .body(body.clone());
+
+        if let Some(secret) = secret {
+            request = request.header("X-Desdemona-Signature",
+                                     format!("sha256={}", sign(secret, &body)));
+        }
+
+        match request.send().await {
+            Ok(response) if response.status().is_success() => return true,
+            Ok(response) => warn!("Callback of job {} was answered with {}", job,
+                                  response.status()),
+            Err(e) => warn!("Callback of job {} failed: {}", job, e),
+        }
+
+        if attempt < MAX_ATTEMPTS {
+            sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use crate::webhook::{is_public, sign, validate};
+
+    #[test]
+    fn signature() {
+        assert_eq!(sign("key", "The quick brown fox jumps over the lazy dog"),
+                   "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8");
+    }
+
+    #[rocket::async_test]
+    async fn callback_url() {
+        assert!(validate("https://93.184.216.34/hook").await.is_ok());
+        assert!(validate("http://[2606:2800:220:1::1]:8080/hook").await.is_ok());
+        assert!(validate("ftp://93.184.216.34/hook").await.is_err());
+        assert!(validate("not a url").await.is_err());
+
+        // The server itself, its private network and the metadata of cloud providers
+        for url in ["http://127.0.0.1/hook", "http://localhost:8000/hook", "http://10.0.0.1/",
+                    "http://192.168.1.1/", "http://169.254.169.254/latest/meta-data/",
+                    "http://0.0.0.0/", "http://[::1]/", "http://[fd00::1]/",
+                    "http://[::ffff:127.0.0.1]/"] {
+            assert!(validate(url).await.is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn public_addresses() {
+        let public = |ip: &str| is_public(ip.parse::<IpAddr>().unwrap());
+        assert!(public("8.8.8.8"));
+        assert!(public("2001:4860:4860::8888"));
+        assert!(!public("172.16.0.1"));
+        assert!(!public("100.64.0.1"));
+        assert!(!public("fe80::1"));
+    }
+}
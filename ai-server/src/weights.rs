@@ -0,0 +1,264 @@
+use std::sync::{Arc, Mutex};
+
+use rocket::data::Capped;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::{Route, State};
+use serde_json::json;
+
+use crate::admin::Admin;
+use crate::config::EngineConfig;
+use crate::engine::Engine;
+use crate::errors::Error;
+use crate::game::{self, Action, Game, Player, Variant, Weights};
+use crate::limits;
+use crate::lru::ResponseCache;
+use crate::probability::{sigmoid, WinModel};
+use crate::probcut;
+use crate::session::{Finished, Sessions};
+use crate::storage::Storage;
+use crate::validation;
+
+const CONFIG: &str = "config";
+const WEIGHTS_KEY: &str = "weights";
+/// Key of the weights the adapted weights stay close to, which the adaptation keeps across
+/// restarts since the adapted weights replace the saved ones
+const BASE_KEY: &str = "base_weights";
+
+/// Largest distance each weight may adapt away from the weights the evaluation starts with
+const MAX_DRIFT: i32 = 2;
+
+/// Replaces the weights of the evaluation with the ones saved in the given storage, if any
+pub fn load(storage: &Storage) -> Result<(), Error> {
+    match storage.load::<Weights>(CONFIG, WEIGHTS_KEY) {
+        Some(weights) => game::set_weights(weights),
+        None => Ok(()),
+    }
+}
+
+/// Makes the evaluation use the given weights and saves them
+fn apply(weights: Weights, storage: &Storage, engine: &Engine, analysis: &ResponseCache,
+         sessions: &Sessions) -> Result<(), Error> {
+    game::set_weights(weights)?;
+    // The cached evaluations and the ProbCut pairs were made with the previous weights
+    engine.clear_cache();
+    analysis.clear();
+    sessions.clear_bots();
+    probcut::reset(storage, engine)?;
+    storage.save(CONFIG, WEIGHTS_KEY, &weights)
+}
+
+/// Returns the states of the given finished game before each of its moves and at its end, or
+/// none if one of its moves breaks the rules
+fn states(finished: &Finished) -> Option<Vec<Game>> {
+    let mut states = vec![finished.initial.clone()];
+    for placement in &finished.moves {
+        let game = states.last()?;
+        let next = match placement {
+            Some(placement) => game.play(&Action::parse(game.current_player(),
+                                                        placement.clone())).ok()?,
+            None => game.pass(),
+        };
+        states.push(next);
+    }
+    Some(states)
+}
+
+/// Returns the gradient of the log loss of the probabilities that the bot wins the given states
+/// against its given result, by term and phase of the given weights
+///
+/// The result of the bot is 1 for a win, 0.5 for a draw and 0 for a loss, and the given function
+/// returns the slope of the logistic curve of each phase.
+fn gradient(states: &[Game], outcome: f64, weights: &Weights, slope: impl Fn(usize) -> f64)
+    -> [[f64; 3]; 3] {
+    let mut gradient = [[0.0; 3]; 3];
+    for game in states {
+        let breakdown = game.breakdown(weights);
+        let phase = breakdown.phase.to_index();
+        let slope = slope(phase);
+        let error = sigmoid(slope * breakdown.total() as f64) - outcome;
+
+        for (i, (_, term)) in breakdown.terms().iter().enumerate() {
+            gradient[i][phase] += error * slope * (term.bot - term.human) as f64
+                / states.len() as f64;
+        }
+    }
+    gradient
+}
+
+/// Returns the given weights moved against the given gradient at the given rate, once the
+/// fractions of a step that each weight has been pushed by add up to a whole step
+///
+/// Each weight moves by at most one step, and not at all if that would take it further than the
+/// largest drift from the given base weights.
+fn step(mut weights: Weights, base: &Weights, rate: f64, gradient: &[[f64; 3]; 3],
+        pending: &mut [[f64; 3]; 3]) -> Weights {
+    let base = [base.placement, base.mobility, base.num_disks];
+    let terms = [&mut weights.placement, &mut weights.mobility, &mut weights.num_disks];
+
+    for (i, term) in terms.into_iter().enumerate() {
+        for phase in 0..3 {
+            let fraction = pending[i][phase] - rate * gradient[i][phase];
+            let step = fraction.trunc().clamp(-1.0, 1.0);
+            pending[i][phase] = (fraction - step).clamp(-1.0, 1.0);
+
+            let moved = term[phase] + step as i32;
+            if (moved - base[i][phase]).abs() <= MAX_DRIFT {
+                term[phase] = moved;
+            }
+        }
+    }
+    weights
+}
+
+/// Nudges the weights of the evaluation towards the results of the finished rated games, so
+/// that the evaluation slowly adapts to the players of the server
+///
+/// Only rated games played to the end under the standard rules count. Clones share the same
+/// progress.
+#[derive(Clone)]
+pub struct Adaptation {
+    enabled: bool,
+    rate: f64,
+    /// Weights the evaluation started with before adapting, which the adapted weights stay
+    /// close to
+    base: Arc<Mutex<Weights>>,
+    /// Fractions of a step that each weight has been pushed by since it last moved, by term and
+    /// phase
+    pending: Arc<Mutex<[[f64; 3]; 3]>>,
+    storage: Storage,
+    engine: Engine,
+    analysis: ResponseCache,
+    sessions: Sessions,
+}
+
+impl Adaptation {
+
+    /// Creates the adaptation of the weights the evaluation uses, once they are loaded, which
+    /// keeps the given storage and caches up to date with the adapted weights
+    pub fn new(config: &EngineConfig, storage: Storage, engine: Engine, analysis: ResponseCache,
+               sessions: Sessions) -> Self {
+        let base = storage.load::<Weights>(CONFIG, BASE_KEY).unwrap_or_else(game::weights);
+        Self {
+            enabled: config.adapt_weights,
+            rate: config.adaptation_rate,
+            base: Arc::new(Mutex::new(base)),
+            pending: Arc::default(),
+            storage,
+            engine,
+            analysis,
+            sessions,
+        }
+    }
+
+    /// Nudges the weights towards the result of the given finished game, if the weights adapt
+    /// and the game is rated and played to the end under the standard rules
+    pub fn record(&self, finished: &Finished, model: &WinModel) -> Result<(), Error> {
+        if !self.enabled || !finished.rated || finished.initial.variant() != Variant::Standard {
+            return Ok(());
+        }
+        // Games lost on time end before the board decides them
+        let states = match states(finished) {
+            Some(states) if states.last().is_some_and(|game| game.is_over()) => states,
+            _ => return Ok(()),
+        };
+        let outcome = match finished.winner {
+            Some(Player::Bot) => 1.0,
+            Some(Player::Human) => 0.0,
+            None => 0.5,
+        };
+
+        let mut pending = self.pending.lock().unwrap();
+        let base = *self.base.lock().unwrap();
+        let weights = game::weights();
+        let gradient = gradient(&states, outcome, &weights, |phase| model.slope(phase));
+        let adapted = step(weights, &base, self.rate, &gradient, &mut pending);
+
+        // Steps under which the evaluation would favour losing every disk are dropped
+        if adapted == weights || game::validate_weights(&adapted).is_err() {
+            return Ok(());
+        }
+        info!("Adapted the evaluation weights to {:?}", adapted);
+        self.storage.save(CONFIG, BASE_KEY, &base)?;
+        apply(adapted, &self.storage, &self.engine, &self.analysis, &self.sessions)
+    }
+
+    /// Makes the adapted weights stay close to the given weights from now on, which the
+    /// administrator chose for the evaluation
+    fn rebase(&self, weights: Weights) -> Result<(), Error> {
+        *self.pending.lock().unwrap() = Default::default();
+        *self.base.lock().unwrap() = weights;
+        self.storage.save(CONFIG, BASE_KEY, &weights)
+    }
+}
+
+#[get("/admin/weights")]
+fn get(_admin: Admin) -> String {
+    json!(game::weights()).to_string()
+}
+
+#[put("/admin/weights", data = "<weights>")]
+fn update(_admin: Admin, weights: Capped<String>, storage: &State<Storage>, engine: &State<Engine>,
+          analysis: &State<ResponseCache>, sessions: &State<Sessions>,
+          adaptation: &State<Adaptation>) -> Result<String, Custom<String>> {
+    let weights: Weights = serde_json::from_str(&limits::complete(weights)?)
+        .map_err(|e| validation::invalid(Status::BadRequest, "body",
+                                         format!("Invalid weights: {}", e)))?;
+
+    apply(weights, storage, engine, analysis, sessions)?;
+    adaptation.rebase(weights)?;
+
+    Ok(json!(weights).to_string())
+}
+
+/// Returns the routes of the evaluation weights
+pub fn routes() -> Vec<Route> {
+    routes![get, update]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::Position;
+    use crate::game::{self, Action, Game, Player, Weights};
+    use crate::weights::{gradient, step};
+
+    #[test]
+    fn reject_weights() {
+        let weights = Weights {
+            placement: [-1; 3],
+            mobility: [0; 3],
+            num_disks: [-1; 3],
+        };
+
+        // Validated without touching the weights of the evaluation, which other tests share
+        assert!(game::validate_weights(&weights).is_err());
+    }
+
+    #[test]
+    fn adapt_weights() {
+        // The bot has three disks more than the human, so its loss pushes the weight of the
+        // disks of the early phase down and its win pushes it up
+        let states = [Game::new().result(&Action::parse(Player::Bot, Position::new(2, 3)))];
+        let slope = |_| 0.01;
+        assert!(gradient(&states, 0.0, &Weights::default(), slope)[2][0] > 0.0);
+        assert!(gradient(&states, 1.0, &Weights::default(), slope)[2][0] < 0.0);
+
+        let base = Weights::default();
+        let mut pending = [[0.0; 3]; 3];
+        let mut gradient = [[0.0; 3]; 3];
+        gradient[2][0] = 1.0;
+
+        let weights = step(base, &base, 0.5, &gradient, &mut pending);
+        assert_eq!(weights, base);
+        let weights = step(weights, &base, 0.5, &gradient, &mut pending);
+        assert_eq!(weights.num_disks, [base.num_disks[0] - 1, base.num_disks[1], 0]);
+
+        // However far the games push it, a weight stays close to the base
+        let mut weights = weights;
+        for _ in 0..10 {
+            weights = step(weights, &base, 10.0, &gradient, &mut pending);
+        }
+        assert_eq!(weights.num_disks[0], base.num_disks[0] - 2);
+        assert_eq!((weights.placement, weights.mobility), (base.placement, base.mobility));
+    }
+}
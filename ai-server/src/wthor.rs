@@ -0,0 +1,242 @@
+//! Reader of the WTHOR databases of the French Othello Federation
+//!
+//! Every file starts with a header of 16 bytes followed by fixed-size records: games in the
+//! `.wtb` files, and the names of the players in `.jou` and of the tournaments in `.trn`, which
+//! the games refer to by their index. Numbers are little-endian and names are Latin-1 strings
+//! padded with zeros.
+
+use std::slice::ChunksExact;
+
+use crate::board::{Position, BOARD_SIZE};
+use crate::errors::Error;
+use crate::errors::Error::ParseError;
+use crate::ggf;
+
+/// Length of the header at the start of every WTHOR file
+const HEADER_LEN: usize = 16;
+
+/// Length of a game record in a .wtb file
+const GAME_LEN: usize = 68;
+
+/// Length of a player record in a .jou file
+const PLAYER_LEN: usize = 20;
+
+/// Length of a tournament record in a .trn file
+const TOURNAMENT_LEN: usize = 26;
+
+/// Maximum number of moves of a game record
+const MAX_MOVES: usize = 60;
+
+/// Header of a WTHOR file
+#[derive(Debug, PartialEq)]
+pub struct Header {
+    /// Year, month and day the file was created
+    pub created: (u16, u8, u8),
+    /// Number of games of a .wtb file
+    pub num_games: u32,
+    /// Number of players of a .jou file or tournaments of a .trn file
+    pub num_names: u16,
+    /// Year the games were played
+    pub year: u16,
+    /// Depth from the end of the games at which the theoretical scores were computed
+    pub depth: u8,
+}
+
+impl Header {
+
+    /// Parses the header at the start of the given file
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < HEADER_LEN {
+            return Err(ParseError("The WTHOR file is missing its header".to_string()));
+        }
+
+        // Files of 10x10 games and of solitaires hold records of other lengths
+        if !matches!(data[12], 0 | 8) || data[13] != 0 {
+            return Err(ParseError("Only the WTHOR files of 8x8 games are supported".to_string()));
+        }
+
+        Ok(Self {
+            created: (data[0] as u16 * 100 + data[1] as u16, data[2], data[3]),
+            num_games: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+            num_names: u16::from_le_bytes([data[8], data[9]]),
+            year: u16::from_le_bytes([data[10], data[11]]),
+            depth: data[14],
+        })
+    }
+}
+
+/// Game of a WTHOR database
+///
+/// Dark plays first, as black does in the database.
+#[derive(Debug, PartialEq)]
+pub struct WthorGame {
+    pub tournament: u16,
+    pub dark_player: u16,
+    pub light_player: u16,
+    /// Number of dark disks at the end of the game
+    pub dark_score: u8,
+    /// Number of dark disks with perfect play from the depth given by the header
+    pub theoretical_score: u8,
+    record: [u8; MAX_MOVES],
+}
+
+impl WthorGame {
+
+    /// Returns the placements of the game in order, leaving out the passes
+    pub fn moves(&self) -> impl Iterator<Item=Position> + '_ {
+        self.record.iter().map_while(|&byte| parse_move(byte))
+    }
+}
+
+/// Iterator over the games of a .wtb file
+pub struct Games<'a> {
+    records: ChunksExact<'a, u8>,
+}
+
+impl Iterator for Games<'_> {
+    type Item = WthorGame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.records.next()?;
+        let mut moves = [0; MAX_MOVES];
+        moves.copy_from_slice(&record[8..]);
+
+        Some(WthorGame {
+            tournament: u16::from_le_bytes([record[0], record[1]]),
+            dark_player: u16::from_le_bytes([record[2], record[3]]),
+            light_player: u16::from_le_bytes([record[4], record[5]]),
+            dark_score: record[6],
+            theoretical_score: record[7],
+            record: moves,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.records.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Games<'_> {}
+
+/// Converts a WTHOR move, encoded as 10 * row + column from 1 to 8, to a position
+///
+/// The moves are in the standard orientation
+fn parse_move(byte: u8) -> Option<Position> {
+    let (row, col) = ((byte / 10) as usize, (byte % 10) as usize);
+
+    if !(1..=BOARD_SIZE).contains(&row) || !(1..=BOARD_SIZE).contains(&col) {
+        return None;
+    }
+    Some(ggf::mirror(&Position::new(row - 1, col - 1)))
+}
+
+/// Returns the records of the given length that follow the header, checking their number
+fn records(data: &[u8], count: usize, len: usize) -> Result<ChunksExact<'_, u8>, Error> {
+    let records = &data[HEADER_LEN..];
+
+    if records.len() != count * len {
+        return Err(ParseError(format!("Expected {} records of {} bytes but got {} bytes",
+                                      count, len, records.len())));
+    }
+    Ok(records.chunks_exact(len))
+}
+
+/// Parses the given record of a Latin-1 name padded with zeros
+fn parse_name(record: &[u8]) -> String {
+    record.iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| byte as char)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+/// Returns an iterator over the games of a .wtb file
+pub fn games(data: &[u8]) -> Result<Games<'_>, Error> {
+    let header = Header::parse(data)?;
+
+    Ok(Games {
+        records: records(data, header.num_games as usize, GAME_LEN)?,
+    })
+}
+
+/// Parses the names of the players of a .jou file, indexed as in the games
+pub fn players(data: &[u8]) -> Result<Vec<String>, Error> {
+    let header = Header::parse(data)?;
+    Ok(records(data, header.num_names as usize, PLAYER_LEN)?.map(parse_name).collect())
+}
+
+/// Parses the names of the tournaments of a .trn file, indexed as in the games
+pub fn tournaments(data: &[u8]) -> Result<Vec<String>, Error> {
+    let header = Header::parse(data)?;
+    Ok(records(data, header.num_names as usize, TOURNAMENT_LEN)?.map(parse_name).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::Position;
+    use crate::wthor::{games, players, tournaments, Header, GAME_LEN, HEADER_LEN, PLAYER_LEN};
+
+    /// Returns a header of a file created on 2023-01-02 holding the given numbers of records
+    fn header(num_games: u8, num_names: u8) -> Vec<u8> {
+        vec![20, 23, 1, 2, num_games, 0, 0, 0, num_names, 0, 0xe7, 0x07, 8, 0, 22, 0]
+    }
+
+    #[test]
+    fn parse_header() {
+        assert_eq!(Header::parse(&header(3, 4)).unwrap(), Header {
+            created: (2023, 1, 2),
+            num_games: 3,
+            num_names: 4,
+            year: 2023,
+            depth: 22,
+        });
+
+        let mut data = header(0, 0);
+        data[12] = 10;
+        assert!(Header::parse(&data).is_err());
+        assert!(Header::parse(&[0; 3]).is_err());
+    }
+
+    #[test]
+    fn parse_games() {
+        let mut data = header(1, 0);
+
+        let mut record = vec![0; GAME_LEN];
+        record[0] = 7;
+        record[2] = 1;
+        record[4] = 2;
+        record[6] = 40;
+        record[7] = 36;
+        record[8] = 56; // f5
+        record[9] = 64; // d6
+        record[10] = 99; // invalid, ending the moves
+        record[11] = 33; // c3
+        data.extend(record);
+
+        let mut parsed = games(&data).unwrap();
+        assert_eq!(parsed.len(), 1);
+
+        let game = parsed.next().unwrap();
+        assert_eq!((game.tournament, game.dark_player, game.light_player), (7, 1, 2));
+        assert_eq!((game.dark_score, game.theoretical_score), (40, 36));
+        assert_eq!(game.moves().collect::<Vec<_>>(),
+                   vec![Position::new(4, 2), Position::new(5, 4)]);
+        assert!(parsed.next().is_none());
+
+        assert!(games(&data[..HEADER_LEN + 1]).is_err());
+    }
+
+    #[test]
+    fn parse_names() {
+        let mut data = header(0, 2);
+        for name in ["Tastet Marc", "Caspard Hervé"] {
+            let mut record: Vec<u8> = name.chars().map(|ch| ch as u8).collect();
+            record.resize(PLAYER_LEN, 0);
+            data.extend(record);
+        }
+
+        assert_eq!(players(&data).unwrap(), vec!["Tastet Marc", "Caspard Hervé"]);
+        assert!(tournaments(&data).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs;
+use std::sync::Arc;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::board::{Board, Disk, Position};
+use crate::errors::Error;
+use crate::errors::Error::{IllegalMove, InvalidArgument, ParseError};
+use crate::game::{Action, Game, Player};
+use crate::ggf;
+
+/// Openings of the XOT set, which are balanced positions reached after 8 plies from which
+/// games are much less alike than from the initial board
+///
+/// Openings are kept in the standard orientation, one sequence of moves such as "f5d6c3d3"
+/// per line of the file.
+#[derive(Clone, Default)]
+pub struct Xot {
+    openings: Arc<Vec<Vec<Position>>>,
+}
+
+/// Plays the given opening from the initial board with the given player to move first
+///
+/// The first player takes the role of black, so the opening is mirrored when they own the dark
+/// disks, which start on the squares of white in the standard orientation.
+pub fn start(opening: &[Position], first: Player) -> Result<Game, Error> {
+    let mut game = Game::parse(Board::new(), first);
+
+    for placement in opening {
+        let placement = match first.disk() {
+            Disk::Dark => ggf::mirror(placement),
+            Disk::Light => placement.clone(),
+        };
+        let action = Action::parse(game.current_player(), placement);
+
+        game = game.play(&action)
+            .map_err(|_| IllegalMove(format!("Illegal move of the opening: {}", action)))?;
+    }
+    Ok(game)
+}
+
+impl Xot {
+
+    /// Parses the given openings, one per line, ignoring blank lines
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let openings = text.lines()
+            .map(|line| line.chars().filter(|ch| !ch.is_whitespace()).collect::<String>())
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let opening = line.as_bytes().chunks(2)
+                    .map(|chunk| Position::parse_notation(&String::from_utf8_lossy(chunk)
+                        .to_lowercase()))
+                    .collect::<Result<Vec<Position>, Error>>()
+                    .map_err(|_| ParseError(format!("Invalid opening: {}", line)))?;
+
+                start(&opening, Player::Human)?;
+                Ok(opening)
+            })
+            .collect::<Result<Vec<Vec<Position>>, Error>>()?;
+
+        Ok(Self {
+            openings: Arc::new(openings),
+        })
+    }
+
+    /// Loads the openings from the file at the given path
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| InvalidArgument(format!("Failed to read {}: {}", path, e)))?;
+        Self::parse(&text)
+    }
+
+    /// Returns the number of openings
+    pub fn len(&self) -> usize {
+        self.openings.len()
+    }
+
+    /// Checks if no opening is loaded
+    pub fn is_empty(&self) -> bool {
+        self.openings.is_empty()
+    }
+
+    /// Returns a random opening
+    pub fn choose(&self, rng: &mut impl Rng) -> Result<&[Position], Error> {
+        self.openings.choose(rng)
+            .map(|opening| opening.as_slice())
+            .ok_or_else(|| InvalidArgument("No XOT openings are loaded".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Player;
+    use crate::xot::{start, Xot};
+
+    #[test]
+    fn openings() {
+        let xot = Xot::parse("f5d6c3d3c4f4c5b3\n\nF5 F6 E6 F4 E3 C5 C4 D3\n").unwrap();
+        assert_eq!(xot.len(), 2);
+
+        let opening = xot.choose(&mut rand::thread_rng()).unwrap();
+        for first in [Player::Human, Player::Bot] {
+            let game = start(opening, first).unwrap();
+            assert_eq!(game.current_player(), first);
+            assert_eq!(game.board().to_string().matches(['D', 'L']).count(), 12);
+        }
+
+        assert!(Xot::parse("f5d6a1").is_err());
+        assert!(Xot::parse("f5f5").is_err());
+        assert!(Xot::default().choose(&mut rand::thread_rng()).is_err());
+    }
+}